@@ -21,12 +21,12 @@ fn main() {
         let ctx = scope.spawn().unwrap();
         ctx.add_sys_include_path("/usr/include/x86_64-linux-gnu")
             .add_library_path("/usr/lib/x86_64-linux-gnu")
-            .add_sys_include_path("/vfs/headers/base")
-            .add_sys_include_path("/vfs/headers/win32")
-            .add_library_path("/vfs/libraries");
+            .use_embedded_sysroot()
+            .unwrap();
 
         let compile_ret = ctx
             .set_output_type(OutputType::Memory)
+            .unwrap()
             .set_call_back({
                 let err_warn = err_warn.clone();
                 move |msg| {
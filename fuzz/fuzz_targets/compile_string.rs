@@ -0,0 +1,21 @@
+//! Arbitrary bytes as C source, compiled and relocated in memory.
+//!
+//! Nothing is run; the target only checks that tcc rejects bad input
+//! through diagnostics instead of crashing.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tcc::OutputType;
+
+fuzz_target!(|source: &[u8]| {
+    let _ = tcc::scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .collect_diagnostics(true);
+        if ctx.compile_bytes(source).is_ok() {
+            let _ = ctx.relocate();
+        }
+    });
+});
@@ -0,0 +1,23 @@
+//! Arbitrary command line strings through `tcc_set_options`, followed by a
+//! small compile so the parsed settings take effect.
+
+#![no_main]
+
+use std::ffi::CString;
+
+use libfuzzer_sys::fuzz_target;
+use tcc::OutputType;
+
+fuzz_target!(|options: &[u8]| {
+    let Ok(options) = CString::new(options) else {
+        return;
+    };
+    let _ = tcc::scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .collect_diagnostics(true);
+        ctx.set_options(&options);
+        let _ = ctx.compile_str("int main(void) { return 0; }");
+    });
+});
@@ -0,0 +1,78 @@
+//! Sequences of `open`, `read`, `lseek` and `close` on the VFS as tcc issues
+//! them, against a mounted file, a missing one and bogus descriptors.
+
+#![no_main]
+
+use core::ffi::{c_int, c_void, CStr};
+
+use libfuzzer_sys::{arbitrary::Arbitrary, fuzz_target};
+use tcc_sys::vfs::{self, vfs_close, vfs_lseek, vfs_open, vfs_read};
+
+const PATHS: [&CStr; 3] = [
+    c"/vfs/fuzz/mounted.h",
+    c"/vfs/fuzz/missing.h",
+    c"/vfs/headers/base/stddef.h",
+];
+
+#[derive(Debug, Arbitrary)]
+enum Op {
+    Open { path: u8 },
+    Read { fd: u8, len: u16 },
+    Seek { fd: u8, offset: i32, whence: u8 },
+    Close { fd: u8 },
+}
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    contents: Vec<u8>,
+    ops:      Vec<Op>,
+}
+
+fuzz_target!(|input: Input| {
+    vfs::mount("/vfs/fuzz/mounted.h", input.contents);
+    // descriptors handed out so far; out of range indices try stale or made
+    // up ones
+    let mut fds: Vec<c_int> = Vec::new();
+    let fd = |fds: &[c_int], index: u8| {
+        fds.get(index as usize)
+            .copied()
+            .unwrap_or(index as c_int - 128)
+    };
+    let mut buf = vec![0u8; u16::MAX as usize];
+    for op in input.ops {
+        unsafe {
+            match op {
+                Op::Open { path } => {
+                    let path = PATHS[path as usize % PATHS.len()];
+                    // read only, so nothing on disk is ever touched
+                    let fd = vfs_open(path.as_ptr(), libc::O_RDONLY);
+                    if fd >= 0 {
+                        fds.push(fd);
+                    }
+                }
+                Op::Read { fd: index, len } => {
+                    let len = len as usize;
+                    let read = vfs_read(fd(&fds, index), buf.as_mut_ptr() as *mut c_void, len);
+                    assert!(read <= len as isize);
+                }
+                Op::Seek {
+                    fd: index,
+                    offset,
+                    whence,
+                } => {
+                    vfs_lseek(fd(&fds, index), offset.into(), (whence % 4).into());
+                }
+                Op::Close { fd: index } => {
+                    let fd = fd(&fds, index);
+                    if vfs_close(fd) == 0 {
+                        fds.retain(|&open| open != fd);
+                    }
+                }
+            }
+        }
+    }
+    for fd in fds {
+        unsafe { vfs_close(fd) };
+    }
+    vfs::unmount("/vfs/fuzz/mounted.h");
+});
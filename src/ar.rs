@@ -0,0 +1,209 @@
+//! Static libraries in the common `ar` format, with the GNU symbol index
+//! linkers need to pick members, as `tcc -ar` writes them.
+//!
+//! ```no_run
+//! # fn main() -> Result<(), tcc::Error> {
+//! // objects written with OutputType::Obj
+//! tcc::ar::create("libgreet.a", &["hello.o", "bye.o"])?;
+//! # Ok(())
+//! # }
+//! ```
+
+use alloc::{format, string::String, vec::Vec};
+#[cfg(feature = "std")] use std::{fs, path::Path};
+
+#[cfg(feature = "std")] use crate::Error;
+
+/// Write the archive of the object files `objects` to `path`, members named
+/// after the objects' file names, see [`create_bytes`].
+#[cfg(feature = "std")]
+pub fn create<P: AsRef<Path>, O: AsRef<Path>>(path: P, objects: &[O]) -> Result<(), Error> {
+    let mut files = Vec::with_capacity(objects.len());
+    for object in objects {
+        let object = object.as_ref();
+        let name = object.file_name().unwrap_or(object.as_os_str());
+        files.push((name.to_string_lossy().into_owned(), fs::read(object)?));
+    }
+    let members: Vec<(&str, &[u8])> = files
+        .iter()
+        .map(|(name, data)| (name.as_str(), data.as_slice()))
+        .collect();
+    fs::write(path, create_bytes(&members))?;
+    Ok(())
+}
+
+/// An archive of `members`, given as file name and contents.
+///
+/// The index lists the global symbols defined by members that are ELF
+/// objects, which is what tcc produces; other members are stored but not
+/// indexed. Timestamps and owners are zero so the output is reproducible.
+pub fn create_bytes(members: &[(&str, &[u8])]) -> Vec<u8> {
+    // names that do not fit the header go to the `//` member
+    let mut long_names = Vec::new();
+    let names: Vec<String> = members
+        .iter()
+        .map(|(name, _)| {
+            match name.len() < 16 {
+                true => format!("{name}/"),
+                false => {
+                    let offset = long_names.len();
+                    long_names.extend_from_slice(name.as_bytes());
+                    long_names.extend_from_slice(b"/\n");
+                    format!("/{offset}")
+                }
+            }
+        })
+        .collect();
+    let symbols: Vec<Vec<&[u8]>> = members.iter().map(|(_, data)| elf_symbols(data)).collect();
+
+    let index_len = 4 + symbols
+        .iter()
+        .flatten()
+        .map(|symbol| 4 + symbol.len() + 1)
+        .sum::<usize>();
+    let mut offset = 8 + member_len(index_len);
+    if !long_names.is_empty() {
+        offset += member_len(long_names.len());
+    }
+    let mut offsets = Vec::new();
+    for (_, data) in members {
+        offsets.push(offset);
+        offset += member_len(data.len());
+    }
+
+    let count = symbols.iter().map(Vec::len).sum::<usize>();
+    let mut index = Vec::with_capacity(index_len);
+    index.extend_from_slice(&(count as u32).to_be_bytes());
+    for (symbols, &offset) in symbols.iter().zip(&offsets) {
+        for _ in symbols {
+            index.extend_from_slice(&(offset as u32).to_be_bytes());
+        }
+    }
+    for symbol in symbols.iter().flatten() {
+        index.extend_from_slice(symbol);
+        index.push(0);
+    }
+
+    let mut out = Vec::with_capacity(offset);
+    out.extend_from_slice(b"!<arch>\n");
+    push_member(&mut out, "/", &index);
+    if !long_names.is_empty() {
+        push_member(&mut out, "//", &long_names);
+    }
+    for (name, (_, data)) in names.iter().zip(members) {
+        push_member(&mut out, name, data);
+    }
+    out
+}
+
+/// Size of a member with `len` bytes of data, header and padding included.
+fn member_len(len: usize) -> usize {
+    60 + len + len % 2
+}
+
+fn push_member(out: &mut Vec<u8>, name: &str, data: &[u8]) {
+    let header = format!(
+        "{name:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+        0,
+        0,
+        0,
+        644,
+        data.len()
+    );
+    out.extend_from_slice(header.as_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(b'\n');
+    }
+}
+
+/// Names of the global and weak symbols an ELF object defines, empty if
+/// `data` is not a well-formed ELF object.
+fn elf_symbols(data: &[u8]) -> Vec<&[u8]> {
+    elf_symbols_checked(data).unwrap_or_default()
+}
+
+fn elf_symbols_checked(data: &[u8]) -> Option<Vec<&[u8]>> {
+    const SHT_SYMTAB: u32 = 2;
+    const STB_GLOBAL: u8 = 1;
+    const STB_WEAK: u8 = 2;
+
+    if data.get(..4)? != b"\x7fELF" {
+        return None;
+    }
+    let wide = match data.get(4)? {
+        1 => false,
+        2 => true,
+        _ => return None,
+    };
+    let big = *data.get(5)? == 2;
+    let read = |offset: usize, size: usize| -> Option<u64> {
+        let bytes = data.get(offset..offset.checked_add(size)?)?;
+        let mut value = 0u64;
+        for i in 0..size {
+            let byte = match big {
+                true => bytes[i],
+                false => bytes[size - 1 - i],
+            };
+            value = value << 8 | u64::from(byte);
+        }
+        Some(value)
+    };
+    let word = if wide { 8 } else { 4 };
+    let read_word = |offset: usize| read(offset, word).map(|value| value as usize);
+
+    let (shoff, shentsize, shnum) = match wide {
+        true => (read_word(0x28)?, read(0x3a, 2)?, read(0x3c, 2)?),
+        false => (read_word(0x20)?, read(0x2e, 2)?, read(0x30, 2)?),
+    };
+    // offset, size and link of section `index`
+    let section = |index: usize| -> Option<(u32, usize, usize, usize)> {
+        let base = shoff.checked_add(index.checked_mul(shentsize as usize)?)?;
+        let kind = read(base + 4, 4)? as u32;
+        match wide {
+            true => {
+                Some((
+                    kind,
+                    read_word(base + 24)?,
+                    read_word(base + 32)?,
+                    read(base + 40, 4)? as usize,
+                ))
+            }
+            false => {
+                Some((
+                    kind,
+                    read_word(base + 16)?,
+                    read_word(base + 20)?,
+                    read(base + 24, 4)? as usize,
+                ))
+            }
+        }
+    };
+
+    let mut names = Vec::new();
+    for index in 0..shnum as usize {
+        let (kind, offset, size, link) = section(index)?;
+        if kind != SHT_SYMTAB {
+            continue;
+        }
+        let (_, strings, strings_len, _) = section(link)?;
+        let strings = data.get(strings..strings.checked_add(strings_len)?)?;
+        let entry = if wide { 24 } else { 16 };
+        for symbol in (offset..offset.checked_add(size)?).step_by(entry).skip(1) {
+            let (info, shndx) = match wide {
+                true => (read(symbol + 4, 1)?, read(symbol + 6, 2)?),
+                false => (read(symbol + 12, 1)?, read(symbol + 14, 2)?),
+            };
+            let bind = (info >> 4) as u8;
+            if shndx == 0 || (bind != STB_GLOBAL && bind != STB_WEAK) {
+                continue;
+            }
+            let name = strings.get(read(symbol, 4)? as usize..)?;
+            let name = &name[..name.iter().position(|&b| b == 0)?];
+            if !name.is_empty() {
+                names.push(name);
+            }
+        }
+    }
+    Some(names)
+}
@@ -0,0 +1,148 @@
+//! Directories for files produced by [`Context::output_file`] and friends.
+//!
+//! [`Context::output_file`]: crate::Context::output_file
+
+use alloc::{format, string::String, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::Mutex,
+};
+
+use crate::Error;
+
+/// Directories created by this process so far, to keep their names apart.
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+/// A directory handing out unique paths for output files and removing them
+/// again when dropped.
+///
+/// ```
+/// use tcc::{artifacts::OutDir, Context, OutputType};
+///
+/// let out = OutDir::temp().unwrap();
+/// let obj = out.path("add.o");
+/// # let _ = tcc::scoped(|scope| -> Result<(), tcc::Error> {
+/// #     let ctx = scope.spawn()?;
+/// ctx.set_output_type(OutputType::Obj)?;
+/// ctx.compile_str("int add(int a, int b) { return a + b; }")?;
+/// ctx.output_file(&obj)?;
+/// #     Ok(())
+/// # });
+/// // `obj` is gone once `out` is dropped
+/// ```
+#[derive(Debug)]
+pub struct OutDir {
+    dir:     PathBuf,
+    /// whether the directory itself was created here and goes on drop
+    owned:   bool,
+    handed:  Mutex<Vec<PathBuf>>,
+    cleanup: bool,
+}
+
+impl OutDir {
+    /// A new, empty directory in the system's temporary directory, only
+    /// accessible by the current user on Unix, removed with everything in
+    /// it when dropped.
+    pub fn temp() -> Result<Self, Error> {
+        loop {
+            let id = CREATED.fetch_add(1, Ordering::Relaxed);
+            let dir = env::temp_dir().join(format!("tcc-rs-out-{}-{id}", process::id()));
+            let mut builder = fs::DirBuilder::new();
+            // nobody else gets to plant files or links in it
+            #[cfg(unix)]
+            std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+            match builder.create(&dir) {
+                Ok(()) => return Ok(Self::new(dir, true)),
+                // left behind by an earlier process with the same id
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Use `dir`, creating it if needed.
+    ///
+    /// Only the files handed out by [`OutDir::path`] are removed when
+    /// dropped; the directory and anything else in it are left alone.
+    pub fn at(dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        Ok(Self::new(dir.to_path_buf(), false))
+    }
+
+    fn new(dir: PathBuf, owned: bool) -> Self {
+        OutDir {
+            dir,
+            owned,
+            handed: Mutex::default(),
+            cleanup: true,
+        }
+    }
+
+    /// The directory itself.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// A path in the directory named after `name` that neither exists nor
+    /// was handed out before.
+    ///
+    /// `name` is used as is when free, else a counter is inserted before its
+    /// extension: `add.o`, `add-1.o`, `add-2.o`, ...
+    pub fn path(&self, name: &str) -> PathBuf {
+        let (stem, extension) = match name.rfind('.') {
+            Some(dot) if dot > 0 => name.split_at(dot),
+            _ => (name, ""),
+        };
+        let mut handed = self.handed.lock().unwrap();
+        let path = (0..)
+            .map(|n| {
+                let name = match n {
+                    0 => String::from(name),
+                    n => format!("{stem}-{n}{extension}"),
+                };
+                self.dir.join(name)
+            })
+            .find(|path| !handed.contains(path) && !path.exists())
+            .unwrap();
+        handed.push(path.clone());
+        path
+    }
+
+    /// The handed out paths a file was written to, in the order they were
+    /// handed out.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let handed = self.handed.lock().unwrap();
+        handed
+            .iter()
+            .filter(|path| path.exists())
+            .cloned()
+            .collect()
+    }
+
+    /// Give up the files, leaving them in place when dropped, and return the
+    /// directory.
+    pub fn keep(mut self) -> PathBuf {
+        self.cleanup = false;
+        self.dir.clone()
+    }
+}
+
+impl Drop for OutDir {
+    fn drop(&mut self) {
+        if !self.cleanup {
+            return;
+        }
+        // cleaning up is best effort, a file may still be open on Windows
+        if self.owned {
+            let _ = fs::remove_dir_all(&self.dir);
+            return;
+        }
+        for path in self.handed.get_mut().unwrap().drain(..) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! Rejecting sources that use functions on a deny-list.
+//!
+//! A cheap first line of defense for sandboxes, not a replacement for one:
+//! a determined program can still reach the functions through pointers it
+//! computes.
+
+use alloc::{string::String, vec::Vec};
+
+use tcc_sys::PREPROCESS_OUTPUT;
+
+use crate::{screen::Scan, Context, Error};
+
+/// Functions that start processes or load code, for
+/// [`Context::set_banned_functions`]; `exec*` covers `execve` and the like.
+pub const DEFAULT_BANNED_FUNCTIONS: &[&str] = &[
+    "system",
+    "popen",
+    "exec*",
+    "fork",
+    "vfork",
+    "posix_spawn*",
+    "dlopen",
+];
+
+/// Whether `name` is matched by `pattern`, an exact name or a prefix
+/// followed by `*`.
+fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == pattern,
+    }
+}
+
+/// Whether the `{` at `index` of `scan` opens the body of a struct, union
+/// or enum, whose names declare members rather than use anything.
+fn aggregate_body(scan: &Scan, index: usize) -> bool {
+    let mut parens = 0usize;
+    for back in 1..=index as isize {
+        match scan.token(index, -back) {
+            "struct" | "union" | "enum" if parens == 0 => return true,
+            ")" => parens += 1,
+            "(" => {
+                parens = parens.saturating_sub(1);
+                // only attributes come in parentheses before the body,
+                // anything else makes this a function returning a struct
+                let before = scan.token(index, -back - 1);
+                if parens == 0
+                    && !matches!(
+                        before,
+                        "__attribute__" | "__attribute" | "__declspec" | "_Alignas"
+                    )
+                {
+                    return false;
+                }
+            }
+            _ if parens > 0 => {}
+            token if token.starts_with(|c: char| c.is_alphabetic() || c == '_') => {}
+            _ => return false,
+        }
+    }
+    false
+}
+
+/// Index of every token of `scan` using a banned function.
+///
+/// Declarations and struct members of the same name are not uses; calls
+/// are, and so is taking a function's address as a value, casts included.
+pub(crate) fn uses(banned: &[String], scan: &Scan) -> Vec<usize> {
+    let tokens = &scan.tokens;
+    let mut found = Vec::new();
+    // for each open brace, whether it is a struct, union or enum body
+    let mut braces = Vec::new();
+    for (index, &(_, token)) in tokens.iter().enumerate() {
+        match token {
+            "{" => braces.push(aggregate_body(scan, index)),
+            "}" => drop(braces.pop()),
+            _ => {}
+        }
+        let (previous, next) = (scan.token(index, -1), scan.token(index, 1));
+        let member = previous == "." || (previous == ">" && scan.token(index, -2) == "-");
+        // a call, or the function's address taken as a value; after `)`
+        // either a cast or, followed by `(`, a declarator behind attributes
+        let used = match braces.last() {
+            None => matches!(previous, "=" | "&") || (previous == ")" && next != "("),
+            Some(true) => false,
+            Some(false) => {
+                next == "("
+                    || matches!(
+                        previous,
+                        "=" | "&" | "*" | "(" | ")" | "," | "{" | "?" | ":" | "return"
+                    )
+            }
+        };
+        if used && !member && banned.iter().any(|pattern| matches(pattern, token)) {
+            found.push(index);
+        }
+    }
+    found
+}
+
+impl<'err> Context<'err> {
+    /// Reject sources using any of `functions`, exact names or prefixes
+    /// followed by `*` like `exec*`, see [`DEFAULT_BANNED_FUNCTIONS`]. An
+    /// empty list turns the check off.
+    ///
+    /// Sources passed as text are preprocessed in a [fork](Context::fork)
+    /// of this context before they are compiled, and every use of a banned
+    /// function fails the compilation with [`Error::BannedFunction`] and an
+    /// error diagnostic naming the line; a source that does not preprocess
+    /// fails with the error of the fork. As for
+    /// [`Context::set_gnu_extensions`], files added by path are not
+    /// checked. Needs the vendored libtcc to preprocess, else this fails
+    /// with [`Error::UnsupportedOption`].
+    pub fn set_banned_functions<I, S>(&mut self, functions: I) -> Result<&mut Self, Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        if !PREPROCESS_OUTPUT {
+            return Err(Error::UnsupportedOption {
+                option: "banned functions",
+                reason: "only the vendored libtcc can preprocess sources for the check",
+            });
+        }
+        self.banned = functions.into_iter().map(Into::into).collect();
+        Ok(self)
+    }
+}
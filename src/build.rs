@@ -0,0 +1,256 @@
+//! Compiling C from build scripts with the vendored tcc, modelled on the
+//! `cc` crate.
+//!
+//! Nothing has to be installed on the building machine, which makes this a
+//! fallback for crates whose C is simple enough for tcc:
+//!
+//! ```no_run
+//! // build.rs
+//! tcc::build::Build::new()
+//!     .file("src/foo.c")
+//!     .define("FOO", Some("1"))
+//!     .compile("foo");
+//! ```
+//!
+//! tcc writes ELF objects, so only targets linking ELF can use the static
+//! library, and only when building for the host.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    println,
+};
+
+use crate::{ar, capabilities, jit, lock, Context, Error, ExecutableFormat, OutputType};
+
+/// A static library to build, see the [module documentation](self).
+#[derive(Debug, Clone, Default)]
+pub struct Build {
+    files:          Vec<PathBuf>,
+    includes:       Vec<PathBuf>,
+    defines:        Vec<(String, Option<String>)>,
+    flags:          Vec<String>,
+    out_dir:        Option<PathBuf>,
+    cargo_metadata: Option<bool>,
+}
+
+impl Build {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a C file to compile.
+    pub fn file(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.files.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Add several C files to compile.
+    pub fn files<P: AsRef<Path>>(&mut self, paths: impl IntoIterator<Item = P>) -> &mut Self {
+        for path in paths {
+            self.file(path);
+        }
+        self
+    }
+
+    /// Add an include directory.
+    pub fn include(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.includes.push(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Define `name`, to `value` or else to 1 like `-Dname`.
+    pub fn define<'a>(&mut self, name: &str, value: impl Into<Option<&'a str>>) -> &mut Self {
+        let value = value.into().map(ToString::to_string);
+        self.defines.push((name.to_string(), value));
+        self
+    }
+
+    /// Pass `flag` to tcc as on its command line.
+    pub fn flag(&mut self, flag: &str) -> &mut Self {
+        self.flags.push(flag.to_string());
+        self
+    }
+
+    /// Where objects and the library go, `OUT_DIR` by default.
+    pub fn out_dir(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        self.out_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Whether to print the `cargo:` lines linking the library, on by
+    /// default.
+    pub fn cargo_metadata(&mut self, enable: bool) -> &mut Self {
+        self.cargo_metadata = Some(enable);
+        self
+    }
+
+    /// Build the static library `name` and tell cargo to link it, panicking
+    /// on failure like `cc` does.
+    pub fn compile(&self, name: &str) {
+        if let Err(err) = self.try_compile(name) {
+            panic!("failed to build {name} with tcc: {err}");
+        }
+    }
+
+    /// Build the static library `name`, `lib<name>.a` on most targets, and
+    /// tell cargo to link it.
+    pub fn try_compile(&self, name: &str) -> Result<(), Error> {
+        let (host, target) = (env::var("HOST"), env::var("TARGET"));
+        if matches!((host, target), (Ok(host), Ok(target)) if host != target) {
+            return Err(Error::UnsupportedOption {
+                option: "build",
+                reason: "the vendored tcc only compiles for the host",
+            });
+        }
+        if capabilities().format != ExecutableFormat::Elf {
+            return Err(Error::UnsupportedOption {
+                option: "build",
+                reason: "the system linker does not take tcc's ELF objects",
+            });
+        }
+        let out_dir = match &self.out_dir {
+            Some(dir) => dir.clone(),
+            None => {
+                env::var_os("OUT_DIR")
+                    .map(PathBuf::from)
+                    .ok_or(Error::UnsupportedOption {
+                        option: "build",
+                        reason: "OUT_DIR is not set, not running in a build script",
+                    })?
+            }
+        };
+        fs::create_dir_all(&out_dir)?;
+
+        let mut objects = Vec::new();
+        for (index, file) in self.files.iter().enumerate() {
+            let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+            let object = out_dir.join(format!("{index}-{stem}.o"));
+            self.object(file, &object)?;
+            objects.push(object);
+        }
+        let lib = out_dir.join(library_file_name(name, LibraryKind::Static));
+        ar::create(&lib, &objects)?;
+
+        if self.cargo_metadata.unwrap_or(true) {
+            link_library(lib, LibraryKind::Static)?;
+        }
+        Ok(())
+    }
+
+    /// Compile `file` into the object `out`.
+    fn object(&self, file: &Path, out: &Path) -> Result<(), Error> {
+        let _lock = lock();
+        let mut ctx = Context::new()?;
+        ctx.set_output_type(OutputType::Obj)?
+            .collect_diagnostics(true);
+        for dir in &self.includes {
+            ctx.add_include_path(dir);
+        }
+        for (name, value) in &self.defines {
+            let define = jit::c_strings([name.as_str()].into_iter().chain(value.as_deref()))?;
+            ctx.define(&define[0], define.get(1).map(|val| val.as_c_str()));
+        }
+        for flag in jit::c_strings(self.flags.iter().map(String::as_str))? {
+            ctx.set_options(&flag);
+        }
+        ctx.add_file(file)?;
+        ctx.output_file(out)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// How a library is linked, see [`link_library`].
+pub enum LibraryKind {
+    Static,
+    Dynamic,
+}
+
+/// The target being built for: from the variables cargo gives build
+/// scripts, else the target this crate was compiled for.
+fn target() -> (String, String) {
+    let var = |name, fallback: &str| env::var(name).unwrap_or_else(|_| fallback.to_string());
+    (
+        var("CARGO_CFG_TARGET_OS", env::consts::OS),
+        var(
+            "CARGO_CFG_TARGET_ENV",
+            if cfg!(target_env = "msvc") {
+                "msvc"
+            } else {
+                ""
+            },
+        ),
+    )
+}
+
+/// Prefix and extension of `kind` libraries on the target.
+fn library_naming(kind: LibraryKind) -> (&'static str, &'static str) {
+    let (os, env) = target();
+    match (kind, os.as_str(), env.as_str()) {
+        (LibraryKind::Static, "windows", "msvc") => ("", ".lib"),
+        (LibraryKind::Static, ..) => ("lib", ".a"),
+        (LibraryKind::Dynamic, "windows", _) => ("", ".dll"),
+        (LibraryKind::Dynamic, "macos" | "ios" | "tvos" | "watchos" | "visionos", _) => {
+            ("lib", ".dylib")
+        }
+        (LibraryKind::Dynamic, ..) => ("lib", ".so"),
+    }
+}
+
+/// The file name of library `name` on the target: `libfoo.a`, `foo.lib`,
+/// `libfoo.so`, `libfoo.dylib` or `foo.dll` for `foo`.
+///
+/// ```
+/// use tcc::build::{library_file_name, LibraryKind};
+///
+/// # if cfg!(target_os = "linux") {
+/// assert_eq!(library_file_name("foo", LibraryKind::Dynamic), "libfoo.so");
+/// # }
+/// ```
+pub fn library_file_name(name: &str, kind: LibraryKind) -> String {
+    let (prefix, extension) = library_naming(kind);
+    format!("{prefix}{name}{extension}")
+}
+
+/// Print the `cargo:` lines linking the library at `path`, typically written
+/// by [`Context::output_file`](crate::Context::output_file) to a path from
+/// [`library_file_name`].
+///
+/// Fails if the file name does not follow the target's naming for `kind`
+/// libraries, as the linker would not find it.
+pub fn link_library(path: impl AsRef<Path>, kind: LibraryKind) -> Result<(), Error> {
+    for line in link_lines(path.as_ref(), kind)? {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+pub(crate) fn link_lines(path: &Path, kind: LibraryKind) -> Result<[String; 2], Error> {
+    let (prefix, extension) = library_naming(kind);
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_prefix(prefix)?.strip_suffix(extension))
+        .filter(|name| !name.is_empty())
+        .ok_or(Error::UnsupportedOption {
+            option: "link_library",
+            reason: "the file name does not follow the target's library naming",
+        })?;
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+    let kind = match kind {
+        LibraryKind::Static => "static",
+        LibraryKind::Dynamic => "dylib",
+    };
+    Ok([
+        format!("cargo:rustc-link-search=native={}", dir.display()),
+        format!("cargo:rustc-link-lib={kind}={name}"),
+    ])
+}
@@ -0,0 +1,210 @@
+//! `NT_GNU_BUILD_ID` notes in ELF executables and shared objects.
+
+use alloc::{ffi::CString, string::String, vec, vec::Vec};
+use core::{
+    fmt::Write,
+    hash::{BuildHasher, Hasher},
+};
+use std::{collections::hash_map::RandomState, fs, path::Path, time::SystemTime};
+
+use crate::{capabilities, Context, Error, ExecutableFormat, OutputType};
+
+/// Section the note is compiled into and found by in the output.
+const SECTION: &str = ".note.gnu.build-id";
+const NT_GNU_BUILD_ID: u32 = 3;
+const SHT_NOTE: u32 = 7;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// Build ID of an ELF output, see [`Context::set_build_id`].
+pub enum BuildId {
+    /// SHA-1 of the output with the ID zeroed, like `ld --build-id=sha1`:
+    /// identical builds get identical IDs.
+    Sha1OfContents,
+    /// A random 16 byte version 4 UUID, like `ld --build-id=uuid`.
+    Uuid,
+    /// The given bytes, like `ld --build-id=0x…`.
+    Bytes(Vec<u8>),
+}
+
+impl BuildId {
+    fn len(&self) -> usize {
+        match self {
+            BuildId::Sha1OfContents => 20,
+            BuildId::Uuid => 16,
+            BuildId::Bytes(bytes) => bytes.len(),
+        }
+    }
+}
+
+impl<'err> Context<'err> {
+    /// Give the executable or shared object an `NT_GNU_BUILD_ID` note, so
+    /// symbol servers and debuginfod can match it with its debug
+    /// information.
+    ///
+    /// The output type must already be [`OutputType::Exe`] or
+    /// [`OutputType::Dll`] and tcc must target ELF. The note is compiled
+    /// into a `.note.gnu.build-id` section and filled in by
+    /// [`Context::output_file`] once the output is linked, before any
+    /// [output transform](Context::set_output_transform) runs. tcc puts
+    /// the section in no `PT_NOTE` segment, so the ID is found through the
+    /// section headers, as `readelf -n` and elfutils do. Fails with
+    /// [`Error::UnsupportedOption`] if a build ID is already set or `Bytes`
+    /// are empty.
+    pub fn set_build_id(&mut self, id: BuildId) -> Result<&mut Self, Error> {
+        let unsupported = |reason| {
+            Err(Error::UnsupportedOption {
+                option: "--build-id",
+                reason,
+            })
+        };
+        if capabilities().format != ExecutableFormat::Elf {
+            return unsupported("build IDs are ELF notes");
+        }
+        if !matches!(self.output_type(), Some(OutputType::Exe | OutputType::Dll)) {
+            return unsupported("only applies to executables and shared objects");
+        }
+        if self.build_id.is_some() {
+            return unsupported("the build ID is already set");
+        }
+        if id.len() == 0 {
+            return unsupported("the build ID is empty");
+        }
+
+        // name and a zeroed descriptor, both padded to 4 bytes
+        let mut note = Vec::new();
+        for word in [4, id.len() as u32, NT_GNU_BUILD_ID] {
+            note.extend_from_slice(&word.to_le_bytes());
+        }
+        note.extend_from_slice(b"GNU\0");
+        note.resize(note.len() + id.len().next_multiple_of(4), 0);
+        let mut source = String::new();
+        let _ = write!(
+            source,
+            "__attribute__((section(\"{SECTION}\"), aligned(4)))\nconst unsigned char \
+             __tcc_rs_build_id[{}] = {{",
+            note.len()
+        );
+        for byte in note {
+            let _ = write!(source, "{byte},");
+        }
+        source.push_str("};\n");
+        self.compile_unscreened(&CString::new(source).unwrap())?;
+        self.build_id = Some(id);
+        Ok(self)
+    }
+
+    /// Fill in the build ID of the output tcc wrote to `path`.
+    pub(crate) fn write_build_id(&self, path: &Path) -> Result<(), Error> {
+        let Some(id) = &self.build_id else {
+            return Ok(());
+        };
+        let mut elf = fs::read(path)?;
+        let (header, desc) = find_note(&elf).ok_or(Error::Output)?;
+        elf[header..header + 4].copy_from_slice(&SHT_NOTE.to_le_bytes());
+        let bytes = match id {
+            BuildId::Sha1OfContents => sha1(&elf).to_vec(),
+            BuildId::Uuid => uuid().to_vec(),
+            BuildId::Bytes(bytes) => bytes.clone(),
+        };
+        elf[desc..desc + bytes.len()].copy_from_slice(&bytes);
+        fs::write(path, elf)?;
+        Ok(())
+    }
+}
+
+/// Offsets of the `sh_type` of the build ID section and of the note
+/// descriptor in a little-endian `elf`.
+fn find_note(elf: &[u8]) -> Option<(usize, usize)> {
+    let read = |at: usize, len: usize| -> Option<usize> {
+        let bytes = elf.get(at..at + len)?;
+        Some(bytes.iter().rev().fold(0, |n, &b| n << 8 | b as usize))
+    };
+    if elf.get(..4)? != b"\x7fELF" || elf.get(5)? != &1 {
+        return None;
+    }
+    // offsets of e_shoff, the e_shentsize triple, sh_offset and sh_size
+    let (wide, shoff, shent, offset) = match elf.get(4)? {
+        1 => (4, 0x20, 0x2e, 0x10),
+        2 => (8, 0x28, 0x3a, 0x18),
+        _ => return None,
+    };
+    let shoff = read(shoff, wide)?;
+    let (entsize, count, strndx) = (read(shent, 2)?, read(shent + 2, 2)?, read(shent + 4, 2)?);
+    let header = |index: usize| shoff + index * entsize;
+    let strtab = read(header(strndx) + offset, wide)?;
+    (0..count).find_map(|index| {
+        let name = strtab + read(header(index), 4)?;
+        let name = elf.get(name..name + SECTION.len() + 1)?;
+        if &name[..SECTION.len()] != SECTION.as_bytes() || name[SECTION.len()] != 0 {
+            return None;
+        }
+        let start = read(header(index) + offset, wide)?;
+        let size = read(header(index) + offset + wide, wide)?;
+        // namesz, descsz, type, "GNU\0"
+        let desc = start + 16;
+        let len = read(start + 4, 4)?;
+        (desc + len <= start + size && desc + len <= elf.len()).then_some((header(index) + 4, desc))
+    })
+}
+
+/// A random version 4 UUID.
+fn uuid() -> [u8; 16] {
+    let mut uuid = [0; 16];
+    let time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos());
+    for (i, half) in uuid.chunks_mut(8).enumerate() {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(time);
+        hasher.write_usize(i);
+        half.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    uuid[6] = uuid[6] & 0x0f | 0x40;
+    uuid[8] = uuid[8] & 0x3f | 0x80;
+    uuid
+}
+
+/// SHA-1 digest of `data`.
+pub(crate) fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    message.resize((data.len() + 9).next_multiple_of(64), 0);
+    let end = message.len();
+    message[end - 8..].copy_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = vec![0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..20 => ((b & c) | (!b & d), 0x5a827999),
+                20..40 => (b ^ c ^ d, 0x6ed9eba1),
+                40..60 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let t = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            (e, d, c, b, a) = (d, c, b.rotate_left(30), a, t);
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (out, h) in digest.chunks_mut(4).zip(h) {
+        out.copy_from_slice(&h.to_be_bytes());
+    }
+    digest
+}
@@ -0,0 +1,128 @@
+//! Configuration from lists, as read from configuration files, with every
+//! entry checked before any is applied.
+
+use alloc::{ffi::CString, string::String, vec::Vec};
+#[cfg(feature = "std")] use std::path::Path;
+
+#[cfg(not(feature = "std"))] use unix_path::Path;
+
+use crate::{try_to_cstr, Context, Error};
+
+/// Why `name` is not a macro name, plain or with a parameter list like
+/// `MAX(a, b)`.
+fn check_macro_name(name: &str) -> Option<&'static str> {
+    let (ident, params) = match name.split_once('(') {
+        Some((ident, params)) => (ident, Some(params)),
+        None => (name, None),
+    };
+    let identifier = ident.bytes().next().is_some_and(|b| !b.is_ascii_digit())
+        && ident
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_');
+    if !identifier {
+        return Some("not a macro name");
+    }
+    match params {
+        Some(params) if !params.ends_with(')') || params.contains(['\n', '\0']) => {
+            Some("unterminated parameter list")
+        }
+        _ => None,
+    }
+}
+
+impl<'err> Context<'err> {
+    /// [`Context::add_include_path`] every path of `paths`, in order.
+    ///
+    /// Fails with [`Error::InvalidEntry`] for the first path that is empty or
+    /// contains a NUL byte, before any is added.
+    pub fn add_include_paths<P: AsRef<Path>>(&mut self, paths: &[P]) -> Result<&mut Self, Error> {
+        for (index, path) in paths.iter().enumerate() {
+            let path = path.as_ref();
+            let invalid = |reason| {
+                Error::InvalidEntry {
+                    index,
+                    entry: path.to_string_lossy().into_owned(),
+                    reason,
+                }
+            };
+            if path.as_os_str().is_empty() {
+                return Err(invalid("empty path"));
+            }
+            if try_to_cstr(path).is_none() {
+                return Err(invalid("contains a NUL byte"));
+            }
+        }
+        for path in paths {
+            self.add_include_path(path);
+        }
+        Ok(self)
+    }
+
+    /// [`Context::define`] every macro of `defines`, in order, like
+    /// [`Context::define_many`] for names and values read as text.
+    ///
+    /// Names may carry a parameter list, like `MAX(a, b)`. Fails with
+    /// [`Error::InvalidEntry`] for the first entry whose name is not a macro
+    /// name or whose value spans lines, before any is defined.
+    pub fn defines(&mut self, defines: &[(&str, Option<&str>)]) -> Result<&mut Self, Error> {
+        let mut checked = Vec::with_capacity(defines.len());
+        for (index, &(name, value)) in defines.iter().enumerate() {
+            let invalid = |reason| {
+                let mut entry = String::from(name);
+                if let Some(value) = value {
+                    entry.push('=');
+                    entry.push_str(value);
+                }
+                Error::InvalidEntry {
+                    index,
+                    entry,
+                    reason,
+                }
+            };
+            if let Some(reason) = check_macro_name(name) {
+                return Err(invalid(reason));
+            }
+            // tcc pastes the definition into a line of its own
+            if value.is_some_and(|value| value.contains(['\n', '\r', '\0'])) {
+                return Err(invalid("value spans lines"));
+            }
+            let value = value.map(|value| CString::new(value).unwrap());
+            checked.push((CString::new(name).unwrap(), value));
+        }
+        for (name, value) in &checked {
+            self.define(name, value.as_deref());
+        }
+        Ok(self)
+    }
+
+    /// [`Context::add_library`] every library of `names`, in order.
+    ///
+    /// Fails with [`Error::InvalidEntry`] for the first name that is empty or
+    /// contains a NUL byte, before any is added, or for the first library
+    /// tcc does not find, after the ones before it were added.
+    pub fn add_libraries<S: AsRef<str>>(&mut self, names: &[S]) -> Result<&mut Self, Error> {
+        let invalid = |index, name: &str, reason| {
+            Error::InvalidEntry {
+                index,
+                entry: String::from(name),
+                reason,
+            }
+        };
+        let mut checked = Vec::with_capacity(names.len());
+        for (index, name) in names.iter().enumerate() {
+            let name = name.as_ref();
+            if name.is_empty() {
+                return Err(invalid(index, name, "empty name"));
+            }
+            let name =
+                CString::new(name).map_err(|_| invalid(index, name, "contains a NUL byte"))?;
+            checked.push(name);
+        }
+        for (index, name) in checked.iter().enumerate() {
+            if self.add_library(name).is_err() {
+                return Err(invalid(index, &name.to_string_lossy(), "library not found"));
+            }
+        }
+        Ok(self)
+    }
+}
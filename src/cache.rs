@@ -0,0 +1,64 @@
+//! Header contents shared by contexts, see [`HeaderCache`].
+
+use crate::{metrics, Context};
+
+#[derive(Clone, Default)]
+/// Headers read from disk, shared by every context using the cache.
+///
+/// Services that spin up many short-lived contexts pay for reading and
+/// looking up heavyweight headers like `windows.h` on every compile; with a
+/// cache each header is read once per process and include path probes that
+/// missed are not repeated. Entries are never checked against the disk
+/// again, call [`HeaderCache::clear`] after headers change.
+///
+/// ```
+/// use tcc::{HeaderCache, OutputType};
+///
+/// let cache = HeaderCache::new();
+/// for _ in 0..2 {
+///     tcc::scoped(|scope| {
+///         let ctx = scope.spawn().unwrap();
+///         ctx.set_output_type(OutputType::Memory)
+///             .unwrap()
+///             .use_header_cache(&cache);
+///         ctx.compile_string(c"int f(void) { return 0; }").unwrap();
+///     })
+///     .unwrap();
+/// }
+/// ```
+pub struct HeaderCache(tcc_sys::vfs::Cache);
+
+impl HeaderCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of paths cached, including ones found missing.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Forget everything, e.g. after headers changed on disk.
+    pub fn clear(&self) {
+        self.0.clear();
+    }
+
+    /// Run the compilation `f` with headers served from the cache.
+    pub(crate) fn scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        let (ret, hits) = tcc_sys::vfs::with_cache(&self.0, f);
+        metrics::cache_hits("headers", hits);
+        ret
+    }
+}
+
+impl<'err> Context<'err> {
+    /// Read headers through `cache`, shared with other contexts.
+    pub fn use_header_cache(&mut self, cache: &HeaderCache) -> &mut Self {
+        self.header_cache = Some(cache.clone());
+        self
+    }
+}
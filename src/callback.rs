@@ -0,0 +1,149 @@
+//! Marshalling of Rust closures into C callbacks.
+//!
+//! C function pointers can not carry any state, so every expansion of
+//! [`tcc_callback!`](crate::tcc_callback) gets its own `static` [`Slot`] and
+//! an `extern "C"` shim that forwards to whatever closure is stored there.
+//! The returned [`Callback`] is the keep-alive token: the closure stays
+//! installed until it is dropped.
+
+#[doc(hidden)] pub use alloc::boxed::Box;
+use core::ffi::c_void;
+
+use spin::RwLock;
+
+#[doc(hidden)] pub use crate::panic_policy::guard;
+
+/// Storage for the closure behind a single `tcc_callback!` expansion.
+pub struct Slot<F: ?Sized> {
+    inner: RwLock<Option<Box<F>>>,
+}
+
+impl<F: ?Sized> Default for Slot<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F: ?Sized> Slot<F> {
+    pub const fn new() -> Self {
+        Slot {
+            inner: RwLock::new(None),
+        }
+    }
+
+    /// Run `call` with the installed closure.
+    ///
+    /// # Panics
+    /// If the [`Callback`] owning this slot was already dropped. As this is
+    /// reached from an `extern "C"` shim, the panic aborts the process.
+    pub fn call<R>(&self, call: impl FnOnce(&F) -> R) -> R {
+        let guard = self.inner.read();
+        let f = guard
+            .as_deref()
+            .expect("C callback invoked after its keep-alive token was dropped");
+        call(f)
+    }
+}
+
+trait Clear: Sync {
+    fn clear(&self);
+}
+
+impl<F: ?Sized> Clear for Slot<F>
+where
+    Box<F>: Send + Sync,
+{
+    fn clear(&self) {
+        *self.inner.write() = None;
+    }
+}
+
+/// A C callback created by [`tcc_callback!`](crate::tcc_callback).
+///
+/// Dropping it uninstalls the closure, so it must outlive every piece of
+/// compiled code that may still call [`Callback::as_ptr`].
+pub struct Callback {
+    ptr:  *const c_void,
+    slot: &'static dyn Clear,
+}
+
+impl Callback {
+    #[doc(hidden)]
+    pub fn new<F: ?Sized>(ptr: *const c_void, slot: &'static Slot<F>, f: Box<F>) -> Self
+    where
+        Box<F>: Send + Sync,
+    {
+        let mut inner = slot.inner.write();
+        assert!(
+            inner.is_none(),
+            "a callback created at this tcc_callback! site is still alive"
+        );
+        *inner = Some(f);
+        Callback { ptr, slot }
+    }
+
+    /// Address of the `extern "C"` shim, suitable for
+    /// [`Context::add_symbol`](crate::Context::add_symbol).
+    pub fn as_ptr(&self) -> *const c_void {
+        self.ptr
+    }
+}
+
+impl Drop for Callback {
+    fn drop(&mut self) {
+        self.slot.clear();
+    }
+}
+
+/// Turn a Rust closure into a C callback.
+///
+/// Argument and return types must be spelled out and be FFI-safe. The closure
+/// has to be `Send + Sync + 'static` since it is kept in a `static` slot; only
+/// one [`Callback`] per expansion site may be alive at a time.
+///
+/// A panic in the closure is handled according to the
+/// [`PanicPolicy`](crate::PanicPolicy); unless that aborts, C gets all zero
+/// bits back, so return types other than integers, floats and raw pointers
+/// need the default policy.
+///
+/// ```no_run
+/// use tcc::tcc_callback;
+///
+/// let scale = 2.0;
+/// let cb = tcc_callback!(|x: i32, y: f64| -> f64 { x as f64 * y * scale });
+/// // unsafe { ctx.add_symbol(c"mul", cb.as_ptr()) };
+/// # drop(cb);
+/// ```
+#[macro_export]
+macro_rules! tcc_callback {
+    (|| $body:block) => {
+        $crate::tcc_callback!(|| -> () $body)
+    };
+    (|| -> $ret:ty $body:block) => {{
+        static SLOT: $crate::callback::Slot<dyn Fn() -> $ret + Send + Sync> =
+            $crate::callback::Slot::new();
+        extern "C" fn shim() -> $ret {
+            unsafe { $crate::callback::guard(|| SLOT.call(|f| f())) }
+        }
+        let f: $crate::callback::Box<dyn Fn() -> $ret + Send + Sync> =
+            $crate::callback::Box::new(move || -> $ret { $body });
+        $crate::callback::Callback::new(shim as extern "C" fn() -> $ret as *const _, &SLOT, f)
+    }};
+    (|$($arg:ident : $ty:ty),+ $(,)?| $body:block) => {
+        $crate::tcc_callback!(|$($arg: $ty),+| -> () $body)
+    };
+    (|$($arg:ident : $ty:ty),+ $(,)?| -> $ret:ty $body:block) => {{
+        static SLOT: $crate::callback::Slot<dyn Fn($($ty),+) -> $ret + Send + Sync> =
+            $crate::callback::Slot::new();
+        extern "C" fn shim($($arg: $ty),+) -> $ret {
+            unsafe { $crate::callback::guard(|| SLOT.call(|f| f($($arg),+))) }
+        }
+        let f: $crate::callback::Box<dyn Fn($($ty),+) -> $ret + Send + Sync> =
+            $crate::callback::Box::new(move |$($arg: $ty),+| -> $ret { $body });
+        $crate::callback::Callback::new(
+            shim as extern "C" fn($($ty),+) -> $ret as *const _,
+            &SLOT,
+            f,
+        )
+    }};
+}
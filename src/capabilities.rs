@@ -0,0 +1,237 @@
+//! What the linked tinycc was built with.
+
+use alloc::{ffi::CString, format, string::String};
+use core::fmt;
+
+use tcc_sys::tcc_define_symbol;
+
+use crate::Context;
+
+/// Version of the linked tinycc, read from its `VERSION` file.
+pub const VERSION_TCC: &str = tcc_sys::TCC_VERSION;
+
+/// Commit of the vendored tinycc checkout, `unknown` if it was not built
+/// from a git work tree.
+pub const TCC_COMMIT: &str = tcc_sys::TCC_COMMIT;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Backend tinycc generates code for.
+pub enum Arch {
+    I386,
+    X86_64,
+    Arm32,
+    Arm64,
+    C67,
+    RiscV64,
+}
+
+impl fmt::Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Arch::I386 => "i386",
+            Arch::X86_64 => "x86_64",
+            Arch::Arm32 => "arm",
+            Arch::Arm64 => "arm64",
+            Arch::C67 => "c67",
+            Arch::RiscV64 => "riscv64",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Object and executable format tinycc emits.
+pub enum ExecutableFormat {
+    Elf,
+    Pe,
+    MachO,
+}
+
+impl fmt::Display for ExecutableFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ExecutableFormat::Elf => "ELF",
+            ExecutableFormat::Pe => "PE",
+            ExecutableFormat::MachO => "Mach-O",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// How `<stdatomic.h>` is implemented for generated code.
+pub enum Atomics {
+    /// tcc emits atomic instructions itself
+    Native,
+
+    /// every operation calls into the host, which only works for in-memory
+    /// code; see the embedded `stdatomic.h` for what is supported
+    Fallback,
+
+    /// libtcc was not built by this crate
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// How floating point arguments and results are passed.
+pub enum FloatAbi {
+    /// in floating point registers
+    Hard,
+
+    /// ARM `softfp`: computed with VFP instructions but passed in core
+    /// registers, for `gnueabi` systems; see the `arm-softfp` feature of
+    /// `tcc-sys`
+    SoftFp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// What `long double` is in generated code.
+pub enum LongDouble {
+    /// the same as `double`
+    Double,
+
+    /// the x87 80-bit extended format, padded to 12 or 16 bytes
+    X87Extended,
+
+    /// IEEE quadruple precision, computed in software
+    Quad,
+}
+
+impl LongDouble {
+    /// Bits of precision, the value of `LDBL_MANT_DIG`.
+    pub fn mantissa_digits(self) -> u32 {
+        match self {
+            LongDouble::Double => 53,
+            LongDouble::X87Extended => 64,
+            LongDouble::Quad => 113,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+/// Compile-time configuration of tinycc, see [`capabilities`].
+pub struct Capabilities {
+    pub arch:                   Arch,
+    pub format:                 ExecutableFormat,
+    /// tinycc version, e.g. `0.9.28rc`
+    pub version:                &'static str,
+    /// see [`TCC_COMMIT`]
+    pub commit:                 &'static str,
+    /// `-b` bound checking is compiled in
+    pub bcheck:                 bool,
+    /// `-bt` backtraces are compiled in
+    pub backtrace:              bool,
+    /// file access goes through the virtual file system
+    pub vfs:                    bool,
+    /// tcc's own headers are embedded
+    pub embedded_headers:       bool,
+    /// the win32 headers are embedded
+    pub embedded_win32_headers: bool,
+    /// `libtcc1` and friends are embedded
+    pub embedded_libraries:     bool,
+    /// a static musl is embedded, see
+    /// [`Context::static_musl_exe`](crate::Context::static_musl_exe)
+    pub embedded_musl:          bool,
+    /// scalar x86 intrinsic headers are embedded, see
+    /// [`Context::use_simd_headers`](crate::Context::use_simd_headers)
+    pub embedded_simd_headers:  bool,
+    /// what C11 atomics generated code can rely on
+    pub atomics:                Atomics,
+    pub float_abi:              FloatAbi,
+    pub long_double:            LongDouble,
+    /// generated functions keep a frame pointer chain profilers can walk,
+    /// see [`Context::keep_frame_pointers`](crate::Context::keep_frame_pointers)
+    pub frame_pointers:         bool,
+}
+
+/// Report how the linked tinycc was configured.
+pub fn capabilities() -> Capabilities {
+    let arch = match tcc_sys::TCC_TARGET {
+        "TCC_TARGET_I386" => Arch::I386,
+        "TCC_TARGET_X86_64" => Arch::X86_64,
+        "TCC_TARGET_ARM" => Arch::Arm32,
+        "TCC_TARGET_ARM64" => Arch::Arm64,
+        "TCC_TARGET_C67" => Arch::C67,
+        "TCC_TARGET_RISCV64" => Arch::RiscV64,
+        other => unreachable!("tcc-sys reported unknown target {other}"),
+    };
+    let atomics = match tcc_sys::TCC_ATOMICS {
+        "native" => Atomics::Native,
+        "fallback" => Atomics::Fallback,
+        _ => Atomics::Unknown,
+    };
+    let format = match tcc_sys::TCC_LINKAGE {
+        "TCC_TARGET_PE" => ExecutableFormat::Pe,
+        "TCC_TARGET_MACHO" => ExecutableFormat::MachO,
+        _ => ExecutableFormat::Elf,
+    };
+    let float_abi = match tcc_sys::TCC_FLOAT_ABI {
+        "softfp" => FloatAbi::SoftFp,
+        _ => FloatAbi::Hard,
+    };
+    // as in the LDOUBLE_SIZE of each backend
+    let long_double = match (arch, format) {
+        (Arch::I386, _) => LongDouble::X87Extended,
+        (Arch::X86_64, ExecutableFormat::Pe) => LongDouble::Double,
+        (Arch::X86_64, _) => LongDouble::X87Extended,
+        (Arch::Arm64, ExecutableFormat::MachO) => LongDouble::Double,
+        (Arch::Arm64 | Arch::RiscV64, _) => LongDouble::Quad,
+        (Arch::Arm32 | Arch::C67, _) => LongDouble::Double,
+    };
+    // every backend sets up a frame pointer, but ARM's are APCS frames
+    // that unwinders expecting a {fp, lr} record do not follow, and C67
+    // has no convention for walking them
+    let frame_pointers = matches!(
+        arch,
+        Arch::I386 | Arch::X86_64 | Arch::Arm64 | Arch::RiscV64
+    );
+
+    Capabilities {
+        arch,
+        format,
+        version: VERSION_TCC,
+        commit: TCC_COMMIT,
+        bcheck: tcc_sys::TCC_RUNTIME_CHECKS,
+        backtrace: tcc_sys::TCC_RUNTIME_CHECKS,
+        vfs: tcc_sys::VFS,
+        embedded_headers: tcc_sys::EMBED_HEADERS,
+        embedded_win32_headers: tcc_sys::EMBED_HEADERS_WIN32,
+        embedded_libraries: tcc_sys::EMBED_LIBRARIES,
+        embedded_musl: tcc_sys::EMBED_MUSL,
+        embedded_simd_headers: tcc_sys::EMBED_SIMD,
+        atomics,
+        float_abi,
+        long_double,
+        frame_pointers,
+    }
+}
+
+impl Context<'_> {
+    /// Predefine what numerical code checks to learn the float ABI, like gcc
+    /// does: `__LDBL_MANT_DIG__` and, for ARM passing floats in VFP
+    /// registers, `__ARM_PCS_VFP`.
+    ///
+    /// These are part of the compiler, not of the configuration, so they
+    /// are not recorded in the [`ConfigLog`](crate::ConfigLog).
+    pub(crate) fn float_defaults(&mut self) {
+        let caps = capabilities();
+        let digits = CString::new(format!("{}", caps.long_double.mantissa_digits())).unwrap();
+        unsafe {
+            tcc_define_symbol(self.inner, c"__LDBL_MANT_DIG__".as_ptr(), digits.as_ptr());
+            if caps.arch == Arch::Arm32 && caps.float_abi == FloatAbi::Hard {
+                tcc_define_symbol(self.inner, c"__ARM_PCS_VFP".as_ptr(), c"1".as_ptr());
+            }
+        }
+    }
+
+    /// One-line description of the compiler, such as
+    /// `tcc 0.9.28rc (3a5e7c1) x86_64 ELF`, meant for bug reports and cache
+    /// keys.
+    pub fn version_string(&self) -> String {
+        let caps = capabilities();
+        let commit = caps.commit.get(..7).unwrap_or(caps.commit);
+        format!(
+            "tcc {} ({commit}) {} {}",
+            caps.version, caps.arch, caps.format
+        )
+    }
+}
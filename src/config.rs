@@ -0,0 +1,554 @@
+//! Record of the configuration applied to a [`Context`], see
+//! [`Context::record`].
+
+use alloc::{
+    ffi::CString,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    ffi::c_int,
+    fmt::{self, Write},
+    str::FromStr,
+};
+
+use tcc_sys::*;
+
+use crate::{diag::CallBack, map_c_ret, Context, Error, OutputType};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// A single configuration call, as passed to tcc.
+pub enum Setting {
+    /// [`Context::set_lib_path`]
+    LibPath(CString),
+    /// [`Context::set_options`]
+    Options(CString),
+    /// [`Context::add_include_path`]
+    IncludePath(CString),
+    /// [`Context::add_sys_include_path`]
+    SysIncludePath(CString),
+    /// [`Context::define`], `None` as value for a bare `-D`
+    Define(CString, Option<CString>),
+    /// [`Context::undefine_symbol`]
+    Undefine(CString),
+    /// [`Context::set_output_type`]
+    OutputType(OutputType),
+    /// [`Context::add_library_path`]
+    LibraryPath(CString),
+    /// [`Context::add_library`]
+    Library(CString),
+}
+
+impl Setting {
+    /// Apply to `s`, returning tcc's status code where it has one.
+    ///
+    /// # Safety
+    /// `s` must be a live tcc state.
+    pub(crate) unsafe fn apply(&self, s: *mut TCCState) -> c_int {
+        match self {
+            Setting::LibPath(path) => {
+                tcc_set_lib_path(s, path.as_ptr());
+                0
+            }
+            Setting::Options(option) => tcc_set_options(s, option.as_ptr()),
+            Setting::IncludePath(path) => tcc_add_include_path(s, path.as_ptr()),
+            Setting::SysIncludePath(path) => tcc_add_sysinclude_path(s, path.as_ptr()),
+            Setting::Define(sym, val) => {
+                let val = val.as_ref().map_or(core::ptr::null(), |val| val.as_ptr());
+                tcc_define_symbol(s, sym.as_ptr(), val);
+                0
+            }
+            Setting::Undefine(sym) => {
+                tcc_undefine_symbol(s, sym.as_ptr());
+                0
+            }
+            Setting::OutputType(output) => tcc_set_output_type(s, *output as c_int),
+            Setting::LibraryPath(path) => tcc_add_library_path(s, path.as_ptr()),
+            Setting::Library(name) => tcc_add_library(s, name.as_ptr()),
+        }
+    }
+}
+
+fn output_name(output: OutputType) -> &'static str {
+    match output {
+        OutputType::Memory => "memory",
+        OutputType::Exe => "exe",
+        OutputType::Dll => "dll",
+        OutputType::Obj => "obj",
+    }
+}
+
+fn parse_output(name: &str) -> Option<OutputType> {
+    Some(match name {
+        "memory" => OutputType::Memory,
+        "exe" => OutputType::Exe,
+        "dll" => OutputType::Dll,
+        "obj" => OutputType::Obj,
+        _ => return None,
+    })
+}
+
+/// Escape everything but printable ASCII, so any value fits on one line.
+/// Names of defines also escape spaces, which end them.
+fn escape(f: &mut fmt::Formatter<'_>, value: &CString, name: bool) -> fmt::Result {
+    for &b in value.as_bytes() {
+        match b {
+            b'\\' => f.write_str("\\\\")?,
+            b' ' if name => f.write_str("\\x20")?,
+            0x20..=0x7e => f.write_char(b as char)?,
+            _ => write!(f, "\\x{b:02x}")?,
+        }
+    }
+    Ok(())
+}
+
+fn unescape(value: &str) -> Option<CString> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        rest = tail;
+        if b != b'\\' {
+            bytes.push(b);
+            continue;
+        }
+        match rest {
+            [b'\\', tail @ ..] => {
+                bytes.push(b'\\');
+                rest = tail;
+            }
+            [b'x', hi, lo, tail @ ..] => {
+                let nibble = |c: u8| (c as char).to_digit(16);
+                bytes.push((nibble(*hi)? * 16 + nibble(*lo)?) as u8);
+                rest = tail;
+            }
+            _ => return None,
+        }
+    }
+    CString::new(bytes).ok()
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+/// Every configuration call made on a [`Context`], in order.
+///
+/// The log serializes to one line per call through [`Display`](fmt::Display)
+/// and parses back with [`FromStr`], so it can be attached to bug reports or
+/// used as a cache key, and [replayed](ConfigLog::replay) onto a fresh
+/// context.
+pub struct ConfigLog {
+    pub settings: Vec<Setting>,
+}
+
+impl ConfigLog {
+    /// Apply every recorded call to `ctx`, in order.
+    ///
+    /// Fails with [`Error::InvalidState`] once `ctx` is relocated or written
+    /// out, or preprocesses, as [`Context::set_output_type`] does.
+    pub fn replay(&self, ctx: &mut Context) -> Result<(), Error> {
+        ctx.check_compiling("replay")?;
+        for setting in &self.settings {
+            if let Setting::OutputType(output) = setting {
+                ctx.set_output_type(*output)?;
+                continue;
+            }
+            // the default Windows libraries set_output_type links are
+            // recorded too
+            if matches!(setting, Setting::Library(_)) && ctx.config.contains(setting) {
+                continue;
+            }
+            let ret = ctx.apply(setting.clone());
+            if let Setting::Library(name) = setting {
+                map_c_ret(ret).map_err(|()| ctx.library_not_found(name))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ConfigLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for setting in &self.settings {
+            let (name, value) = match setting {
+                Setting::LibPath(path) => ("lib_path", path),
+                Setting::Options(option) => ("options", option),
+                Setting::IncludePath(path) => ("include", path),
+                Setting::SysIncludePath(path) => ("sysinclude", path),
+                Setting::Define(sym, val) => {
+                    f.write_str("define ")?;
+                    escape(f, sym, true)?;
+                    if let Some(val) = val {
+                        f.write_char(' ')?;
+                        escape(f, val, false)?;
+                    }
+                    f.write_char('\n')?;
+                    continue;
+                }
+                Setting::Undefine(sym) => ("undefine", sym),
+                Setting::OutputType(output) => {
+                    writeln!(f, "output {}", output_name(*output))?;
+                    continue;
+                }
+                Setting::LibraryPath(path) => ("library_path", path),
+                Setting::Library(name) => ("library", name),
+            };
+            write!(f, "{name} ")?;
+            escape(f, value, false)?;
+            f.write_char('\n')?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A [`ConfigLog`] line that could not be parsed, numbered from 1.
+pub struct ParseConfigError {
+    pub line: usize,
+}
+
+impl fmt::Display for ParseConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration log entry on line {}", self.line)
+    }
+}
+
+impl core::error::Error for ParseConfigError {}
+
+impl FromStr for ConfigLog {
+    type Err = ParseConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut settings = Vec::new();
+        for (index, line) in s.lines().enumerate() {
+            let err = ParseConfigError { line: index + 1 };
+            if line.is_empty() {
+                continue;
+            }
+            let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+            let setting = match name {
+                "lib_path" => Setting::LibPath(unescape(value).ok_or(err)?),
+                "options" => Setting::Options(unescape(value).ok_or(err)?),
+                "include" => Setting::IncludePath(unescape(value).ok_or(err)?),
+                "sysinclude" => Setting::SysIncludePath(unescape(value).ok_or(err)?),
+                "define" => {
+                    match value.split_once(' ') {
+                        Some((sym, val)) => {
+                            Setting::Define(
+                                unescape(sym).ok_or(err)?,
+                                Some(unescape(val).ok_or(err)?),
+                            )
+                        }
+                        None => Setting::Define(unescape(value).ok_or(err)?, None),
+                    }
+                }
+                "undefine" => Setting::Undefine(unescape(value).ok_or(err)?),
+                "output" => Setting::OutputType(parse_output(value).ok_or(err)?),
+                "library_path" => Setting::LibraryPath(unescape(value).ok_or(err)?),
+                "library" => Setting::Library(unescape(value).ok_or(err)?),
+                _ => return Err(err),
+            };
+            settings.push(setting);
+        }
+        Ok(ConfigLog { settings })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Summary of a context's configuration, grouped by kind, each list in the
+/// order it was applied. See [`Context::describe`].
+pub struct ContextDescription {
+    pub lib_path:          Option<CString>,
+    pub include_paths:     Vec<CString>,
+    pub sys_include_paths: Vec<CString>,
+    /// `None` as value marks an undefine.
+    pub defines:           Vec<(CString, Option<CString>)>,
+    pub library_paths:     Vec<CString>,
+    pub libraries:         Vec<CString>,
+    pub options:           Vec<CString>,
+    pub output_type:       Option<OutputType>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// One difference found by [`ContextDescription::diff`].
+pub enum Difference {
+    /// `value` is only present in the other description.
+    Added { field: &'static str, value: String },
+    /// `value` is only present in this description.
+    Removed { field: &'static str, value: String },
+    /// Same entries, applied in a different order.
+    Reordered { field: &'static str },
+    /// A single-valued setting differs.
+    Changed {
+        field: &'static str,
+        from:  String,
+        to:    String,
+    },
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::Added { field, value } => write!(f, "+ {field}: {value}"),
+            Difference::Removed { field, value } => write!(f, "- {field}: {value}"),
+            Difference::Reordered { field } => {
+                write!(f, "~ {field}: same entries, different order")
+            }
+            Difference::Changed { field, from, to } => write!(f, "~ {field}: {from} -> {to}"),
+        }
+    }
+}
+
+fn lossy(value: &CString) -> String {
+    value.to_string_lossy().into_owned()
+}
+
+fn diff_lists(field: &'static str, ours: &[String], theirs: &[String], out: &mut Vec<Difference>) {
+    let before = out.len();
+    for value in ours.iter().filter(|value| !theirs.contains(value)) {
+        out.push(Difference::Removed {
+            field,
+            value: value.clone(),
+        });
+    }
+    for value in theirs.iter().filter(|value| !ours.contains(value)) {
+        out.push(Difference::Added {
+            field,
+            value: value.clone(),
+        });
+    }
+    if out.len() == before && ours != theirs {
+        out.push(Difference::Reordered { field });
+    }
+}
+
+fn diff_single(
+    field: &'static str,
+    ours: Option<String>,
+    theirs: Option<String>,
+    out: &mut Vec<Difference>,
+) {
+    if ours != theirs {
+        let unset = || "(unset)".to_string();
+        out.push(Difference::Changed {
+            field,
+            from: ours.unwrap_or_else(unset),
+            to: theirs.unwrap_or_else(unset),
+        });
+    }
+}
+
+impl ContextDescription {
+    fn from_settings(settings: &[Setting]) -> Self {
+        let mut desc = ContextDescription::default();
+        for setting in settings {
+            match setting {
+                Setting::LibPath(path) => desc.lib_path = Some(path.clone()),
+                Setting::Options(option) => desc.options.push(option.clone()),
+                Setting::IncludePath(path) => desc.include_paths.push(path.clone()),
+                Setting::SysIncludePath(path) => desc.sys_include_paths.push(path.clone()),
+                Setting::Define(sym, val) => {
+                    let val = val.clone().unwrap_or_else(|| c"1".into());
+                    desc.defines.push((sym.clone(), Some(val)))
+                }
+                Setting::Undefine(sym) => desc.defines.push((sym.clone(), None)),
+                Setting::OutputType(output) => desc.output_type = Some(*output),
+                Setting::LibraryPath(path) => desc.library_paths.push(path.clone()),
+                Setting::Library(name) => desc.libraries.push(name.clone()),
+            }
+        }
+        desc
+    }
+
+    /// Everything that has to change to get from `self` to `other`.
+    pub fn diff(&self, other: &Self) -> Vec<Difference> {
+        let mut out = Vec::new();
+        let strings = |values: &[CString]| values.iter().map(lossy).collect::<Vec<_>>();
+        let defines = |defines: &[(CString, Option<CString>)]| {
+            defines
+                .iter()
+                .map(|(sym, val)| {
+                    match val {
+                        Some(val) => format!("{}={}", lossy(sym), lossy(val)),
+                        None => format!("-U{}", lossy(sym)),
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        diff_single(
+            "lib_path",
+            self.lib_path.as_ref().map(lossy),
+            other.lib_path.as_ref().map(lossy),
+            &mut out,
+        );
+        diff_single(
+            "output_type",
+            self.output_type
+                .map(|output| output_name(output).to_string()),
+            other
+                .output_type
+                .map(|output| output_name(output).to_string()),
+            &mut out,
+        );
+        diff_lists(
+            "options",
+            &strings(&self.options),
+            &strings(&other.options),
+            &mut out,
+        );
+        diff_lists(
+            "include_paths",
+            &strings(&self.include_paths),
+            &strings(&other.include_paths),
+            &mut out,
+        );
+        diff_lists(
+            "sys_include_paths",
+            &strings(&self.sys_include_paths),
+            &strings(&other.sys_include_paths),
+            &mut out,
+        );
+        diff_lists(
+            "defines",
+            &defines(&self.defines),
+            &defines(&other.defines),
+            &mut out,
+        );
+        diff_lists(
+            "library_paths",
+            &strings(&self.library_paths),
+            &strings(&other.library_paths),
+            &mut out,
+        );
+        diff_lists(
+            "libraries",
+            &strings(&self.libraries),
+            &strings(&other.libraries),
+            &mut out,
+        );
+        out
+    }
+}
+
+impl<'err> Context<'err> {
+    /// Every configuration call made on this context so far.
+    pub fn record(&self) -> ConfigLog {
+        ConfigLog {
+            settings: self.config.clone(),
+        }
+    }
+
+    /// Pick up the environment variables a cc-like tool honors:
+    ///
+    /// - `CPATH`: include paths, like `-I`
+    /// - `C_INCLUDE_PATH`: system include paths, like `-isystem`
+    /// - `LIBRARY_PATH`: library paths, like `-L`
+    /// - `TCC_RS_FLAGS`: extra options for [`Context::set_options`]
+    ///
+    /// Nothing is read from the environment unless this is called, so
+    /// embedded use stays hermetic. Empty path list entries are skipped.
+    #[cfg(feature = "std")]
+    pub fn apply_env(&mut self) -> &mut Self {
+        self.apply_vars(|name| std::env::var_os(name))
+    }
+
+    /// [`Context::apply_env`] with the variables looked up through `var`.
+    #[cfg(feature = "std")]
+    pub(crate) fn apply_vars(
+        &mut self,
+        var: impl Fn(&str) -> Option<std::ffi::OsString>,
+    ) -> &mut Self {
+        use std::env::split_paths;
+
+        let paths = |name: &str| {
+            var(name)
+                .map(|value| {
+                    split_paths(&value)
+                        .filter(|path| !path.as_os_str().is_empty())
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        };
+        for path in paths("CPATH") {
+            self.add_include_path(path);
+        }
+        for path in paths("C_INCLUDE_PATH") {
+            self.add_sys_include_path(path);
+        }
+        for path in paths("LIBRARY_PATH") {
+            self.add_library_path(path);
+        }
+        if let Some(flags) = var("TCC_RS_FLAGS")
+            .and_then(|flags| flags.into_string().ok())
+            .and_then(|flags| CString::new(flags).ok())
+        {
+            self.set_options(&flags);
+        }
+        self
+    }
+
+    /// A fresh context configured like this one, to prepare a template
+    /// once and stamp out a copy per request.
+    ///
+    /// The [recorded](Context::record) configuration is replayed, skipping
+    /// what every new context starts with, and the settings kept on the Rust
+    /// side are copied: default packing, output and source limits, GNU
+    /// extensions, the symbol prefix, banned functions, inline assembly,
+    /// include depth, header cache, the embedded sysroot fallback, path remaps,
+    /// diagnostics format and collector settings, a
+    /// [`SharedCallBack`](crate::SharedCallBack), `provide_libm` and the
+    /// environment. Compiled code, collected diagnostics, closures and the
+    /// per-context heap and stdio shims are not; to share compiled code,
+    /// add an object built once with [`Context::output_bytes`] to each
+    /// fork.
+    pub fn fork(&self) -> Result<Context<'err>, Error> {
+        let mut fork = Context::new()?;
+        let defaults = match self.config.starts_with(&fork.config) {
+            true => fork.config.len(),
+            false => 0,
+        };
+        for setting in &self.config[defaults..] {
+            let ret = fork.apply(setting.clone());
+            if let Setting::Library(name) = setting {
+                map_c_ret(ret).map_err(|()| fork.library_not_found(name))?;
+            }
+        }
+
+        fork.pack = self.pack;
+        fork.max_output = self.max_output;
+        fork.source_limits = self.source_limits;
+        fork.gnu = self.gnu;
+        fork.symbol_prefix = self.symbol_prefix.clone();
+        #[cfg(feature = "std")]
+        {
+            fork.banned = self.banned.clone();
+            fork.inline_asm = self.inline_asm;
+        }
+        #[cfg(feature = "vfs")]
+        {
+            fork.include_depth = self.include_depth;
+            fork.header_cache = self.header_cache.clone();
+            fork.sysroot_fallback = self.sysroot_fallback;
+            fork.sysroot = self.sysroot;
+        }
+        fork.link_last = self.link_last.clone();
+        fork.shims.libm = self.shims.libm;
+        fork.shims.env = self.shims.env.clone();
+
+        let (sink, from) = (&mut fork.sink, &self.sink);
+        sink.format = from.format;
+        sink.collector.enabled = from.collector.enabled;
+        sink.collector.dedup = from.collector.dedup;
+        sink.collector.max = from.collector.max;
+        sink.remaps = from.remaps.clone();
+        if let Some(CallBack::Shared(call_back)) = &from.user {
+            sink.user = Some(CallBack::Shared(call_back.clone()));
+        }
+        Ok(fork)
+    }
+
+    /// The configuration of this context grouped by kind, e.g. to
+    /// [`diff`](ContextDescription::diff) against another one.
+    pub fn describe(&self) -> ContextDescription {
+        ContextDescription::from_settings(&self.config)
+    }
+}
@@ -0,0 +1,100 @@
+//! Tracking of live compiler states, to find contexts and modules a
+//! long-running program never drops.
+//!
+//! Every [`Context`](crate::Context), [`RelocatedCtx`](crate::RelocatedCtx)
+//! and [`Module`](crate::Module) records a backtrace when created, which
+//! makes creating them noticeably slower; this is why tracking is behind the
+//! `debug` feature.
+
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use std::{backtrace::Backtrace, sync::Mutex, time::Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// What a [`LiveState`] is.
+pub enum StateKind {
+    Context,
+    Relocated,
+    Module,
+}
+
+#[derive(Debug, Clone)]
+/// A compiler state that was not dropped yet, see [`live_states`].
+pub struct LiveState {
+    pub kind:      StateKind,
+    /// Creation order, unique within the process.
+    pub id:        u64,
+    /// Time since creation.
+    pub age:       Duration,
+    /// Where the state was created.
+    pub backtrace: Arc<Backtrace>,
+}
+
+impl fmt::Display for LiveState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:?} #{} alive for {:?}, created at:",
+            self.kind, self.id, self.age
+        )?;
+        write!(f, "{}", self.backtrace)
+    }
+}
+
+struct Entry {
+    kind:      StateKind,
+    created:   Instant,
+    backtrace: Arc<Backtrace>,
+}
+
+static NEXT: AtomicU64 = AtomicU64::new(0);
+static LIVE: Mutex<BTreeMap<u64, Entry>> = Mutex::new(BTreeMap::new());
+
+/// The states alive right now, oldest first.
+///
+/// ```
+/// for state in tcc::debug::live_states() {
+///     eprintln!("{state}");
+/// }
+/// ```
+pub fn live_states() -> Vec<LiveState> {
+    let live = LIVE.lock().unwrap_or_else(|err| err.into_inner());
+    live.iter()
+        .map(|(&id, entry)| {
+            LiveState {
+                kind: entry.kind,
+                id,
+                age: entry.created.elapsed(),
+                backtrace: entry.backtrace.clone(),
+            }
+        })
+        .collect()
+}
+
+/// Registration of a state, removed when dropped along with it.
+pub(crate) struct Tracked(u64);
+
+impl Tracked {
+    pub(crate) fn new(kind: StateKind) -> Self {
+        let id = NEXT.fetch_add(1, Ordering::Relaxed);
+        let entry = Entry {
+            kind,
+            created: Instant::now(),
+            backtrace: Arc::new(Backtrace::force_capture()),
+        };
+        let mut live = LIVE.lock().unwrap_or_else(|err| err.into_inner());
+        live.insert(id, entry);
+        Tracked(id)
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        let mut live = LIVE.lock().unwrap_or_else(|err| err.into_inner());
+        live.remove(&self.0);
+    }
+}
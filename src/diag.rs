@@ -0,0 +1,354 @@
+//! Diagnostics reported by tcc through its error callback.
+//!
+//! The vendored libtcc also reports what it would print to stderr itself,
+//! such as some linker messages; those saying neither `error:` nor
+//! `warning:` arrive as warnings.
+
+use alloc::{
+    boxed::Box,
+    collections::BTreeSet,
+    ffi::CString,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    ffi::{c_char, c_void, CStr},
+    fmt::{self, Write},
+};
+#[cfg(feature = "std")]
+use std::sync::mpsc::Sender;
+
+#[cfg(feature = "pretty-errors")] mod pretty;
+mod sarif;
+
+#[cfg(feature = "pretty-errors")]
+pub use pretty::render_pretty;
+pub use sarif::to_sarif;
+
+use crate::{json::write_str, panic_policy::guard};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Severity of a diagnostic.
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => f.write_str("error"),
+            Severity::Warning => f.write_str("warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// Position in a source file, as far as tcc reports it.
+pub struct Location {
+    pub file: String,
+    pub line: u32,
+}
+
+impl Location {
+    fn parse(s: &str) -> Option<Self> {
+        let (file, line) = s.rsplit_once(':')?;
+        Some(Location {
+            file: file.to_string(),
+            line: line.parse().ok()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// A single message emitted by tcc.
+pub struct Diagnostic {
+    pub severity:      Severity,
+    /// `None` for messages not tied to a file, such as linker errors.
+    pub location:      Option<Location>,
+    /// Include chain leading to `location`, outermost first.
+    pub included_from: Vec<Location>,
+    pub message:       String,
+}
+
+impl Diagnostic {
+    /// Parse a message in tcc's `file:line: error: message` format.
+    ///
+    /// Messages that do not follow the format are kept verbatim as errors.
+    pub fn parse(msg: &str) -> Self {
+        let mut included_from = Vec::new();
+        let mut rest = msg;
+        while let Some(include) = rest.strip_prefix("In file included from ") {
+            let (loc, tail) = include.split_once('\n').unwrap_or((include, ""));
+            included_from.extend(Location::parse(loc.trim_end_matches(':')));
+            rest = tail;
+        }
+
+        let (prefix, severity, message) =
+            split_severity(rest).unwrap_or(("", Severity::Error, rest));
+        Diagnostic {
+            severity,
+            location: Location::parse(prefix),
+            included_from,
+            message: message.to_string(),
+        }
+    }
+}
+
+fn split_severity(s: &str) -> Option<(&str, Severity, &str)> {
+    const MARKERS: [(&str, Severity); 2] = [
+        ("error: ", Severity::Error),
+        ("warning: ", Severity::Warning),
+    ];
+
+    for (marker, severity) in MARKERS {
+        if let Some(message) = s.strip_prefix(marker) {
+            return Some(("", severity, message));
+        }
+    }
+    MARKERS
+        .iter()
+        .filter_map(|(marker, severity)| {
+            let at = s
+                .match_indices(marker)
+                .find(|(i, _)| s[..*i].ends_with(": "))?
+                .0;
+            Some((at, *severity, marker.len()))
+        })
+        .min_by_key(|(at, ..)| *at)
+        .map(|(at, severity, len)| (&s[..at - 2], severity, &s[at + len..]))
+}
+
+impl Diagnostic {
+    /// Serialize as a single-line JSON object.
+    ///
+    /// ```json
+    /// {"severity":"error","file":"<string>","line":3,"included_from":[],"message":"..."}
+    /// ```
+    /// `file` and `line` are `null` for messages without a location.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        let _ = write!(out, r#"{{"severity":"{}","file":"#, self.severity);
+        match &self.location {
+            Some(loc) => {
+                write_str(&mut out, &loc.file);
+                let _ = write!(out, r#","line":{}"#, loc.line);
+            }
+            None => out.push_str(r#"null,"line":null"#),
+        }
+        out.push_str(r#","included_from":["#);
+        for (index, loc) in self.included_from.iter().enumerate() {
+            if index > 0 {
+                out.push(',');
+            }
+            out.push_str(r#"{"file":"#);
+            write_str(&mut out, &loc.file);
+            let _ = write!(out, r#","line":{}}}"#, loc.line);
+        }
+        out.push_str(r#"],"message":"#);
+        write_str(&mut out, &self.message);
+        out.push('}');
+        out
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for loc in &self.included_from {
+            writeln!(f, "In file included from {}:{}:", loc.file, loc.line)?;
+        }
+        if let Some(loc) = &self.location {
+            write!(f, "{}:{}: ", loc.file, loc.line)?;
+        }
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// How messages are passed to the callback set by
+/// [`Context::set_call_back`](crate::Context::set_call_back).
+pub enum Format {
+    /// tcc's own `file:line: error: message` text (default)
+    #[default]
+    Text,
+
+    /// one JSON object per message, see [`Diagnostic::to_json`]
+    Json,
+}
+
+/// Built-in diagnostics collector with deduplication and a message cap.
+#[derive(Default)]
+pub(crate) struct Collector {
+    pub(crate) enabled:     bool,
+    pub(crate) dedup:       bool,
+    pub(crate) max:         Option<usize>,
+    pub(crate) diagnostics: Vec<Diagnostic>,
+    pub(crate) suppressed:  usize,
+    seen:                   BTreeSet<(Severity, String)>,
+    admitted:               usize,
+}
+
+impl Collector {
+    /// Decide whether `diag` gets through, counting it as suppressed if not.
+    ///
+    /// Deduplication keys on severity and message text only, so the same
+    /// complaint about different lines is reported once.
+    fn admit(&mut self, diag: &Diagnostic) -> bool {
+        if self.dedup && !self.seen.insert((diag.severity, diag.message.clone())) {
+            self.suppressed += 1;
+            return false;
+        }
+        if self.max.is_some_and(|max| self.admitted >= max) {
+            self.suppressed += 1;
+            return false;
+        }
+        self.admitted += 1;
+        true
+    }
+
+    pub(crate) fn take(&mut self) -> Vec<Diagnostic> {
+        self.seen.clear();
+        self.admitted = 0;
+        self.suppressed = 0;
+        core::mem::take(&mut self.diagnostics)
+    }
+}
+
+type UserCallBack<'err> = Box<dyn FnMut(&CStr) + 'err>;
+
+/// A callback that can be shared by contexts on different threads, see
+/// [`Context::set_shared_call_back`](crate::Context::set_shared_call_back).
+pub type SharedCallBack = Arc<dyn Fn(&Diagnostic) + Send + Sync>;
+
+/// The callback messages end up in.
+pub(crate) enum CallBack<'err> {
+    /// owned by one context, gets the text in the chosen [`Format`]
+    Text(UserCallBack<'err>),
+    /// gets the parsed diagnostic
+    Shared(SharedCallBack),
+}
+
+type Filter<'err> = Box<dyn FnMut(Diagnostic) -> Option<Diagnostic> + 'err>;
+
+/// Target of tcc's error callback: the collector plus the user's callback.
+#[derive(Default)]
+pub(crate) struct Sink<'err> {
+    pub(crate) collector:       Collector,
+    pub(crate) format:          Format,
+    pub(crate) user:            Option<CallBack<'err>>,
+    pub(crate) filter:          Option<Filter<'err>>,
+    #[cfg(feature = "std")]
+    pub(crate) senders:         Vec<Sender<Diagnostic>>,
+    /// `(from, to)` path prefixes, later ones take precedence
+    pub(crate) remaps:          Vec<(String, String)>,
+    /// header of the last `include file '…' not found` error
+    pub(crate) missing_include: Option<String>,
+}
+
+impl<'err> Sink<'err> {
+    pub(crate) fn dispatch(&mut self, msg: &CStr) {
+        let remapped = remap_text(&self.remaps, &msg.to_string_lossy())
+            .map(|text| CString::new(text).expect("remapping adds no NUL"));
+        let msg = remapped.as_deref().unwrap_or(msg);
+        let mut diag = Diagnostic::parse(&msg.to_string_lossy());
+        if let Some(header) = missing_include(&diag.message) {
+            self.missing_include = Some(String::from(header));
+        }
+        let mut rewritten = None;
+        if let Some(filter) = &mut self.filter {
+            let parsed = diag.clone();
+            diag = match filter(diag) {
+                Some(diag) => diag,
+                None => return,
+            };
+            if diag != parsed {
+                let text = diag.to_string().replace('\0', "");
+                rewritten = Some(CString::new(text).unwrap());
+            }
+        }
+        let msg = rewritten.as_deref().unwrap_or(msg);
+        if !self.collector.admit(&diag) {
+            return;
+        }
+
+        match &mut self.user {
+            Some(CallBack::Shared(user)) => user(&diag),
+            Some(CallBack::Text(user)) => {
+                match self.format {
+                    Format::Text => user(msg),
+                    Format::Json => {
+                        let json = CString::new(diag.to_json()).expect("JSON escapes NUL");
+                        user(&json)
+                    }
+                }
+            }
+            #[cfg(feature = "std")]
+            None if !self.collector.enabled => std::eprintln!("{}", msg.to_string_lossy()),
+            None => {}
+        }
+        // receivers that hung up are forgotten
+        #[cfg(feature = "std")]
+        self.senders.retain(|tx| tx.send(diag.clone()).is_ok());
+        if self.collector.enabled {
+            self.collector.diagnostics.push(diag);
+        }
+    }
+}
+
+/// The header of tcc's error for an `#include` it did not find.
+fn missing_include(message: &str) -> Option<&str> {
+    message
+        .strip_prefix("include file '")?
+        .strip_suffix("' not found")
+}
+
+/// `path` with the prefix mapped by the last matching entry of `remaps`.
+pub(crate) fn remap_path(remaps: &[(String, String)], path: &str) -> Option<String> {
+    let (from, to) = remaps
+        .iter()
+        .rev()
+        .find(|(from, _)| path.starts_with(from.as_str()))?;
+    Some([to.as_str(), &path[from.len()..]].concat())
+}
+
+/// `text` with the prefixes in `remaps` mapped wherever a path starts, or
+/// `None` if nothing matched.
+fn remap_text(remaps: &[(String, String)], text: &str) -> Option<String> {
+    if remaps.is_empty() {
+        return None;
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut changed = false;
+    let mut boundary = true;
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if boundary {
+            let matched = remaps
+                .iter()
+                .rev()
+                .find(|(from, _)| rest.starts_with(from.as_str()));
+            if let Some((from, to)) = matched {
+                out.push_str(to);
+                rest = &rest[from.len()..];
+                changed = true;
+                boundary = false;
+                continue;
+            }
+        }
+        boundary = !is_path_char(c);
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    changed.then_some(out)
+}
+
+fn is_path_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '/' | '\\' | '.' | '_' | '-' | '+' | '~')
+}
+
+/// Real call back of tcc.
+pub(crate) extern "C" fn call_back(opaque: *mut c_void, msg: *const c_char) {
+    let sink = opaque as *mut Sink;
+    unsafe { guard(|| (*sink).dispatch(CStr::from_ptr(msg))) }
+}
@@ -0,0 +1,89 @@
+//! rustc-style rendering of diagnostics for terminals.
+
+use alloc::string::{String, ToString};
+use core::fmt::Write;
+
+use super::{Diagnostic, Severity};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const GUTTER: &str = "\x1b[1;34m";
+
+fn severity_color(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "\x1b[1;31m",
+        Severity::Warning => "\x1b[1;33m",
+    }
+}
+
+/// Render `diagnostics` the way rustc does, quoting the offending line of
+/// `source`, the string that was handed to tcc.
+///
+/// Lines are only quoted for diagnostics that point into `source` itself,
+/// not into one of its includes. Set `color` to emit ANSI escapes.
+pub fn render_pretty(diagnostics: &[Diagnostic], source: &str, color: bool) -> String {
+    let paint = |code: &'static str| if color { code } else { "" };
+    let mut out = String::new();
+
+    for diag in diagnostics {
+        let _ = writeln!(
+            out,
+            "{}{}{}: {}{}{}",
+            paint(severity_color(diag.severity)),
+            diag.severity,
+            paint(RESET),
+            paint(BOLD),
+            diag.message,
+            paint(RESET),
+        );
+
+        let Some(loc) = &diag.location else {
+            out.push('\n');
+            continue;
+        };
+        let number = loc.line.to_string();
+        let pad = " ".repeat(number.len());
+        let _ = writeln!(
+            out,
+            "{pad}{}-->{} {}:{}",
+            paint(GUTTER),
+            paint(RESET),
+            loc.file,
+            loc.line
+        );
+
+        let quoted = diag.included_from.is_empty() || loc.file == "<string>";
+        let text = (loc.line as usize)
+            .checked_sub(1)
+            .and_then(|index| source.lines().nth(index))
+            .filter(|_| quoted);
+        if let Some(text) = text {
+            let indent = text.len() - text.trim_start().len();
+            let width = text.trim().chars().count().max(1);
+            let _ = writeln!(out, "{pad} {}|{}", paint(GUTTER), paint(RESET));
+            let _ = writeln!(out, "{}{number} |{} {text}", paint(GUTTER), paint(RESET));
+            let _ = writeln!(
+                out,
+                "{pad} {}|{} {}{}{}{}",
+                paint(GUTTER),
+                paint(RESET),
+                &text[..indent],
+                paint(severity_color(diag.severity)),
+                "^".repeat(width),
+                paint(RESET),
+            );
+        }
+        for include in diag.included_from.iter().rev() {
+            let _ = writeln!(
+                out,
+                "{pad} {}={} note: included from {}:{}",
+                paint(GUTTER),
+                paint(RESET),
+                include.file,
+                include.line
+            );
+        }
+        out.push('\n');
+    }
+    out
+}
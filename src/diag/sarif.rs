@@ -0,0 +1,58 @@
+//! SARIF 2.1.0 export.
+
+use alloc::string::String;
+use core::fmt::Write;
+
+use super::{Diagnostic, Location, Severity};
+use crate::json::write_str;
+
+fn write_location(out: &mut String, loc: &Location, message: Option<&str>) {
+    out.push_str(r#"{"physicalLocation":{"artifactLocation":{"uri":"#);
+    write_str(out, &loc.file);
+    let _ = write!(out, r#"}},"region":{{"startLine":{}}}}}"#, loc.line);
+    if let Some(message) = message {
+        out.push_str(r#","message":{"text":"#);
+        write_str(out, message);
+        out.push('}');
+    }
+    out.push('}');
+}
+
+/// Serialize `diagnostics` as a SARIF 2.1.0 log with a single run.
+///
+/// Include chains become `relatedLocations` of the result.
+pub fn to_sarif(diagnostics: &[Diagnostic]) -> String {
+    let mut out = String::from(
+        r#"{"$schema":"https://json.schemastore.org/sarif-2.1.0.json","version":"2.1.0","runs":[{"tool":{"driver":{"name":"tcc","informationUri":"https://repo.or.cz/w/tinycc.git"}},"results":["#,
+    );
+    for (index, diag) in diagnostics.iter().enumerate() {
+        if index > 0 {
+            out.push(',');
+        }
+        let level = match diag.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let _ = write!(out, r#"{{"level":"{level}","message":{{"text":"#);
+        write_str(&mut out, &diag.message);
+        out.push('}');
+        if let Some(loc) = &diag.location {
+            out.push_str(r#","locations":["#);
+            write_location(&mut out, loc, None);
+            out.push(']');
+        }
+        if !diag.included_from.is_empty() {
+            out.push_str(r#","relatedLocations":["#);
+            for (index, loc) in diag.included_from.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                write_location(&mut out, loc, Some("included from here"));
+            }
+            out.push(']');
+        }
+        out.push('}');
+    }
+    out.push_str("]}]}");
+    out
+}
@@ -0,0 +1,162 @@
+//! GNU C extensions tcc accepts, and banning them from sources.
+//!
+//! tcc has no switch to turn its GNU extensions off, so banned ones are
+//! found by scanning the source text before it is compiled. The scan sees
+//! what was passed in, not what the preprocessor makes of it: uses hidden in
+//! included headers are not found, uses in macro definitions are.
+
+use alloc::{ffi::CString, format, string::String, vec::Vec};
+
+use crate::{Context, Error};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Which GNU extensions sources may use, see
+/// [`Context::set_gnu_extensions`].
+pub struct GnuExtensions {
+    /// `({ int x = f(); x * x; })`
+    pub statement_expressions: bool,
+
+    /// `typeof(x)`, also spelled `__typeof__` or `__typeof`
+    pub typeof_operator: bool,
+
+    /// `case 'a' ... 'z':`
+    pub case_ranges: bool,
+}
+
+impl GnuExtensions {
+    /// Everything tcc accepts, the default.
+    pub const ALLOWED: GnuExtensions = GnuExtensions {
+        statement_expressions: true,
+        typeof_operator:       true,
+        case_ranges:           true,
+    };
+    /// Only ISO C, for sources that have to build with other compilers too.
+    pub const BANNED: GnuExtensions = GnuExtensions {
+        statement_expressions: false,
+        typeof_operator:       false,
+        case_ranges:           false,
+    };
+}
+
+impl Default for GnuExtensions {
+    fn default() -> Self {
+        Self::ALLOWED
+    }
+}
+
+/// Line and description of every banned extension used in `source`.
+fn violations(allowed: GnuExtensions, source: &str) -> Vec<(usize, &'static str)> {
+    let mut found = Vec::new();
+    let mut previous = "";
+    // `?` seen since the last `case`, each one pairs with a `:`
+    let mut in_case = None;
+    for (line, token) in tokens(source) {
+        match token {
+            "{" if previous == "(" && !allowed.statement_expressions => {
+                found.push((line, "statement expressions"))
+            }
+            "typeof" | "__typeof__" | "__typeof" if !allowed.typeof_operator => {
+                found.push((line, "typeof"))
+            }
+            "case" => in_case = Some(0),
+            "?" => in_case = in_case.map(|open| open + 1),
+            ":" => {
+                in_case = match in_case {
+                    Some(open) if open > 0 => Some(open - 1),
+                    _ => None,
+                }
+            }
+            "..." if in_case.is_some() && !allowed.case_ranges => found.push((line, "case ranges")),
+            _ => {}
+        }
+        previous = token;
+    }
+    found
+}
+
+/// Identifiers, numbers and punctuators of `source` with their line numbers,
+/// without comments and literals. Only `...` is kept as a multi-character
+/// punctuator.
+pub(crate) fn tokens(source: &str) -> impl Iterator<Item = (usize, &str)> {
+    let bytes = source.as_bytes();
+    let mut at = 0;
+    let mut line = 1;
+    core::iter::from_fn(move || {
+        loop {
+            let rest = &bytes[at..];
+            let &c = rest.first()?;
+            let len = if c == b'\n' {
+                line += 1;
+                1
+            } else if c.is_ascii_whitespace() || c == b'\\' {
+                1
+            } else if rest.starts_with(b"//") {
+                rest.iter().position(|&c| c == b'\n').unwrap_or(rest.len())
+            } else if rest.starts_with(b"/*") {
+                let len = rest[2..]
+                    .windows(2)
+                    .position(|pair| pair == b"*/")
+                    .map_or(rest.len(), |end| end + 4);
+                line += rest[..len].iter().filter(|&&c| c == b'\n').count();
+                len
+            } else if c == b'"' || c == b'\'' {
+                let mut len = 1;
+                while len < rest.len() && rest[len] != c && rest[len] != b'\n' {
+                    len += if rest[len] == b'\\' { 2 } else { 1 };
+                }
+                // an unterminated literal ends at the line end
+                match rest.get(len) {
+                    Some(&end) if end == c => len + 1,
+                    _ => len.min(rest.len()),
+                }
+            } else {
+                let len = if c.is_ascii_alphanumeric() || c == b'_' || c >= 0x80 {
+                    rest.iter()
+                        .position(|&c| !(c.is_ascii_alphanumeric() || c == b'_' || c >= 0x80))
+                        .unwrap_or(rest.len())
+                } else if rest.starts_with(b"...") {
+                    3
+                } else {
+                    1
+                };
+                let token = &source[at..at + len];
+                at += len;
+                return Some((line, token));
+            };
+            at += len;
+        }
+    })
+}
+
+impl<'err> Context<'err> {
+    /// Choose the GNU extensions sources may use; using a banned one fails
+    /// the compilation with an error naming the line.
+    ///
+    /// Only sources passed as text are checked, as for
+    /// [`Context::set_source_filter`], and only after the filter ran.
+    pub fn set_gnu_extensions(&mut self, extensions: GnuExtensions) -> &mut Self {
+        self.gnu = extensions;
+        self
+    }
+
+    /// Report the banned extensions `source`, called `file`, uses.
+    pub(crate) fn check_dialect(&mut self, file: &str, source: &str) -> Result<(), Error> {
+        if self.gnu == GnuExtensions::ALLOWED {
+            return Ok(());
+        }
+        let found = violations(self.gnu, source);
+        if found.is_empty() {
+            return Ok(());
+        }
+        let start = self.diagnostics().len();
+        for (line, extension) in found {
+            let message = format!("{file}:{line}: error: GNU extension not allowed: {extension}");
+            self.sink
+                .dispatch(&CString::new(message.replace('\0', "")).unwrap());
+        }
+        Err(Error::Compile {
+            code:        Some(String::from(source)),
+            diagnostics: self.diagnostics()[start..].to_vec(),
+        })
+    }
+}
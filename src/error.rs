@@ -0,0 +1,250 @@
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+
+use crate::{source::EncodingError, Diagnostic, Severity, SourceLimit};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Errors reported by this crate.
+pub enum Error {
+    /// `tcc_new` failed, running out of memory is the only known cause.
+    OutOfMemory,
+
+    /// Compiling a source failed.
+    Compile {
+        /// The source handed to tcc, when it came from a string.
+        code:        Option<String>,
+        /// Diagnostics emitted while compiling, only filled in when the
+        /// built-in collector is enabled.
+        diagnostics: Vec<Diagnostic>,
+    },
+
+    /// A source includes a header tcc found in none of the directories it
+    /// searched.
+    IncludeNotFound {
+        /// The name as written in the `#include`.
+        header:       String,
+        /// Directories searched after the including file's own, in order.
+        search_paths: Vec<String>,
+        /// Diagnostics emitted while compiling, as for [`Error::Compile`].
+        diagnostics:  Vec<Diagnostic>,
+    },
+
+    /// A source uses a function banned with
+    /// [`Context::set_banned_functions`](crate::Context::set_banned_functions).
+    BannedFunction {
+        /// The first banned function used.
+        function:    String,
+        /// An error for every use, naming the line.
+        diagnostics: Vec<Diagnostic>,
+    },
+
+    /// A source exceeds one of the limits set with
+    /// [`Context::set_source_limits`](crate::Context::set_source_limits).
+    SourceLimitExceeded {
+        limit: SourceLimit,
+        size:  usize,
+        max:   usize,
+    },
+
+    /// Source text could not be brought into a shape tcc understands.
+    Encoding(EncodingError),
+
+    /// A library added by name could not be found or loaded.
+    LibraryNotFound {
+        name:         String,
+        /// Directories searched, in order.
+        search_paths: Vec<String>,
+        /// File names looked for in each directory, in order.
+        candidates:   Vec<String>,
+    },
+
+    /// A linker script uses what tcc does not support, see
+    /// [`Context::add_linker_script_str`](crate::Context::add_linker_script_str).
+    LinkerScript { line: usize, reason: String },
+
+    /// Relocation failed, typically because of undefined symbols.
+    Relocate,
+
+    /// Writing the output file failed.
+    Output,
+
+    /// A symbol asked for by name is not defined by the compiled code.
+    SymbolNotFound(String),
+
+    /// An entry of a list passed to a bulk method like
+    /// [`Context::add_include_paths`](crate::Context::add_include_paths)
+    /// was rejected; the method tells which entries were applied anyway.
+    InvalidEntry {
+        /// Position of the entry in the list.
+        index:  usize,
+        entry:  String,
+        reason: &'static str,
+    },
+
+    /// A typed option is not available for the target or current settings.
+    UnsupportedOption {
+        option: &'static str,
+        reason: &'static str,
+    },
+
+    /// A method was called in a state of the context that does not allow
+    /// it, like compiling after relocation.
+    InvalidState {
+        operation: &'static str,
+        reason:    &'static str,
+    },
+
+    /// A plugin was not compiled against the version of the interface it is
+    /// bound to, see
+    /// [`PluginInterface::bind_checked`](crate::PluginInterface::bind_checked).
+    PluginAbiMismatch {
+        interface: &'static str,
+        expected:  u32,
+        /// `None` if the plugin does not include the interface's header.
+        found:     Option<u32>,
+    },
+
+    /// Relocated code or an output file is larger than the limit set with
+    /// [`Context::set_max_output_size`](crate::Context::set_max_output_size).
+    OutputTooLarge { size: usize, limit: usize },
+
+    /// Relocated code does not fit the limit of a
+    /// [`CodePool`](crate::exec::CodePool), even with every freed region
+    /// unmapped.
+    PoolExhausted { requested: usize, limit: usize },
+
+    /// The libtcc linked or loaded at runtime is missing or does not match the
+    /// bundled header.
+    IncompatibleLibtcc(&'static str),
+
+    /// Reading or writing through the standard library failed.
+    #[cfg(feature = "std")]
+    Io(std::io::ErrorKind),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::OutOfMemory => f.write_str("out of memory while creating tcc state"),
+            Error::Compile { diagnostics, .. } => {
+                f.write_str("compilation failed")?;
+                match diagnostics
+                    .iter()
+                    .find(|diag| diag.severity == Severity::Error)
+                {
+                    Some(diag) => write!(f, ": {}", diag.message),
+                    None => Ok(()),
+                }
+            }
+            Error::IncludeNotFound {
+                header,
+                search_paths,
+                ..
+            } => {
+                write!(f, "include file {header} not found")?;
+                match search_paths.is_empty() {
+                    true => Ok(()),
+                    false => write!(f, " in {}", search_paths.join(", ")),
+                }
+            }
+            Error::BannedFunction { function, .. } => {
+                write!(f, "call to banned function {function}")
+            }
+            Error::SourceLimitExceeded { limit, size, max } => {
+                write!(f, "source {limit} of {size} exceeds the limit of {max}")
+            }
+            Error::Encoding(err) => write!(f, "unreadable source: {err}"),
+            Error::LibraryNotFound {
+                name,
+                search_paths,
+                candidates,
+            } => {
+                write!(
+                    f,
+                    "library {name} not found, tried {}",
+                    candidates.join(", ")
+                )?;
+                match search_paths.is_empty() {
+                    true => f.write_str(" with no library paths"),
+                    false => write!(f, " in {}", search_paths.join(", ")),
+                }
+            }
+            Error::LinkerScript { line, reason } => {
+                write!(f, "linker script line {line}: {reason}")
+            }
+            Error::Relocate => f.write_str("relocation failed"),
+            Error::Output => f.write_str("failed to write output file"),
+            Error::SymbolNotFound(name) => write!(f, "symbol {name} not found"),
+            Error::InvalidEntry {
+                index,
+                entry,
+                reason,
+            } => write!(f, "entry {index} ({entry}) rejected: {reason}"),
+            Error::UnsupportedOption { option, reason } => {
+                write!(f, "unsupported option {option}: {reason}")
+            }
+            Error::InvalidState { operation, reason } => {
+                write!(f, "{operation} not allowed: {reason}")
+            }
+            Error::PluginAbiMismatch {
+                interface,
+                expected,
+                found: Some(found),
+            } => {
+                write!(
+                    f,
+                    "plugin built against version {found} of interface {interface}, expected \
+                     {expected}"
+                )
+            }
+            Error::PluginAbiMismatch {
+                interface,
+                found: None,
+                ..
+            } => {
+                write!(
+                    f,
+                    "plugin does not declare the version of interface {interface}"
+                )
+            }
+            Error::OutputTooLarge { size, limit } => {
+                write!(f, "output of {size} bytes exceeds the limit of {limit}")
+            }
+            Error::PoolExhausted { requested, limit } => {
+                write!(
+                    f,
+                    "code pool exhausted: {requested} more bytes exceed the limit of {limit}"
+                )
+            }
+            Error::IncompatibleLibtcc(reason) => write!(f, "incompatible libtcc: {reason}"),
+            #[cfg(feature = "std")]
+            Error::Io(kind) => write!(f, "I/O error: {kind}"),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err.kind())
+    }
+}
+
+impl From<Error> for Diagnostic {
+    /// Report an error of this crate through the diagnostics channel.
+    fn from(err: Error) -> Self {
+        Diagnostic {
+            severity:      Severity::Error,
+            location:      None,
+            included_from: Vec::new(),
+            message:       err.to_string(),
+        }
+    }
+}
+
+impl core::error::Error for Diagnostic {}
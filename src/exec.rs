@@ -0,0 +1,191 @@
+//! Running compiled code on threads sized for it.
+//!
+//! Generated C happily recurses deeply or puts large arrays on the stack,
+//! which overflows the 2 MiB Rust gives its threads by default. A
+//! [`CallThread`] runs a call on a fresh thread with a stack of the chosen
+//! size, guarded by inaccessible pages so an overflow faults instead of
+//! silently corrupting neighbouring memory.
+
+use alloc::{boxed::Box, string::String};
+#[cfg(unix)] use std::io;
+use std::panic;
+#[cfg(not(unix))] use std::thread;
+
+use crate::{panic_policy, Error};
+
+mod pool;
+pub use pool::CodePool;
+pub(crate) use pool::Region;
+
+/// Runs calls on dedicated threads, see [`CallThread::builder`].
+///
+/// ```
+/// use tcc::exec::CallThread;
+///
+/// let runner = CallThread::builder().stack_size(64 << 20).build();
+/// assert_eq!(runner.run(|| 6 * 7).unwrap(), 42);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CallThread {
+    stack_size:  usize,
+    guard_pages: usize,
+    name:        Option<String>,
+}
+
+/// Configuration of a [`CallThread`].
+#[derive(Debug, Clone)]
+pub struct CallThreadBuilder(CallThread);
+
+impl Default for CallThreadBuilder {
+    fn default() -> Self {
+        CallThreadBuilder(CallThread {
+            stack_size:  8 << 20,
+            guard_pages: 1,
+            name:        None,
+        })
+    }
+}
+
+impl CallThreadBuilder {
+    /// Stack size in bytes, 8 MiB by default.
+    pub fn stack_size(mut self, bytes: usize) -> Self {
+        self.0.stack_size = bytes;
+        self
+    }
+
+    /// Number of inaccessible pages below the stack, 1 by default.
+    ///
+    /// Code with large stack frames can jump over a single guard page; more
+    /// pages make that less likely. Only honoured on Unix, other platforms
+    /// always use the system's guard page.
+    pub fn guard_pages(mut self, pages: usize) -> Self {
+        self.0.guard_pages = pages;
+        self
+    }
+
+    /// Name of the threads, shown in debuggers where the platform allows
+    /// naming another thread.
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.0.name = Some(name.into());
+        self
+    }
+
+    pub fn build(self) -> CallThread {
+        self.0
+    }
+}
+
+impl CallThread {
+    pub fn builder() -> CallThreadBuilder {
+        CallThreadBuilder::default()
+    }
+
+    /// Run `f` on a new thread and wait for its result.
+    ///
+    /// `f` may borrow from the caller, as the thread is always joined before
+    /// returning. A panic in `f` is resumed on the calling thread; failing to
+    /// create the thread is reported as [`Error::Io`].
+    pub fn run<F, R>(&self, f: F) -> Result<R, Error>
+    where
+        F: FnOnce() -> R + Send,
+        R: Send,
+    {
+        let mut result = None;
+        let slot = &mut result;
+        let f = || {
+            let value = f();
+            // a panic captured on this thread must not stay behind with it
+            panic_policy::resume();
+            value
+        };
+        let call = move || *slot = Some(panic::catch_unwind(panic::AssertUnwindSafe(f)));
+        self.spawn_and_join(Box::new(call))?;
+        match result.expect("call thread finished without a result") {
+            Ok(value) => Ok(value),
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn spawn_and_join(&self, call: Box<dyn FnOnce() + Send + '_>) -> Result<(), Error> {
+        let mut builder = thread::Builder::new().stack_size(self.stack_size);
+        if let Some(name) = &self.name {
+            builder = builder.name(name.clone());
+        }
+        thread::scope(|scope| {
+            let handle = builder.spawn_scoped(scope, call)?;
+            // `call` catches panics itself
+            let _ = handle.join();
+            Ok(())
+        })
+    }
+
+    #[cfg(unix)]
+    fn spawn_and_join(&self, call: Box<dyn FnOnce() + Send + '_>) -> Result<(), Error> {
+        use core::{ffi::c_void, mem::MaybeUninit, ptr::null_mut};
+
+        type Call<'a> = Box<dyn FnOnce() + Send + 'a>;
+
+        extern "C" fn start(call: *mut c_void) -> *mut c_void {
+            let call = unsafe { Box::from_raw(call as *mut Call) };
+            call();
+            null_mut()
+        }
+
+        let page = match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+            size if size > 0 => size as usize,
+            _ => 4096,
+        };
+        let stack_size = self.stack_size.max(libc::PTHREAD_STACK_MIN);
+        let check = |ret: libc::c_int| {
+            match ret {
+                0 => Ok(()),
+                err => Err(Error::from(io::Error::from_raw_os_error(err))),
+            }
+        };
+
+        let mut attr = MaybeUninit::<libc::pthread_attr_t>::uninit();
+        check(unsafe { libc::pthread_attr_init(attr.as_mut_ptr()) })?;
+        let mut attr = unsafe { attr.assume_init() };
+        let spawned = check(unsafe { libc::pthread_attr_setstacksize(&mut attr, stack_size) })
+            .and_then(|()| {
+                let guard = self.guard_pages.saturating_mul(page);
+                check(unsafe { libc::pthread_attr_setguardsize(&mut attr, guard) })
+            })
+            .and_then(|()| {
+                // the thread is joined below, so erasing the lifetime is sound
+                let call = unsafe { core::mem::transmute::<Call<'_>, Call<'static>>(call) };
+                let call = Box::new(call);
+                let call = Box::into_raw(call);
+                let mut thread = MaybeUninit::<libc::pthread_t>::uninit();
+                let ret = unsafe {
+                    libc::pthread_create(thread.as_mut_ptr(), &attr, start, call as *mut c_void)
+                };
+                if ret != 0 {
+                    drop(unsafe { Box::from_raw(call) });
+                }
+                check(ret).map(|()| unsafe { thread.assume_init() })
+            });
+        unsafe { libc::pthread_attr_destroy(&mut attr) };
+        let thread = spawned?;
+
+        if let Some(name) = &self.name {
+            set_name(thread, name);
+        }
+        check(unsafe { libc::pthread_join(thread, null_mut()) })
+    }
+}
+
+#[cfg(all(unix, any(target_os = "linux", target_os = "android")))]
+fn set_name(thread: libc::pthread_t, name: &str) {
+    // Linux limits names to 15 bytes
+    let name: alloc::vec::Vec<u8> = name.bytes().filter(|&b| b != 0).take(15).collect();
+    if let Ok(name) = alloc::ffi::CString::new(name) {
+        unsafe { libc::pthread_setname_np(thread, name.as_ptr()) };
+    }
+}
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+fn set_name(_: libc::pthread_t, _: &str) {
+    // other platforms can only name the current thread
+}
@@ -0,0 +1,238 @@
+//! Executable memory shared by relocated code, see [`CodePool`].
+
+use alloc::{sync::Arc, vec::Vec};
+use core::ptr::NonNull;
+use std::sync::{Mutex, OnceLock};
+
+use crate::{
+    memory::Image,
+    protect::{make_writable, page_size},
+    Context, Error, Module, RelocatedCtx,
+};
+
+/// Address space relocated code is placed in, with a limit on how much of
+/// it is mapped and reuse of what unloaded code left behind.
+///
+/// Relocating the usual way allocates a buffer per module and hands it back
+/// to the allocator afterwards; with thousands of snippets that fragments
+/// the address space and leaves no bound on executable memory.
+/// [`Context::relocate_into`](crate::Context::relocate_into) and
+/// [`Context::into_module_in`](crate::Context::into_module_in) instead take
+/// page-aligned regions from a pool. Region sizes are rounded up to a power
+/// of two pages, so a region freed by a dropped module fits the next module
+/// of similar size. Freed regions stay mapped for reuse until the limit is
+/// reached, then the least recently freed are unmapped first.
+///
+/// Clones share the pool. [`CodePool::global`] is the process-wide one.
+///
+/// ```
+/// use tcc::exec::CodePool;
+///
+/// let pool = CodePool::new(64 << 20);
+/// assert_eq!(pool.mapped(), 0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CodePool(Arc<Mutex<State>>);
+
+#[derive(Debug)]
+struct State {
+    limit:  usize,
+    mapped: usize,
+    in_use: usize,
+    /// `(address, size)` of mapped regions not in use, least recently freed
+    /// first
+    free:   Vec<(usize, usize)>,
+}
+
+impl CodePool {
+    /// A pool mapping at most `limit` bytes.
+    pub fn new(limit: usize) -> Self {
+        CodePool(Arc::new(Mutex::new(State {
+            limit,
+            mapped: 0,
+            in_use: 0,
+            free: Vec::new(),
+        })))
+    }
+
+    /// The process-wide pool, without a limit until one is set.
+    pub fn global() -> &'static CodePool {
+        static GLOBAL: OnceLock<CodePool> = OnceLock::new();
+        GLOBAL.get_or_init(|| CodePool::new(usize::MAX))
+    }
+
+    /// Change the limit. Lowering it unmaps freed regions right away as far
+    /// as needed; regions in use are never taken away.
+    pub fn set_limit(&self, limit: usize) -> &Self {
+        let mut state = self.state();
+        state.limit = limit;
+        state.evict(0);
+        self
+    }
+
+    /// The limit on mapped bytes.
+    pub fn limit(&self) -> usize {
+        self.state().limit
+    }
+
+    /// Bytes mapped, in use or kept for reuse.
+    pub fn mapped(&self) -> usize {
+        self.state().mapped
+    }
+
+    /// Bytes of regions holding live code.
+    pub fn in_use(&self) -> usize {
+        self.state().in_use
+    }
+
+    /// Unmap every region not in use.
+    pub fn trim(&self) {
+        let mut state = self.state();
+        for (address, size) in core::mem::take(&mut state.free) {
+            unsafe { unmap(address, size) };
+            state.mapped -= size;
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// A writable region of at least `len` bytes.
+    pub(crate) fn allocate(&self, len: usize) -> Result<Region, Error> {
+        let page = page_size();
+        let size = len.max(1).div_ceil(page).next_power_of_two() * page;
+        let mut state = self.state();
+        let address = match state.free.iter().rposition(|&(_, free)| free == size) {
+            Some(index) => state.free.remove(index).0,
+            None => {
+                if !state.evict(size) {
+                    return Err(Error::PoolExhausted {
+                        requested: size,
+                        limit:     state.limit,
+                    });
+                }
+                let address = unsafe { map(size) }.ok_or(Error::OutOfMemory)?;
+                state.mapped += size;
+                address
+            }
+        };
+        state.in_use += size;
+        Ok(Region {
+            pool: self.clone(),
+            address: NonNull::new(address as *mut u8).unwrap(),
+            size,
+        })
+    }
+
+    fn release(&self, address: usize, size: usize) {
+        // tcc made parts of the region executable and read-only, the next
+        // user has to write it again
+        unsafe { make_writable(address, size) };
+        let mut state = self.state();
+        state.in_use -= size;
+        state.free.push((address, size));
+        state.evict(0);
+    }
+}
+
+impl State {
+    /// Unmap freed regions, oldest first, until `extra` more bytes fit the
+    /// limit; false if they do not even without any.
+    fn evict(&mut self, extra: usize) -> bool {
+        while self.mapped.saturating_add(extra) > self.limit {
+            if self.free.is_empty() {
+                return false;
+            }
+            let (address, size) = self.free.remove(0);
+            unsafe { unmap(address, size) };
+            self.mapped -= size;
+        }
+        true
+    }
+}
+
+/// Memory taken from a [`CodePool`], given back when dropped.
+pub(crate) struct Region {
+    pool:    CodePool,
+    address: NonNull<u8>,
+    size:    usize,
+}
+
+impl Region {
+    pub(crate) fn as_ptr(&self) -> *const u8 {
+        self.address.as_ptr()
+    }
+
+    pub(crate) fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.address.as_ptr()
+    }
+
+    /// Mapped length, a multiple of the page size.
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl Drop for Region {
+    fn drop(&mut self) {
+        self.pool.release(self.address.as_ptr() as usize, self.size);
+    }
+}
+
+impl<'err> Context<'err> {
+    /// Like [`Context::relocate`], placing the code in `pool`; fails with
+    /// [`Error::PoolExhausted`] if it does not fit.
+    pub fn relocate_into<'a>(
+        &'a mut self,
+        pool: &CodePool,
+    ) -> Result<RelocatedCtx<'a, 'err>, Error> {
+        let image = self.pooled_image(pool)?;
+        Ok(self.relocated(image))
+    }
+
+    /// Like [`Context::into_module`], placing the code in `pool`. The
+    /// module's region is freed for reuse when the module is dropped.
+    pub fn into_module_in(mut self, pool: &CodePool) -> Result<Module, Error> {
+        let image = self.pooled_image(pool)?;
+        Ok(self.module(image))
+    }
+
+    fn pooled_image(&mut self, pool: &CodePool) -> Result<Image, Error> {
+        self.relocate_with(|len| Ok(Image::Pooled(pool.allocate(len)?, len)))
+    }
+}
+
+#[cfg(unix)]
+unsafe fn map(size: usize) -> Option<usize> {
+    let ptr = libc::mmap(
+        core::ptr::null_mut(),
+        size,
+        libc::PROT_READ | libc::PROT_WRITE,
+        libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+        -1,
+        0,
+    );
+    (ptr != libc::MAP_FAILED).then_some(ptr as usize)
+}
+
+#[cfg(unix)]
+unsafe fn unmap(address: usize, size: usize) {
+    libc::munmap(address as *mut libc::c_void, size);
+}
+
+#[cfg(not(unix))]
+fn layout(size: usize) -> std::alloc::Layout {
+    std::alloc::Layout::from_size_align(size, page_size()).unwrap()
+}
+
+#[cfg(not(unix))]
+unsafe fn map(size: usize) -> Option<usize> {
+    let ptr = std::alloc::alloc(layout(size));
+    (!ptr.is_null()).then_some(ptr as usize)
+}
+
+#[cfg(not(unix))]
+unsafe fn unmap(address: usize, size: usize) {
+    std::alloc::dealloc(address as *mut u8, layout(size));
+}
@@ -0,0 +1,116 @@
+//! The global symbols compiled code defines: what they are before
+//! committing memory to relocating it, and what they are called.
+
+use alloc::{ffi::CString, format, string::String, vec::Vec};
+use core::ffi::{c_char, c_int, c_void, CStr};
+
+use tcc_sys::{tcc_rs_list_exports, tcc_rs_prefix_symbols, SECTION_SIZES};
+
+use crate::{Context, Error};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// What an [`ExportedSymbol`] names.
+pub enum SymbolKind {
+    Function,
+    /// a variable, or anything not declared a function
+    Data,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// A global symbol defined by compiled code, see
+/// [`Context::exported_symbols_preview`].
+pub struct ExportedSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+}
+
+impl<'err> Context<'err> {
+    /// The global symbols defined by the code compiled so far, by name.
+    ///
+    /// Works before [`Context::relocate`], so a host can check that a
+    /// submitted plugin defines the entry points it needs and nothing
+    /// unexpected before any memory is committed to it. Static functions
+    /// and variables, hidden symbols, undefined references and symbols added
+    /// with [`Context::add_symbol`] are not listed. Names carry the
+    /// [prefix](Context::set_symbol_prefix) they will have once relocated.
+    /// Fails with [`Error::UnsupportedOption`] unless `tcc-sys` builds the
+    /// vendored libtcc, see `SECTION_SIZES` there.
+    pub fn exported_symbols_preview(&self) -> Result<Vec<ExportedSymbol>, Error> {
+        if !SECTION_SIZES {
+            return Err(Error::UnsupportedOption {
+                option: "exported_symbols_preview",
+                reason: "the symbol table is only read from the vendored libtcc",
+            });
+        }
+        extern "C" fn collect(ctx: *mut c_void, name: *const c_char, function: c_int) {
+            let symbols = unsafe { &mut *(ctx as *mut Vec<ExportedSymbol>) };
+            let name = unsafe { CStr::from_ptr(name) };
+            symbols.push(ExportedSymbol {
+                name: name.to_string_lossy().into_owned(),
+                kind: match function {
+                    0 => SymbolKind::Data,
+                    _ => SymbolKind::Function,
+                },
+            });
+        }
+        let mut symbols: Vec<ExportedSymbol> = Vec::new();
+        unsafe {
+            tcc_rs_list_exports(
+                self.inner,
+                &mut symbols as *mut Vec<ExportedSymbol> as *mut c_void,
+                collect,
+            )
+        };
+        if let Some(prefix) = &self.symbol_prefix {
+            let prefix = prefix.to_string_lossy();
+            for sym in &mut symbols {
+                if sym.name != "main" && !sym.name.starts_with(&*prefix) {
+                    sym.name = format!("{prefix}{}", sym.name);
+                }
+            }
+        }
+        symbols.sort();
+        symbols.dedup();
+        Ok(symbols)
+    }
+
+    /// Put `prefix` in front of the names of the global functions and
+    /// variables this context defines, so modules defining the same names,
+    /// like `init`, can live in one process: with prefix `mod3_` the module's
+    /// `init` is looked up as `mod3_init`.
+    ///
+    /// Symbols are renamed when relocating or writing the output, after
+    /// every source is compiled, so sources refer to each other by the
+    /// names they declare. `main`, names already starting with `prefix` and
+    /// symbols added with [`Context::add_symbol`] keep their names. Fails
+    /// with [`Error::UnsupportedOption`] if `prefix` is not a C identifier
+    /// or `tcc-sys` does not build the vendored libtcc.
+    pub fn set_symbol_prefix(&mut self, prefix: &str) -> Result<&mut Self, Error> {
+        let unsupported = |reason| {
+            Err(Error::UnsupportedOption {
+                option: "symbol prefix",
+                reason,
+            })
+        };
+        if !SECTION_SIZES {
+            return unsupported("symbols are only renamed in the vendored libtcc");
+        }
+        let identifier = prefix
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_')
+            && prefix.bytes().next().is_some_and(|b| !b.is_ascii_digit());
+        if !identifier {
+            return unsupported("the prefix is not a C identifier");
+        }
+        self.symbol_prefix = Some(CString::new(prefix).unwrap());
+        Ok(self)
+    }
+
+    /// Rename the symbols defined so far, see
+    /// [`Context::set_symbol_prefix`].
+    pub(crate) fn prefix_symbols(&mut self) {
+        if let Some(prefix) = &self.symbol_prefix {
+            unsafe { tcc_rs_prefix_symbols(self.inner, prefix.as_ptr()) };
+        }
+    }
+}
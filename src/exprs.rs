@@ -0,0 +1,300 @@
+//! Compile math expressions into callable functions.
+//!
+//! The expression is wrapped into a C function with `math.h` included, so all
+//! of C's arithmetic and the libm functions are available:
+//!
+//! ```no_run
+//! let f = tcc::exprs::compile_unary("sin(x) * x").unwrap();
+//! assert_eq!(f(0.0), 0.0);
+//! ```
+//!
+//! Expressions are checked to be arithmetic before they are compiled:
+//! statements, preprocessor directives, string and character literals,
+//! indexing, taking addresses, dereferencing and pointer casts are rejected,
+//! and identifiers are limited to the variables, arithmetic type names and
+//! the functions and constants of `math.h` that take no pointers, so
+//! untrusted input like a plotter's formula cannot reach memory or functions
+//! beyond `math.h`. The math functions are served by the host, see
+//! [`Context::provide_libm`].
+//!
+//! These helpers take the same global lock as [`scoped`](crate::scoped) and
+//! must not be called from inside it.
+
+use alloc::{format, string::String, vec, vec::Vec};
+use core::mem::transmute;
+
+use crate::{dialect::tokens, lock, Context, Diagnostic, Error, Module, OutputType};
+
+const ENTRY: &str = "__tcc_rs_expr";
+
+/// Functions of `math.h` taking and returning numbers only, each also with
+/// an `f` and an `l` suffix.
+const MATH_FUNCTIONS: &[&str] = &[
+    "acos",
+    "asin",
+    "atan",
+    "atan2",
+    "cos",
+    "sin",
+    "tan",
+    "acosh",
+    "asinh",
+    "atanh",
+    "cosh",
+    "sinh",
+    "tanh",
+    "exp",
+    "exp2",
+    "expm1",
+    "ilogb",
+    "ldexp",
+    "log",
+    "log10",
+    "log1p",
+    "log2",
+    "logb",
+    "scalbn",
+    "scalbln",
+    "cbrt",
+    "fabs",
+    "hypot",
+    "pow",
+    "sqrt",
+    "erf",
+    "erfc",
+    "lgamma",
+    "tgamma",
+    "ceil",
+    "floor",
+    "nearbyint",
+    "rint",
+    "lrint",
+    "llrint",
+    "round",
+    "lround",
+    "llround",
+    "trunc",
+    "fmod",
+    "remainder",
+    "copysign",
+    "nextafter",
+    "nexttoward",
+    "fdim",
+    "fmax",
+    "fmin",
+    "fma",
+];
+
+/// Macros of `math.h` expanding to numbers or classifying them.
+const MATH_MACROS: &[&str] = &[
+    "M_E",
+    "M_LOG2E",
+    "M_LOG10E",
+    "M_LN2",
+    "M_LN10",
+    "M_PI",
+    "M_PI_2",
+    "M_PI_4",
+    "M_1_PI",
+    "M_2_PI",
+    "M_2_SQRTPI",
+    "M_SQRT2",
+    "M_SQRT1_2",
+    "INFINITY",
+    "NAN",
+    "HUGE_VAL",
+    "HUGE_VALF",
+    "HUGE_VALL",
+    "FP_NAN",
+    "FP_INFINITE",
+    "FP_ZERO",
+    "FP_SUBNORMAL",
+    "FP_NORMAL",
+    "fpclassify",
+    "isfinite",
+    "isinf",
+    "isnan",
+    "isnormal",
+    "signbit",
+    "isgreater",
+    "isgreaterequal",
+    "isless",
+    "islessequal",
+    "islessgreater",
+    "isunordered",
+];
+
+/// Keywords naming arithmetic types, for casts.
+const ARITHMETIC: &[&str] = &[
+    "char", "short", "int", "long", "float", "double", "signed", "unsigned", "sizeof",
+];
+
+/// Whether the identifier `name` may appear in an expression in `vars`.
+fn allowed(name: &str, vars: &[&str]) -> bool {
+    let function = |name: &str| MATH_FUNCTIONS.contains(&name);
+    vars.contains(&name)
+        || ARITHMETIC.contains(&name)
+        || MATH_MACROS.contains(&name)
+        || function(name)
+        || name.strip_suffix(['f', 'l']).is_some_and(function)
+}
+
+/// Why `token`, found at byte `at` of `expr` between `prev` and `next`, has
+/// no place in an arithmetic expression in `vars`.
+fn rejected(
+    expr: &str,
+    at: usize,
+    [prev, token, next]: [&str; 3],
+    vars: &[&str],
+) -> Option<&'static str> {
+    let rest = &expr[at..];
+    let operand =
+        |token: &str| token == ")" || token.starts_with(|c: char| c.is_alphanumeric() || c == '_');
+    Some(match token {
+        ";" | "{" | "}" => "statements are not allowed",
+        "#" => "preprocessor directives are not allowed",
+        "[" | "]" => "indexing is not allowed",
+        // `&&` is a logical and, an odd one out takes an address
+        "&" if !expr[..at].ends_with('&')
+            && rest.bytes().take_while(|&b| b == b'&').count() % 2 == 1 =>
+        {
+            "taking addresses is not allowed"
+        }
+        "*" if !operand(prev) => "dereferencing is not allowed",
+        "*" if matches!(next, ")" | "*") => "pointer types are not allowed",
+        "<" | "%" if rest.starts_with("<%") || rest.starts_with("%>") || rest.starts_with("%:") => {
+            "digraphs are not allowed"
+        }
+        _ if token.starts_with('"') || token.starts_with('\'') => {
+            "string and character literals are not allowed"
+        }
+        _ if token.starts_with(|c: char| c.is_alphabetic() || c == '_' || !c.is_ascii())
+            && !allowed(token, vars) =>
+        {
+            "only the variables and what math.h declares can be named"
+        }
+        _ => return None,
+    })
+}
+
+/// Reject what could make `expr` more than an expression in `vars`, before
+/// it is pasted into the generated function.
+fn check(expr: &str, vars: &[&str]) -> Result<(), Error> {
+    let tokens: Vec<_> = tokens(expr).collect();
+    let mut depth = 0usize;
+    let mut reason = None;
+    for (index, &(line, token)) in tokens.iter().enumerate() {
+        let at = token.as_ptr() as usize - expr.as_ptr() as usize;
+        let prev = index.checked_sub(1).map_or("", |index| tokens[index].1);
+        let next = tokens.get(index + 1).map_or("", |&(_, next)| next);
+        reason = match token {
+            "(" => {
+                depth += 1;
+                None
+            }
+            ")" => {
+                match depth.checked_sub(1) {
+                    Some(outer) => {
+                        depth = outer;
+                        None
+                    }
+                    None => Some("unbalanced parentheses"),
+                }
+            }
+            _ => rejected(expr, at, [prev, token, next], vars),
+        }
+        .map(|reason| (line, reason));
+        if reason.is_some() {
+            break;
+        }
+    }
+    if reason.is_none() && depth != 0 {
+        reason = Some((expr.lines().count().max(1), "unbalanced parentheses"));
+    }
+    let Some((line, reason)) = reason else {
+        return Ok(());
+    };
+    Err(Error::Compile {
+        code:        Some(String::from(expr)),
+        diagnostics: vec![Diagnostic::parse(&format!(
+            "<expr>:{line}: error: {reason}"
+        ))],
+    })
+}
+
+fn compile(params: &str, vars: &[&str], prologue: &str, expr: &str) -> Result<Module, Error> {
+    check(expr, vars)?;
+    let source = format!(
+        "#include <math.h>\ndouble {ENTRY}({params}) {{\n{prologue}#line 1 \"<expr>\"\nreturn \
+         ({expr});\n}}\n"
+    );
+
+    let _lock = lock();
+    let mut ctx = Context::new()?;
+    ctx.set_output_type(OutputType::Memory)?
+        .collect_diagnostics(true)
+        .provide_libm(true);
+    ctx.compile_str(&source)?;
+    ctx.into_module()
+}
+
+fn entry(module: &Module) -> Result<*mut core::ffi::c_void, Error> {
+    module
+        .get_symbol(ENTRY)
+        .ok_or_else(|| Error::SymbolNotFound(ENTRY.into()))
+}
+
+/// Compile an expression in `x`.
+pub fn compile_unary(expr: &str) -> Result<impl Fn(f64) -> f64 + Send + Sync, Error> {
+    let module = compile("double x", &["x"], "", expr)?;
+    let f: extern "C" fn(f64) -> f64 = unsafe { transmute(entry(&module)?) };
+    Ok(move |x| {
+        let _module = &module;
+        f(x)
+    })
+}
+
+/// Compile an expression in `x` and `y`.
+pub fn compile_binary(expr: &str) -> Result<impl Fn(f64, f64) -> f64 + Send + Sync, Error> {
+    let module = compile("double x, double y", &["x", "y"], "", expr)?;
+    let f: extern "C" fn(f64, f64) -> f64 = unsafe { transmute(entry(&module)?) };
+    Ok(move |x, y| {
+        let _module = &module;
+        f(x, y)
+    })
+}
+
+/// Compile an expression in the variables `vars`, which receive the values
+/// passed to the returned function in the same order.
+///
+/// # Panics
+/// If a name in `vars` is not a C identifier, or the returned function is
+/// called with a slice whose length differs from `vars`.
+pub fn compile_nary(
+    expr: &str,
+    vars: &[&str],
+) -> Result<impl Fn(&[f64]) -> f64 + Send + Sync, Error> {
+    let prologue: String = vars
+        .iter()
+        .enumerate()
+        .map(|(index, var)| {
+            assert!(
+                var.chars()
+                    .next()
+                    .is_some_and(|c| c == '_' || c.is_ascii_alphabetic())
+                    && var.chars().all(|c| c == '_' || c.is_ascii_alphanumeric()),
+                "invalid variable name {var:?}"
+            );
+            format!("double {var} = __args[{index}];\n")
+        })
+        .collect::<Vec<_>>()
+        .concat();
+    let module = compile("const double *__args", vars, &prologue, expr)?;
+    let f: extern "C" fn(*const f64) -> f64 = unsafe { transmute(entry(&module)?) };
+    let arity = vars.len();
+    Ok(move |args: &[f64]| {
+        let _module = &module;
+        assert_eq!(args.len(), arity, "wrong number of arguments");
+        f(args.as_ptr())
+    })
+}
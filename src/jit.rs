@@ -0,0 +1,210 @@
+//! Compile and run code in one call.
+
+use alloc::{ffi::CString, vec::Vec};
+use core::{
+    ffi::{c_char, c_int, c_void},
+    iter,
+    mem::transmute,
+    ptr::null_mut,
+};
+
+use tcc_sys::tcc_run;
+
+use crate::{
+    capabilities, lock, panic_policy, profile::EMBEDDED_HEADERS, source::EncodingError, Context,
+    Env, Error, Module, OutputType, Severity,
+};
+
+/// A context for in-memory code with `source` compiled, using tcc's embedded
+/// headers when available.
+fn compile(source: &str) -> Result<Context<'static>, Error> {
+    let mut ctx = Context::new()?;
+    ctx.set_output_type(OutputType::Memory)?
+        .collect_diagnostics(true);
+    let caps = capabilities();
+    if caps.vfs && caps.embedded_headers {
+        ctx.add_sys_include_path(EMBEDDED_HEADERS);
+    }
+    ctx.compile_str(source)?;
+    Ok(ctx)
+}
+
+/// Function pointer types a symbol can be used as, see [`jit`].
+///
+/// Implemented for `extern "C"` and `unsafe extern "C"` function pointers
+/// with up to eight arguments.
+///
+/// # Safety
+/// Implementors must be function pointers, the address of the symbol is
+/// reinterpreted as `Self`.
+pub unsafe trait FnPtr: Copy + Send + Sync + 'static {
+    #[doc(hidden)]
+    unsafe fn from_ptr(ptr: *mut c_void) -> Self;
+}
+
+macro_rules! fn_ptr {
+    ($($arg:ident $value:ident),*) => {
+        unsafe impl<R: 'static, $($arg: 'static),*> FnPtr for extern "C" fn($($arg),*) -> R {
+            unsafe fn from_ptr(ptr: *mut c_void) -> Self {
+                transmute::<*mut c_void, Self>(ptr)
+            }
+        }
+
+        unsafe impl<R: 'static, $($arg: 'static),*> FnPtr for unsafe extern "C" fn($($arg),*) -> R {
+            unsafe fn from_ptr(ptr: *mut c_void) -> Self {
+                transmute::<*mut c_void, Self>(ptr)
+            }
+        }
+
+        impl<R: 'static, $($arg: 'static),*> Jitted<extern "C" fn($($arg),*) -> R> {
+            /// Call the function, which the borrow keeps alive.
+            #[allow(clippy::too_many_arguments)]
+            pub fn call(&self, $($value: $arg),*) -> R {
+                (self.f)($($value),*)
+            }
+        }
+    };
+}
+
+fn_ptr!();
+fn_ptr!(A a);
+fn_ptr!(A a, B b);
+fn_ptr!(A a, B b, C c);
+fn_ptr!(A a, B b, C c, D d);
+fn_ptr!(A a, B b, C c, D d, E e);
+fn_ptr!(A a, B b, C c, D d, E e, F f);
+fn_ptr!(A a, B b, C c, D d, E e, F f, G g);
+fn_ptr!(A a, B b, C c, D d, E e, F f, G g, H h);
+
+/// A compiled function together with the code it lives in, see [`jit`].
+///
+/// Functions of a safe `extern "C" fn` type are called with
+/// [`Jitted::call`]; the pointer itself is only handed out by the unsafe
+/// [`Jitted::get`], as it dangles once the handle is dropped.
+pub struct Jitted<F: FnPtr> {
+    module: Module,
+    f:      F,
+}
+
+impl<F: FnPtr> Jitted<F> {
+    /// The code the function belongs to, to look up further symbols.
+    pub fn module(&self) -> &Module {
+        &self.module
+    }
+
+    /// The function pointer.
+    ///
+    /// # Safety
+    /// The pointer must not be called after this handle is dropped, which
+    /// frees the code it points to.
+    pub unsafe fn get(&self) -> F {
+        self.f
+    }
+}
+
+/// Compile `source` in memory and return `symbol` as a function of type `F`.
+///
+/// tcc's own headers are used from the embedded copy when available; for
+/// anything else, such as include paths or options, build a [`Context`] and
+/// use [`Context::into_module`]. Like [`exprs`](crate::exprs) this takes the
+/// global lock and must not be called from inside [`scoped`](crate::scoped).
+///
+/// ```no_run
+/// let add = tcc::jit::<extern "C" fn(i32, i32) -> i32>(
+///     "int add(int a, int b) { return a + b; }",
+///     "add",
+/// )
+/// .unwrap();
+/// assert_eq!(add.call(2, 3), 5);
+/// ```
+///
+/// `F` is not checked against the C declaration; calling through a type that
+/// does not match it is undefined behaviour.
+pub fn jit<F: FnPtr>(source: &str, symbol: &str) -> Result<Jitted<F>, Error> {
+    let module = {
+        let _lock = lock();
+        compile(source)?.into_module()?
+    };
+    let ptr = module
+        .get_symbol(symbol)
+        .ok_or_else(|| Error::SymbolNotFound(symbol.into()))?;
+    let f = unsafe { F::from_ptr(ptr) };
+    Ok(Jitted { module, f })
+}
+
+/// Compile `source` in memory and run its `main`, returning the exit code.
+///
+/// `main` receives `"main"` as `argv[0]`, followed by `args`. tcc sets up
+/// the runtime, so constructors and destructors run and `main` may be
+/// declared with or without parameters. The same restrictions as for
+/// [`jit`] apply.
+///
+/// ```no_run
+/// let code = tcc::run_main("int main(int argc) { return argc; }", &["a", "b"]).unwrap();
+/// assert_eq!(code, 3);
+/// ```
+pub fn run_main(source: &str, args: &[&str]) -> Result<i32, Error> {
+    let args = c_strings(iter::once("main").chain(args.iter().copied()))?;
+    let mut argv = null_terminated(&args);
+
+    let _lock = lock();
+    let mut ctx = compile(source)?;
+    ctx.inject_shims()?;
+    let start = ctx.diagnostics().len();
+    let code = unsafe { tcc_run(ctx.inner, args.len() as c_int, argv.as_mut_ptr()) };
+    panic_policy::resume();
+    // tcc returns -1 both for failed relocation and for `main` doing so
+    let failed = ctx.diagnostics()[start..]
+        .iter()
+        .any(|diag| diag.severity == Severity::Error);
+    if failed {
+        return Err(Error::Relocate);
+    }
+    Ok(code)
+}
+
+/// Compile `source` in memory and run its `main` with the environment `env`,
+/// returning the exit code.
+///
+/// Unlike [`run_main`], which leaves the host's environment visible, the
+/// program only sees the variables of `env`, see [`Context::set_env`].
+/// Constructors and destructors run as with
+/// [`RelocatedCtx::run_main`](crate::RelocatedCtx::run_main).
+///
+/// ```no_run
+/// use tcc::Env;
+///
+/// let source = "char *getenv(const char *);\nint main(void) { return !getenv(\"HOME\"); }";
+/// assert_eq!(tcc::run_main_with_env(source, &[], &Env::new()).unwrap(), 1);
+/// ```
+pub fn run_main_with_env(source: &str, args: &[&str], env: &Env) -> Result<i32, Error> {
+    let _lock = lock();
+    let mut ctx = compile(source)?;
+    ctx.set_env(env.clone());
+    ctx.relocate()?.run_main(args)
+}
+
+/// `strings` as C strings, failing on interior NULs.
+pub(crate) fn c_strings<S: Into<Vec<u8>>>(
+    strings: impl IntoIterator<Item = S>,
+) -> Result<Vec<CString>, Error> {
+    strings
+        .into_iter()
+        .map(CString::new)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| {
+            Error::Encoding(EncodingError::Nul {
+                offset: err.nul_position(),
+            })
+        })
+}
+
+/// A null terminated `argv` style array pointing into `strings`.
+pub(crate) fn null_terminated(strings: &[CString]) -> Vec<*mut c_char> {
+    let mut array: Vec<*mut c_char> = strings
+        .iter()
+        .map(|string| string.as_ptr() as *mut c_char)
+        .collect();
+    array.push(null_mut());
+    array
+}
@@ -0,0 +1,119 @@
+//! GNU ld scripts passed as text, like the `libc.so` glibc installs.
+//!
+//! tcc understands only a few commands: `INPUT` and `GROUP` listing files
+//! and `-l` libraries, possibly inside `AS_NEEDED`, and `OUTPUT_FORMAT` and
+//! `TARGET`, which it ignores. Anything else fails to link, so scripts are
+//! checked up front and rejected naming the line.
+
+use alloc::{format, string::String, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{capabilities, Context, Error, ExecutableFormat};
+
+/// Where scripts are staged while tcc reads them.
+const STAGED: &str = "/vfs/ldscripts";
+
+/// Whether `c` can be part of a file or command name.
+fn is_name(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "/.-_+=$:\\~".contains(c)
+}
+
+/// Tokens of `script` with their line: names and single punctuation
+/// characters.
+fn tokens(script: &str) -> Result<impl Iterator<Item = (usize, &str)>, Error> {
+    let mut found = Vec::new();
+    let (mut rest, mut line) = (script, 1);
+    while let Some(c) = rest.chars().next() {
+        let len = if rest.starts_with("/*") {
+            let end = rest
+                .find("*/")
+                .ok_or_else(|| error(line, "unterminated comment"))?;
+            end + 2
+        } else if is_name(c) {
+            let len = rest.find(|c| !is_name(c)).unwrap_or(rest.len());
+            found.push((line, &rest[..len]));
+            len
+        } else {
+            if !c.is_whitespace() {
+                found.push((line, &rest[..c.len_utf8()]));
+            }
+            c.len_utf8()
+        };
+        line += rest[..len].matches('\n').count();
+        rest = &rest[len..];
+    }
+    Ok(found.into_iter())
+}
+
+fn error(line: usize, reason: impl Into<String>) -> Error {
+    Error::LinkerScript {
+        line,
+        reason: reason.into(),
+    }
+}
+
+/// Check that tcc can link with `script`.
+fn check(script: &str) -> Result<(), Error> {
+    let mut tokens = tokens(script)?;
+    while let Some((line, command)) = tokens.next() {
+        let files = match command {
+            "INPUT" | "GROUP" => true,
+            "OUTPUT_FORMAT" | "TARGET" => false,
+            _ => return Err(error(line, format!("unsupported command {command}"))),
+        };
+        if !matches!(tokens.next(), Some((_, "("))) {
+            return Err(error(line, format!("( expected after {command}")));
+        }
+        let mut depth = 1;
+        while depth > 0 {
+            let Some((line, token)) = tokens.next() else {
+                return Err(error(line, format!("unterminated {command}")));
+            };
+            match token {
+                "(" => return Err(error(line, "unexpected (")),
+                ")" => depth -= 1,
+                "AS_NEEDED" if files && depth == 1 => {
+                    if !matches!(tokens.next(), Some((_, "("))) {
+                        return Err(error(line, "( expected after AS_NEEDED"));
+                    }
+                    depth += 1;
+                }
+                // tcc reads commas into file names
+                _ if files && !token.starts_with(is_name) => {
+                    return Err(error(line, format!("unexpected {token} in {command}")));
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+impl<'err> Context<'err> {
+    /// Link with the GNU ld script `script`, as [`Context::add_file`] would
+    /// with a file holding it, without writing one.
+    ///
+    /// The script is checked first and rejected with
+    /// [`Error::LinkerScript`] if it uses anything but `INPUT`, `GROUP`,
+    /// `AS_NEEDED`, `OUTPUT_FORMAT` and `TARGET`, the subset tcc supports.
+    /// Files it names are looked up as tcc does, relative names and `-l`
+    /// libraries in the [library paths](Context::add_library_path). PE
+    /// targets take no scripts and fail with [`Error::UnsupportedOption`].
+    pub fn add_linker_script_str(&mut self, script: &str) -> Result<(), Error> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        if capabilities().format == ExecutableFormat::Pe {
+            return Err(Error::UnsupportedOption {
+                option: "linker script",
+                reason: "tcc reads ld scripts for ELF and Mach-O targets only",
+            });
+        }
+        self.check_compiling("add_file")?;
+        check(script)?;
+        let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let name = format!("{STAGED}/script{id}.ld");
+        tcc_sys::vfs::mount(&name, script.as_bytes());
+        let ret = self.add_file_as_is(&name);
+        tcc_sys::vfs::unmount(&name);
+        ret
+    }
+}
@@ -27,22 +27,117 @@
 
 #[cfg(feature = "std")] extern crate std as alloc;
 
-use alloc::{boxed::Box, ffi::CString, rc::Rc, string::ToString, vec::Vec};
+use alloc::{boxed::Box, ffi::CString, rc::Rc, string::String, vec, vec::Vec};
 use core::{
     ffi::{c_char, c_int, c_void, CStr},
     mem::ManuallyDrop,
     ptr::null_mut,
 };
+#[cfg(feature = "vfs")] use std::io::Read;
 #[cfg(feature = "std")] use std::path::Path;
-#[cfg(feature = "std")] use std::sync::Mutex;
+#[cfg(feature = "std")]
+use std::sync::{
+    mpsc::{channel, Receiver},
+    Mutex,
+};
 
 #[cfg(not(feature = "std"))] use spin::Mutex;
 use tcc_sys::*;
 use typed_arena::Arena;
 #[cfg(not(feature = "std"))] use unix_path::Path;
 
+pub mod ar;
+#[cfg(feature = "std")] pub mod artifacts;
+#[cfg(feature = "std")] mod banned;
+#[cfg(feature = "std")] pub mod build;
+#[cfg(feature = "std")] mod build_id;
+mod bulk;
+#[cfg(feature = "vfs")] mod cache;
+pub mod callback;
+mod capabilities;
+pub mod config;
+#[cfg(feature = "debug")] pub mod debug;
+pub mod diag;
+mod dialect;
+mod error;
+#[cfg(feature = "std")] pub mod exec;
+mod exports;
+pub mod exprs;
+mod jit;
+mod json;
+#[cfg(feature = "vfs")] mod ldscript;
+mod limits;
+#[cfg(feature = "vfs")] pub mod link;
+#[cfg(feature = "std")] mod map_file;
+mod memory;
+pub mod metrics;
+mod module;
+mod options;
+#[cfg(feature = "std")] mod output;
+mod panic_policy;
+mod platform;
+pub mod plugin;
+#[cfg(feature = "std")] mod preprocess;
+mod profile;
+mod protect;
+#[cfg(feature = "miette")] mod report;
+#[cfg(feature = "std")] mod screen;
+mod search;
+mod shims;
+pub mod source;
+mod symbolication;
+#[cfg(feature = "vfs")] mod sysroot;
+#[cfg(feature = "std")] pub mod testing;
+mod warnings;
+mod windows;
+
+#[cfg(feature = "std")]
+pub use banned::DEFAULT_BANNED_FUNCTIONS;
+#[cfg(feature = "std")] pub use build_id::BuildId;
+#[cfg(feature = "vfs")]
+pub use cache::HeaderCache;
+pub use capabilities::{
+    capabilities, Arch, Atomics, Capabilities, ExecutableFormat, FloatAbi, LongDouble, TCC_COMMIT,
+    VERSION_TCC,
+};
+use config::Setting;
+pub use config::{ConfigLog, ContextDescription};
+use diag::{call_back, CallBack, Sink};
+pub use diag::{Diagnostic, Format, Severity, SharedCallBack};
+pub use dialect::GnuExtensions;
+pub use error::Error;
+pub use exports::{ExportedSymbol, SymbolKind};
+pub use jit::{jit, run_main, run_main_with_env, FnPtr, Jitted};
+pub use limits::{SourceLimit, SourceLimits};
+use memory::Image;
+pub use memory::MemoryUsage;
+pub use module::Module;
+pub use options::{CStd, CharSignedness, RelocationModel};
+pub use panic_policy::PanicPolicy;
+#[cfg(feature = "std")]
+pub use panic_policy::{panic_policy, set_panic_policy, take_panic};
+pub use plugin::{CType, PluginInterface, Resolve};
+pub use profile::Profile;
+#[cfg(feature = "std")]
+pub use screen::{inline_asm_default, set_inline_asm_default};
+pub use shims::{ArenaHandle, Env, HeapError, StdStream, StdioSink};
+use shims::{Environ, Shims};
+use source::SourceFilter;
+pub use symbolication::{DebugSection, SymbolInfo, SymbolicationBundle};
+#[cfg(feature = "runtime-loading")]
+pub use tcc_sys::runtime::{load_library, LoadError};
+pub use warnings::{Level, Warning};
+
 static LOCK: Mutex<()> = Mutex::new(());
 
+/// Take [`LOCK`] for helpers that create their own context.
+pub(crate) fn lock() -> impl Sized {
+    #[cfg(feature = "std")]
+    return LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    #[cfg(not(feature = "std"))]
+    return LOCK.lock();
+}
+
 pub struct ContextGuard<'err, T> {
     #[allow(unused)]
     inner: ManuallyDrop<Rc<Scoped<'err>>>,
@@ -77,12 +172,8 @@ impl<'err> Scoped<'err> {
         Scoped(Arena::new())
     }
 
-    pub fn spawn(&self) -> Result<&mut Context<'err>, ()> {
-        if let Ok(context) = Context::new() {
-            Ok(self.0.alloc(context))
-        } else {
-            Err(())
-        }
+    pub fn spawn(&self) -> Result<&mut Context<'err>, Error> {
+        Ok(self.0.alloc(Context::new()?))
     }
 }
 
@@ -146,7 +237,7 @@ where
     })
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u32)]
 /// Output type of the compilation.
 pub enum OutputType {
@@ -161,80 +252,287 @@ pub enum OutputType {
 
     /// object file
     Obj = TCC_OUTPUT_OBJ,
+}
 
-    /// only preprocess (used internally)
-    Preprocess = TCC_OUTPUT_PREPROCESS,
+impl OutputType {
+    /// Whether the output is written to a file rather than relocated in
+    /// memory.
+    pub fn is_file(self) -> bool {
+        self != OutputType::Memory
+    }
 }
 
-/// Compilation context.
-pub struct Context<'err> {
-    inner:    *mut TCCState,
-    err_func: Option<Box<Box<dyn 'err + FnMut(&CStr)>>>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Where a context is in its lifecycle.
+enum Stage {
+    Compiling,
+    /// switched to preprocessing, see [`Context::preprocess_str`]
+    #[cfg(feature = "std")]
+    Preprocessing,
+    /// relocated or written out, which tcc does once
+    Linked,
 }
 
-/// Real call back of tcc.
-extern "C" fn call_back(opaque: *mut c_void, msg: *const c_char) {
-    let func: *mut &mut dyn FnMut(&CStr) = opaque as *mut &mut dyn FnMut(&CStr);
-    unsafe { (*func)(CStr::from_ptr(msg)) }
+/// Compilation context.
+pub struct Context<'err> {
+    inner:            *mut TCCState,
+    sink:             Box<Sink<'err>>,
+    config:           Vec<Setting>,
+    /// see [`Context::set_default_pack`]
+    pack:             Option<u8>,
+    /// see [`Context::set_source_filter`]
+    filter:           Option<SourceFilter<'err>>,
+    /// see [`Context::set_max_output_size`]
+    max_output:       Option<usize>,
+    /// see [`Context::set_source_limits`]
+    source_limits:    SourceLimits,
+    /// see [`Context::set_gnu_extensions`]
+    gnu:              GnuExtensions,
+    /// see [`Context::set_symbol_prefix`]
+    symbol_prefix:    Option<CString>,
+    /// what the context may still do, see [`Context::check_compiling`]
+    stage:            Stage,
+    /// see [`Context::set_banned_functions`]
+    #[cfg(feature = "std")]
+    banned:           Vec<String>,
+    /// see [`Context::allow_inline_asm`]
+    #[cfg(feature = "std")]
+    inline_asm:       bool,
+    shims:            Shims,
+    /// see [`Context::set_max_include_depth`]
+    #[cfg(feature = "vfs")]
+    include_depth:    Option<usize>,
+    /// see [`Context::use_header_cache`]
+    #[cfg(feature = "vfs")]
+    header_cache:     Option<HeaderCache>,
+    /// see [`Context::fallback_to_embedded_sysroot`]
+    #[cfg(feature = "vfs")]
+    sysroot_fallback: bool,
+    /// see [`Context::embedded_sysroot_used`]
+    #[cfg(feature = "vfs")]
+    sysroot:          Option<&'static str>,
+    /// files linked after everything the user added, see
+    /// [`Context::static_musl_exe`]
+    link_last:        Vec<CString>,
+    /// see [`Context::set_output_transform`]
+    #[cfg(feature = "std")]
+    transform:        Option<output::OutputTransform<'err>>,
+    /// see [`Context::set_build_id`]
+    #[cfg(feature = "std")]
+    build_id:         Option<BuildId>,
+    /// see [`Context::write_map_file`]
+    #[cfg(feature = "std")]
+    map_file:         Option<std::path::PathBuf>,
+    #[cfg(feature = "debug")]
+    _tracked:         debug::Tracked,
 }
 
 impl<'err> Context<'err> {
     /// Create a new context builder
     ///
     /// Context can not live together, mutable reference to guard makes compiler
-    /// check this. Out of memory is only possible reason of failure.
-    pub fn new() -> Result<Self, ()> {
+    /// check this. Out of memory is only possible reason of failure, apart
+    /// from a system libtcc that does not match the bundled header.
+    ///
+    /// When targeting Windows, the embedded win32 headers and import
+    /// definitions are searched and programs link `kernel32` and `user32`,
+    /// so `#include <windows.h>` works without further setup. The target's
+    /// architecture and platform macros are predefined, see
+    /// [`Context::default_defines`].
+    pub fn new() -> Result<Self, Error> {
+        tcc_sys::check_abi().map_err(Error::IncompatibleLibtcc)?;
+
         let inner = unsafe { tcc_new() };
         if inner.is_null() {
-            // OOM
-            Err(())
+            Err(Error::OutOfMemory)
         } else {
-            Ok(Self {
+            let mut ctx = Self {
                 inner,
-                err_func: None,
-            })
+                sink: Box::default(),
+                config: Vec::new(),
+                pack: None,
+                filter: None,
+                max_output: None,
+                source_limits: SourceLimits::default(),
+                gnu: GnuExtensions::ALLOWED,
+                symbol_prefix: None,
+                stage: Stage::Compiling,
+                #[cfg(feature = "std")]
+                banned: Vec::new(),
+                #[cfg(feature = "std")]
+                inline_asm: screen::inline_asm_default(),
+                shims: Shims::default(),
+                #[cfg(feature = "vfs")]
+                include_depth: None,
+                #[cfg(feature = "vfs")]
+                header_cache: None,
+                #[cfg(feature = "vfs")]
+                sysroot_fallback: false,
+                #[cfg(feature = "vfs")]
+                sysroot: None,
+                link_last: Vec::new(),
+                #[cfg(feature = "std")]
+                transform: None,
+                #[cfg(feature = "std")]
+                build_id: None,
+                #[cfg(feature = "std")]
+                map_file: None,
+                #[cfg(feature = "debug")]
+                _tracked: debug::Tracked::new(debug::StateKind::Context),
+            };
+            // see every message, to tell missing headers apart; with no
+            // callback they are printed as tcc would
+            #[cfg(feature = "std")]
+            ctx.install_sink();
+            ctx.windows_defaults();
+            ctx.float_defaults();
+            ctx.platform_defaults();
+            Ok(ctx)
         }
     }
 
     /// set CONFIG_TCCDIR at runtime
     pub fn set_lib_path<T: AsRef<Path>>(&mut self, path: T) -> &mut Self {
-        let path = to_cstr(path);
-        unsafe {
-            tcc_set_lib_path(self.inner, path.as_ptr());
-        }
+        self.apply(Setting::LibPath(to_cstr(path)));
         self
     }
 
     /// set options as from command line (multiple supported)
     pub fn set_options(&mut self, option: &CStr) -> &mut Self {
-        unsafe {
-            tcc_set_options(self.inner, option.as_ptr());
-        }
+        self.apply(Setting::Options(option.to_owned()));
         self
     }
 
+    /// Apply `setting` to this context and record it for replay.
+    fn apply(&mut self, setting: Setting) -> c_int {
+        let ret = unsafe { setting.apply(self.inner) };
+        self.config.push(setting);
+        panic_policy::resume();
+        ret
+    }
+
     /// set error/warning display callback
     pub fn set_call_back<T>(&mut self, f: T) -> &mut Self
     where
         T: FnMut(&CStr) + 'err,
     {
-        let mut user_err_func: Box<Box<dyn FnMut(&CStr)>> = Box::new(Box::new(f));
-        // user_err_func.as_mut().
+        self.sink.user = Some(CallBack::Text(Box::new(f)));
+        self.install_sink()
+    }
+
+    /// Like [`Context::set_call_back`], but with a callback that takes the
+    /// parsed diagnostic and can be shared, e.g. one collector fed by
+    /// contexts on several worker threads.
+    ///
+    /// Replaces the callback set with either method.
+    pub fn set_shared_call_back(&mut self, f: SharedCallBack) -> &mut Self {
+        self.sink.user = Some(CallBack::Shared(f));
+        self.install_sink()
+    }
+
+    /// Pass every diagnostic through `f` before anything else sees it.
+    ///
+    /// Returning `None` drops the message, e.g. a known-noisy warning,
+    /// without counting it as suppressed; returning a changed diagnostic
+    /// replaces it, e.g. to translate the message or attach a hint for
+    /// users who are not programmers. The callback, receivers and
+    /// collector only ever see the result.
+    pub fn set_diagnostic_filter<F>(&mut self, f: F) -> &mut Self
+    where
+        F: FnMut(Diagnostic) -> Option<Diagnostic> + 'err,
+    {
+        self.sink.filter = Some(Box::new(f));
+        self.install_sink()
+    }
+
+    /// Choose how messages are passed to the [`Context::set_call_back`]
+    /// callback.
+    pub fn diagnostics_format(&mut self, format: Format) -> &mut Self {
+        self.sink.format = format;
+        self
+    }
+
+    /// Stream every admitted diagnostic to the returned receiver as it is
+    /// emitted, e.g. to show errors on another thread while a long build is
+    /// still running.
+    ///
+    /// Several receivers can be created, each gets its own copy.
+    #[cfg(feature = "std")]
+    pub fn diagnostics_receiver(&mut self) -> Receiver<Diagnostic> {
+        let (tx, rx) = channel();
+        self.sink.senders.push(tx);
+        self.install_sink();
+        rx
+    }
+
+    /// Keep every diagnostic in the built-in collector, see
+    /// [`Context::diagnostics`].
+    pub fn collect_diagnostics(&mut self, enable: bool) -> &mut Self {
+        self.sink.collector.enabled = enable;
+        self.install_sink()
+    }
+
+    /// Drop messages whose severity and text were already reported, before
+    /// they reach the collector or the callback.
+    pub fn dedup_diagnostics(&mut self, enable: bool) -> &mut Self {
+        self.sink.collector.dedup = enable;
+        self.install_sink()
+    }
+
+    /// Cap the number of messages passed on to the collector and the
+    /// callback; the rest are only counted.
+    pub fn set_max_diagnostics(&mut self, max: Option<usize>) -> &mut Self {
+        self.sink.collector.max = max;
+        self.install_sink()
+    }
+
+    /// Diagnostics collected so far.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.sink.collector.diagnostics
+    }
+
+    /// Take the collected diagnostics and reset the dedup and cap state.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        self.sink.collector.take()
+    }
+
+    /// Number of messages dropped by deduplication or the message cap.
+    pub fn suppressed_diagnostics(&self) -> usize {
+        self.sink.collector.suppressed
+    }
+
+    /// Rewrite paths starting with `from` to start with `to` instead, like
+    /// `-ffile-prefix-map`, so the build machine's absolute paths leak into
+    /// neither error messages nor artifacts.
+    ///
+    /// Diagnostics are rewritten wherever a path appears in them. Debug info
+    /// and `__FILE__` are rewritten for the main file of each translation
+    /// unit added with [`Context::add_file`] or
+    /// [`Context::add_source_bytes`], as tcc takes their names from a
+    /// `#line` directive put in front of the source; headers keep their
+    /// real paths there. [`Context::add_file`] needs the `vfs` feature for
+    /// this. When several prefixes match, the last one added wins.
+    pub fn remap_path_prefix(&mut self, from: &str, to: &str) -> &mut Self {
+        if !from.is_empty() {
+            self.sink.remaps.push((from.to_string(), to.to_string()));
+        }
+        self.install_sink()
+    }
+
+    fn install_sink(&mut self) -> &mut Self {
         unsafe {
             tcc_set_error_func(
                 self.inner,
-                user_err_func.as_mut() as *mut _ as *mut c_void,
+                self.sink.as_mut() as *mut Sink as *mut c_void,
                 Some(call_back),
             )
         }
-        self.err_func = Some(user_err_func);
         self
     }
 
     /// add include path
     pub fn add_include_path<T: AsRef<Path>>(&mut self, path: T) -> &mut Self {
-        let path = to_cstr(path);
-        let ret = unsafe { tcc_add_include_path(self.inner, path.as_ptr()) };
+        let ret = self.apply(Setting::IncludePath(to_cstr(path)));
         // this api only returns 0.
         assert_eq!(ret, 0);
         self
@@ -242,60 +540,295 @@ impl<'err> Context<'err> {
 
     /// add in system include path
     pub fn add_sys_include_path<T: AsRef<Path>>(&mut self, path: T) -> &mut Self {
-        let path = to_cstr(path);
-        let ret = unsafe { tcc_add_sysinclude_path(self.inner, path.as_ptr()) };
+        let ret = self.apply(Setting::SysIncludePath(to_cstr(path)));
         // this api only returns 0.
         assert_eq!(ret, 0);
         self
     }
 
-    /// define preprocessor symbol 'sym'. Can put optional value
-    pub fn define_symbol(&mut self, sym: &CStr, val: &CStr) -> *mut Self {
-        unsafe {
-            tcc_define_symbol(self.inner, sym.as_ptr(), val.as_ptr());
+    /// define preprocessor symbol 'sym', like `-Dsym=val`, or `-Dsym` as
+    /// `1` without a value.
+    ///
+    /// Defining a macro naming another architecture or platform than the
+    /// target warns, see [`Context::default_defines`].
+    pub fn define(&mut self, sym: &CStr, val: Option<&CStr>) -> &mut Self {
+        self.check_platform_macro(sym, true);
+        self.apply(Setting::Define(sym.to_owned(), val.map(CStr::to_owned)));
+        self
+    }
+
+    /// [`Context::define`] every symbol of `defines`, in order.
+    pub fn define_many(&mut self, defines: &[(&CStr, Option<&CStr>)]) -> &mut Self {
+        for &(sym, val) in defines {
+            self.define(sym, val);
         }
         self
     }
 
     /// undefine preprocess symbol 'sym'
     pub fn undefine_symbol(&mut self, sym: &CStr) -> &mut Self {
-        unsafe { tcc_undefine_symbol(self.inner, sym.as_ptr()) }
+        self.check_platform_macro(sym, false);
+        self.apply(Setting::Undefine(sym.to_owned()));
         self
     }
 
-    /// output an executable, library or object file. DO NOT call tcc_relocate()
-    /// before
-    pub fn set_output_type(&mut self, output: OutputType) -> &mut Self {
-        let ret = unsafe { tcc_set_output_type(self.inner, output as c_int) };
+    /// output an executable, library or object file.
+    ///
+    /// Fails with [`Error::InvalidState`] once the code is relocated or
+    /// written out, or the context preprocesses.
+    pub fn set_output_type(&mut self, output: OutputType) -> Result<&mut Self, Error> {
+        self.check_compiling("set_output_type")?;
+        let ret = self.apply(Setting::OutputType(output));
         assert_eq!(ret, 0);
-        self
+        self.windows_libraries(output);
+        Ok(self)
     }
 
     /// add a file (C file, dll, object, library, ld script).
-    pub fn add_file<T: AsRef<Path>>(&mut self, file: T) -> Result<(), ()> {
-        let file = to_cstr(file);
-        let ret = unsafe { tcc_add_file(self.inner, file.as_ptr()) };
-        map_c_ret(ret)
+    pub fn add_file<T: AsRef<Path>>(&mut self, file: T) -> Result<(), Error> {
+        #[cfg(feature = "vfs")]
+        if let Some(name) = self.remapped_source(file.as_ref()) {
+            let bytes = std::fs::read(name)?;
+            return self.add_staged(name, &bytes);
+        }
+        self.add_file_as_is(file)
+    }
+
+    fn add_file_as_is<T: AsRef<Path>>(&mut self, file: T) -> Result<(), Error> {
+        self.check_compiling("add_file")?;
+        #[cfg(feature = "vfs")]
+        self.probe_sysroot(sysroot::Probe::File(file.as_ref()))?;
+        let c_file = to_cstr(file.as_ref());
+        let started = metrics::start();
+        let start = self.diagnostics().len();
+        self.sink.missing_include = None;
+        let ret = self.compiling(true, || unsafe {
+            tcc_add_file(self.inner, c_file.as_ptr())
+        });
+        metrics::compiled(started, "file", ret == 0);
+        map_c_ret(ret).map_err(|()| self.compile_error(start, None))
+    }
+
+    /// Compile `bytes` as if they were the contents of a file called `name`,
+    /// without touching the disk.
+    ///
+    /// The content is staged in the VFS for the duration of the call, under a
+    /// name private to the call next to where `name` would be, so the
+    /// extension picks the language like for [`Context::add_file`], quoted
+    /// includes are searched in the directory of `name`, and diagnostics,
+    /// `__FILE__` and debug info refer to `name`. Files on disk and other
+    /// contexts compiling the same `name` are not affected. The bytes are
+    /// [normalized](crate::source::normalize) as for
+    /// [`Context::compile_bytes`].
+    #[cfg(feature = "vfs")]
+    pub fn add_source_bytes(&mut self, name: &str, bytes: &[u8]) -> Result<(), Error> {
+        let source = self.filter_source(bytes)?;
+        self.check_source_limits(&source)?;
+        self.check_dialect(name, &source)?;
+        self.screen(name, &source)?;
+        self.add_staged(name, source.as_bytes())
+    }
+
+    /// Compile `bytes` as the source `name`, staged in the VFS under a
+    /// unique name in the same directory, behind the prelude.
+    #[cfg(feature = "vfs")]
+    fn add_staged(&mut self, name: &str, bytes: &[u8]) -> Result<(), Error> {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let (dir, base) = name.split_at(name.rfind(['/', '\\']).map_or(0, |at| at + 1));
+        let staged = format!("{dir}.tcc-rs-staged-{id}-{base}");
+        let prelude = self.prelude(name, true).unwrap_or_default();
+        tcc_sys::vfs::mount(&staged, [prelude.as_bytes(), bytes].concat());
+        let ret = self.add_file_as_is(&staged);
+        tcc_sys::vfs::unmount(&staged);
+        ret
+    }
+
+    /// `file` if it is a C source whose name [`Context::remap_path_prefix`]
+    /// changes.
+    #[cfg(feature = "vfs")]
+    fn remapped_source<'a>(&self, file: &'a Path) -> Option<&'a str> {
+        let name = file.to_str()?;
+        let c = file.extension().is_some_and(|ext| ext == "c");
+        (c && diag::remap_path(&self.sink.remaps, name).is_some()).then_some(name)
+    }
+
+    /// Like [`Context::add_source_bytes`], reading the content from `reader`.
+    #[cfg(feature = "vfs")]
+    pub fn add_source_reader<R: Read>(&mut self, name: &str, mut reader: R) -> Result<(), Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        self.add_source_bytes(name, &bytes)
     }
 
     ///  compile a string containing a C source.
-    pub fn compile_string(&mut self, p: &CStr) -> Result<(), ()> {
-        let ret = unsafe { tcc_compile_string(self.inner, p.as_ptr()) };
-        map_c_ret(ret)
+    ///
+    /// Fails with [`Error::InvalidState`] once the code is relocated or
+    /// written out, or the context preprocesses, as does adding files.
+    /// [Banned functions](Context::set_banned_functions), forbidden
+    /// [inline assembly](Context::allow_inline_asm) and the
+    /// [token limit](SourceLimits::max_tokens) are checked first.
+    pub fn compile_string(&mut self, p: &CStr) -> Result<(), Error> {
+        self.check_compiling("compile_string")?;
+        #[cfg(feature = "std")]
+        self.screen("<string>", &p.to_string_lossy())?;
+        self.compile_unscreened(p)
+    }
+
+    /// [`Context::compile_string`] without the checks, for code generated by
+    /// this crate.
+    pub(crate) fn compile_unscreened(&mut self, p: &CStr) -> Result<(), Error> {
+        self.check_compiling("compile_string")?;
+        #[cfg(feature = "vfs")]
+        self.probe_sysroot(sysroot::Probe::String(p))?;
+        let started = metrics::start();
+        let start = self.diagnostics().len();
+        self.sink.missing_include = None;
+        let prefixed = self.prefixed_string(p);
+        let source = prefixed.as_deref().unwrap_or(p);
+        let ret = self.compiling(false, || unsafe {
+            tcc_compile_string(self.inner, source.as_ptr())
+        });
+        metrics::compiled(started, "string", ret == 0);
+        map_c_ret(ret).map_err(|()| self.compile_error(start, Some(p)))
+    }
+
+    /// Compile `p` with the built-in collector switched on, returning the
+    /// outcome together with the diagnostics it produced.
+    ///
+    /// The collector is restored to its previous state afterwards; if it was
+    /// already enabled the diagnostics are also kept there.
+    pub fn compile_with_diagnostics(&mut self, p: &CStr) -> (Result<(), Error>, Vec<Diagnostic>) {
+        let enabled = self.sink.collector.enabled;
+        self.collect_diagnostics(true);
+        let start = self.diagnostics().len();
+        let ret = self.compile_string(p);
+        let diagnostics = if enabled {
+            self.diagnostics()[start..].to_vec()
+        } else {
+            self.sink.collector.diagnostics.split_off(start)
+        };
+        self.collect_diagnostics(enabled);
+        (ret, diagnostics)
+    }
+
+    /// Syntax-check `p` without touching this context: it is compiled to an
+    /// object in a scratch tcc state configured like this one, so nothing
+    /// is relocated or written out.
+    ///
+    /// Returns the diagnostics on failure; warnings of a successful check are
+    /// dropped.
+    pub fn check(&mut self, p: &CStr) -> Result<(), Vec<Diagnostic>> {
+        let scratch = unsafe { tcc_new() };
+        if scratch.is_null() {
+            return Err(vec![Diagnostic::from(Error::OutOfMemory)]);
+        }
+
+        let enabled = self.sink.collector.enabled;
+        self.sink.collector.enabled = true;
+        let start = self.diagnostics().len();
+        let ret = unsafe {
+            tcc_set_error_func(
+                scratch,
+                self.sink.as_mut() as *mut Sink as *mut c_void,
+                Some(call_back),
+            );
+            // libraries are only needed for linking
+            let mut output_set = false;
+            for setting in &self.config {
+                match setting {
+                    Setting::Library(_) => continue,
+                    Setting::OutputType(_) => {
+                        output_set = true;
+                        Setting::OutputType(OutputType::Obj).apply(scratch);
+                    }
+                    setting => {
+                        setting.apply(scratch);
+                    }
+                }
+            }
+            if !output_set {
+                Setting::OutputType(OutputType::Obj).apply(scratch);
+            }
+            let prefixed = self.prefixed_string(p);
+            let source = prefixed.as_deref().unwrap_or(p);
+            let ret = self.compiling(false, || tcc_compile_string(scratch, source.as_ptr()));
+            tcc_delete(scratch);
+            ret
+        };
+        let diagnostics = self.sink.collector.diagnostics.split_off(start);
+        self.sink.collector.enabled = enabled;
+
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(diagnostics)
+        }
+    }
+
+    /// `p` with the prelude of [`Context::prelude`] in front, if there is one.
+    fn prefixed_string(&self, p: &CStr) -> Option<CString> {
+        let prelude = self.prelude("<string>", false)?;
+        Some(CString::new([prelude.as_bytes(), p.to_bytes()].concat()).unwrap())
+    }
+
+    /// Run the compilation `f` under [`Context::set_max_include_depth`] and
+    /// [`Context::use_header_cache`]; `file` tells whether tcc opens the
+    /// main source itself.
+    #[cfg(feature = "vfs")]
+    fn compiling<R>(&self, file: bool, f: impl FnOnce() -> R) -> R {
+        let limited = || {
+            match self.include_depth {
+                Some(depth) => tcc_sys::vfs::limit_open_files(depth + usize::from(file), f),
+                None => f(),
+            }
+        };
+        let ret = match &self.header_cache {
+            Some(cache) => cache.scope(limited),
+            None => limited(),
+        };
+        panic_policy::resume();
+        ret
+    }
+
+    #[cfg(not(feature = "vfs"))]
+    fn compiling<R>(&self, _file: bool, f: impl FnOnce() -> R) -> R {
+        let ret = f();
+        panic_policy::resume();
+        ret
+    }
+
+    /// Build [`Error::Compile`] from the diagnostics collected since `start`,
+    /// or [`Error::IncludeNotFound`] if a header was missing.
+    fn compile_error(&mut self, start: usize, code: Option<&CStr>) -> Error {
+        let diagnostics = self.diagnostics()[start..].to_vec();
+        match self.sink.missing_include.take() {
+            Some(header) => self.include_not_found(header, diagnostics),
+            None => {
+                Error::Compile {
+                    code: code.map(|code| code.to_string_lossy().into_owned()),
+                    diagnostics,
+                }
+            }
+        }
     }
 
     /// Equivalent to -Lpath option.
     pub fn add_library_path<T: AsRef<Path>>(&mut self, path: T) -> &mut Self {
-        let path = to_cstr(path);
-        let ret = unsafe { tcc_add_library_path(self.inner, path.as_ptr()) };
+        let ret = self.apply(Setting::LibraryPath(to_cstr(path)));
         assert_eq!(ret, 0);
         self
     }
 
     /// The library name is the same as the argument of the '-l' option.
-    pub fn add_library(&mut self, lib_name: &CStr) -> Result<(), ()> {
-        let ret = unsafe { tcc_add_library(self.inner, lib_name.as_ptr()) };
-        map_c_ret(ret)
+    ///
+    /// Fails with [`Error::LibraryNotFound`], telling the directories and
+    /// file names tried, if tcc finds no such library.
+    pub fn add_library(&mut self, lib_name: &CStr) -> Result<(), Error> {
+        let ret = self.apply(Setting::Library(lib_name.to_owned()));
+        map_c_ret(ret).map_err(|()| self.library_not_found(lib_name))
     }
 
     /// Add a symbol to the compiled program.
@@ -308,45 +841,154 @@ impl<'err> Context<'err> {
     }
 
     /// output an executable, library or object file.
-    pub fn output_file<T: AsRef<Path>>(&mut self, file_name: T) -> Result<(), ()> {
-        let file_name = to_cstr(file_name);
-        let ret = unsafe { tcc_output_file(self.inner, file_name.as_ptr()) };
+    ///
+    /// Like relocating, this links the code and can only be done once. The
+    /// output type must be a file, else this fails with
+    /// [`Error::InvalidState`].
+    pub fn output_file<T: AsRef<Path>>(&mut self, file_name: T) -> Result<(), Error> {
+        self.write_output(file_name.as_ref())?;
+        #[cfg(feature = "std")]
+        self.finish_output(file_name.as_ref())?;
+        Ok(())
+    }
 
-        map_c_ret(ret)
+    /// Have tcc write the output to `file_name`, see [`Context::output_file`].
+    fn write_output(&mut self, file_name: &Path) -> Result<(), Error> {
+        self.check_compiling("output_file")?;
+        if !self.output_type().is_some_and(OutputType::is_file) {
+            return Err(Error::InvalidState {
+                operation: "output_file",
+                reason:    "the output type is memory, relocate instead",
+            });
+        }
+        self.stage = Stage::Linked;
+        let path = to_cstr(file_name);
+        for file in core::mem::take(&mut self.link_last) {
+            if unsafe { tcc_add_file(self.inner, file.as_ptr()) } != 0 {
+                return Err(Error::Output);
+            }
+        }
+        self.prefix_symbols();
+        let ret = unsafe { tcc_output_file(self.inner, path.as_ptr()) };
+        panic_policy::resume();
+        map_c_ret(ret).map_err(|()| Error::Output)
+    }
+
+    /// Check and post-process the output tcc wrote to `file_name`.
+    #[cfg(feature = "std")]
+    fn finish_output(&mut self, file_name: &Path) -> Result<(), Error> {
+        let size = std::fs::metadata(file_name)?.len();
+        if let Err(err) = self.check_output_size(size.try_into().unwrap_or(usize::MAX)) {
+            let _ = std::fs::remove_file(file_name);
+            return Err(err);
+        }
+        self.write_build_id(file_name)?;
+        self.write_map(file_name)?;
+        self.transform_output(file_name)
     }
 
     /// do all relocations (needed before get symbol)
-    pub fn relocate<'a>(&'a mut self) -> Result<RelocatedCtx<'a, 'err>, ()> {
+    ///
+    /// tcc relocates only once: relocating again, or compiling and changing
+    /// the output type afterwards, fails with [`Error::InvalidState`], as
+    /// does relocating when the output type is a file.
+    pub fn relocate<'a>(&'a mut self) -> Result<RelocatedCtx<'a, 'err>, Error> {
+        let bin = self.relocate_image()?;
+        Ok(self.relocated(bin))
+    }
+
+    fn relocated<'a>(&'a mut self, bin: Image) -> RelocatedCtx<'a, 'err> {
+        RelocatedCtx {
+            inner: self,
+            bin,
+            frozen: false,
+            #[cfg(feature = "debug")]
+            _tracked: debug::Tracked::new(debug::StateKind::Relocated),
+        }
+    }
+
+    /// Relocate into a buffer of our own, which holds the code from then on.
+    fn relocate_image(&mut self) -> Result<Image, Error> {
+        self.relocate_with(|len| Ok(Image::Heap(Vec::with_capacity(len))))
+    }
+
+    /// Relocate into the memory `allocate` returns for the length tcc asks
+    /// for.
+    fn relocate_with(
+        &mut self,
+        allocate: impl FnOnce(usize) -> Result<Image, Error>,
+    ) -> Result<Image, Error> {
+        self.check_compiling("relocate")?;
+        if self.output_type().is_some_and(OutputType::is_file) {
+            return Err(Error::InvalidState {
+                operation: "relocate",
+                reason:    "the output type is a file, see output_file",
+            });
+        }
+        self.stage = Stage::Linked;
+        self.inject_shims()?;
+        self.prefix_symbols();
         // pass null ptr to get required length
         let len = unsafe { tcc_relocate(self.inner, null_mut()) };
+        panic_policy::resume();
         if len == -1 {
-            return Err(());
+            return Err(Error::Relocate);
         };
-        let mut bin = Vec::with_capacity(len as usize);
-        let ret = unsafe { tcc_relocate(self.inner, bin.as_mut_ptr() as *mut c_void) };
+        self.check_output_size(len as usize)?;
+        let mut image = allocate(len as usize)?;
+        let ptr = match &mut image {
+            Image::Heap(bin) => bin.as_mut_ptr(),
+            #[cfg(feature = "std")]
+            Image::Pooled(region, _) => region.as_mut_ptr(),
+        };
+        let ret = unsafe { tcc_relocate(self.inner, ptr as *mut c_void) };
+        panic_policy::resume();
         if ret != 0 {
-            return Err(());
+            return Err(Error::Relocate);
         }
-        unsafe {
-            bin.set_len(len as usize);
+        if let Image::Heap(bin) = &mut image {
+            unsafe { bin.set_len(len as usize) };
         }
+        // stale instructions would run on ARM, which gets final protections
+        // right away too
+        if cfg!(any(target_arch = "arm", target_arch = "aarch64")) && SECTION_SIZES {
+            self.protect_image(&image)?;
+        } else {
+            let (start, len) = image.span();
+            unsafe { protect::flush_icache(start, len) };
+        }
+        metrics::relocated(image.len());
+        Ok(image)
+    }
 
-        Ok(RelocatedCtx {
-            inner: self,
-            _bin:  bin,
-        })
+    /// Fail with [`Error::InvalidState`] for `operation` unless the context
+    /// still compiles.
+    fn check_compiling(&self, operation: &'static str) -> Result<(), Error> {
+        let reason = match self.stage {
+            Stage::Compiling => return Ok(()),
+            #[cfg(feature = "std")]
+            Stage::Preprocessing => "the context only preprocesses",
+            Stage::Linked => "the code is already relocated or written out",
+        };
+        Err(Error::InvalidState { operation, reason })
     }
 }
 
-#[cfg(target_family = "unix")]
 fn to_cstr<T: AsRef<Path>>(p: T) -> CString {
+    try_to_cstr(p).unwrap()
+}
+
+/// `p` as a C string, `None` if it contains a NUL byte.
+#[cfg(target_family = "unix")]
+fn try_to_cstr<T: AsRef<Path>>(p: T) -> Option<CString> {
     use std::os::unix::ffi::OsStrExt;
-    CString::new(p.as_ref().as_os_str().as_bytes()).unwrap()
+    CString::new(p.as_ref().as_os_str().as_bytes()).ok()
 }
 
 #[cfg(target_family = "windows")]
-fn to_cstr<T: AsRef<Path>>(p: T) -> CString {
-    CString::new(p.as_ref().to_string_lossy().to_string().as_bytes()).unwrap()
+fn try_to_cstr<T: AsRef<Path>>(p: T) -> Option<CString> {
+    use alloc::string::ToString;
+    CString::new(p.as_ref().to_string_lossy().to_string().as_bytes()).ok()
 }
 
 // preprocessor
@@ -368,8 +1010,11 @@ fn map_c_ret(code: c_int) -> Result<(), ()> {
 
 /// Relocated compilation context
 pub struct RelocatedCtx<'a, 'err> {
-    inner: &'a mut Context<'err>,
-    _bin:  Vec<u8>,
+    inner:    &'a mut Context<'err>,
+    bin:      Image,
+    frozen:   bool,
+    #[cfg(feature = "debug")]
+    _tracked: debug::Tracked,
 }
 
 impl<'a, 'err> RelocatedCtx<'a, 'err> {
@@ -386,6 +1031,73 @@ impl<'a, 'err> RelocatedCtx<'a, 'err> {
             Some(addr)
         }
     }
+
+    /// Run the module's `main` like a program, returning its exit code.
+    ///
+    /// `main` receives `"main"` as `argv[0]` followed by `args`, and the
+    /// environment set with [`Context::set_env`], or else the host's, in its
+    /// third parameter; it may be declared with fewer parameters. As with
+    /// [`run_main`], the module's constructors run before `main` and its
+    /// destructors after it, in reverse order.
+    ///
+    /// `main` calling `exit` ends the host process.
+    pub fn run_main(&mut self, args: &[&str]) -> Result<i32, Error> {
+        type Main = unsafe extern "C" fn(c_int, *mut *mut c_char, *mut *mut c_char) -> c_int;
+        type Ctor = unsafe extern "C" fn(c_int, *mut *mut c_char, *mut *mut c_char);
+        type Dtor = unsafe extern "C" fn();
+
+        let main = unsafe { self.get_symbol(c"main") }
+            .ok_or_else(|| Error::SymbolNotFound("main".into()))?;
+        let main = unsafe { core::mem::transmute::<*mut c_void, Main>(main) };
+        let args = jit::c_strings(core::iter::once("main").chain(args.iter().copied()))?;
+        let environ = match &self.inner.shims.environ {
+            Some(environ) => environ.clone(),
+            #[cfg(feature = "std")]
+            None => Environ::new(&Env::new().inherit())?,
+            #[cfg(not(feature = "std"))]
+            None => Environ::new(&Env::new())?,
+        };
+        // the strings outlive the call, `main` may keep pointers into them
+        let mut argv = jit::null_terminated(&args);
+        let (argc, argv, envp) = (args.len() as c_int, argv.as_mut_ptr(), environ.envp());
+
+        unsafe {
+            for ctor in self.function_array::<Ctor>(c"__preinit_array") {
+                ctor(argc, argv, envp);
+            }
+            for ctor in self.function_array::<Ctor>(c"__init_array") {
+                ctor(argc, argv, envp);
+            }
+            let code = main(argc, argv, envp);
+            for dtor in self
+                .function_array::<Dtor>(c"__fini_array")
+                .into_iter()
+                .rev()
+            {
+                dtor();
+            }
+            panic_policy::resume();
+            Ok(code)
+        }
+    }
+
+    /// The function pointers between the `<name>_start` and `<name>_end`
+    /// symbols tcc defines for the section, empty if it has none.
+    unsafe fn function_array<F: Copy>(&mut self, name: &CStr) -> Vec<F> {
+        let symbol = |suffix: &str| {
+            let mut symbol = name.to_bytes().to_vec();
+            symbol.extend_from_slice(suffix.as_bytes());
+            CString::new(symbol).unwrap()
+        };
+        let start = self.get_symbol(&symbol("_start"));
+        let end = self.get_symbol(&symbol("_end"));
+        let (Some(start), Some(end)) = (start, end) else {
+            return Vec::new();
+        };
+        let (start, end) = (start as *const F, end as *const F);
+        let len = (end as usize).saturating_sub(start as usize) / core::mem::size_of::<F>();
+        core::slice::from_raw_parts(start, len).to_vec()
+    }
 }
 
 #[cfg(test)] mod tests;
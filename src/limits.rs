@@ -0,0 +1,191 @@
+//! Limits protecting services from sources that blow up when compiled.
+
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+
+use crate::{dialect::tokens, Context, Error};
+
+impl<'err> Context<'err> {
+    /// Fail with [`Error::OutputTooLarge`] instead of relocating more than
+    /// `bytes` of code and data, or writing a larger executable, library or
+    /// object file; `None` removes the limit.
+    ///
+    /// The size is known before anything is allocated, so a snippet that
+    /// macro-expands into gigabytes of code fails before it takes up a
+    /// [`CodePool`](crate::exec::CodePool) or the heap. Files are checked
+    /// once tcc wrote them and removed when too large.
+    pub fn set_max_output_size(&mut self, bytes: Option<usize>) -> &mut Self {
+        self.max_output = bytes;
+        self
+    }
+
+    /// Fail if an output of `size` bytes exceeds the limit.
+    pub(crate) fn check_output_size(&self, size: usize) -> Result<(), Error> {
+        match self.max_output {
+            Some(limit) if size > limit => Err(Error::OutputTooLarge { size, limit }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+/// Limits on sources passed as text, see [`Context::set_source_limits`];
+/// `None` is unlimited.
+pub struct SourceLimits {
+    /// Bytes of a source as passed in.
+    pub max_source_bytes: Option<usize>,
+    /// Macros expanding to macros expanding to macros, counting the
+    /// outermost, among those the source defines.
+    pub max_macro_depth:  Option<usize>,
+    /// Identifiers, numbers and punctuators after preprocessing, including
+    /// those of included headers; literals are not counted.
+    pub max_tokens:       Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Which of the [`SourceLimits`] a source exceeded.
+pub enum SourceLimit {
+    SourceBytes,
+    MacroDepth,
+    Tokens,
+}
+
+impl core::fmt::Display for SourceLimit {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            SourceLimit::SourceBytes => "source size",
+            SourceLimit::MacroDepth => "macro expansion depth",
+            SourceLimit::Tokens => "token count",
+        })
+    }
+}
+
+impl<'err> Context<'err> {
+    /// Reject sources passed as text that exceed `limits` with
+    /// [`Error::SourceLimitExceeded`], protecting services from
+    /// preprocessor bombs.
+    ///
+    /// Size and macro depth are checked before tcc sees the source. The
+    /// depth is worked out from the `#define`s of the source itself, so
+    /// macros nesting into an exponential expansion are rejected before
+    /// they are expanded. Tokens are counted on the preprocessor output, in
+    /// a [fork](Context::fork) of this context like
+    /// [`Context::set_banned_functions`] does, which needs `std` and the
+    /// vendored libtcc; without them limiting tokens fails with
+    /// [`Error::UnsupportedOption`].
+    pub fn set_source_limits(&mut self, limits: SourceLimits) -> Result<&mut Self, Error> {
+        if limits.max_tokens.is_some() && !(cfg!(feature = "std") && tcc_sys::PREPROCESS_OUTPUT) {
+            return Err(Error::UnsupportedOption {
+                option: "max_tokens",
+                reason: "only the vendored libtcc can preprocess sources for the check",
+            });
+        }
+        self.source_limits = limits;
+        Ok(self)
+    }
+
+    /// Check `source` against the limits that need no preprocessing.
+    pub(crate) fn check_source_limits(&self, source: &str) -> Result<(), Error> {
+        let SourceLimits {
+            max_source_bytes,
+            max_macro_depth,
+            ..
+        } = self.source_limits;
+        exceeds(SourceLimit::SourceBytes, source.len(), max_source_bytes)?;
+        if max_macro_depth.is_some() {
+            exceeds(
+                SourceLimit::MacroDepth,
+                macro_depth(source),
+                max_macro_depth,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Fail if `size` exceeds `max`.
+pub(crate) fn exceeds(limit: SourceLimit, size: usize, max: Option<usize>) -> Result<(), Error> {
+    match max {
+        Some(max) if size > max => Err(Error::SourceLimitExceeded { limit, size, max }),
+        _ => Ok(()),
+    }
+}
+
+/// Longest chain of macros defined in `source` expanding to one another.
+fn macro_depth(source: &str) -> usize {
+    let source = source.replace("\\\r\n", " ").replace("\\\n", " ");
+    let mut macros = BTreeMap::new();
+    for line in source.lines() {
+        let Some(define) = line
+            .trim_start()
+            .strip_prefix('#')
+            .map(str::trim_start)
+            .and_then(|line| line.strip_prefix("define"))
+            .filter(|rest| rest.starts_with([' ', '\t']))
+        else {
+            continue;
+        };
+        let mut tokens = tokens(define).map(|(_, token)| token);
+        let Some(name) = tokens.next() else {
+            continue;
+        };
+        // parameters of function-like macros are not references
+        let mut params = Vec::new();
+        if define.trim_start()[name.len()..].starts_with('(') {
+            for token in tokens.by_ref() {
+                match token {
+                    ")" => break,
+                    "(" | "," => {}
+                    param => params.push(param),
+                }
+            }
+        }
+        let body: Vec<&str> = tokens.filter(|token| !params.contains(token)).collect();
+        macros.insert(name, body);
+    }
+
+    // longest path by depth-first search, without recursion so a long
+    // chain of macros can not overflow the stack; a macro is not expanded
+    // again inside itself, so edges back into the path are ignored
+    let names: Vec<&str> = macros.keys().copied().collect();
+    let edges: Vec<Vec<usize>> = names
+        .iter()
+        .map(|name| {
+            let body = &macros[name];
+            body.iter()
+                .filter_map(|token| names.binary_search(token).ok())
+                .collect()
+        })
+        .collect();
+    let (mut depth, mut done, mut seen) = (
+        vec![0; names.len()],
+        vec![false; names.len()],
+        vec![false; names.len()],
+    );
+    for root in 0..names.len() {
+        if seen[root] {
+            continue;
+        }
+        seen[root] = true;
+        let mut stack = vec![(root, 0)];
+        while let Some((node, next)) = stack.last_mut() {
+            let node = *node;
+            if let Some(&child) = edges[node].get(*next) {
+                *next += 1;
+                if !seen[child] {
+                    seen[child] = true;
+                    stack.push((child, 0));
+                }
+                continue;
+            }
+            let inner = edges[node]
+                .iter()
+                .filter(|&&child| done[child])
+                .map(|&child| depth[child])
+                .max();
+            depth[node] = inner.unwrap_or(0) + 1;
+            done[node] = true;
+            stack.pop();
+        }
+    }
+    depth.into_iter().max().unwrap_or(0)
+}
@@ -0,0 +1,53 @@
+//! Linking objects compiled elsewhere, for pipelines that compile in
+//! parallel and link once:
+//!
+//! ```no_run
+//! # fn main() -> Result<(), tcc::Error> {
+//! use tcc::OutputType;
+//!
+//! // each written by its own context with OutputType::Obj
+//! let objects: Vec<Vec<u8>> = Vec::new();
+//! let exe = tcc::link::combine(OutputType::Exe, &objects)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use alloc::{format, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{lock, Context, Error, OutputType};
+
+/// Where objects are staged while tcc reads them.
+const STAGED: &str = "/vfs/link";
+
+/// Link the object files `objects` into an executable, shared object or,
+/// for [`OutputType::Obj`], one relocatable object, and return its bytes.
+///
+/// The objects are staged in the VFS and linked by a fresh context with the
+/// default library paths, in order, so later ones can use symbols of earlier
+/// ones as when passing them to `tcc` on the command line. Like
+/// [`scoped`](crate::scoped) this takes the global lock, so it must not be
+/// called from inside it. Fails with [`Error::InvalidState`] for
+/// [`OutputType::Memory`], and with [`Error::Compile`] for bytes tcc does
+/// not take as an object.
+pub fn combine<O: AsRef<[u8]>>(output_type: OutputType, objects: &[O]) -> Result<Vec<u8>, Error> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let _lock = lock();
+    let mut ctx = Context::new()?;
+    ctx.set_output_type(output_type)?;
+    if !output_type.is_file() {
+        return Err(Error::InvalidState {
+            operation: "combine",
+            reason:    "the output type is memory, link into a file type",
+        });
+    }
+    let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+    for (index, object) in objects.iter().enumerate() {
+        let name = format!("{STAGED}/{id}-{index}.o");
+        tcc_sys::vfs::mount(&name, object.as_ref());
+        let ret = ctx.add_file_as_is(&name);
+        tcc_sys::vfs::unmount(&name);
+        ret?;
+    }
+    ctx.output_bytes()
+}
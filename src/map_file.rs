@@ -0,0 +1,207 @@
+//! Link maps of executables and shared objects, for tracking what makes
+//! generated artifacts large.
+
+use alloc::{string::String, vec::Vec};
+use core::fmt::Write;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{capabilities, Context, Error, ExecutableFormat, OutputType};
+
+const SHF_ALLOC: u64 = 2;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+const SHT_SYMTAB: u32 = 2;
+const SHT_DYNSYM: u32 = 11;
+
+/// A loaded section or a function or variable in one.
+struct Entry {
+    addr:    u64,
+    size:    u64,
+    name:    String,
+    /// name of the section a symbol is in
+    section: Option<String>,
+}
+
+/// Sections and symbols of the ELF file `elf`, `None` if it is malformed.
+fn parse(elf: &[u8]) -> Option<(Vec<Entry>, Vec<Entry>)> {
+    if elf.get(..4)? != b"\x7fELF" {
+        return None;
+    }
+    let wide = match elf.get(4)? {
+        1 => false,
+        2 => true,
+        _ => return None,
+    };
+    let big = *elf.get(5)? == 2;
+    let read = |at: usize, len: usize| -> Option<u64> {
+        let bytes = elf.get(at..at.checked_add(len)?)?;
+        let fold = |n: u64, &b: &u8| n << 8 | u64::from(b);
+        Some(match big {
+            true => bytes.iter().fold(0, fold),
+            false => bytes.iter().rev().fold(0, fold),
+        })
+    };
+    let word = if wide { 8 } else { 4 };
+    let (shoff, shent) = match wide {
+        true => (read(0x28, 8)?, 0x3a),
+        false => (read(0x20, 4)?, 0x2e),
+    };
+    let (entsize, count, strndx) = (read(shent, 2)?, read(shent + 2, 2)?, read(shent + 4, 2)?);
+    // name, type, flags, addr, offset, size and link of section `index`
+    let header = |index: u64| -> Option<(u64, u32, u64, u64, u64, u64, u64)> {
+        let at = usize::try_from(shoff.checked_add(index.checked_mul(entsize)?)?).ok()?;
+        let field = |n: usize| read(at + 8 + n * word, word);
+        Some((
+            read(at, 4)?,
+            read(at + 4, 4)? as u32,
+            field(0)?,
+            field(1)?,
+            field(2)?,
+            field(3)?,
+            read(at + 8 + 4 * word, 4)?,
+        ))
+    };
+    let string = |table: u64, offset: u64| -> Option<String> {
+        let (_, _, _, _, start, size, _) = header(table)?;
+        let end = start.checked_add(size)?;
+        let table = elf.get(usize::try_from(start).ok()?..usize::try_from(end).ok()?)?;
+        let name = table.get(usize::try_from(offset).ok()?..)?;
+        let name = &name[..name.iter().position(|&b| b == 0)?];
+        Some(String::from_utf8_lossy(name).into_owned())
+    };
+
+    let mut sections = Vec::new();
+    // from .symtab, or from .dynsym if the output is stripped
+    let (mut symbols, mut dynamic) = (Vec::new(), Vec::new());
+    let mut names = Vec::new();
+    for index in 0..count {
+        let (name, _, _, _, _, _, _) = header(index)?;
+        names.push(string(strndx, name)?);
+    }
+    for index in 0..count {
+        let (_, kind, flags, addr, offset, size, link) = header(index)?;
+        if flags & SHF_ALLOC != 0 && size != 0 {
+            sections.push(Entry {
+                addr,
+                size,
+                name: names[index as usize].clone(),
+                section: None,
+            });
+        }
+        let table = match kind {
+            SHT_SYMTAB => &mut symbols,
+            SHT_DYNSYM => &mut dynamic,
+            _ => continue,
+        };
+        let sym_size = if wide { 24 } else { 16 };
+        for at in (offset..offset.checked_add(size)?)
+            .step_by(sym_size)
+            .skip(1)
+        {
+            let at = usize::try_from(at).ok()?;
+            let (info, shndx, value, size) = match wide {
+                true => {
+                    (
+                        read(at + 4, 1)?,
+                        read(at + 6, 2)?,
+                        read(at + 8, 8)?,
+                        read(at + 16, 8)?,
+                    )
+                }
+                false => {
+                    (
+                        read(at + 12, 1)?,
+                        read(at + 14, 2)?,
+                        read(at + 4, 4)?,
+                        read(at + 8, 4)?,
+                    )
+                }
+            };
+            let section = names
+                .get(shndx as usize)
+                .filter(|_| shndx != 0 && shndx < 0xff00);
+            let (Some(section), STT_OBJECT | STT_FUNC) = (section, info as u8 & 0xf) else {
+                continue;
+            };
+            table.push(Entry {
+                addr: value,
+                size,
+                name: string(link, read(at, 4)?)?,
+                section: Some(section.clone()),
+            });
+        }
+    }
+    if symbols.is_empty() {
+        symbols = dynamic;
+    }
+    sections.sort_by_key(|entry| entry.addr);
+    symbols.sort_by(|a, b| (a.addr, &a.name).cmp(&(b.addr, &b.name)));
+    symbols.dedup_by(|a, b| a.addr == b.addr && a.name == b.name);
+    Some((sections, symbols))
+}
+
+/// The map of the ELF file `elf`: its loaded sections, then its functions
+/// and variables, each by address.
+fn render(elf: &[u8]) -> Option<String> {
+    let (sections, symbols) = parse(elf)?;
+    let mut map = String::from("Sections:\n  address            size       name\n");
+    for section in &sections {
+        let _ = writeln!(
+            map,
+            "  {:#018x} {:#010x} {}",
+            section.addr, section.size, section.name
+        );
+    }
+    map.push_str("\nSymbols:\n  address            size       section          name\n");
+    for symbol in &symbols {
+        let section = symbol.section.as_deref().unwrap_or("");
+        let _ = writeln!(
+            map,
+            "  {:#018x} {:#010x} {section:<16} {}",
+            symbol.addr, symbol.size, symbol.name
+        );
+    }
+    Some(map)
+}
+
+impl<'err> Context<'err> {
+    /// Write a map of the executable or shared object to `path` when
+    /// [`Context::output_file`] writes it: the address and size of every
+    /// loaded section, then of every function and variable with the section
+    /// it is in.
+    ///
+    /// The map is read back from the linked output, before any
+    /// [output transform](Context::set_output_transform) runs. The output
+    /// type must already be [`OutputType::Exe`] or [`OutputType::Dll`] and
+    /// tcc must target ELF, else this fails with
+    /// [`Error::UnsupportedOption`].
+    pub fn write_map_file<P: AsRef<Path>>(&mut self, path: P) -> Result<&mut Self, Error> {
+        let unsupported = |reason| {
+            Err(Error::UnsupportedOption {
+                option: "-Map",
+                reason,
+            })
+        };
+        if capabilities().format != ExecutableFormat::Elf {
+            return unsupported("maps are read from ELF outputs");
+        }
+        if !matches!(self.output_type(), Some(OutputType::Exe | OutputType::Dll)) {
+            return unsupported("only applies to executables and shared objects");
+        }
+        self.map_file = Some(PathBuf::from(path.as_ref()));
+        Ok(self)
+    }
+
+    /// Write the map of the output tcc wrote to `output`.
+    pub(crate) fn write_map(&self, output: &Path) -> Result<(), Error> {
+        let Some(path) = &self.map_file else {
+            return Ok(());
+        };
+        let map = render(&fs::read(output)?).ok_or(Error::Output)?;
+        fs::write(path, map)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+//! Memory taken by relocated code, for quotas covering more than the heap.
+
+use alloc::vec::Vec;
+
+use tcc_sys::tcc_rs_section_sizes;
+
+use crate::{Context, RelocatedCtx};
+
+/// Memory relocated code lives in.
+pub(crate) enum Image {
+    /// a buffer of our own
+    Heap(Vec<u8>),
+    /// a region of a [`CodePool`](crate::exec::CodePool)
+    #[cfg(feature = "std")]
+    Pooled(crate::exec::Region, usize),
+}
+
+impl Image {
+    /// Length of the relocated code.
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Image::Heap(bin) => bin.len(),
+            #[cfg(feature = "std")]
+            Image::Pooled(_, len) => *len,
+        }
+    }
+
+    /// Start and length of the memory owned by the image, which may be
+    /// longer than the code.
+    pub(crate) fn span(&self) -> (usize, usize) {
+        match self {
+            Image::Heap(bin) => (bin.as_ptr() as usize, bin.capacity()),
+            #[cfg(feature = "std")]
+            Image::Pooled(region, _) => (region.as_ptr() as usize, region.size()),
+        }
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        // pages tcc or `protect` made read-only go back to the allocator,
+        // pooled regions are reset by the pool
+        if let Image::Heap(_) = self {
+            let (start, len) = self.span();
+            unsafe { crate::protect::make_writable(start, len) };
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+/// What the sections of relocated code take up, see
+/// [`RelocatedCtx::memory_usage`].
+///
+/// The section sizes are 0 unless `tcc-sys` builds the vendored libtcc,
+/// see `SECTION_SIZES` there; `total_mapped` is always known.
+pub struct MemoryUsage {
+    /// executable code
+    pub code_bytes:   usize,
+    /// initialized data, read-only data, the GOT and the like
+    pub data_bytes:   usize,
+    /// zero-initialized data
+    pub bss_bytes:    usize,
+    /// the whole image, with the padding between sections of different
+    /// protection
+    pub total_mapped: usize,
+}
+
+impl<'err> Context<'err> {
+    /// Usage of the relocated sections, `image` being the relocated length.
+    pub(crate) fn memory_usage(&self, image: usize) -> MemoryUsage {
+        let mut usage = MemoryUsage {
+            total_mapped: image,
+            ..MemoryUsage::default()
+        };
+        unsafe {
+            tcc_rs_section_sizes(
+                self.inner,
+                &mut usage.code_bytes,
+                &mut usage.data_bytes,
+                &mut usage.bss_bytes,
+            )
+        };
+        usage
+    }
+}
+
+impl<'a, 'err> RelocatedCtx<'a, 'err> {
+    /// Memory taken by the relocated code and data.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.inner.memory_usage(self.bin.len())
+    }
+}
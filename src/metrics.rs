@@ -0,0 +1,84 @@
+//! Names of the metrics recorded through the [`metrics`](https://docs.rs/metrics)
+//! facade when the `metrics` feature is enabled.
+//!
+//! Nothing is recorded unless the embedding application installs a recorder,
+//! such as `metrics-exporter-prometheus`. Without the feature the names are
+//! still available but nothing is emitted.
+
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+
+/// Counter of compilations started, labelled with `source` (`file` or
+/// `string`).
+pub const COMPILES: &str = "tcc_compiles_total";
+
+/// Counter of compilations that succeeded, labelled like [`COMPILES`].
+pub const COMPILES_SUCCEEDED: &str = "tcc_compiles_succeeded_total";
+
+/// Histogram of compilation times in seconds, labelled like [`COMPILES`].
+pub const COMPILE_DURATION: &str = "tcc_compile_duration_seconds";
+
+/// Counter of bytes of code and data relocated into memory.
+pub const RELOCATED_BYTES: &str = "tcc_relocated_bytes_total";
+
+/// Counter of lookups answered from a cache, labelled with `cache`.
+pub const CACHE_HITS: &str = "tcc_cache_hits_total";
+
+/// Register units and descriptions of all metrics with the installed
+/// recorder, so exporters can show them.
+#[cfg(feature = "metrics")]
+pub fn describe() {
+    use metrics::{describe_counter, describe_histogram, Unit};
+
+    describe_counter!(COMPILES, Unit::Count, "compilations started");
+    describe_counter!(
+        COMPILES_SUCCEEDED,
+        Unit::Count,
+        "compilations that succeeded"
+    );
+    describe_histogram!(COMPILE_DURATION, Unit::Seconds, "time spent compiling");
+    describe_counter!(RELOCATED_BYTES, Unit::Bytes, "bytes relocated into memory");
+    describe_counter!(CACHE_HITS, Unit::Count, "lookups answered from a cache");
+}
+
+/// Start of a compilation being measured.
+pub(crate) struct Started {
+    #[cfg(feature = "metrics")]
+    at: Instant,
+}
+
+pub(crate) fn start() -> Started {
+    Started {
+        #[cfg(feature = "metrics")]
+        at:                             Instant::now(),
+    }
+}
+
+/// Record a finished compilation of a `source` (`file` or `string`).
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub(crate) fn compiled(started: Started, source: &'static str, ok: bool) {
+    #[cfg(feature = "metrics")]
+    {
+        metrics::counter!(COMPILES, "source" => source).increment(1);
+        if ok {
+            metrics::counter!(COMPILES_SUCCEEDED, "source" => source).increment(1);
+        }
+        metrics::histogram!(COMPILE_DURATION, "source" => source)
+            .record(started.at.elapsed().as_secs_f64());
+    }
+}
+
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub(crate) fn relocated(bytes: usize) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(RELOCATED_BYTES).increment(bytes as u64);
+}
+
+/// Record `hits` lookups answered from `cache`.
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub(crate) fn cache_hits(cache: &'static str, hits: usize) {
+    #[cfg(feature = "metrics")]
+    if hits > 0 {
+        metrics::counter!(CACHE_HITS, "cache" => cache).increment(hits as u64);
+    }
+}
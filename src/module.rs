@@ -0,0 +1,83 @@
+//! Relocated code that outlives its compiler state.
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use core::ffi::{c_char, c_void, CStr};
+
+use tcc_sys::tcc_list_symbols;
+
+use crate::{memory::Image, shims::KeepAlive, Context, Error, MemoryUsage};
+
+/// Code relocated by [`Context::into_module`].
+///
+/// The tcc state is gone, only the code image and the symbol table remain, so
+/// a module has no lifetime and can be moved freely.
+pub struct Module {
+    _image:      Image,
+    symbols:     BTreeMap<String, usize>,
+    usage:       MemoryUsage,
+    _keep_alive: Vec<KeepAlive>,
+    #[cfg(feature = "debug")]
+    _tracked:    crate::debug::Tracked,
+}
+
+// The image is not written by Rust after relocation and symbols are plain
+// addresses; whether compiled code may run concurrently is up to that code.
+unsafe impl Send for Module {}
+unsafe impl Sync for Module {}
+
+impl Module {
+    /// Address of `sym`, or `None` if it is not defined.
+    ///
+    /// The address is only valid as long as the module is alive.
+    pub fn get_symbol(&self, sym: &str) -> Option<*mut c_void> {
+        self.symbols.get(sym).map(|&addr| addr as *mut c_void)
+    }
+
+    /// Names of all symbols, in lexicographic order.
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.symbols.keys().map(String::as_str)
+    }
+
+    /// Memory taken by the module's code and data, see
+    /// [`RelocatedCtx::memory_usage`](crate::RelocatedCtx::memory_usage).
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.usage
+    }
+}
+
+impl<'err> Context<'err> {
+    /// Relocate the compiled code and drop the tcc state, keeping only what
+    /// is needed to call into the code.
+    pub fn into_module(mut self) -> Result<Module, Error> {
+        let image = self.relocate_image()?;
+        Ok(self.module(image))
+    }
+
+    /// Wrap the relocated `image` with the symbol table.
+    pub(crate) fn module(&mut self, image: Image) -> Module {
+        let usage = self.memory_usage(image.len());
+
+        extern "C" fn collect(ctx: *mut c_void, name: *const c_char, val: *const c_void) {
+            let symbols = unsafe { &mut *(ctx as *mut BTreeMap<String, usize>) };
+            let name = unsafe { CStr::from_ptr(name) };
+            symbols.insert(name.to_string_lossy().into_owned(), val as usize);
+        }
+        let mut symbols = BTreeMap::new();
+        unsafe {
+            tcc_list_symbols(
+                self.inner,
+                &mut symbols as *mut BTreeMap<String, usize> as *mut c_void,
+                Some(collect),
+            )
+        };
+
+        Module {
+            _image: image,
+            symbols,
+            usage,
+            _keep_alive: core::mem::take(&mut self.shims.keep_alive),
+            #[cfg(feature = "debug")]
+            _tracked: crate::debug::Tracked::new(crate::debug::StateKind::Module),
+        }
+    }
+}
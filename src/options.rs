@@ -0,0 +1,357 @@
+//! Typed wrappers around `tcc_set_options` switches.
+//!
+//! Every option ends up as a plain [`Context::set_options`] call, so it is
+//! recorded in the [`ConfigLog`](crate::ConfigLog) like any other setting.
+
+use alloc::{ffi::CString, format, string::String};
+use core::ffi::{c_char, c_void, CStr};
+
+use tcc_sys::{
+    tcc_compile_string, tcc_delete, tcc_new, tcc_set_error_func, tcc_set_options,
+    tcc_set_output_type, TCC_OUTPUT_OBJ,
+};
+
+use crate::{capabilities, config::Setting, Arch, Context, Error, ExecutableFormat, OutputType};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+/// Code generation model, see [`Context::set_relocation_model`].
+pub enum RelocationModel {
+    /// whatever tcc picks for the output type (default)
+    #[default]
+    Default,
+
+    /// position independent code, `-fPIC`
+    Pic,
+
+    /// position dependent code, `-fno-pic`
+    Static,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Signedness of plain `char`, see [`Context::set_char_signedness`].
+pub enum CharSignedness {
+    /// `-fsigned-char`
+    Signed,
+
+    /// `-funsigned-char`
+    Unsigned,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// C language standard, see [`Context::set_std`].
+pub enum CStd {
+    C89,
+    C99,
+    C11,
+    C2x,
+}
+
+impl CStd {
+    fn option(self) -> &'static str {
+        match self {
+            CStd::C89 => "-std=c89",
+            CStd::C99 => "-std=c99",
+            CStd::C11 => "-std=c11",
+            CStd::C2x => "-std=c2x",
+        }
+    }
+
+    /// Source that only compiles if the option took effect.
+    fn probe(self) -> &'static CStr {
+        match self {
+            CStd::C89 => c"#ifdef __STDC_VERSION__\n#error\n#endif\n",
+            CStd::C99 => c"#if __STDC_VERSION__ != 199901L\n#error\n#endif\n",
+            CStd::C11 => c"#if __STDC_VERSION__ != 201112L\n#error\n#endif\n",
+            CStd::C2x => c"#if __STDC_VERSION__ <= 201710L\n#error\n#endif\n",
+        }
+    }
+
+    /// Whether the linked tinycc implements `self`, checked by compiling
+    /// the probe in a scratch state.
+    fn is_supported(self) -> bool {
+        accepts(self.option(), self.probe())
+    }
+}
+
+/// Whether the linked tinycc takes `options` and then compiles `probe`,
+/// tried in a scratch state.
+fn accepts(options: &str, probe: &CStr) -> bool {
+    extern "C" fn ignore(_: *mut c_void, _: *const c_char) {}
+
+    let options = CString::new(options).unwrap();
+    unsafe {
+        let scratch = tcc_new();
+        if scratch.is_null() {
+            return false;
+        }
+        tcc_set_error_func(scratch, core::ptr::null_mut(), Some(ignore));
+        let ok = tcc_set_options(scratch, options.as_ptr()) >= 0
+            && tcc_set_output_type(scratch, TCC_OUTPUT_OBJ as _) == 0
+            && tcc_compile_string(scratch, probe.as_ptr()) == 0;
+        tcc_delete(scratch);
+        ok
+    }
+}
+
+/// What [`Context::gc_sections`] passes to tcc.
+const GC_SECTIONS: [&str; 3] = [
+    "-ffunction-sections",
+    "-fdata-sections",
+    "-Wl,--gc-sections",
+];
+
+impl<'err> Context<'err> {
+    /// Output type selected last, if any.
+    pub(crate) fn output_type(&self) -> Option<OutputType> {
+        self.config.iter().rev().find_map(|setting| {
+            match setting {
+                Setting::OutputType(output) => Some(*output),
+                _ => None,
+            }
+        })
+    }
+
+    /// Pass a single option to tcc, failing if tcc rejects it.
+    pub(crate) fn set_option(&mut self, option: &'static str) -> Result<&mut Self, Error> {
+        let ret = self.apply(Setting::Options(CString::new(option).unwrap()));
+        if ret < 0 {
+            return Err(Error::UnsupportedOption {
+                option,
+                reason: "rejected by tcc",
+            });
+        }
+        Ok(self)
+    }
+
+    /// Build a position independent executable (`-pie`) or not (`-no-pie`).
+    ///
+    /// The output type must already be [`OutputType::Exe`]. Only ELF targets
+    /// can choose, Mach-O executables are always position independent and PE
+    /// images are not. Android only runs position independent executables.
+    pub fn set_pie(&mut self, pie: bool) -> Result<&mut Self, Error> {
+        let option = if pie { "-pie" } else { "-no-pie" };
+        let unsupported = |reason| Err(Error::UnsupportedOption { option, reason });
+
+        if self.output_type() != Some(OutputType::Exe) {
+            return unsupported("only applies to executables");
+        }
+        if cfg!(target_os = "android") && !pie {
+            return unsupported("Android only runs PIE executables");
+        }
+        match (capabilities().format, pie) {
+            (ExecutableFormat::Elf, _) => self.set_option(option),
+            (ExecutableFormat::MachO, true) => Ok(self),
+            (ExecutableFormat::MachO, false) => unsupported("Mach-O executables are always PIE"),
+            (ExecutableFormat::Pe, _) => unsupported("not supported for PE images"),
+        }
+    }
+
+    /// Choose between position independent and position dependent code.
+    ///
+    /// Must be called after the output type is set: dynamic libraries can not
+    /// be built from [`RelocationModel::Static`] code, and the C67 backend only
+    /// generates position dependent code.
+    pub fn set_relocation_model(&mut self, model: RelocationModel) -> Result<&mut Self, Error> {
+        let option = match model {
+            RelocationModel::Default => return Ok(self),
+            RelocationModel::Pic => "-fPIC",
+            RelocationModel::Static => "-fno-pic",
+        };
+        let unsupported = |reason| Err(Error::UnsupportedOption { option, reason });
+
+        match (model, self.output_type()) {
+            (_, None) => return unsupported("set the output type first"),
+            (RelocationModel::Static, Some(OutputType::Dll)) => {
+                return unsupported("dynamic libraries need position independent code")
+            }
+            _ => {}
+        }
+        if model == RelocationModel::Pic && capabilities().arch == Arch::C67 {
+            return unsupported("the C67 backend has no PIC code generation");
+        }
+        self.set_option(option)
+    }
+
+    /// Select the C standard, failing if the linked tinycc does not implement
+    /// it.
+    ///
+    /// tcc ignores `-std=` values it does not know; here the option is probed
+    /// first by checking `__STDC_VERSION__` in a scratch state, so an
+    /// unsupported standard is reported instead of silently compiling as C99.
+    pub fn set_std(&mut self, std: CStd) -> Result<&mut Self, Error> {
+        if !std.is_supported() {
+            return Err(Error::UnsupportedOption {
+                option: std.option(),
+                reason: "not implemented by this tinycc",
+            });
+        }
+        self.set_option(std.option())
+    }
+
+    /// Pin the signedness of plain `char` instead of using the target default.
+    pub fn set_char_signedness(&mut self, signedness: CharSignedness) -> Result<&mut Self, Error> {
+        self.set_option(match signedness {
+            CharSignedness::Signed => "-fsigned-char",
+            CharSignedness::Unsigned => "-funsigned-char",
+        })
+    }
+
+    /// Allow `$` in identifiers (`-fdollars-in-identifiers`).
+    pub fn dollars_in_identifiers(&mut self, enable: bool) -> Result<&mut Self, Error> {
+        self.set_option(match enable {
+            true => "-fdollars-in-identifiers",
+            false => "-fno-dollars-in-identifiers",
+        })
+    }
+
+    /// Accept Microsoft extensions such as anonymous struct members
+    /// (`-fms-extensions`).
+    pub fn ms_extensions(&mut self, enable: bool) -> Result<&mut Self, Error> {
+        self.set_option(match enable {
+            true => "-fms-extensions",
+            false => "-fno-ms-extensions",
+        })
+    }
+
+    /// Prefix C symbols with an underscore (`-fleading-underscore`).
+    pub fn leading_underscore(&mut self, enable: bool) -> Result<&mut Self, Error> {
+        self.set_option(match enable {
+            true => "-fleading-underscore",
+            false => "-fno-leading-underscore",
+        })
+    }
+
+    /// Place uninitialized globals in common storage (`-fcommon`).
+    pub fn common(&mut self, enable: bool) -> Result<&mut Self, Error> {
+        self.set_option(match enable {
+            true => "-fcommon",
+            false => "-fno-common",
+        })
+    }
+
+    /// Keep frame pointers in generated functions
+    /// (`-fno-omit-frame-pointer`), so sampling profilers unwinding by frame
+    /// pointer walk through compiled code into the host's frames.
+    ///
+    /// tcc never omits them, so this only checks that the frames can be
+    /// walked: it fails with [`Error::UnsupportedOption`] where
+    /// [`Capabilities::frame_pointers`](crate::Capabilities::frame_pointers)
+    /// is false. Not keeping them always succeeds and changes nothing.
+    pub fn keep_frame_pointers(&mut self, keep: bool) -> Result<&mut Self, Error> {
+        if keep && !capabilities().frame_pointers {
+            return Err(Error::UnsupportedOption {
+                option: "-fno-omit-frame-pointer",
+                reason: "this backend's frames can not be walked by frame pointer",
+            });
+        }
+        Ok(self)
+    }
+
+    /// Drop functions and data nothing in the executable or shared object
+    /// refers to (`-Wl,--gc-sections`).
+    ///
+    /// Code compiled from now on gets a section per function and variable
+    /// (`-ffunction-sections -fdata-sections`) the linker can drop one by
+    /// one, so call it after setting the output type and before adding
+    /// sources. The output type must be [`OutputType::Exe`] or
+    /// [`OutputType::Dll`] and tcc must target ELF; the linked tinycc is
+    /// asked first, and fails this with [`Error::UnsupportedOption`] if it
+    /// has no section GC. Once on it can not be turned off.
+    pub fn gc_sections(&mut self, enable: bool) -> Result<&mut Self, Error> {
+        let option = "--gc-sections";
+        let unsupported = |reason| Err(Error::UnsupportedOption { option, reason });
+
+        let enabled = self.config.iter().any(|setting| {
+            matches!(setting, Setting::Options(options) if options.to_bytes() == GC_SECTIONS[2].as_bytes())
+        });
+        match (enable, enabled) {
+            (false, false) | (true, true) => return Ok(self),
+            (false, true) => return unsupported("can not be turned off once on"),
+            (true, false) => {}
+        }
+        if capabilities().format != ExecutableFormat::Elf {
+            return unsupported("only the ELF linker collects sections");
+        }
+        if !matches!(self.output_type(), Some(OutputType::Exe | OutputType::Dll)) {
+            return unsupported("only applies to executables and shared objects");
+        }
+        if !accepts(&GC_SECTIONS.join(" "), c"") {
+            return unsupported("not implemented by this tinycc");
+        }
+        for option in GC_SECTIONS {
+            self.set_option(option)?;
+        }
+        Ok(self)
+    }
+
+    /// Default struct packing, as if every source started with
+    /// `#pragma pack(n)`; `None` restores the target's natural alignment.
+    ///
+    /// Applies to [`Context::compile_string`] and
+    /// [`Context::add_source_bytes`], whose content passes through this crate;
+    /// files added by path are left alone.
+    pub fn set_default_pack(&mut self, pack: Option<u8>) -> Result<&mut Self, Error> {
+        if pack.is_some_and(|pack| !pack.is_power_of_two() || pack > 16) {
+            return Err(Error::UnsupportedOption {
+                option: "#pragma pack",
+                reason: "alignment must be 1, 2, 4, 8 or 16",
+            });
+        }
+        self.pack = pack;
+        Ok(self)
+    }
+
+    /// Stop with an error when headers nest deeper than `depth` includes,
+    /// `None` leaves only tcc's own limit of 32.
+    ///
+    /// The error lists the whole include chain and calls it a cycle if a
+    /// header includes itself along the way, so malicious or broken
+    /// include graphs in untrusted sources fail fast and legibly. The limit
+    /// is enforced where tcc opens files, hence the `vfs` feature.
+    #[cfg(feature = "vfs")]
+    pub fn set_max_include_depth(&mut self, depth: Option<usize>) -> &mut Self {
+        self.include_depth = depth;
+        self
+    }
+
+    /// Lines to put in front of the source `name`, ending in a `#line`
+    /// directive so diagnostics keep pointing at the user's lines, under
+    /// the name [`Context::remap_path_prefix`] gives the file. `None` when
+    /// there is nothing to put, unless `rename` asks for the directive
+    /// anyway, as for sources compiled under another name.
+    pub(crate) fn prelude(&self, name: &str, rename: bool) -> Option<String> {
+        let remapped = crate::diag::remap_path(&self.sink.remaps, name);
+        if self.pack.is_none() && remapped.is_none() && !rename {
+            return None;
+        }
+        let mut prelude = String::new();
+        if let Some(pack) = self.pack {
+            prelude.push_str(&format!("#pragma pack({pack})\n"));
+        }
+        let name = remapped.as_deref().unwrap_or(name);
+        let name = name.replace('\\', "\\\\").replace('"', "\\\"");
+        prelude.push_str(&format!("#line 1 \"{name}\"\n"));
+        Some(prelude)
+    }
+
+    /// Settings for math-heavy JIT code, in one call.
+    ///
+    /// Enables `-O2`, which tcc mostly takes as a promise to headers via
+    /// `__OPTIMIZE__`, defines `NDEBUG` so `assert` costs nothing, and makes
+    /// libm available: linked on ELF targets when producing files, through
+    /// [`Context::provide_libm`] in memory. tcc has no `-ffast-math`
+    /// equivalent, so floating point semantics stay strict.
+    ///
+    /// Call it after setting the output type.
+    pub fn numeric_kernel_profile(&mut self) -> Result<&mut Self, Error> {
+        self.set_option("-O2")?;
+        self.apply(Setting::Define(c"NDEBUG".into(), Some(c"1".into())));
+
+        let output = self.output_type().unwrap_or(OutputType::Memory);
+        let links = matches!(output, OutputType::Exe | OutputType::Dll);
+        if links && capabilities().format == ExecutableFormat::Elf {
+            self.add_library(c"m")?;
+        }
+        Ok(self.provide_libm(true))
+    }
+}
@@ -0,0 +1,167 @@
+//! Output of artifacts to writers and memory instead of named files.
+
+use alloc::{boxed::Box, vec::Vec};
+use std::{
+    fs,
+    fs::File,
+    io::{self, Cursor, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::{artifacts::OutDir, Context, Error};
+
+pub(crate) type OutputTransform<'err> = Box<dyn FnMut(Vec<u8>) -> Vec<u8> + 'err>;
+
+/// `inner` seen from where the artifact starts. Once the artifact exceeds
+/// the limit of [`Context::set_max_output_size`] nothing more reaches
+/// `inner`, only positions are tracked to learn the full size.
+#[cfg(feature = "vfs")]
+struct Limited<'a, W> {
+    inner:     &'a mut W,
+    /// position of `inner` the artifact starts at
+    start:     u64,
+    /// position in the artifact
+    pos:       u64,
+    /// furthest position written
+    end:       u64,
+    limit:     Option<usize>,
+    too_large: bool,
+}
+
+#[cfg(feature = "vfs")]
+impl<W: Write + Seek> Write for Limited<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let wanted = self.pos + buf.len() as u64;
+        self.too_large |= self.limit.is_some_and(|limit| wanted > limit as u64);
+        let written = match self.too_large {
+            true => buf.len(),
+            false => self.inner.write(buf)?,
+        };
+        self.pos += written as u64;
+        self.end = self.end.max(self.pos);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(feature = "vfs")]
+impl<W: Write + Seek> Seek for Limited<'_, W> {
+    fn seek(&mut self, from: SeekFrom) -> io::Result<u64> {
+        if self.too_large {
+            let pos = match from {
+                SeekFrom::Start(pos) => Some(pos),
+                SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+                SeekFrom::End(offset) => self.end.checked_add_signed(offset),
+            };
+            self.pos = pos.ok_or(io::ErrorKind::InvalidInput)?;
+            return Ok(self.pos);
+        }
+        let from = match from {
+            SeekFrom::Start(pos) => SeekFrom::Start(self.start + pos),
+            from => from,
+        };
+        let pos = self.inner.seek(from)?;
+        self.pos = pos
+            .checked_sub(self.start)
+            .ok_or(io::ErrorKind::InvalidInput)?;
+        Ok(self.pos)
+    }
+}
+
+impl<'err> Context<'err> {
+    /// Run the bytes of every executable, library or object file through
+    /// `transform` before they are written by [`Context::output_file`] or
+    /// returned by [`Context::output_to`] and [`Context::output_bytes`].
+    ///
+    /// The transform gets the whole artifact and returns what to write
+    /// instead, to inject a build ID, strip sections or sign the artifact.
+    /// Files tcc writes next to it, like the `.def` file of a Windows DLL,
+    /// are left alone.
+    pub fn set_output_transform<F>(&mut self, transform: F) -> &mut Self
+    where
+        F: FnMut(Vec<u8>) -> Vec<u8> + 'err,
+    {
+        self.transform = Some(Box::new(transform));
+        self
+    }
+
+    /// Apply the output transform to the artifact tcc wrote to `path`.
+    pub(crate) fn transform_output(&mut self, path: &Path) -> Result<(), Error> {
+        if let Some(transform) = &mut self.transform {
+            let bytes = transform(fs::read(path)?);
+            fs::write(path, bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Write the executable, library or object file into `writer`.
+    ///
+    /// tcc can only write to named files, so the artifact is spooled through
+    /// a file in a fresh private temporary directory and streamed from there.
+    /// Without an [output transform](Context::set_output_transform) it is
+    /// never held in memory as a whole; the transform gets it as bytes.
+    /// Writers that can seek avoid the file with
+    /// [`Context::output_to_seekable`]. Returns the number of bytes written.
+    pub fn output_to<W: Write + ?Sized>(&mut self, writer: &mut W) -> Result<u64, Error> {
+        let dir = OutDir::temp()?;
+        let path = dir.path("output");
+        self.output_file(&path)?;
+        Ok(io::copy(&mut File::open(&path)?, writer)?)
+    }
+
+    /// Write the executable, library or object file into `writer` as tcc
+    /// produces it, starting at the current position of `writer`.
+    ///
+    /// With the `vfs` feature tcc writes ELF and Mach-O outputs through
+    /// `writer` on Linux, macOS and FreeBSD, and seeks in it where the format
+    /// needs to. Elsewhere, for PE outputs, and with an output transform,
+    /// [build ID](Context::set_build_id) or
+    /// [map file](Context::write_map_file), which work on the finished
+    /// file, the artifact is spooled like for [`Context::output_to`]. An
+    /// artifact exceeding [`Context::set_max_output_size`] fails with
+    /// [`Error::OutputTooLarge`], and nothing past the limit reaches
+    /// `writer`. Returns the number of bytes written.
+    pub fn output_to_seekable<W: Write + Seek>(&mut self, writer: &mut W) -> Result<u64, Error> {
+        let dir = OutDir::temp()?;
+        let path = dir.path("output");
+        #[cfg(feature = "vfs")]
+        if let (None, None, None, Some(name)) = (
+            &self.transform,
+            &self.build_id,
+            &self.map_file,
+            path.to_str(),
+        ) {
+            let start = writer.stream_position()?;
+            let mut limited = Limited {
+                inner: writer,
+                start,
+                pos: 0,
+                end: 0,
+                limit: self.max_output,
+                too_large: false,
+            };
+            let (ret, captured) =
+                tcc_sys::vfs::capture_output(name, &mut limited, || self.write_output(&path));
+            let captured = captured?;
+            ret?;
+            if captured {
+                self.check_output_size(limited.end.try_into().unwrap_or(usize::MAX))?;
+                return Ok(limited.end);
+            }
+            self.finish_output(&path)?;
+            return Ok(io::copy(&mut File::open(&path)?, writer)?);
+        }
+        self.output_file(&path)?;
+        Ok(io::copy(&mut File::open(&path)?, writer)?)
+    }
+
+    /// Return the executable, library or object file as bytes.
+    pub fn output_bytes(&mut self) -> Result<Vec<u8>, Error> {
+        let mut bytes = Cursor::new(Vec::new());
+        self.output_to_seekable(&mut bytes)?;
+        Ok(bytes.into_inner())
+    }
+}
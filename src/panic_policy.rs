@@ -0,0 +1,115 @@
+//! What happens when a Rust callback panics with C frames above it.
+//!
+//! Unwinding through C is undefined behaviour, so callbacks are always
+//! stopped at the boundary; [`PanicPolicy`] decides what happens next.
+
+#[cfg(feature = "std")] use alloc::boxed::Box;
+#[cfg(feature = "std")] use core::any::Any;
+#[cfg(feature = "std")]
+use core::{
+    cell::RefCell,
+    mem::MaybeUninit,
+    panic::AssertUnwindSafe,
+    sync::atomic::{AtomicU8, Ordering},
+};
+#[cfg(feature = "std")] use std::{panic, process};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+/// Handling of panics in callbacks called from C, see [`set_panic_policy`].
+pub enum PanicPolicy {
+    /// Abort the process after the panic message is printed.
+    #[default]
+    Abort,
+
+    /// Return to C as if the callback returned all zero bits, and keep the
+    /// panic for [`take_panic`]. The C code carries on, possibly with a
+    /// result it did not expect.
+    CaptureAndContinue,
+
+    /// Like [`PanicPolicy::CaptureAndContinue`], then resume the panic as
+    /// soon as control is back in this crate: when the tcc call or the
+    /// `main` or [`CallThread`](crate::exec::CallThread) call that led to
+    /// the callback returns.
+    Propagate,
+}
+
+#[cfg(feature = "std")]
+static POLICY: AtomicU8 = AtomicU8::new(PanicPolicy::Abort as u8);
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static CAPTURED: RefCell<Option<Box<dyn Any + Send>>> = const { RefCell::new(None) };
+}
+
+/// Set how panics in callbacks called from C are handled, for every thread.
+///
+/// This covers diagnostic callbacks and filters, closures made with
+/// [`tcc_callback!`](crate::tcc_callback) and other host functions called by
+/// compiled code. Without `std` panics always abort.
+#[cfg(feature = "std")]
+pub fn set_panic_policy(policy: PanicPolicy) {
+    POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+/// The policy set with [`set_panic_policy`].
+#[cfg(feature = "std")]
+pub fn panic_policy() -> PanicPolicy {
+    match POLICY.load(Ordering::Relaxed) {
+        1 => PanicPolicy::CaptureAndContinue,
+        2 => PanicPolicy::Propagate,
+        _ => PanicPolicy::Abort,
+    }
+}
+
+/// Take the payload of a panic captured on this thread and not resumed yet.
+///
+/// Only the first panic is kept until it is taken.
+#[cfg(feature = "std")]
+pub fn take_panic() -> Option<Box<dyn Any + Send>> {
+    CAPTURED.with(|captured| captured.borrow_mut().take())
+}
+
+/// Run the Rust side of a callback called from C, following the
+/// [`PanicPolicy`].
+///
+/// # Safety
+/// Unless the policy is [`PanicPolicy::Abort`], a panic makes this return all
+/// zero bits, which must be a valid `R`, as for integers, floats and raw
+/// pointers.
+#[cfg(feature = "std")]
+pub unsafe fn guard<R>(f: impl FnOnce() -> R) -> R {
+    let payload = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(value) => return value,
+        Err(payload) => payload,
+    };
+    if panic_policy() == PanicPolicy::Abort {
+        process::abort();
+    }
+    CAPTURED.with(|captured| {
+        captured.borrow_mut().get_or_insert(payload);
+    });
+    MaybeUninit::zeroed().assume_init()
+}
+
+/// Without `std` panics can not be caught and abort at the boundary.
+///
+/// # Safety
+/// Always safe, `unsafe` to match the `std` version.
+#[cfg(not(feature = "std"))]
+pub unsafe fn guard<R>(f: impl FnOnce() -> R) -> R {
+    f()
+}
+
+/// Resume a captured panic under [`PanicPolicy::Propagate`].
+#[cfg(feature = "std")]
+pub(crate) fn resume() {
+    if panic_policy() != PanicPolicy::Propagate {
+        return;
+    }
+    if let Some(payload) = take_panic() {
+        panic::resume_unwind(payload);
+    }
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn resume() {}
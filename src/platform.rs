@@ -0,0 +1,132 @@
+//! Platform macros generated code tests, following the backend tcc
+//! generates code for rather than the host the crate was built on.
+
+use alloc::{ffi::CString, format, vec::Vec};
+use core::ffi::CStr;
+
+use tcc_sys::tcc_define_symbol;
+
+use crate::{capabilities, config::Setting, Arch, Context, ExecutableFormat};
+
+/// Macros naming each backend as gcc and clang predefine them, the first
+/// one is defined.
+const ARCH_MACROS: &[(Arch, &[&CStr])] = &[
+    (Arch::I386, &[c"__i386__", c"__i386"]),
+    (Arch::X86_64, &[c"__x86_64__", c"__amd64__"]),
+    (Arch::Arm32, &[c"__arm__"]),
+    (Arch::Arm64, &[c"__aarch64__"]),
+    (Arch::C67, &[c"__C67__"]),
+    (Arch::RiscV64, &[c"__riscv"]),
+];
+
+/// Opts `<stdio.h>` and friends into declaring GNU and POSIX extensions.
+const GNU_SOURCE: &CStr = c"_GNU_SOURCE";
+
+/// Whether the target tcc generates code for defines `name`, `None` for
+/// macros naming no architecture or platform.
+fn targets(name: &CStr) -> Option<bool> {
+    let caps = capabilities();
+    if let Some((arch, _)) = ARCH_MACROS
+        .iter()
+        .find(|(_, macros)| macros.contains(&name))
+    {
+        return Some(*arch == caps.arch);
+    }
+    let pe = caps.format == ExecutableFormat::Pe;
+    match name.to_bytes() {
+        b"_WIN32" => Some(pe),
+        b"_WIN64" => Some(pe && matches!(caps.arch, Arch::X86_64 | Arch::Arm64)),
+        _ => None,
+    }
+}
+
+/// The architecture and platform macros of the target.
+fn platform_macros() -> Vec<(&'static CStr, Option<&'static CStr>)> {
+    let caps = capabilities();
+    let mut macros = Vec::new();
+    if let Some((_, names)) = ARCH_MACROS.iter().find(|(arch, _)| *arch == caps.arch) {
+        macros.push((names[0], Some(c"1")));
+    }
+    for name in [c"_WIN32", c"_WIN64"] {
+        if targets(name) == Some(true) {
+            macros.push((name, Some(c"1")));
+        }
+    }
+    macros
+}
+
+impl<'err> Context<'err> {
+    /// Predefine the architecture and platform macros of the target, see
+    /// [`Context::default_defines`].
+    ///
+    /// Like [`Context::float_defaults`] these are part of the compiler and
+    /// not recorded in the [`ConfigLog`](crate::ConfigLog).
+    pub(crate) fn platform_defaults(&mut self) {
+        for (name, value) in platform_macros() {
+            let value = value.map_or(core::ptr::null(), CStr::as_ptr);
+            unsafe { tcc_define_symbol(self.inner, name.as_ptr(), value) };
+        }
+    }
+
+    /// Define `_GNU_SOURCE`, so C library headers declare GNU and POSIX
+    /// extensions like `asprintf` or `memmem`, or undefine it again.
+    ///
+    /// Off by default, as with gcc: code written against ISO C could clash
+    /// with the extra declarations.
+    pub fn set_gnu_source(&mut self, enable: bool) -> &mut Self {
+        let setting = match enable {
+            true => Setting::Define(GNU_SOURCE.into(), None),
+            false => Setting::Undefine(GNU_SOURCE.into()),
+        };
+        self.apply(setting);
+        self
+    }
+
+    /// The platform macros every compilation of this context sees, e.g. to
+    /// pass them on to the compiler building the code JIT'ed code is linked
+    /// against.
+    ///
+    /// These follow [`capabilities`], not the host: the backend's
+    /// architecture macro like `__x86_64__`, `_WIN32` and on 64-bit targets
+    /// `_WIN64` when tcc targets PE, also when a Linux host builds for
+    /// Windows with the `link-pe` feature of `tcc-sys`, and `_GNU_SOURCE`
+    /// while [`Context::set_gnu_source`] is on. Defining another backend's
+    /// or platform's macro with [`Context::define`], or undefining the
+    /// target's with [`Context::undefine_symbol`], is reported as a warning
+    /// diagnostic since it changes what headers declare but not the code tcc
+    /// generates.
+    pub fn default_defines(&self) -> Vec<(&'static CStr, Option<&'static CStr>)> {
+        let mut defines = platform_macros();
+        let gnu_source = self.config.iter().rev().find_map(|setting| {
+            match setting {
+                Setting::Define(name, _) if name.as_c_str() == GNU_SOURCE => Some(true),
+                Setting::Undefine(name) if name.as_c_str() == GNU_SOURCE => Some(false),
+                _ => None,
+            }
+        });
+        if gnu_source == Some(true) {
+            defines.push((GNU_SOURCE, None));
+        }
+        defines
+    }
+
+    /// Warn if defining (or undefining) `name` contradicts the target.
+    pub(crate) fn check_platform_macro(&mut self, name: &CStr, defining: bool) {
+        match targets(name) {
+            Some(targeted) if targeted != defining => {}
+            _ => return,
+        }
+        let caps = capabilities();
+        let action = match defining {
+            true => "defined",
+            false => "undefined",
+        };
+        let message = format!(
+            "warning: {} {action}, but tcc still generates {} {} code",
+            name.to_string_lossy(),
+            caps.arch,
+            caps.format
+        );
+        self.sink.dispatch(&CString::new(message).unwrap());
+    }
+}
@@ -0,0 +1,316 @@
+//! Plugin ABIs declared once on the Rust side, see
+//! [`tcc_plugin_interface!`](crate::tcc_plugin_interface).
+//!
+//! An interface names the functions a plugin must define. The macro turns it
+//! into a Rust trait, a C header declaring the functions for plugins to
+//! include, and a binding resolving them in relocated code into a boxed
+//! trait object, so host and plugins can not disagree on the signatures.
+//!
+//! The header also defines the ABI version of the interface as a symbol, so
+//! [`PluginInterface::bind_checked`] can refuse plugins compiled against
+//! another version of it.
+
+#[doc(hidden)]
+pub use alloc::{boxed::Box, string::String};
+use alloc::{ffi::CString, format, vec::Vec};
+use core::ffi::{c_char, c_void};
+#[doc(hidden)] pub use core::marker::PhantomData;
+
+use tcc_sys::tcc_get_symbol;
+
+#[cfg(feature = "vfs")] use crate::Context;
+use crate::{Error, Module, RelocatedCtx};
+
+/// Where the VFS serves the headers of plugin interfaces from.
+#[cfg(feature = "vfs")]
+const PLUGIN_HEADERS: &str = "/vfs/headers/plugins";
+
+/// Rust types usable in plugin interfaces, with their C spelling.
+pub trait CType {
+    fn c_type() -> String;
+}
+
+macro_rules! c_type {
+    ($($ty:ty => $c:expr),* $(,)?) => {
+        $(impl CType for $ty {
+            fn c_type() -> String {
+                String::from($c)
+            }
+        })*
+    };
+}
+
+c_type! {
+    () => "void",
+    c_void => "void",
+    bool => "_Bool",
+    i8 => if c_char::MIN != 0 { "char" } else { "signed char" },
+    u8 => if c_char::MIN == 0 { "char" } else { "unsigned char" },
+    i16 => "short",
+    u16 => "unsigned short",
+    i32 => "int",
+    u32 => "unsigned int",
+    i64 => "long long",
+    u64 => "unsigned long long",
+    isize => "__PTRDIFF_TYPE__",
+    usize => "__SIZE_TYPE__",
+    f32 => "float",
+    f64 => "double",
+}
+
+impl<T: CType + ?Sized> CType for *const T {
+    fn c_type() -> String {
+        format!("{} const *", T::c_type())
+    }
+}
+
+impl<T: CType + ?Sized> CType for *mut T {
+    fn c_type() -> String {
+        format!("{} *", T::c_type())
+    }
+}
+
+/// Relocated code plugins are bound from: a [`RelocatedCtx`] or a
+/// [`Module`].
+pub trait Resolve {
+    /// Address of `symbol`, or `None` if it is not defined.
+    fn resolve(&self, symbol: &str) -> Option<*mut c_void>;
+}
+
+impl Resolve for Module {
+    fn resolve(&self, symbol: &str) -> Option<*mut c_void> {
+        self.get_symbol(symbol)
+    }
+}
+
+impl Resolve for RelocatedCtx<'_, '_> {
+    fn resolve(&self, symbol: &str) -> Option<*mut c_void> {
+        let symbol = CString::new(symbol).ok()?;
+        let addr = unsafe { tcc_get_symbol(self.inner.inner, symbol.as_ptr()) };
+        (!addr.is_null()).then_some(addr)
+    }
+}
+
+/// A plugin ABI, implemented by
+/// [`tcc_plugin_interface!`](crate::tcc_plugin_interface) for `dyn Trait`.
+pub trait PluginInterface {
+    /// The trait object bound to code borrowed for `'a`.
+    type Bound<'a>: ?Sized + 'a;
+
+    /// Name of the trait, and of the header as `Name.h`.
+    const NAME: &'static str;
+
+    /// C declarations of the functions.
+    fn declarations() -> String;
+
+    /// ABI version of the interface: its `ABI_VERSION` if it declares one,
+    /// else a hash of the [declarations](PluginInterface::declarations).
+    fn version() -> u32;
+
+    /// The header plugins include: the declarations and, as the weak symbol
+    /// `__tcc_rs_plugin_abi_Name`, the [version](PluginInterface::version).
+    fn header() -> String {
+        let name = Self::NAME;
+        format!(
+            "/* plugin interface {name} */\n#ifndef TCC_PLUGIN_{name}_H\n#define \
+             TCC_PLUGIN_{name}_H\n{}__attribute__((weak)) const unsigned int {} = {}u;\n#endif\n",
+            Self::declarations(),
+            version_symbol(name),
+            Self::version(),
+        )
+    }
+
+    /// Resolve the functions in `code`, failing with
+    /// [`Error::SymbolNotFound`] for the first one it does not define.
+    ///
+    /// The definitions are not checked against the declarations; plugins
+    /// should include the [header](PluginInterface::header), so tcc rejects
+    /// definitions that differ.
+    fn bind<'a, C: Resolve + ?Sized>(code: &'a C) -> Result<Box<Self::Bound<'a>>, Error>;
+
+    /// Like [`PluginInterface::bind`], but first check that `code` was
+    /// compiled against this [version](PluginInterface::version) of the
+    /// header, else fail with [`Error::PluginAbiMismatch`].
+    ///
+    /// Plugins not including the header have no version and are refused.
+    fn bind_checked<'a, C: Resolve + ?Sized>(code: &'a C) -> Result<Box<Self::Bound<'a>>, Error> {
+        let found = code
+            .resolve(&version_symbol(Self::NAME))
+            .map(|addr| unsafe { *(addr as *const u32) });
+        let expected = Self::version();
+        if found != Some(expected) {
+            return Err(Error::PluginAbiMismatch {
+                interface: Self::NAME,
+                expected,
+                found,
+            });
+        }
+        Self::bind(code)
+    }
+}
+
+/// Symbol holding the ABI version of interface `name`.
+fn version_symbol(name: &str) -> String {
+    format!("__tcc_rs_plugin_abi_{name}")
+}
+
+#[doc(hidden)]
+/// The declared ABI version of an interface or the FNV-1a hash of its
+/// `declarations`.
+pub fn version(declared: &[u32], declarations: &str) -> u32 {
+    declared.first().copied().unwrap_or_else(|| {
+        declarations.bytes().fold(0x811c9dc5, |hash, byte| {
+            (hash ^ u32::from(byte)).wrapping_mul(0x01000193)
+        })
+    })
+}
+
+#[doc(hidden)]
+/// Append the C declaration of function `name` to `header`.
+pub fn declare(header: &mut String, name: &str, ret: String, args: &[(&str, String)]) {
+    let args = match args {
+        [] => String::from("void"),
+        args => {
+            let args: Vec<_> = args
+                .iter()
+                .map(|(name, ty)| format!("{ty} {name}"))
+                .collect();
+            args.join(", ")
+        }
+    };
+    header.push_str(&format!("{ret} {name}({args});\n"));
+}
+
+#[doc(hidden)]
+/// Address of `symbol` in `code`.
+pub fn resolve<C: Resolve + ?Sized>(code: &C, symbol: &str) -> Result<*mut c_void, Error> {
+    code.resolve(symbol)
+        .ok_or_else(|| Error::SymbolNotFound(symbol.into()))
+}
+
+#[cfg(feature = "vfs")]
+impl<'err> Context<'err> {
+    /// Make the header of plugin interface `P` includable as `"Name.h"`,
+    /// for `dyn Name` declared with
+    /// [`tcc_plugin_interface!`](crate::tcc_plugin_interface).
+    ///
+    /// The header is served by the VFS to every context, so interfaces need
+    /// distinct names.
+    pub fn use_plugin_interface<P: PluginInterface + ?Sized>(&mut self) -> &mut Self {
+        let path = format!("{PLUGIN_HEADERS}/{}.h", P::NAME);
+        tcc_sys::vfs::mount(&path, P::header().into_bytes());
+        self.add_include_path(PLUGIN_HEADERS)
+    }
+}
+
+/// Declare a plugin ABI: a trait, the C header plugins implement it with,
+/// and the binding of compiled plugins to the trait.
+///
+/// Each `fn` becomes a trait method taking `&self` and a C function of the
+/// same name. Argument and return types must implement [`CType`]; functions
+/// may take up to eight arguments. `dyn Trait` implements
+/// [`PluginInterface`], see [`Context::use_plugin_interface`] for the header
+/// and [`PluginInterface::bind_checked`] for the binding.
+///
+/// An optional `const ABI_VERSION: u32 = …;` before the functions sets the
+/// version plugins are checked against; bump it whenever the meaning of the
+/// interface changes without its signatures changing, like the layout of a
+/// struct passed by pointer. Without it the version follows the signatures.
+///
+/// ```no_run
+/// use tcc::{tcc_plugin_interface, PluginInterface};
+///
+/// tcc_plugin_interface! {
+///     trait MyPlugin {
+///         const ABI_VERSION: u32 = 2;
+///         fn init();
+///         fn tick(dt: f32) -> i32;
+///     }
+/// }
+///
+/// # fn load(module: &tcc::Module) -> Result<(), tcc::Error> {
+/// // ctx.use_plugin_interface::<dyn MyPlugin>(), then compile a plugin
+/// // doing #include "MyPlugin.h"
+/// let plugin = <dyn MyPlugin>::bind_checked(module)?;
+/// plugin.init();
+/// plugin.tick(0.5);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The trait object borrows the code it was bound from, so it can not
+/// outlive it.
+#[macro_export]
+macro_rules! tcc_plugin_interface {
+    (@ret) => { () };
+    (@ret $ret:ty) => { $ret };
+    (
+        $(#[$meta:meta])*
+        $vis:vis trait $name:ident {
+            $(const ABI_VERSION: u32 = $version:expr;)?
+            $(
+                $(#[$fmeta:meta])*
+                fn $f:ident($($arg:ident : $ty:ty),* $(,)?) $(-> $ret:ty)?;
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        $vis trait $name {
+            $(
+                $(#[$fmeta])*
+                fn $f(&self, $($arg: $ty),*) -> $crate::tcc_plugin_interface!(@ret $($ret)?);
+            )*
+        }
+
+        const _: () = {
+            #[allow(non_snake_case)]
+            struct Bound<'a> {
+                $($f: unsafe extern "C" fn($($ty),*) -> $crate::tcc_plugin_interface!(@ret $($ret)?),)*
+                _code: $crate::plugin::PhantomData<&'a ()>,
+            }
+
+            impl $name for Bound<'_> {
+                $(
+                    fn $f(&self, $($arg: $ty),*) -> $crate::tcc_plugin_interface!(@ret $($ret)?) {
+                        unsafe { (self.$f)($($arg),*) }
+                    }
+                )*
+            }
+
+            impl $crate::PluginInterface for dyn $name {
+                type Bound<'a> = dyn $name + 'a;
+
+                const NAME: &'static str = stringify!($name);
+
+                fn declarations() -> $crate::plugin::String {
+                    let mut declarations = $crate::plugin::String::new();
+                    $(
+                        $crate::plugin::declare(
+                            &mut declarations,
+                            stringify!($f),
+                            <$crate::tcc_plugin_interface!(@ret $($ret)?) as $crate::CType>::c_type(),
+                            &[$((stringify!($arg), <$ty as $crate::CType>::c_type())),*],
+                        );
+                    )*
+                    declarations
+                }
+
+                fn version() -> u32 {
+                    $crate::plugin::version(&[$($version)?], &Self::declarations())
+                }
+
+                fn bind<'a, C: $crate::Resolve + ?Sized>(
+                    code: &'a C,
+                ) -> ::core::result::Result<$crate::plugin::Box<dyn $name + 'a>, $crate::Error> {
+                    Ok($crate::plugin::Box::new(Bound {
+                        $($f: unsafe {
+                            <unsafe extern "C" fn($($ty),*) -> $crate::tcc_plugin_interface!(@ret $($ret)?)
+                                as $crate::FnPtr>::from_ptr($crate::plugin::resolve(code, stringify!($f))?)
+                        },)*
+                        _code: $crate::plugin::PhantomData,
+                    }))
+                }
+            }
+        };
+    };
+}
@@ -0,0 +1,62 @@
+//! Running only the preprocessor, like `tcc -E`.
+
+use alloc::string::String;
+use std::{fs, path::Path};
+
+use tcc_sys::{
+    tcc_rs_preprocess_done, tcc_rs_preprocess_to, tcc_set_output_type, PREPROCESS_OUTPUT,
+    TCC_OUTPUT_PREPROCESS,
+};
+
+use crate::{artifacts::OutDir, to_cstr, Context, Error, Stage};
+
+impl<'err> Context<'err> {
+    /// Preprocess the file at `path` and return the output.
+    ///
+    /// The context is switched to preprocessing: it can preprocess again,
+    /// but compiling, relocating or writing output afterwards fails with
+    /// [`Error::InvalidState`]. Line markers are written unless `-P` is passed
+    /// with [`Context::set_options`]. Only the vendored libtcc can hand its
+    /// output over; with any other this fails with
+    /// [`Error::UnsupportedOption`].
+    pub fn preprocess_file(&mut self, path: impl AsRef<Path>) -> Result<String, Error> {
+        self.preprocessing(|ctx| ctx.add_file(path))
+    }
+
+    /// Preprocess `source` and return the output, see
+    /// [`Context::preprocess_file`].
+    pub fn preprocess_str(&mut self, source: &str) -> Result<String, Error> {
+        self.preprocessing(|ctx| ctx.compile_str(source))
+    }
+
+    /// Run `f` with the preprocessor output going to a temporary file, and
+    /// return what was written there.
+    fn preprocessing(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<String, Error> {
+        if !PREPROCESS_OUTPUT {
+            return Err(Error::UnsupportedOption {
+                option: "-E",
+                reason: "only the vendored libtcc can redirect preprocessor output",
+            });
+        }
+        if self.stage != Stage::Preprocessing {
+            self.check_compiling("preprocess")?;
+            unsafe { tcc_set_output_type(self.inner, TCC_OUTPUT_PREPROCESS as _) };
+        }
+        let dir = OutDir::temp()?;
+        let path = dir.path("preprocessed.i");
+        if unsafe { tcc_rs_preprocess_to(self.inner, to_cstr(&path).as_ptr()) } != 0 {
+            return Err(Error::Output);
+        }
+        // the source goes through the compiling methods
+        self.stage = Stage::Compiling;
+        let ret = f(self);
+        self.stage = Stage::Preprocessing;
+        unsafe { tcc_rs_preprocess_done(self.inner) };
+        let output = fs::read(&path);
+        ret?;
+        Ok(String::from_utf8_lossy(&output?).into_owned())
+    }
+}
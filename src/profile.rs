@@ -0,0 +1,303 @@
+//! Named bundles of settings for common ways of using tcc.
+
+use alloc::{ffi::CString, format, vec::Vec};
+
+use crate::{
+    capabilities, config::Setting, windows::EMBEDDED_LIBRARIES, Context, Error, ExecutableFormat,
+    OutputType,
+};
+
+/// Where the VFS serves tcc's embedded headers from.
+pub(crate) const EMBEDDED_HEADERS: &str = "/vfs/headers/base";
+
+/// Where the VFS serves the embedded musl from, with `include` and `lib`.
+pub(crate) const EMBEDDED_MUSL: &str = "/vfs/headers/musl";
+
+/// Where the VFS serves the embedded intrinsic headers from.
+const EMBEDDED_SIMD: &str = "/vfs/headers/simd";
+
+/// tcc's runtime support library among the embedded libraries.
+const EMBEDDED_LIBTCC1: &str = "/vfs/libraries/libtcc1.a";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+/// Preset configuration, see [`Context::with_profile`].
+pub enum Profile {
+    /// Untrusted snippets compiled and run in memory.
+    ///
+    /// Host headers are not searched (`-nostdinc`), only tcc's own embedded
+    /// headers when the VFS provides them. Warnings are errors, so calls to
+    /// undeclared functions are rejected instead of guessed, and math
+    /// functions come from [`Context::provide_libm`] rather than the dynamic
+    /// loader.
+    SandboxedScripting,
+
+    /// Quick edit-run cycles in memory: debug information (`-g`), all
+    /// warnings, and backtraces (`-bt`) where tcc was built with them.
+    FastDebug,
+
+    /// Math-heavy code in memory, see [`Context::numeric_kernel_profile`].
+    ReleaseKernel,
+
+    /// Object files for environments without a C library: no host headers
+    /// or libraries (`-nostdinc`, `-nostdlib`), only tcc's freestanding
+    /// headers when embedded.
+    Freestanding,
+
+    /// On-device scripting in Android apps, in memory.
+    ///
+    /// Devices ship no headers, so only tcc's embedded headers are searched
+    /// (`-nostdinc`); libraries resolve against bionic's `/system/lib64` or
+    /// `/system/lib`. Applying the profile fails with
+    /// [`Error::UnsupportedOption`] where the system's W^X policy forbids
+    /// making written memory executable, as for apps targeting API 29 and
+    /// later, since nothing compiled in memory could run there. Executables
+    /// built later are always position independent, see
+    /// [`Context::set_pie`].
+    Android,
+}
+
+impl<'err> Context<'err> {
+    /// Apply the settings of `profile`.
+    ///
+    /// Profiles are a starting point; everything they set goes through the
+    /// regular methods and shows up in the [`ConfigLog`](crate::ConfigLog),
+    /// so later calls can refine or override it.
+    pub fn with_profile(&mut self, profile: Profile) -> Result<&mut Self, Error> {
+        let caps = capabilities();
+        let embedded = caps.vfs && caps.embedded_headers;
+        match profile {
+            Profile::SandboxedScripting => {
+                self.set_output_type(OutputType::Memory)?;
+                self.set_option("-nostdinc")?;
+                if embedded {
+                    self.add_sys_include_path(EMBEDDED_HEADERS);
+                }
+                self.set_option("-Wall")?;
+                self.set_option("-Werror")?;
+                self.provide_libm(true);
+            }
+            Profile::FastDebug => {
+                self.set_output_type(OutputType::Memory)?;
+                self.set_option("-g")?;
+                self.set_option("-Wall")?;
+                if caps.backtrace {
+                    self.set_option("-bt")?;
+                }
+            }
+            Profile::ReleaseKernel => {
+                self.set_output_type(OutputType::Memory)?;
+                self.numeric_kernel_profile()?;
+            }
+            Profile::Freestanding => {
+                self.set_output_type(OutputType::Obj)?;
+                self.set_option("-nostdinc")?;
+                self.set_option("-nostdlib")?;
+                if embedded {
+                    self.add_sys_include_path(EMBEDDED_HEADERS);
+                }
+                self.apply(Setting::Undefine(c"__STDC_HOSTED__".into()));
+                self.apply(Setting::Define(
+                    c"__STDC_HOSTED__".into(),
+                    Some(c"0".into()),
+                ));
+            }
+            Profile::Android => {
+                if !can_execute_written_memory() {
+                    return Err(Error::UnsupportedOption {
+                        option: "memory output",
+                        reason: "W^X policy forbids executing written memory",
+                    });
+                }
+                self.set_output_type(OutputType::Memory)?;
+                self.set_option("-nostdinc")?;
+                if embedded {
+                    self.add_sys_include_path(EMBEDDED_HEADERS);
+                }
+                let bionic = match cfg!(target_pointer_width = "64") {
+                    true => "/system/lib64",
+                    false => "/system/lib",
+                };
+                self.add_library_path(bionic);
+                self.provide_libm(true);
+            }
+        }
+        Ok(self)
+    }
+}
+
+impl<'err> Context<'err> {
+    /// Set up a fully static Linux executable linked against the embedded
+    /// musl, which runs without any C library installed.
+    ///
+    /// Compiles with `-static -nostdinc -nostdlib`: headers come from tcc
+    /// and musl only, musl's start files and `libc.a` are linked around
+    /// everything added to the context, so no toolchain has to be installed
+    /// on the building machine either. The `musl` feature embeds the musl
+    /// found in `MUSL_SYSROOT` at build time; without it this fails with
+    /// [`Error::UnsupportedOption`], as it does for non-ELF targets.
+    pub fn static_musl_exe(&mut self) -> Result<&mut Self, Error> {
+        let caps = capabilities();
+        let unsupported = |reason| {
+            Err(Error::UnsupportedOption {
+                option: "-static",
+                reason,
+            })
+        };
+        if caps.format != ExecutableFormat::Elf {
+            return unsupported("musl executables are ELF");
+        }
+        if !caps.vfs || !caps.embedded_musl {
+            return unsupported("musl is not embedded, see the `musl` feature");
+        }
+
+        self.set_output_type(OutputType::Exe)?;
+        self.set_option("-static")?;
+        self.set_option("-nostdinc")?;
+        self.set_option("-nostdlib")?;
+        if caps.embedded_headers {
+            self.add_sys_include_path(EMBEDDED_HEADERS);
+        }
+        self.add_sys_include_path(format!("{EMBEDDED_MUSL}/include"));
+        let lib = format!("{EMBEDDED_MUSL}/lib");
+        self.add_library_path(&lib);
+        for crt in ["crt1.o", "crti.o"] {
+            self.add_file(format!("{lib}/{crt}"))?;
+        }
+
+        let mut last = Vec::new();
+        last.push(format!("{lib}/libc.a"));
+        if caps.embedded_libraries {
+            last.push(EMBEDDED_LIBTCC1.into());
+        }
+        last.push(format!("{lib}/crtn.o"));
+        self.link_last = last
+            .into_iter()
+            .map(|file| CString::new(file).unwrap())
+            .collect();
+        Ok(self)
+    }
+
+    /// Set up building Linux objects or shared libraries for deployment
+    /// from any host, like a CI machine running Windows.
+    ///
+    /// Sets `output`, which must be [`OutputType::Obj`] or
+    /// [`OutputType::Dll`], and compiles with `-nostdinc` against tcc's and
+    /// the embedded musl's headers only. Shared libraries are linked with
+    /// `-nostdlib` and leave C library symbols to the loader of the system
+    /// they are deployed to. For executables see
+    /// [`Context::static_musl_exe`].
+    ///
+    /// Fails with [`Error::UnsupportedOption`] unless tcc targets ELF, which
+    /// Windows builds do with the `link-elf` feature of `tcc-sys`, and the
+    /// `musl` feature embeds the headers.
+    pub fn cross_linux(&mut self, output: OutputType) -> Result<&mut Self, Error> {
+        let caps = capabilities();
+        let unsupported = |reason| {
+            Err(Error::UnsupportedOption {
+                option: "cross_linux",
+                reason,
+            })
+        };
+        if caps.format != ExecutableFormat::Elf {
+            return unsupported("tcc does not target ELF, see the `link-elf` feature of tcc-sys");
+        }
+        if !caps.vfs || !caps.embedded_musl {
+            return unsupported("the Linux headers are not embedded, see the `musl` feature");
+        }
+        if !matches!(output, OutputType::Obj | OutputType::Dll) {
+            return unsupported("only objects and shared libraries are built for deployment");
+        }
+
+        self.set_output_type(output)?;
+        self.set_option("-nostdinc")?;
+        if caps.embedded_headers {
+            self.add_sys_include_path(EMBEDDED_HEADERS);
+        }
+        self.add_sys_include_path(format!("{EMBEDDED_MUSL}/include"));
+        if output == OutputType::Dll {
+            self.set_option("-nostdlib")?;
+        }
+        Ok(self)
+    }
+}
+
+impl<'err> Context<'err> {
+    /// Make `<immintrin.h>`, `<xmmintrin.h>`, `<emmintrin.h>` and
+    /// `<x86intrin.h>` available, so kernels written with SSE, SSE2, AVX and
+    /// FMA intrinsics for gcc and clang compile unchanged.
+    ///
+    /// tcc has no vector instructions; the embedded headers implement each
+    /// intrinsic lane by lane in C and define `__TCC_RS_SIMD_SCALAR`. The
+    /// square root intrinsics call `sqrtf` and `sqrt`, see
+    /// [`Context::provide_libm`]. Needs the `simd` feature, else this fails
+    /// with [`Error::UnsupportedOption`].
+    pub fn use_simd_headers(&mut self) -> Result<&mut Self, Error> {
+        let caps = capabilities();
+        if !caps.vfs || !caps.embedded_simd_headers {
+            return Err(Error::UnsupportedOption {
+                option: "simd headers",
+                reason: "the headers are not embedded, see the `simd` feature",
+            });
+        }
+        self.add_sys_include_path(EMBEDDED_SIMD);
+        Ok(self)
+    }
+}
+
+impl<'err> Context<'err> {
+    /// Use the embedded headers and libraries as tcc's own installation, so
+    /// nothing has to be installed next to the program.
+    ///
+    /// Points [`Context::set_lib_path`] at the embedded libraries, where tcc
+    /// finds `libtcc1.a`, and searches the embedded headers before the
+    /// host's; the win32 headers are searched anyway when targeting Windows.
+    /// Fails with [`Error::UnsupportedOption`] if neither is embedded, see
+    /// the `embed-headers` and `embed-libraries` features of `tcc-sys`.
+    pub fn use_embedded_sysroot(&mut self) -> Result<&mut Self, Error> {
+        let caps = capabilities();
+        if !caps.vfs || !(caps.embedded_headers || caps.embedded_libraries) {
+            return Err(Error::UnsupportedOption {
+                option: "embedded sysroot",
+                reason: "nothing is embedded, see the `vfs` feature",
+            });
+        }
+        if caps.embedded_libraries {
+            self.set_lib_path(EMBEDDED_LIBRARIES);
+        }
+        if caps.embedded_headers {
+            self.add_sys_include_path(EMBEDDED_HEADERS);
+        }
+        Ok(self)
+    }
+}
+
+/// Whether anonymous memory can be made executable after being written, as
+/// relocating in memory does; SELinux denies it to recent Android apps.
+#[cfg(unix)]
+fn can_execute_written_memory() -> bool {
+    use core::ptr::null_mut;
+
+    unsafe {
+        let page = libc::sysconf(libc::_SC_PAGESIZE).max(4096) as usize;
+        let ptr = libc::mmap(
+            null_mut(),
+            page,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        );
+        if ptr == libc::MAP_FAILED {
+            return false;
+        }
+        let executable = libc::mprotect(ptr, page, libc::PROT_READ | libc::PROT_EXEC) == 0;
+        libc::munmap(ptr, page);
+        executable
+    }
+}
+
+#[cfg(not(unix))]
+fn can_execute_written_memory() -> bool {
+    true
+}
@@ -0,0 +1,227 @@
+//! Page protections and instruction cache maintenance for relocated code.
+//!
+//! tcc writes code through ordinary data accesses. Where instruction and
+//! data caches are not coherent, as on ARM, stale instructions run unless
+//! the instruction cache is flushed afterwards, and nothing but page
+//! protections keeps code from being overwritten.
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::ffi::c_void;
+
+use tcc_sys::{tcc_rs_list_sections, SECTION_SIZES};
+
+use crate::{memory::Image, Context, Error, RelocatedCtx};
+
+/// `sh_flags` bits of interest.
+const SHF_WRITE: usize = 0x1;
+const SHF_EXECINSTR: usize = 0x4;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Access to a page, always readable.
+pub(crate) struct Access {
+    pub(crate) write:   bool,
+    pub(crate) execute: bool,
+}
+
+impl Context<'_> {
+    /// Access every page fully inside `image` needs for the sections on it;
+    /// pages shared with memory outside the image are left alone.
+    pub(crate) fn page_access(&self, image: &Image) -> Result<BTreeMap<usize, Access>, Error> {
+        if !SECTION_SIZES {
+            return Err(Error::UnsupportedOption {
+                option: "protect",
+                reason: "section addresses are only known for the vendored libtcc",
+            });
+        }
+        extern "C" fn collect(ctx: *mut c_void, addr: usize, size: usize, flags: usize) {
+            let sections = unsafe { &mut *(ctx as *mut Vec<(usize, usize, usize)>) };
+            sections.push((addr, size, flags));
+        }
+        let mut sections: Vec<(usize, usize, usize)> = Vec::new();
+        unsafe {
+            tcc_rs_list_sections(
+                self.inner,
+                &mut sections as *mut Vec<(usize, usize, usize)> as *mut c_void,
+                collect,
+            )
+        };
+
+        let page = page_size();
+        let (start, len) = image.span();
+        let first = start.div_ceil(page);
+        let end = (start + len) / page;
+        let mut pages = BTreeMap::new();
+        for (addr, size, flags) in sections {
+            let from = (addr / page).max(first);
+            let to = (addr + size).div_ceil(page).min(end);
+            for index in from..to {
+                let access: &mut Access = pages.entry(index).or_default();
+                access.write |= flags & SHF_WRITE != 0;
+                access.execute |= flags & SHF_EXECINSTR != 0;
+            }
+        }
+        Ok(pages)
+    }
+
+    /// Give the pages of `image` the access in `pages`, then flush the
+    /// instruction cache over the image.
+    pub(crate) fn apply_access(&self, image: &Image, pages: &BTreeMap<usize, Access>) {
+        let page = page_size();
+        for (&index, &access) in pages {
+            unsafe { set_access(index * page, page, access) };
+        }
+        let (start, len) = image.span();
+        unsafe { flush_icache(start, len) };
+    }
+
+    /// Final protections for `image`: code read and execute, writable data
+    /// read and write, the rest read-only. A page holding both code and
+    /// writable data stays writable and executable.
+    pub(crate) fn protect_image(&self, image: &Image) -> Result<(), Error> {
+        let pages = self.page_access(image)?;
+        self.apply_access(image, &pages);
+        Ok(())
+    }
+}
+
+impl<'a, 'err> RelocatedCtx<'a, 'err> {
+    /// Set the final page protections of the relocated code and flush the
+    /// instruction cache, as relocating already does on ARM.
+    ///
+    /// Code pages become read and execute, writable data read and write,
+    /// read-only data read-only. Only pages entirely inside the buffer the
+    /// code was relocated into are changed, so code relocated into a
+    /// [`CodePool`](crate::exec::CodePool) is covered completely. Fails
+    /// with [`Error::UnsupportedOption`] unless `tcc-sys` builds the
+    /// vendored libtcc, which knows where the sections went.
+    pub fn protect(&mut self) -> Result<(), Error> {
+        self.inner.protect_image(&self.bin)?;
+        self.frozen = false;
+        Ok(())
+    }
+
+    /// Drop write access to every page holding code, so no page of the
+    /// relocated code is writable and executable at once (W^X).
+    ///
+    /// Pages with only data keep the access of their sections, as with
+    /// [`RelocatedCtx::protect`]; writable data sharing a page with code
+    /// becomes read-only until [`RelocatedCtx::unfreeze`]. Fails like
+    /// [`RelocatedCtx::protect`].
+    pub fn freeze(&mut self) -> Result<(), Error> {
+        self.set_code_writable(false)?;
+        self.frozen = true;
+        Ok(())
+    }
+
+    /// Make the pages holding code writable again, for patching it in
+    /// place. The instruction cache is flushed when freezing again.
+    pub fn unfreeze(&mut self) -> Result<(), Error> {
+        self.set_code_writable(true)?;
+        self.frozen = false;
+        Ok(())
+    }
+
+    /// Whether [`RelocatedCtx::freeze`] was the last to change the page
+    /// protections.
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    fn set_code_writable(&mut self, write: bool) -> Result<(), Error> {
+        let mut pages = self.inner.page_access(&self.bin)?;
+        for access in pages.values_mut().filter(|access| access.execute) {
+            access.write = write;
+        }
+        self.inner.apply_access(&self.bin, &pages);
+        Ok(())
+    }
+}
+
+/// Flush the instruction cache for `len` bytes at `start` after writing
+/// code there; nothing to do where the caches are coherent.
+pub(crate) unsafe fn flush_icache(start: usize, len: usize) {
+    #[cfg(all(unix, any(target_arch = "arm", target_arch = "aarch64")))]
+    {
+        extern "C" {
+            fn __clear_cache(start: *mut c_void, end: *mut c_void);
+        }
+        __clear_cache(start as *mut c_void, (start + len) as *mut c_void);
+    }
+    #[cfg(windows)]
+    {
+        FlushInstructionCache(GetCurrentProcess(), start as *const c_void, len);
+    }
+    #[cfg(not(any(windows, all(unix, any(target_arch = "arm", target_arch = "aarch64")))))]
+    let _ = (start, len);
+}
+
+/// Make the pages entirely inside `len` bytes at `start` readable and
+/// writable again.
+pub(crate) unsafe fn make_writable(start: usize, len: usize) {
+    let page = page_size();
+    let first = start.div_ceil(page) * page;
+    let end = (start + len) / page * page;
+    if end > first {
+        set_access(first, end - first, Access::WRITABLE);
+    }
+}
+
+impl Access {
+    pub(crate) const WRITABLE: Access = Access {
+        write:   true,
+        execute: false,
+    };
+}
+
+#[cfg(unix)]
+pub(crate) fn page_size() -> usize {
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as usize,
+        _ => 4096,
+    }
+}
+
+#[cfg(unix)]
+unsafe fn set_access(start: usize, len: usize, access: Access) {
+    let mut prot = libc::PROT_READ;
+    if access.write {
+        prot |= libc::PROT_WRITE;
+    }
+    if access.execute {
+        prot |= libc::PROT_EXEC;
+    }
+    libc::mprotect(start as *mut c_void, len, prot);
+}
+
+#[cfg(windows)]
+#[link(name = "kernel32")]
+extern "system" {
+    fn VirtualProtect(address: *mut c_void, size: usize, protect: u32, old: *mut u32) -> i32;
+    fn FlushInstructionCache(process: *mut c_void, address: *const c_void, size: usize) -> i32;
+    fn GetCurrentProcess() -> *mut c_void;
+}
+
+#[cfg(windows)]
+pub(crate) fn page_size() -> usize {
+    4096
+}
+
+#[cfg(windows)]
+unsafe fn set_access(start: usize, len: usize, access: Access) {
+    let protect = match (access.write, access.execute) {
+        (false, false) => 0x02, // PAGE_READONLY
+        (true, false) => 0x04,  // PAGE_READWRITE
+        (false, true) => 0x20,  // PAGE_EXECUTE_READ
+        (true, true) => 0x40,   // PAGE_EXECUTE_READWRITE
+    };
+    let mut old = 0;
+    VirtualProtect(start as *mut c_void, len, protect, &mut old);
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn page_size() -> usize {
+    4096
+}
+
+#[cfg(not(any(unix, windows)))]
+unsafe fn set_access(_: usize, _: usize, _: Access) {}
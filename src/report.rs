@@ -0,0 +1,105 @@
+//! [`miette`] integration, enabled by the `miette` feature.
+
+use alloc::boxed::Box;
+
+use miette::{LabeledSpan, SourceCode};
+
+use crate::{Diagnostic, Error, Severity};
+
+fn severity(severity: Severity) -> miette::Severity {
+    match severity {
+        Severity::Error => miette::Severity::Error,
+        Severity::Warning => miette::Severity::Warning,
+    }
+}
+
+/// Byte span of line `line` (1-based) of `code`, without its terminator.
+fn line_span(code: &str, line: u32) -> Option<(usize, usize)> {
+    let mut offset = 0;
+    for (index, text) in code.split_inclusive('\n').enumerate() {
+        if index + 1 == line as usize {
+            return Some((offset, text.trim_end_matches(['\r', '\n']).len()));
+        }
+        offset += text.len();
+    }
+    None
+}
+
+impl miette::Diagnostic for Diagnostic {
+    fn severity(&self) -> Option<miette::Severity> {
+        Some(severity(self.severity))
+    }
+}
+
+impl miette::Diagnostic for Error {
+    fn code<'a>(&'a self) -> Option<Box<dyn core::fmt::Display + 'a>> {
+        let code = match self {
+            Error::OutOfMemory => "tcc::out_of_memory",
+            Error::Compile { .. } => "tcc::compile",
+            Error::IncludeNotFound { .. } => "tcc::include_not_found",
+            Error::BannedFunction { .. } => "tcc::banned_function",
+            Error::SourceLimitExceeded { .. } => "tcc::source_limit_exceeded",
+            Error::Encoding(_) => "tcc::encoding",
+            Error::LibraryNotFound { .. } => "tcc::library_not_found",
+            Error::LinkerScript { .. } => "tcc::linker_script",
+            Error::Relocate => "tcc::relocate",
+            Error::Output => "tcc::output",
+            Error::SymbolNotFound(_) => "tcc::symbol_not_found",
+            Error::InvalidEntry { .. } => "tcc::invalid_entry",
+            Error::UnsupportedOption { .. } => "tcc::unsupported_option",
+            Error::InvalidState { .. } => "tcc::invalid_state",
+            Error::PluginAbiMismatch { .. } => "tcc::plugin_abi_mismatch",
+            Error::OutputTooLarge { .. } => "tcc::output_too_large",
+            Error::PoolExhausted { .. } => "tcc::pool_exhausted",
+            Error::IncompatibleLibtcc(_) => "tcc::incompatible_libtcc",
+            Error::Io(_) => "tcc::io",
+        };
+        Some(Box::new(code))
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        match self {
+            Error::Compile {
+                code: Some(code), ..
+            } => Some(code as &dyn SourceCode),
+            _ => None,
+        }
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        let Error::Compile {
+            code: Some(code),
+            diagnostics,
+        } = self
+        else {
+            return None;
+        };
+        // only diagnostics pointing into the compiled string itself
+        let labels = diagnostics.iter().filter_map(move |diag| {
+            let loc = diag
+                .location
+                .as_ref()
+                .filter(|_| diag.included_from.is_empty())?;
+            let (offset, len) = line_span(code, loc.line)?;
+            Some(LabeledSpan::new(Some(diag.message.clone()), offset, len))
+        });
+        Some(Box::new(labels))
+    }
+
+    fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn miette::Diagnostic> + 'a>> {
+        match self {
+            Error::Compile { diagnostics, .. }
+            | Error::IncludeNotFound { diagnostics, .. }
+            | Error::BannedFunction { diagnostics, .. }
+                if !diagnostics.is_empty() =>
+            {
+                Some(Box::new(
+                    diagnostics
+                        .iter()
+                        .map(|diag| diag as &dyn miette::Diagnostic),
+                ))
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,212 @@
+//! Checks on the preprocessor output of sources before they are compiled:
+//! [banned functions](Context::set_banned_functions),
+//! [inline assembly](Context::allow_inline_asm) and the
+//! [token count](Context::set_source_limits).
+//!
+//! Unlike the GNU extension check, these see what the preprocessor makes of
+//! a source, so uses hidden behind macros or in inline functions of included
+//! headers are found too.
+
+use alloc::{
+    ffi::CString,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use tcc_sys::PREPROCESS_OUTPUT;
+
+use crate::{banned, dialect::tokens, limits, Context, Error, SourceLimit, SourceLimits};
+
+static INLINE_ASM: AtomicBool = AtomicBool::new(true);
+
+/// Whether contexts created from now on accept inline assembly, see
+/// [`Context::allow_inline_asm`]. Forbidding it fails with
+/// [`Error::UnsupportedOption`] where the check is unavailable.
+pub fn set_inline_asm_default(allow: bool) -> Result<(), Error> {
+    if !allow {
+        needs_preprocessor("inline asm")?;
+    }
+    INLINE_ASM.store(allow, Ordering::Relaxed);
+    Ok(())
+}
+
+/// The default set with [`set_inline_asm_default`], initially true.
+pub fn inline_asm_default() -> bool {
+    INLINE_ASM.load(Ordering::Relaxed)
+}
+
+fn needs_preprocessor(option: &'static str) -> Result<(), Error> {
+    match PREPROCESS_OUTPUT {
+        true => Ok(()),
+        false => {
+            Err(Error::UnsupportedOption {
+                option,
+                reason: "only the vendored libtcc can preprocess sources for the check",
+            })
+        }
+    }
+}
+
+/// Tokens of preprocessor output, without line markers, and where the
+/// lines came from.
+pub(crate) struct Scan<'a> {
+    /// output line and text
+    pub(crate) tokens: Vec<(usize, &'a str)>,
+    /// file and line of each output line, following `# 12 "file.c"`
+    origin:            Vec<(String, usize)>,
+}
+
+impl<'a> Scan<'a> {
+    fn new(output: &'a str) -> Self {
+        let mut origin = Vec::new();
+        let mut markers = Vec::new();
+        let (mut file, mut line) = (String::from("<string>"), 1);
+        for (index, text) in output.lines().enumerate() {
+            origin.push((file.clone(), line));
+            line += 1;
+            let Some(marker) = text.strip_prefix('#') else {
+                continue;
+            };
+            markers.push(index + 1);
+            let mut marker = marker.trim_start().splitn(2, ' ');
+            if let (Some(number), Some(name)) = (marker.next(), marker.next()) {
+                if let (Ok(number), Some(name)) = (number.parse(), name.split('"').nth(1)) {
+                    (file, line) = (name.to_string(), number);
+                }
+            }
+        }
+        let tokens = tokens(output)
+            .filter(|(line, _)| markers.binary_search(line).is_err())
+            .collect();
+        Scan { tokens, origin }
+    }
+
+    /// The token `offset` tokens away from `index`, empty past either end.
+    pub(crate) fn token(&self, index: usize, offset: isize) -> &'a str {
+        index
+            .checked_add_signed(offset)
+            .and_then(|index| self.tokens.get(index))
+            .map_or("", |&(_, token)| token)
+    }
+
+    /// File and line token `index` came from.
+    fn origin(&self, index: usize) -> (&str, usize) {
+        let line = self.tokens[index].0;
+        self.origin
+            .get(line - 1)
+            .map_or(("<string>", line), |(file, line)| (file.as_str(), *line))
+    }
+}
+
+/// Index of every token of `scan` starting inline assembly.
+///
+/// Statements in function bodies and top-level blocks are; `asm` labels
+/// naming the symbol of a declaration, as C library headers use, are not.
+fn inline_asm(scan: &Scan) -> Vec<usize> {
+    let mut found = Vec::new();
+    let mut depth = 0usize;
+    for (index, &(_, token)) in scan.tokens.iter().enumerate() {
+        match token {
+            "{" => depth += 1,
+            "}" => depth = depth.saturating_sub(1),
+            "asm" | "__asm" | "__asm__"
+                if depth > 0 || matches!(scan.token(index, -1), "" | ";" | "}") =>
+            {
+                found.push(index)
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+impl<'err> Context<'err> {
+    /// Accept inline assembly in sources or not; contexts start with
+    /// [`inline_asm_default`].
+    ///
+    /// `asm` statements and top-level `asm` blocks can do anything, whatever
+    /// else a sandbox restricts. When forbidden, sources passed as text are
+    /// preprocessed in a [fork](Context::fork) of this context before they
+    /// are compiled, and every `asm` block fails the compilation with an
+    /// error diagnostic naming the line. `asm` labels on declarations stay
+    /// allowed. Forbidding needs the vendored libtcc to preprocess, else
+    /// this fails with [`Error::UnsupportedOption`].
+    pub fn allow_inline_asm(&mut self, allow: bool) -> Result<&mut Self, Error> {
+        if !allow {
+            needs_preprocessor("inline asm")?;
+        }
+        self.inline_asm = allow;
+        Ok(self)
+    }
+
+    /// Run the checks on the preprocessor output of `source`, called
+    /// `file`.
+    pub(crate) fn screen(&mut self, file: &str, source: &str) -> Result<(), Error> {
+        let max_tokens = self.source_limits.max_tokens;
+        if self.banned.is_empty() && self.inline_asm && max_tokens.is_none() {
+            return Ok(());
+        }
+        let mut scratch = self.fork()?;
+        scratch.banned.clear();
+        scratch.inline_asm = true;
+        scratch.source_limits = SourceLimits::default();
+        scratch.sink.user = None;
+        scratch.sink.collector.enabled = true;
+        // the check fails closed: a source that does not preprocess is
+        // rejected, reporting what the fork found wrong with it
+        let output = match scratch.preprocess_str(source) {
+            Ok(output) => output,
+            Err(err) => {
+                for diag in scratch.diagnostics() {
+                    let message = diag.to_string().replace('\0', "");
+                    self.sink.dispatch(&CString::new(message).unwrap());
+                }
+                return Err(err);
+            }
+        };
+        let scan = Scan::new(&output);
+        limits::exceeds(SourceLimit::Tokens, scan.tokens.len(), max_tokens)?;
+
+        let mut found = Vec::new();
+        let banned = banned::uses(&self.banned, &scan);
+        for &index in &banned {
+            let name = scan.tokens[index].1;
+            found.push((index, format!("call to banned function '{name}'")));
+        }
+        if !self.inline_asm {
+            for index in inline_asm(&scan) {
+                found.push((index, String::from("inline assembly not allowed")));
+            }
+        }
+        if found.is_empty() {
+            return Ok(());
+        }
+        found.sort_by_key(|&(index, _)| index);
+
+        let start = self.diagnostics().len();
+        for (index, message) in found {
+            let (origin, line) = scan.origin(index);
+            let origin = if origin == "<string>" { file } else { origin };
+            let message = format!("{origin}:{line}: error: {message}");
+            self.sink
+                .dispatch(&CString::new(message.replace('\0', "")).unwrap());
+        }
+        let diagnostics = self.diagnostics()[start..].to_vec();
+        Err(match banned.first() {
+            Some(&index) => {
+                Error::BannedFunction {
+                    function: scan.tokens[index].1.to_string(),
+                    diagnostics,
+                }
+            }
+            None => {
+                Error::Compile {
+                    code: Some(String::from(source)),
+                    diagnostics,
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,111 @@
+//! Why `-l` or `#include` found nothing: the directories and file names
+//! tcc tried.
+
+use alloc::{format, string::String, vec::Vec};
+use core::ffi::{c_char, c_void, CStr};
+
+use tcc_sys::{tcc_rs_include_search, tcc_rs_library_search, SECTION_SIZES};
+
+use crate::{capabilities, config::Setting, Context, Diagnostic, Error, ExecutableFormat};
+
+extern "C" fn push_dir(ctx: *mut c_void, dir: *const c_char) {
+    let dirs = unsafe { &mut *(ctx as *mut Vec<String>) };
+    dirs.push(
+        unsafe { CStr::from_ptr(dir) }
+            .to_string_lossy()
+            .into_owned(),
+    );
+}
+
+/// File names `tcc_add_library` looks for `name` under, in order.
+fn candidates(name: &str, static_only: bool) -> Vec<String> {
+    let (names, shared): (&[(&str, &str)], usize) = match capabilities().format {
+        ExecutableFormat::Pe => {
+            (
+                &[
+                    ("", ".def"),
+                    ("lib", ".def"),
+                    ("", ".dll"),
+                    ("lib", ".dll"),
+                    ("lib", ".a"),
+                ],
+                4,
+            )
+        }
+        ExecutableFormat::MachO => (&[("lib", ".dylib"), ("lib", ".tbd"), ("lib", ".a")], 2),
+        ExecutableFormat::Elf => (&[("lib", ".so"), ("lib", ".a")], 1),
+    };
+    let names = if static_only { &names[shared..] } else { names };
+    names
+        .iter()
+        .map(|(prefix, suffix)| format!("{prefix}{name}{suffix}"))
+        .collect()
+}
+
+impl<'err> Context<'err> {
+    /// [`Error::LibraryNotFound`] for library `name`, with where tcc
+    /// looked.
+    ///
+    /// Only the vendored libtcc tells its default directories and whether
+    /// it links statically; elsewhere the directories are the ones added
+    /// with [`Context::add_library_path`].
+    pub(crate) fn library_not_found(&self, name: &CStr) -> Error {
+        let mut search_paths = Vec::new();
+        let static_only = match SECTION_SIZES {
+            true => {
+                let ctx = &mut search_paths as *mut Vec<String> as *mut c_void;
+                unsafe { tcc_rs_library_search(self.inner, ctx, push_dir) != 0 }
+            }
+            false => {
+                for setting in &self.config {
+                    if let Setting::LibraryPath(path) = setting {
+                        search_paths.push(path.to_string_lossy().into_owned());
+                    }
+                }
+                false
+            }
+        };
+        let name = name.to_string_lossy().into_owned();
+        Error::LibraryNotFound {
+            candidates: candidates(&name, static_only),
+            name,
+            search_paths,
+        }
+    }
+
+    /// [`Error::IncludeNotFound`] for `header`, with where tcc looked and
+    /// the diagnostics of the failed compilation.
+    ///
+    /// Only the vendored libtcc tells its default directories; elsewhere
+    /// they are the ones added with [`Context::add_include_path`] and
+    /// [`Context::add_sys_include_path`].
+    pub(crate) fn include_not_found(&self, header: String, diagnostics: Vec<Diagnostic>) -> Error {
+        let mut search_paths = Vec::new();
+        match SECTION_SIZES {
+            true => {
+                let ctx = &mut search_paths as *mut Vec<String> as *mut c_void;
+                unsafe { tcc_rs_include_search(self.inner, ctx, push_dir) };
+            }
+            false => {
+                let (mut user, mut system) = (Vec::new(), Vec::new());
+                for setting in &self.config {
+                    match setting {
+                        Setting::IncludePath(path) => {
+                            user.push(path.to_string_lossy().into_owned())
+                        }
+                        Setting::SysIncludePath(path) => {
+                            system.push(path.to_string_lossy().into_owned())
+                        }
+                        _ => {}
+                    }
+                }
+                search_paths = [user, system].concat();
+            }
+        }
+        Error::IncludeNotFound {
+            header,
+            search_paths,
+            diagnostics,
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Host implementations handed to compiled code through `tcc_add_symbol`.
+//!
+//! Shims are registered right before relocation and only for symbols the
+//! compiled code does not define itself, so user definitions always win.
+
+use alloc::{collections::BTreeSet, ffi::CString, sync::Arc, vec::Vec};
+use core::{
+    any::Any,
+    ffi::{c_char, c_int, c_void, CStr},
+    ptr::null,
+};
+
+use tcc_sys::{tcc_add_symbol, tcc_compile_string, tcc_list_symbols, tcc_rs_list_exports};
+
+use crate::{capabilities, map_c_ret, Atomics, Context, Error};
+
+mod atomic;
+mod env;
+mod heap;
+mod libm;
+#[cfg(feature = "std")] mod pthread;
+mod stdio;
+
+pub use env::Env;
+pub(crate) use env::Environ;
+pub use heap::{ArenaHandle, HeapError};
+pub use stdio::{StdStream, StdioSink};
+
+pub(crate) type KeepAlive = Arc<dyn Any + Send + Sync>;
+
+#[derive(Default)]
+/// Which shims a context wants.
+pub(crate) struct Shims {
+    pub(crate) libm:       bool,
+    pub(crate) heap:       Option<ArenaHandle>,
+    pub(crate) stdio:      Option<StdioSink>,
+    #[cfg(feature = "std")]
+    pub(crate) pthreads:   Option<Arc<pthread::Threads>>,
+    pub(crate) env:        Option<Env>,
+    /// `env` as handed to the compiled code once relocated
+    pub(crate) environ:    Option<Arc<Environ>>,
+    /// state the injected code points into, handed on to a
+    /// [`Module`](crate::Module)
+    pub(crate) keep_alive: Vec<KeepAlive>,
+}
+
+impl<'err> Context<'err> {
+    /// Make the host's math functions (`sin`, `pow`, `sqrtf`, ...) available
+    /// to in-memory code, so relocation does not depend on libm being
+    /// resolvable through the dynamic loader.
+    ///
+    /// File outputs are unaffected, they link libm like any other library.
+    pub fn provide_libm(&mut self, enable: bool) -> &mut Self {
+        self.shims.libm = enable;
+        self
+    }
+
+    /// Serve `malloc`, `calloc`, `realloc` and `free` of the compiled code
+    /// from `arena` instead of the C library.
+    ///
+    /// Pointers from other allocators, such as those returned by `strdup`
+    /// of the C library, must not be passed to the replaced `free`; they are
+    /// ignored there.
+    pub fn use_host_allocator(&mut self, arena: ArenaHandle) -> &mut Self {
+        self.shims.keep_alive.push(arena.keep_alive());
+        self.shims.heap = Some(arena);
+        self
+    }
+
+    /// Send `printf`, `fprintf`, `puts` and `putchar` of the compiled code to
+    /// `sink` instead of the C library, so output can be captured and does not
+    /// depend on a libc being available.
+    ///
+    /// `fprintf` to `stderr` lands in the sink's stderr, any other stream in
+    /// its stdout. Formatting is done in Rust and supports the C99 conversions
+    /// except `%a`, which is printed as is, and `%n`, which is ignored.
+    pub fn capture_stdio(&mut self, sink: StdioSink) -> &mut Self {
+        self.shims.keep_alive.push(sink.keep_alive());
+        self.shims.stdio = Some(sink);
+        self
+    }
+
+    /// Provide `pthread_create`, `pthread_join` and the `pthread_mutex_*`
+    /// functions to compiled code, backed by `std::thread`, so code that
+    /// spawns workers runs the same where the platform has no pthreads.
+    ///
+    /// Only these functions are provided; attributes are ignored, and
+    /// `pthread_t` and `pthread_mutex_t` objects are only used as handles, so
+    /// their layout does not matter. Threads must be joined before the code
+    /// is dropped.
+    #[cfg(feature = "std")]
+    pub fn provide_pthreads(&mut self, enable: bool) -> &mut Self {
+        self.shims.pthreads = enable.then(|| {
+            let threads = Arc::new(pthread::Threads::default());
+            self.shims.keep_alive.push(threads.clone());
+            threads
+        });
+        self
+    }
+
+    /// Give compiled code the variables of `env` instead of the host's
+    /// environment.
+    ///
+    /// `getenv`, `environ` and the `envp` that
+    /// [`RelocatedCtx::run_main`](crate::RelocatedCtx::run_main) passes to
+    /// `main` all see `env`, as it is when the code is relocated. Functions
+    /// of the C library that read the environment themselves, such as
+    /// `setlocale`, are not affected.
+    pub fn set_env(&mut self, env: Env) -> &mut Self {
+        self.shims.env = Some(env);
+        self
+    }
+
+    /// Register the requested shims, right before relocating.
+    pub(crate) fn inject_shims(&mut self) -> Result<(), Error> {
+        let shims = &self.shims;
+        #[cfg(feature = "std")]
+        let pthreads = shims.pthreads.is_some();
+        #[cfg(not(feature = "std"))]
+        let pthreads = false;
+        let atomics = capabilities().atomics == Atomics::Fallback;
+        if !shims.libm
+            && shims.heap.is_none()
+            && shims.stdio.is_none()
+            && !pthreads
+            && !atomics
+            && shims.env.is_none()
+        {
+            return Ok(());
+        }
+
+        extern "C" fn collect(ctx: *mut c_void, name: *const c_char, _: *const c_void) {
+            let defined = unsafe { &mut *(ctx as *mut BTreeSet<CString>) };
+            defined.insert(unsafe { CStr::from_ptr(name) }.to_owned());
+        }
+        extern "C" fn collect_export(ctx: *mut c_void, name: *const c_char, _: c_int) {
+            collect(ctx, name, null());
+        }
+        // before relocation tcc_list_symbols skips symbols at offset 0 of
+        // their section, like the first function; it still sees those added
+        // with tcc_add_symbol, and stands in for the exports where they
+        // cannot be listed
+        let mut defined = BTreeSet::new();
+        let ctx = &mut defined as *mut BTreeSet<CString> as *mut c_void;
+        unsafe {
+            tcc_rs_list_exports(self.inner, ctx, collect_export);
+            tcc_list_symbols(self.inner, ctx, Some(collect));
+        };
+
+        let inner = self.inner;
+        let mut add = |name: &CStr, addr: *const c_void| {
+            if !defined.contains(name) {
+                unsafe { tcc_add_symbol(inner, name.as_ptr(), addr) };
+            }
+        };
+        if atomics {
+            atomic::symbols(&mut add);
+        }
+        if self.shims.libm {
+            libm::double(&mut add);
+            libm::float(&mut add);
+        }
+        let mut sources = Vec::new();
+        let wanted = |name: &str| {
+            !defined
+                .iter()
+                .any(|sym: &CString| sym.as_bytes() == name.as_bytes())
+        };
+        if let Some(arena) = &self.shims.heap {
+            heap::symbols(&mut add);
+            sources.push(heap::source(arena, wanted));
+        }
+        if let Some(sink) = &self.shims.stdio {
+            stdio::symbols(&mut add);
+            sources.push(stdio::source(sink, wanted));
+        }
+        #[cfg(feature = "std")]
+        if let Some(threads) = &self.shims.pthreads {
+            pthread::symbols(&mut add);
+            sources.push(pthread::source(threads, wanted));
+        }
+        if let Some(env) = &self.shims.env {
+            let environ = Environ::new(env)?;
+            env::symbols(&environ, &mut add);
+            sources.push(env::source(&environ, wanted));
+            self.shims.keep_alive.push(environ.clone());
+            self.shims.environ = Some(environ);
+        }
+        for source in sources {
+            let source = CString::new(source).unwrap();
+            let ret = unsafe { tcc_compile_string(inner, source.as_ptr()) };
+            map_c_ret(ret).map_err(|()| Error::Relocate)?;
+        }
+        Ok(())
+    }
+}
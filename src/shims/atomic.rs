@@ -0,0 +1,101 @@
+//! Host side of the fallback `stdatomic.h`, see
+//! [`Atomics::Fallback`](crate::Atomics::Fallback).
+
+use core::{
+    ffi::{c_int, c_void, CStr},
+    ptr::{read_unaligned, write_unaligned},
+    sync::atomic::{fence, AtomicU16, AtomicU32, AtomicU8, Ordering::SeqCst},
+};
+
+// keep in sync with `tcc-sys/fallback/stdatomic.h`
+const LOAD: c_int = 0;
+const SWAP: c_int = 1;
+const ADD: c_int = 2;
+const SUB: c_int = 3;
+const AND: c_int = 4;
+const OR: c_int = 5;
+const XOR: c_int = 6;
+const FENCE: c_int = 7;
+
+macro_rules! rmw {
+    ($atomic:ty, $int:ty, $ptr:expr, $op:expr, $value:expr, $old:expr) => {{
+        let atomic = unsafe { <$atomic>::from_ptr($ptr as *mut $int) };
+        let value = unsafe { read_unaligned($value as *const $int) };
+        let old = match $op {
+            LOAD => atomic.load(SeqCst),
+            SWAP => atomic.swap(value, SeqCst),
+            ADD => atomic.fetch_add(value, SeqCst),
+            SUB => atomic.fetch_sub(value, SeqCst),
+            AND => atomic.fetch_and(value, SeqCst),
+            OR => atomic.fetch_or(value, SeqCst),
+            XOR => atomic.fetch_xor(value, SeqCst),
+            _ => unreachable!("unknown atomic operation {}", $op),
+        };
+        unsafe { write_unaligned($old as *mut $int, old) };
+    }};
+}
+
+macro_rules! cas {
+    ($atomic:ty, $int:ty, $ptr:expr, $expected:expr, $desired:expr) => {{
+        let atomic = unsafe { <$atomic>::from_ptr($ptr as *mut $int) };
+        let expected = $expected as *mut $int;
+        let desired = unsafe { read_unaligned($desired as *const $int) };
+        match atomic.compare_exchange(unsafe { read_unaligned(expected) }, desired, SeqCst, SeqCst)
+        {
+            Ok(_) => 1,
+            Err(actual) => {
+                unsafe { write_unaligned(expected, actual) };
+                0
+            }
+        }
+    }};
+}
+
+/// `op` on the `size` byte object at `ptr` with the bits at `value`, writing
+/// the previous bits to `old`, or a full fence.
+///
+/// The header rejects other sizes at compile time.
+extern "C" fn atomic_rmw(
+    ptr: *mut c_void,
+    size: usize,
+    op: c_int,
+    value: *const c_void,
+    old: *mut c_void,
+) {
+    if op == FENCE {
+        fence(SeqCst);
+        return;
+    }
+    match size {
+        1 => rmw!(AtomicU8, u8, ptr, op, value, old),
+        2 => rmw!(AtomicU16, u16, ptr, op, value, old),
+        4 => rmw!(AtomicU32, u32, ptr, op, value, old),
+        #[cfg(target_has_atomic = "64")]
+        8 => rmw!(core::sync::atomic::AtomicU64, u64, ptr, op, value, old),
+        _ => unreachable!("atomic access to {size} bytes"),
+    }
+}
+
+/// Compare the bits at `ptr` with those at `expected` and exchange them for
+/// those at `desired`, writing the current bits to `expected` on failure.
+extern "C" fn atomic_cas(
+    ptr: *mut c_void,
+    size: usize,
+    expected: *mut c_void,
+    desired: *const c_void,
+) -> c_int {
+    match size {
+        1 => cas!(AtomicU8, u8, ptr, expected, desired),
+        2 => cas!(AtomicU16, u16, ptr, expected, desired),
+        4 => cas!(AtomicU32, u32, ptr, expected, desired),
+        #[cfg(target_has_atomic = "64")]
+        8 => cas!(core::sync::atomic::AtomicU64, u64, ptr, expected, desired),
+        _ => unreachable!("atomic access to {size} bytes"),
+    }
+}
+
+/// Rust entry points, registered under these names.
+pub(super) fn symbols(mut f: impl FnMut(&CStr, *const c_void)) {
+    f(c"__tcc_rs_atomic_rmw", atomic_rmw as *const c_void);
+    f(c"__tcc_rs_atomic_cas", atomic_cas as *const c_void);
+}
@@ -0,0 +1,148 @@
+//! The environment seen by compiled code, through `main`'s third parameter,
+//! `getenv` and `environ`.
+
+use alloc::{collections::BTreeMap, ffi::CString, format, string::String, sync::Arc, vec::Vec};
+use core::{
+    ffi::{c_char, c_void, CStr},
+    ptr::null_mut,
+};
+
+use crate::{jit, Error};
+
+/// Environment variables of compiled code, see
+/// [`Context::set_env`](crate::Context::set_env) and [`run_main_with_env`].
+///
+/// Starts out empty, so nothing of the host's environment, such as tokens or
+/// proxy settings, reaches the program unless asked for.
+///
+/// ```
+/// use tcc::Env;
+///
+/// let env = Env::new().set("LANG", "C").set("HOME", "/nonexistent");
+/// ```
+///
+/// [`run_main_with_env`]: crate::run_main_with_env
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Env {
+    inherit: bool,
+    /// `None` removes an inherited variable
+    vars:    BTreeMap<String, Option<String>>,
+}
+
+impl Env {
+    /// An empty environment.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start from the host's environment as it is when the code is
+    /// relocated, with [`Env::set`] and [`Env::unset`] applied on top.
+    #[cfg(feature = "std")]
+    pub fn inherit(mut self) -> Self {
+        self.inherit = true;
+        self
+    }
+
+    /// Drop every variable, inherited or set so far.
+    pub fn clear(mut self) -> Self {
+        self.inherit = false;
+        self.vars.clear();
+        self
+    }
+
+    /// Set `name` to `value`, replacing an inherited value.
+    pub fn set(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.vars.insert(name.into(), Some(value.into()));
+        self
+    }
+
+    /// Remove `name`, whether inherited or set before.
+    pub fn unset(mut self, name: impl Into<String>) -> Self {
+        self.vars.insert(name.into(), None);
+        self
+    }
+
+    /// The variables as `NAME=value` strings, sorted by name.
+    pub(crate) fn entries(&self) -> Vec<String> {
+        let mut vars = BTreeMap::new();
+        #[cfg(feature = "std")]
+        if self.inherit {
+            // variables that are not valid UTF-8 can not be represented here
+            vars.extend(std::env::vars().map(|(name, value)| (name, Some(value))));
+        }
+        vars.extend(self.vars.clone());
+        vars.into_iter()
+            .filter_map(|(name, value)| Some(format!("{name}={}", value?)))
+            .collect()
+    }
+}
+
+/// The variables of an [`Env`] laid out the way C expects them.
+pub(crate) struct Environ {
+    strings: Vec<CString>,
+    array:   Vec<*mut c_char>,
+    /// what `environ` of the compiled code points to
+    environ: *mut *mut c_char,
+}
+
+// the pointers only point into `strings`, which are never changed
+unsafe impl Send for Environ {}
+unsafe impl Sync for Environ {}
+
+impl Environ {
+    pub(crate) fn new(env: &Env) -> Result<Arc<Self>, Error> {
+        let strings = jit::c_strings(env.entries())?;
+        let mut array = jit::null_terminated(&strings);
+        let environ = array.as_mut_ptr();
+        Ok(Arc::new(Environ {
+            strings,
+            array,
+            environ,
+        }))
+    }
+
+    /// `envp` for `main`.
+    pub(crate) fn envp(&self) -> *mut *mut c_char {
+        self.array.as_ptr() as *mut *mut c_char
+    }
+
+    fn get(&self, name: &[u8]) -> *mut c_char {
+        self.strings
+            .iter()
+            .find_map(|var| {
+                let value = var.as_bytes().strip_prefix(name)?.strip_prefix(b"=")?;
+                Some(value.as_ptr() as *mut c_char)
+            })
+            .unwrap_or(null_mut())
+    }
+}
+
+extern "C" fn env_get(environ: *const c_void, name: *const c_char) -> *mut c_char {
+    if name.is_null() {
+        return null_mut();
+    }
+    let environ = unsafe { &*(environ as *const Environ) };
+    environ.get(unsafe { CStr::from_ptr(name) }.to_bytes())
+}
+
+/// Rust entry points and the `environ` variable, registered under these
+/// names.
+pub(super) fn symbols(environ: &Arc<Environ>, mut f: impl FnMut(&CStr, *const c_void)) {
+    f(c"__tcc_rs_getenv", env_get as *const c_void);
+    let variable = &environ.environ as *const *mut *mut c_char as *const c_void;
+    f(c"environ", variable);
+    f(c"__environ", variable);
+}
+
+/// C definition of `getenv` if `wanted`, reading `environ`.
+pub(super) fn source(environ: &Arc<Environ>, wanted: impl Fn(&str) -> bool) -> String {
+    if !wanted("getenv") {
+        return String::new();
+    }
+    let state = format!("(void *){:#x}ULL", Arc::as_ptr(environ) as usize);
+    concat!(
+        "char *__tcc_rs_getenv(void *, const char *);\n",
+        "char *getenv(const char *n) { return __tcc_rs_getenv(STATE, n); }\n",
+    )
+    .replace("STATE", &state)
+}
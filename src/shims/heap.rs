@@ -0,0 +1,338 @@
+//! `malloc` and friends for compiled code, served from a Rust-side arena.
+
+use alloc::{
+    alloc::{alloc, alloc_zeroed, dealloc, realloc},
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::String,
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    alloc::Layout,
+    ffi::{c_void, CStr},
+    fmt,
+    ptr::null_mut,
+};
+
+use spin::Mutex;
+
+/// Alignment of every block, enough for any C type `malloc` has to serve.
+const ALIGN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Misuse of the arena by compiled code, recorded in debug mode.
+///
+/// `site` is the return address of the offending `malloc`, `free`, ... call.
+pub enum HeapError {
+    /// Still allocated when the context or module using the arena was dropped.
+    Leak {
+        ptr:  usize,
+        size: usize,
+        site: usize,
+    },
+
+    /// `free` or `realloc` of a block that was already freed.
+    DoubleFree { ptr: usize, site: usize },
+
+    /// `free` or `realloc` of a pointer this arena never handed out.
+    InvalidFree { ptr: usize, site: usize },
+}
+
+impl fmt::Display for HeapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeapError::Leak { ptr, size, site } => {
+                write!(
+                    f,
+                    "{size} bytes at {ptr:#x} allocated from {site:#x} leaked"
+                )
+            }
+            HeapError::DoubleFree { ptr, site } => {
+                write!(f, "double free of {ptr:#x} from {site:#x}")
+            }
+            HeapError::InvalidFree { ptr, site } => {
+                write!(f, "free of unknown pointer {ptr:#x} from {site:#x}")
+            }
+        }
+    }
+}
+
+impl core::error::Error for HeapError {}
+
+#[derive(Clone, Copy)]
+struct Block {
+    size: usize,
+    site: usize,
+}
+
+#[derive(Default)]
+struct Heap {
+    /// live blocks by address
+    blocks: BTreeMap<usize, Block>,
+    bytes:  usize,
+    peak:   usize,
+    total:  usize,
+    debug:  bool,
+    /// addresses freed and not handed out again, to tell double frees apart
+    freed:  BTreeSet<usize>,
+    errors: Vec<HeapError>,
+}
+
+impl Heap {
+    fn insert(&mut self, ptr: *mut u8, size: usize, site: *const c_void) -> *mut c_void {
+        if !ptr.is_null() {
+            let site = site as usize;
+            self.blocks.insert(ptr as usize, Block { size, site });
+            self.bytes += size;
+            self.total += 1;
+            self.peak = self.peak.max(self.bytes);
+            if self.debug {
+                self.freed.remove(&(ptr as usize));
+            }
+        }
+        ptr as *mut c_void
+    }
+
+    /// Forget the block at `ptr`, reporting misuse in debug mode.
+    fn remove(&mut self, ptr: *mut c_void, site: *const c_void) -> Option<Block> {
+        let (ptr, site) = (ptr as usize, site as usize);
+        match self.blocks.remove(&ptr) {
+            Some(block) => {
+                self.bytes -= block.size;
+                if self.debug {
+                    self.freed.insert(ptr);
+                }
+                Some(block)
+            }
+            None => {
+                if self.debug {
+                    self.errors.push(match self.freed.contains(&ptr) {
+                        true => HeapError::DoubleFree { ptr, site },
+                        false => HeapError::InvalidFree { ptr, site },
+                    });
+                }
+                None
+            }
+        }
+    }
+
+    fn report_leaks(&mut self) {
+        let leaks = self.blocks.iter().map(|(&ptr, block)| {
+            HeapError::Leak {
+                ptr,
+                size: block.size,
+                site: block.site,
+            }
+        });
+        self.errors.extend(leaks);
+    }
+}
+
+fn layout(size: usize) -> Option<Layout> {
+    // `malloc(0)` still hands out a unique pointer
+    Layout::from_size_align(size.max(1), ALIGN).ok()
+}
+
+#[derive(Clone, Default)]
+/// Arena backing `malloc`, `calloc`, `realloc` and `free` of compiled code,
+/// see [`Context::use_host_allocator`](crate::Context::use_host_allocator).
+///
+/// Every block is tracked, so the arena can report what a script uses and
+/// release everything it leaked in one go. Memory is returned when the last
+/// handle is gone; contexts and modules using the arena hold a handle.
+pub struct ArenaHandle(Arc<Mutex<Heap>>);
+
+impl ArenaHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Arena that records [`HeapError`]s: double and invalid frees as they
+    /// happen, leaks when a context or module using it is dropped.
+    ///
+    /// Misuse is harmless either way, as blocks the arena does not know are
+    /// never released; debug mode only makes it visible.
+    pub fn with_debug() -> Self {
+        let arena = Self::default();
+        arena.0.lock().debug = true;
+        arena
+    }
+
+    /// Take the errors recorded so far, see [`ArenaHandle::with_debug`].
+    pub fn take_errors(&self) -> Vec<HeapError> {
+        core::mem::take(&mut self.0.lock().errors)
+    }
+
+    /// Bytes currently allocated.
+    pub fn allocated_bytes(&self) -> usize {
+        self.0.lock().bytes
+    }
+
+    /// Highest value [`ArenaHandle::allocated_bytes`] ever reached.
+    pub fn peak_bytes(&self) -> usize {
+        self.0.lock().peak
+    }
+
+    /// Number of blocks not freed yet.
+    pub fn live_allocations(&self) -> usize {
+        self.0.lock().blocks.len()
+    }
+
+    /// Number of blocks ever allocated.
+    pub fn total_allocations(&self) -> usize {
+        self.0.lock().total
+    }
+
+    /// Free every live block.
+    ///
+    /// # Safety
+    /// Compiled code must not touch memory it got from this arena afterwards.
+    pub unsafe fn free_all(&self) {
+        let mut heap = self.0.lock();
+        for (ptr, block) in core::mem::take(&mut heap.blocks) {
+            dealloc(ptr as *mut u8, layout(block.size).unwrap());
+        }
+        heap.bytes = 0;
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const c_void {
+        Arc::as_ptr(&self.0) as *const c_void
+    }
+
+    /// What a context or module using the arena keeps, checking for leaks
+    /// when dropped in debug mode.
+    pub(crate) fn keep_alive(&self) -> Arc<dyn core::any::Any + Send + Sync> {
+        Arc::new(LeakCheck(self.clone()))
+    }
+}
+
+struct LeakCheck(ArenaHandle);
+
+impl Drop for LeakCheck {
+    fn drop(&mut self) {
+        let mut heap = self.0 .0.lock();
+        if heap.debug {
+            heap.report_leaks();
+        }
+    }
+}
+
+impl Drop for Heap {
+    fn drop(&mut self) {
+        for (&ptr, block) in &self.blocks {
+            unsafe { dealloc(ptr as *mut u8, layout(block.size).unwrap()) };
+        }
+    }
+}
+
+fn heap<'a>(heap: *const c_void) -> &'a Mutex<Heap> {
+    unsafe { &*(heap as *const Mutex<Heap>) }
+}
+
+extern "C" fn heap_malloc(arena: *const c_void, size: usize, site: *const c_void) -> *mut c_void {
+    let Some(layout) = layout(size) else {
+        return null_mut();
+    };
+    heap(arena)
+        .lock()
+        .insert(unsafe { alloc(layout) }, size, site)
+}
+
+extern "C" fn heap_calloc(
+    arena: *const c_void,
+    count: usize,
+    size: usize,
+    site: *const c_void,
+) -> *mut c_void {
+    let Some(layout) = count.checked_mul(size).and_then(layout) else {
+        return null_mut();
+    };
+    heap(arena)
+        .lock()
+        .insert(unsafe { alloc_zeroed(layout) }, count * size, site)
+}
+
+extern "C" fn heap_realloc(
+    arena: *const c_void,
+    ptr: *mut c_void,
+    size: usize,
+    site: *const c_void,
+) -> *mut c_void {
+    if ptr.is_null() {
+        return heap_malloc(arena, size, site);
+    }
+    let mut heap = heap(arena).lock();
+    let Some(new_size) = layout(size).map(|layout| layout.size()) else {
+        return null_mut();
+    };
+    let Some(old) = heap.remove(ptr, site) else {
+        return null_mut();
+    };
+    let new = unsafe { realloc(ptr as *mut u8, layout(old.size).unwrap(), new_size) };
+    if new.is_null() {
+        // the old block is still valid
+        heap.insert(ptr as *mut u8, old.size, old.site as *const c_void);
+        return null_mut();
+    }
+    heap.insert(new, size, site)
+}
+
+extern "C" fn heap_free(arena: *const c_void, ptr: *mut c_void, site: *const c_void) {
+    if ptr.is_null() {
+        return;
+    }
+    let mut heap = heap(arena).lock();
+    if let Some(block) = heap.remove(ptr, site) {
+        unsafe { dealloc(ptr as *mut u8, layout(block.size).unwrap()) };
+    }
+}
+
+/// Rust entry points, registered under these names.
+pub(super) fn symbols(mut f: impl FnMut(&CStr, *const c_void)) {
+    f(c"__tcc_rs_heap_malloc", heap_malloc as *const c_void);
+    f(c"__tcc_rs_heap_calloc", heap_calloc as *const c_void);
+    f(c"__tcc_rs_heap_realloc", heap_realloc as *const c_void);
+    f(c"__tcc_rs_heap_free", heap_free as *const c_void);
+}
+
+const PROTOTYPES: &str = concat!(
+    "typedef __SIZE_TYPE__ size_t;\n",
+    "void *__tcc_rs_heap_malloc(void *, size_t, void *);\n",
+    "void *__tcc_rs_heap_calloc(void *, size_t, size_t, void *);\n",
+    "void *__tcc_rs_heap_realloc(void *, void *, size_t, void *);\n",
+    "void __tcc_rs_heap_free(void *, void *, void *);\n",
+    "#define SITE __builtin_return_address(0)\n",
+);
+
+/// C definitions of the functions in `wanted`, forwarding to the arena.
+pub(super) fn source(arena: &ArenaHandle, wanted: impl Fn(&str) -> bool) -> String {
+    let arena = format!("(void *){:#x}ULL", arena.as_ptr() as usize);
+    let mut source = String::from(PROTOTYPES);
+    let functions = [
+        (
+            "malloc",
+            "void *malloc(size_t n) { return __tcc_rs_heap_malloc(ARENA, n, SITE); }\n",
+        ),
+        (
+            "calloc",
+            "void *calloc(size_t c, size_t n) { return __tcc_rs_heap_calloc(ARENA, c, n, SITE); \
+             }\n",
+        ),
+        (
+            "realloc",
+            "void *realloc(void *p, size_t n) { return __tcc_rs_heap_realloc(ARENA, p, n, SITE); \
+             }\n",
+        ),
+        (
+            "free",
+            "void free(void *p) { __tcc_rs_heap_free(ARENA, p, SITE); }\n",
+        ),
+    ];
+    for (name, definition) in functions {
+        if wanted(name) {
+            source.push_str(&definition.replace("ARENA", &arena));
+        }
+    }
+    source
+}
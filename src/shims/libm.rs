@@ -0,0 +1,72 @@
+//! The host's math library, see
+//! [`Context::provide_libm`](crate::Context::provide_libm).
+
+use core::ffi::{c_int, c_void, CStr};
+
+macro_rules! libm {
+    ($visit:ident: $($name:ident($($arg:ty),*) -> $ret:ty;)*) => {
+        extern "C" {
+            $(fn $name($(_: $arg),*) -> $ret;)*
+        }
+
+        pub(crate) fn $visit(mut f: impl FnMut(&CStr, *const c_void)) {
+            $(f(
+                CStr::from_bytes_with_nul(concat!(stringify!($name), "\0").as_bytes()).unwrap(),
+                $name as unsafe extern "C" fn($($arg),*) -> $ret as *const c_void,
+            );)*
+        }
+    };
+}
+
+libm! {
+    double:
+    sin(f64) -> f64;
+    cos(f64) -> f64;
+    tan(f64) -> f64;
+    asin(f64) -> f64;
+    acos(f64) -> f64;
+    atan(f64) -> f64;
+    atan2(f64, f64) -> f64;
+    sinh(f64) -> f64;
+    cosh(f64) -> f64;
+    tanh(f64) -> f64;
+    exp(f64) -> f64;
+    exp2(f64) -> f64;
+    log(f64) -> f64;
+    log2(f64) -> f64;
+    log10(f64) -> f64;
+    pow(f64, f64) -> f64;
+    sqrt(f64) -> f64;
+    cbrt(f64) -> f64;
+    hypot(f64, f64) -> f64;
+    fabs(f64) -> f64;
+    floor(f64) -> f64;
+    ceil(f64) -> f64;
+    round(f64) -> f64;
+    trunc(f64) -> f64;
+    fmod(f64, f64) -> f64;
+    fmin(f64, f64) -> f64;
+    fmax(f64, f64) -> f64;
+    ldexp(f64, c_int) -> f64;
+}
+
+// 32-bit Windows only has these as inline functions in the headers
+#[cfg(not(all(windows, target_arch = "x86")))]
+libm! {
+    float:
+    sinf(f32) -> f32;
+    cosf(f32) -> f32;
+    tanf(f32) -> f32;
+    atan2f(f32, f32) -> f32;
+    expf(f32) -> f32;
+    logf(f32) -> f32;
+    powf(f32, f32) -> f32;
+    sqrtf(f32) -> f32;
+    fabsf(f32) -> f32;
+    floorf(f32) -> f32;
+    ceilf(f32) -> f32;
+    fmodf(f32, f32) -> f32;
+}
+
+#[cfg(all(windows, target_arch = "x86"))]
+pub(crate) fn float(_: impl FnMut(&CStr, *const c_void)) {}
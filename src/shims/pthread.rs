@@ -0,0 +1,235 @@
+//! A minimal pthread API for compiled code on top of `std::thread`, see
+//! [`Context::provide_pthreads`](crate::Context::provide_pthreads).
+//!
+//! Threads and mutexes are looked up by the address of the C object, so the
+//! layout of the platform's `pthread_mutex_t`, if there even is one, does not
+//! matter and any initializer works.
+
+use alloc::{collections::BTreeMap, format, string::String, sync::Arc};
+use core::{
+    ffi::{c_int, c_void, CStr},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use std::{
+    sync::{Condvar, Mutex},
+    thread::{self, JoinHandle},
+};
+
+const EPERM: c_int = 1;
+const ESRCH: c_int = 3;
+const EBUSY: c_int = 16;
+// the BSDs kept the 4.4BSD numbering
+#[cfg(any(
+    target_vendor = "apple",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "dragonfly"
+))]
+const EAGAIN: c_int = 35;
+#[cfg(not(any(
+    target_vendor = "apple",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "dragonfly"
+)))]
+const EAGAIN: c_int = 11;
+
+type Start = extern "C" fn(*mut c_void) -> *mut c_void;
+
+#[derive(Default)]
+struct Lock {
+    locked:   Mutex<bool>,
+    unlocked: Condvar,
+}
+
+#[derive(Default)]
+/// Threads and mutexes of one context.
+pub(crate) struct Threads {
+    next:    AtomicUsize,
+    threads: Mutex<BTreeMap<usize, JoinHandle<usize>>>,
+    mutexes: Mutex<BTreeMap<usize, Arc<Lock>>>,
+}
+
+impl Threads {
+    fn mutex(&self, mutex: *mut c_void) -> Arc<Lock> {
+        let mut mutexes = self.mutexes.lock().unwrap();
+        mutexes.entry(mutex as usize).or_default().clone()
+    }
+}
+
+fn threads<'a>(threads: *const c_void) -> &'a Threads {
+    unsafe { &*(threads as *const Threads) }
+}
+
+extern "C" fn thread_create(
+    state: *const c_void,
+    thread: *mut usize,
+    start: Start,
+    arg: *mut c_void,
+) -> c_int {
+    let state = threads(state);
+    let arg = arg as usize;
+    let spawned = thread::Builder::new().spawn(move || start(arg as *mut c_void) as usize);
+    let Ok(handle) = spawned else {
+        return EAGAIN;
+    };
+    let id = state.next.fetch_add(1, Ordering::Relaxed) + 1;
+    state.threads.lock().unwrap().insert(id, handle);
+    unsafe { *thread = id };
+    0
+}
+
+extern "C" fn thread_join(state: *const c_void, thread: usize, ret: *mut *mut c_void) -> c_int {
+    let handle = threads(state).threads.lock().unwrap().remove(&thread);
+    let Some(handle) = handle else {
+        return ESRCH;
+    };
+    // a panic can not unwind out of the `extern "C"` start routine
+    let value = handle.join().unwrap_or(0);
+    if !ret.is_null() {
+        unsafe { *ret = value as *mut c_void };
+    }
+    0
+}
+
+extern "C" fn mutex_init(state: *const c_void, mutex: *mut c_void) -> c_int {
+    let mut mutexes = threads(state).mutexes.lock().unwrap();
+    mutexes.insert(mutex as usize, Arc::default());
+    0
+}
+
+extern "C" fn mutex_destroy(state: *const c_void, mutex: *mut c_void) -> c_int {
+    let mut mutexes = threads(state).mutexes.lock().unwrap();
+    let locked = mutexes
+        .get(&(mutex as usize))
+        .is_some_and(|lock| *lock.locked.lock().unwrap());
+    if locked {
+        return EBUSY;
+    }
+    mutexes.remove(&(mutex as usize));
+    0
+}
+
+extern "C" fn mutex_lock(state: *const c_void, mutex: *mut c_void) -> c_int {
+    let lock = threads(state).mutex(mutex);
+    let mut locked = lock.locked.lock().unwrap();
+    while *locked {
+        locked = lock.unlocked.wait(locked).unwrap();
+    }
+    *locked = true;
+    0
+}
+
+extern "C" fn mutex_trylock(state: *const c_void, mutex: *mut c_void) -> c_int {
+    let lock = threads(state).mutex(mutex);
+    let mut locked = lock.locked.lock().unwrap();
+    match *locked {
+        true => EBUSY,
+        false => {
+            *locked = true;
+            0
+        }
+    }
+}
+
+extern "C" fn mutex_unlock(state: *const c_void, mutex: *mut c_void) -> c_int {
+    let lock = threads(state).mutex(mutex);
+    let mut locked = lock.locked.lock().unwrap();
+    if !*locked {
+        return EPERM;
+    }
+    *locked = false;
+    lock.unlocked.notify_one();
+    0
+}
+
+/// Rust entry points, registered under these names.
+pub(super) fn symbols(mut f: impl FnMut(&CStr, *const c_void)) {
+    f(c"__tcc_rs_pthread_create", thread_create as *const c_void);
+    f(c"__tcc_rs_pthread_join", thread_join as *const c_void);
+    f(c"__tcc_rs_pthread_mutex_init", mutex_init as *const c_void);
+    f(
+        c"__tcc_rs_pthread_mutex_destroy",
+        mutex_destroy as *const c_void,
+    );
+    f(c"__tcc_rs_pthread_mutex_lock", mutex_lock as *const c_void);
+    f(
+        c"__tcc_rs_pthread_mutex_trylock",
+        mutex_trylock as *const c_void,
+    );
+    f(
+        c"__tcc_rs_pthread_mutex_unlock",
+        mutex_unlock as *const c_void,
+    );
+}
+
+const PROTOTYPES: &str = concat!(
+    "typedef __SIZE_TYPE__ __tcc_rs_thread;\n",
+    "typedef void *(*__tcc_rs_start)(void *);\n",
+    "int __tcc_rs_pthread_create(void *, void *, __tcc_rs_start, void *);\n",
+    "int __tcc_rs_pthread_join(void *, __tcc_rs_thread, void **);\n",
+    "int __tcc_rs_pthread_mutex_init(void *, void *);\n",
+    "int __tcc_rs_pthread_mutex_destroy(void *, void *);\n",
+    "int __tcc_rs_pthread_mutex_lock(void *, void *);\n",
+    "int __tcc_rs_pthread_mutex_trylock(void *, void *);\n",
+    "int __tcc_rs_pthread_mutex_unlock(void *, void *);\n",
+);
+
+/// C definitions of the functions in `wanted`, forwarding to `threads`.
+pub(super) fn source(threads: &Arc<Threads>, wanted: impl Fn(&str) -> bool) -> String {
+    let state = format!("(void *){:#x}ULL", Arc::as_ptr(threads) as usize);
+    let mut source = String::from(PROTOTYPES);
+    let functions = [
+        (
+            "pthread_create",
+            concat!(
+                "int pthread_create(void *t, const void *attr, __tcc_rs_start f, void *arg) {\n",
+                "    return __tcc_rs_pthread_create(STATE, t, f, arg);\n",
+                "}\n",
+            ),
+        ),
+        (
+            "pthread_join",
+            concat!(
+                "int pthread_join(__tcc_rs_thread t, void **ret) {\n",
+                "    return __tcc_rs_pthread_join(STATE, t, ret);\n",
+                "}\n",
+            ),
+        ),
+        (
+            "pthread_mutex_init",
+            concat!(
+                "int pthread_mutex_init(void *m, const void *attr) {\n",
+                "    return __tcc_rs_pthread_mutex_init(STATE, m);\n",
+                "}\n",
+            ),
+        ),
+        (
+            "pthread_mutex_destroy",
+            "int pthread_mutex_destroy(void *m) { return __tcc_rs_pthread_mutex_destroy(STATE, \
+             m); }\n",
+        ),
+        (
+            "pthread_mutex_lock",
+            "int pthread_mutex_lock(void *m) { return __tcc_rs_pthread_mutex_lock(STATE, m); }\n",
+        ),
+        (
+            "pthread_mutex_trylock",
+            "int pthread_mutex_trylock(void *m) { return __tcc_rs_pthread_mutex_trylock(STATE, \
+             m); }\n",
+        ),
+        (
+            "pthread_mutex_unlock",
+            "int pthread_mutex_unlock(void *m) { return __tcc_rs_pthread_mutex_unlock(STATE, m); \
+             }\n",
+        ),
+    ];
+    for (name, definition) in functions {
+        if wanted(name) {
+            source.push_str(definition);
+        }
+    }
+    source.replace("STATE", &state)
+}
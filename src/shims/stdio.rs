@@ -0,0 +1,456 @@
+//! `printf` and friends for compiled code, writing into Rust-side buffers.
+//!
+//! The variadic entry points are compiled C that walks the format string and
+//! fetches each argument with `va_arg`; the conversion itself happens here, so
+//! a bad format can at worst print garbage. `%n` is ignored.
+
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    ffi::{c_char, c_int, c_void, CStr},
+    slice,
+};
+
+use spin::Mutex;
+
+use crate::panic_policy::guard;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Standard stream written by compiled code.
+pub enum StdStream {
+    Stdout,
+    Stderr,
+}
+
+type Writer = Box<dyn FnMut(StdStream, &[u8]) + Send>;
+
+#[derive(Default)]
+struct Output {
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    writer: Option<Writer>,
+}
+
+impl Output {
+    fn write(&mut self, stream: c_int, bytes: &[u8]) -> c_int {
+        let stream = match stream {
+            2 => StdStream::Stderr,
+            _ => StdStream::Stdout,
+        };
+        match (&mut self.writer, stream) {
+            (Some(writer), _) => writer(stream, bytes),
+            (None, StdStream::Stdout) => self.stdout.extend_from_slice(bytes),
+            (None, StdStream::Stderr) => self.stderr.extend_from_slice(bytes),
+        }
+        bytes.len().try_into().unwrap_or(c_int::MAX)
+    }
+}
+
+#[derive(Clone, Default)]
+/// Destination of `printf`, `fprintf`, `puts` and `putchar` of compiled
+/// code, see [`Context::capture_stdio`](crate::Context::capture_stdio).
+///
+/// Output is buffered per stream until taken, or handed to a writer as it is
+/// produced.
+pub struct StdioSink(Arc<Mutex<Output>>);
+
+impl StdioSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pass output on to `writer` as it is produced instead of buffering it.
+    pub fn with_writer<F>(writer: F) -> Self
+    where
+        F: FnMut(StdStream, &[u8]) + Send + 'static,
+    {
+        let sink = Self::default();
+        sink.0.lock().writer = Some(Box::new(writer));
+        sink
+    }
+
+    /// Take what was written to `stdout` so far.
+    pub fn take_stdout(&self) -> Vec<u8> {
+        core::mem::take(&mut self.0.lock().stdout)
+    }
+
+    /// Take what was written to `stderr` so far.
+    pub fn take_stderr(&self) -> Vec<u8> {
+        core::mem::take(&mut self.0.lock().stderr)
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const c_void {
+        Arc::as_ptr(&self.0) as *const c_void
+    }
+
+    pub(crate) fn keep_alive(&self) -> Arc<dyn core::any::Any + Send + Sync> {
+        self.0.clone()
+    }
+}
+
+fn output<'a>(sink: *const c_void) -> &'a Mutex<Output> {
+    unsafe { &*(sink as *const Mutex<Output>) }
+}
+
+/// One conversion, filled in by the C side; keep in sync with `PROTOTYPES`.
+#[repr(C)]
+struct Spec {
+    /// everything after the `%`, `len` bytes
+    text:       *const c_char,
+    len:        c_int,
+    /// number of flag characters at the start of `text`
+    flags:      c_int,
+    /// 0 for none, negative if left justified through `*`
+    width:      c_int,
+    /// negative for none
+    precision:  c_int,
+    conversion: c_int,
+    /// integer argument, unsigned ones as their bit pattern
+    integer:    i64,
+    real:       f64,
+    pointer:    *const c_void,
+}
+
+extern "C" fn stdio_write(
+    sink: *const c_void,
+    stream: c_int,
+    bytes: *const c_char,
+    len: usize,
+) -> c_int {
+    let bytes = unsafe { slice::from_raw_parts(bytes as *const u8, len) };
+    // the writer is user code
+    unsafe { guard(|| output(sink).lock().write(stream, bytes)) }
+}
+
+extern "C" fn stdio_format(sink: *const c_void, stream: c_int, spec: *const Spec) -> c_int {
+    let bytes = format_spec(unsafe { &*spec });
+    unsafe { guard(|| output(sink).lock().write(stream, &bytes)) }
+}
+
+/// Render a single conversion the way C's `printf` would.
+fn format_spec(spec: &Spec) -> Vec<u8> {
+    let text = unsafe { slice::from_raw_parts(spec.text as *const u8, spec.len as usize) };
+    let flags = &text[..spec.flags as usize];
+    let left = flags.contains(&b'-') || spec.width < 0;
+    let plus = flags.contains(&b'+');
+    let space = flags.contains(&b' ');
+    let alt = flags.contains(&b'#');
+    let zero = flags.contains(&b'0');
+    let width = spec.width.unsigned_abs() as usize;
+    let precision = usize::try_from(spec.precision).ok();
+    let sign = |negative: bool| {
+        match (negative, plus, space) {
+            (true, ..) => "-",
+            (false, true, _) => "+",
+            (false, false, true) => " ",
+            _ => "",
+        }
+    };
+    let digits = |digits: String| {
+        match precision {
+            Some(0) if digits == "0" => String::new(),
+            Some(p) if p > digits.len() => format!("{digits:0>p$}"),
+            _ => digits,
+        }
+    };
+
+    let unsigned = spec.integer as u64;
+    let conversion = u8::try_from(spec.conversion).unwrap_or(0);
+    let (prefix, body) = match conversion {
+        b'd' | b'i' => {
+            (
+                sign(spec.integer < 0),
+                digits(spec.integer.unsigned_abs().to_string()).into_bytes(),
+            )
+        }
+        b'u' => ("", digits(unsigned.to_string()).into_bytes()),
+        b'o' => {
+            let mut body = digits(format!("{unsigned:o}"));
+            if alt && !body.starts_with('0') {
+                body.insert(0, '0');
+            }
+            ("", body.into_bytes())
+        }
+        b'x' | b'X' => {
+            let prefix = match (alt && unsigned != 0, conversion) {
+                (false, _) => "",
+                (true, b'x') => "0x",
+                (true, _) => "0X",
+            };
+            let body = digits(format!("{unsigned:x}"));
+            let body = match conversion {
+                b'X' => body.to_ascii_uppercase(),
+                _ => body,
+            };
+            (prefix, body.into_bytes())
+        }
+        b'p' => ("0x", format!("{:x}", spec.pointer as usize).into_bytes()),
+        b'c' => ("", Vec::from([spec.integer as u8])),
+        b's' if spec.pointer.is_null() => ("", Vec::from(*b"(null)")),
+        b's' => {
+            let mut body = Vec::new();
+            let mut p = spec.pointer as *const u8;
+            while precision.is_none_or(|precision| body.len() < precision) {
+                match unsafe { *p } {
+                    0 => break,
+                    byte => body.push(byte),
+                }
+                p = unsafe { p.add(1) };
+            }
+            ("", body)
+        }
+        b'e' | b'E' | b'f' | b'F' | b'g' | b'G' => {
+            let body = float(spec.real.abs(), conversion, precision.unwrap_or(6), alt);
+            (sign(spec.real.is_sign_negative()), body.into_bytes())
+        }
+        // would write through a pointer
+        b'n' => return Vec::new(),
+        _ => {
+            let mut verbatim = Vec::from(*b"%");
+            verbatim.extend_from_slice(text);
+            return verbatim;
+        }
+    };
+
+    let numeric = !matches!(conversion, b'c' | b's' | b'p');
+    let is_float = matches!(conversion, b'e' | b'E' | b'f' | b'F' | b'g' | b'G');
+    let fill = width.saturating_sub(prefix.len() + body.len());
+    let mut out = Vec::with_capacity(prefix.len() + body.len() + fill);
+    // `0` is ignored for integers with a precision, and for `inf` and `nan`
+    let zero_fill = zero
+        && numeric
+        && !left
+        && match is_float {
+            true => spec.real.is_finite(),
+            false => precision.is_none(),
+        };
+    if !left && !zero_fill {
+        out.resize(fill, b' ');
+    }
+    out.extend_from_slice(prefix.as_bytes());
+    if zero_fill {
+        out.resize(out.len() + fill, b'0');
+    }
+    out.extend_from_slice(&body);
+    if left {
+        out.resize(out.len() + fill, b' ');
+    }
+    out
+}
+
+/// `%e`, `%f` or `%g` of a non-negative `value`.
+fn float(value: f64, conversion: u8, precision: usize, alt: bool) -> String {
+    let out = if value.is_nan() {
+        String::from("nan")
+    } else if value.is_infinite() {
+        String::from("inf")
+    } else {
+        match conversion.to_ascii_lowercase() {
+            b'f' if alt && precision == 0 => format!("{value:.0}."),
+            b'f' => format!("{value:.precision$}"),
+            b'e' => exponential(value, precision, alt),
+            _ => {
+                let precision = precision.max(1);
+                let exponent = exponential(value, precision - 1, false);
+                let exponent: i32 = exponent[exponent.find('e').unwrap() + 1..].parse().unwrap();
+                let out = match (-4..precision as i32).contains(&exponent) {
+                    true => format!("{value:.*}", (precision as i32 - 1 - exponent) as usize),
+                    false => exponential(value, precision - 1, alt),
+                };
+                match alt {
+                    true => out,
+                    false => trim_zeros(out),
+                }
+            }
+        }
+    };
+    match conversion.is_ascii_uppercase() {
+        true => out.to_ascii_uppercase(),
+        false => out,
+    }
+}
+
+/// `%e` of a finite, non-negative `value`; Rust leaves out the exponent's
+/// sign and padding.
+fn exponential(value: f64, precision: usize, alt: bool) -> String {
+    let out = format!("{value:.precision$e}");
+    let (mantissa, exponent) = out.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let dot = if alt && precision == 0 { "." } else { "" };
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{mantissa}{dot}e{sign}{:02}", exponent.unsigned_abs())
+}
+
+/// Drop trailing zeros of the fraction, as `%g` does without `#`.
+fn trim_zeros(out: String) -> String {
+    let (number, exponent) = match out.find('e') {
+        Some(at) => out.split_at(at),
+        None => (out.as_str(), ""),
+    };
+    match number.contains('.') {
+        true => {
+            let number = number.trim_end_matches('0').trim_end_matches('.');
+            format!("{number}{exponent}")
+        }
+        false => out,
+    }
+}
+
+/// Rust entry points, registered under these names.
+pub(super) fn symbols(mut f: impl FnMut(&CStr, *const c_void)) {
+    f(c"__tcc_rs_stdio_write", stdio_write as *const c_void);
+    f(c"__tcc_rs_stdio_format", stdio_format as *const c_void);
+}
+
+const PROTOTYPES: &str = concat!(
+    "#include <stdarg.h>\n",
+    "typedef __SIZE_TYPE__ size_t;\n",
+    "struct __tcc_rs_spec {\n",
+    "    const char *text; int len, flags, width, precision, conversion;\n",
+    "    long long integer; double real; const void *pointer;\n",
+    "};\n",
+    "int __tcc_rs_stdio_write(void *, int, const char *, size_t);\n",
+    "int __tcc_rs_stdio_format(void *, int, struct __tcc_rs_spec *);\n",
+    "#if defined __APPLE__ || defined __FreeBSD__ || defined __DragonFly__\n",
+    "extern void *__stderrp __attribute__((weak));\n",
+    "#define __tcc_rs_stderr (&__stderrp ? __stderrp : 0)\n",
+    "#else\n",
+    "extern void *stderr __attribute__((weak));\n",
+    "#define __tcc_rs_stderr (&stderr ? stderr : 0)\n",
+    "#endif\n",
+);
+
+const VPRINTF: &str = concat!(
+    "static int __tcc_rs_vprintf(int stream, const char *f, va_list ap) {\n",
+    "    int n = 0;\n",
+    "    while (*f) {\n",
+    "        struct __tcc_rs_spec s;\n",
+    "        int l = 0, h = 0;\n",
+    "        const char *start = f;\n",
+    "        if (*f != '%') {\n",
+    "            while (*f && *f != '%') f++;\n",
+    "            n += __tcc_rs_stdio_write(SINK, stream, start, f - start);\n",
+    "            continue;\n",
+    "        }\n",
+    "        if (f[1] == '%') {\n",
+    "            n += __tcc_rs_stdio_write(SINK, stream, f, 1);\n",
+    "            f += 2;\n",
+    "            continue;\n",
+    "        }\n",
+    "        s.text = ++f;\n",
+    "        while (*f == '-' || *f == '+' || *f == ' ' || *f == '#' || *f == '0') f++;\n",
+    "        s.flags = f - s.text;\n",
+    "        s.width = 0;\n",
+    "        if (*f == '*') {\n",
+    "            s.width = va_arg(ap, int);\n",
+    "            f++;\n",
+    "        } else while (*f >= '0' && *f <= '9') s.width = s.width * 10 + *f++ - '0';\n",
+    "        s.precision = -1;\n",
+    "        if (*f == '.') {\n",
+    "            s.precision = 0;\n",
+    "            if (*++f == '*') {\n",
+    "                s.precision = va_arg(ap, int);\n",
+    "                f++;\n",
+    "            } else while (*f >= '0' && *f <= '9') s.precision = s.precision * 10 + *f++ - \
+     '0';\n",
+    "        }\n",
+    "        for (;; f++) {\n",
+    "            if (*f == 'l') l++;\n",
+    "            else if (*f == 'h') h++;\n",
+    "            else if (*f == 'L' || *f == 'j' || *f == 'q') l = 2;\n",
+    "            else if (*f == 'z' || *f == 't') l = sizeof(size_t) == sizeof(long) ? 1 : 2;\n",
+    "            else break;\n",
+    "        }\n",
+    "        s.conversion = *f;\n",
+    "        if (*f) f++;\n",
+    "        s.len = f - s.text;\n",
+    "        switch (s.conversion) {\n",
+    "        case 'd': case 'i':\n",
+    "            s.integer = l > 1 ? va_arg(ap, long long) : l ? va_arg(ap, long) : va_arg(ap, \
+     int);\n",
+    "            if (h == 1) s.integer = (short)s.integer;\n",
+    "            if (h > 1) s.integer = (signed char)s.integer;\n",
+    "            break;\n",
+    "        case 'u': case 'o': case 'x': case 'X':\n",
+    "            s.integer = l > 1 ? va_arg(ap, unsigned long long)\n",
+    "                : l ? va_arg(ap, unsigned long) : va_arg(ap, unsigned);\n",
+    "            if (h == 1) s.integer = (unsigned short)s.integer;\n",
+    "            if (h > 1) s.integer = (unsigned char)s.integer;\n",
+    "            break;\n",
+    "        case 'c':\n",
+    "            s.integer = va_arg(ap, int);\n",
+    "            break;\n",
+    "        case 'e': case 'E': case 'f': case 'F': case 'g': case 'G': case 'a': case 'A':\n",
+    "            s.real = l > 1 ? (double)va_arg(ap, long double) : va_arg(ap, double);\n",
+    "            break;\n",
+    "        case 's': case 'p': case 'n':\n",
+    "            s.pointer = va_arg(ap, void *);\n",
+    "            break;\n",
+    "        }\n",
+    "        n += __tcc_rs_stdio_format(SINK, stream, &s);\n",
+    "    }\n",
+    "    return n;\n",
+    "}\n",
+);
+
+/// C definitions of the functions in `wanted`, writing into `sink`.
+pub(super) fn source(sink: &StdioSink, wanted: impl Fn(&str) -> bool) -> String {
+    let sink = format!("(void *){:#x}ULL", sink.as_ptr() as usize);
+    let mut source = String::from(PROTOTYPES);
+    source.push_str(VPRINTF);
+    let functions = [
+        (
+            "printf",
+            concat!(
+                "int printf(const char *f, ...) {\n",
+                "    va_list ap; int n;\n",
+                "    va_start(ap, f); n = __tcc_rs_vprintf(1, f, ap); va_end(ap);\n",
+                "    return n;\n",
+                "}\n",
+            ),
+        ),
+        (
+            "fprintf",
+            concat!(
+                "int fprintf(void *stream, const char *f, ...) {\n",
+                "    va_list ap; int n;\n",
+                "    int s = stream && stream == __tcc_rs_stderr ? 2 : 1;\n",
+                "    va_start(ap, f); n = __tcc_rs_vprintf(s, f, ap); va_end(ap);\n",
+                "    return n;\n",
+                "}\n",
+            ),
+        ),
+        (
+            "puts",
+            concat!(
+                "int puts(const char *s) {\n",
+                "    const char *e = s;\n",
+                "    while (*e) e++;\n",
+                "    int n = __tcc_rs_stdio_write(SINK, 1, s, e - s);\n",
+                "    return n + __tcc_rs_stdio_write(SINK, 1, \"\\n\", 1);\n",
+                "}\n",
+            ),
+        ),
+        (
+            "putchar",
+            concat!(
+                "int putchar(int c) {\n",
+                "    char b = c;\n",
+                "    __tcc_rs_stdio_write(SINK, 1, &b, 1);\n",
+                "    return (unsigned char)c;\n",
+                "}\n",
+            ),
+        ),
+    ];
+    for (name, definition) in functions {
+        if wanted(name) {
+            source.push_str(definition);
+        }
+    }
+    source.replace("SINK", &sink)
+}
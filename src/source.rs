@@ -0,0 +1,285 @@
+//! Source text handling before it reaches tcc.
+//!
+//! tcc reads plain bytes and expects UTF-8 with `\n` line endings; anything
+//! else shows up as garbled identifiers, wide string literals or line numbers.
+//! [`normalize`] brings source text into that shape or reports why it can't.
+
+use alloc::{boxed::Box, ffi::CString, string::String, vec::Vec};
+use core::fmt::{self, Write};
+
+use crate::{capabilities, Context, Error, ExecutableFormat};
+
+mod builder;
+mod quote;
+
+pub use builder::{string_literal, Builder};
+#[doc(hidden)] pub use quote::Quote;
+pub use quote::ToCLiteral;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Why source text could not be normalized, offsets are in input bytes.
+pub enum EncodingError {
+    /// Neither valid UTF-8 nor marked as UTF-16 by a byte order mark.
+    InvalidUtf8 { offset: usize },
+
+    /// UTF-16 input with an odd length or an unpaired surrogate.
+    InvalidUtf16 { offset: usize },
+
+    /// A NUL character, which would silently end the source for tcc.
+    Nul { offset: usize },
+
+    /// A character in a wide character constant that takes more than one
+    /// code unit, like `u'😀'`.
+    WideChar { offset: usize },
+}
+
+impl fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodingError::InvalidUtf8 { offset } => write!(f, "invalid UTF-8 at byte {offset}"),
+            EncodingError::InvalidUtf16 { offset } => write!(f, "invalid UTF-16 at byte {offset}"),
+            EncodingError::Nul { offset } => write!(f, "NUL character at byte {offset}"),
+            EncodingError::WideChar { offset } => {
+                write!(
+                    f,
+                    "character at byte {offset} does not fit a wide character"
+                )
+            }
+        }
+    }
+}
+
+impl core::error::Error for EncodingError {}
+
+/// Turn source bytes into UTF-8 text with `\n` line endings.
+///
+/// A UTF-8 byte order mark is dropped, UTF-16 input (detected by its byte
+/// order mark) is transcoded, and `\r\n` as well as lone `\r` become `\n`.
+///
+/// Non-ASCII characters in wide literals (`L`, `u` and `U` prefixes) are
+/// written as hex escapes of their UTF-16 or UTF-32 code units, so they
+/// come out the same whichever way the linked tcc reads the bytes; `L`
+/// literals get UTF-16 where `wchar_t` has 16 bits, as on Windows. Narrow
+/// and `u8` literals keep their UTF-8 bytes.
+pub fn normalize(bytes: &[u8]) -> Result<String, EncodingError> {
+    let text = match bytes {
+        [0xEF, 0xBB, 0xBF, rest @ ..] => utf8(rest, 3)?,
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes)?,
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes)?,
+        _ => utf8(bytes, 0)?,
+    };
+    let input_offset = |offset: usize| {
+        match bytes {
+            [0xEF, 0xBB, 0xBF, ..] => 3 + offset,
+            [0xFF, 0xFE, ..] | [0xFE, 0xFF, ..] => 2 + 2 * text[..offset].encode_utf16().count(),
+            _ => offset,
+        }
+    };
+
+    if let Some(offset) = text.find('\0') {
+        return Err(EncodingError::Nul {
+            offset: input_offset(offset),
+        });
+    }
+    let text = match wide_literals(&text) {
+        Ok(Some(escaped)) => escaped,
+        Ok(None) => text,
+        Err(offset) => {
+            return Err(EncodingError::WideChar {
+                offset: input_offset(offset),
+            })
+        }
+    };
+    if !text.contains('\r') {
+        return Ok(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\r' {
+            chars.next_if_eq(&'\n');
+            out.push('\n');
+        } else {
+            out.push(c);
+        }
+    }
+    Ok(out)
+}
+
+/// `text` with the non-ASCII characters of wide literals escaped, `None` if
+/// there are none, or the offset of a character not fitting a wide
+/// character constant.
+fn wide_literals(text: &str) -> Result<Option<String>, usize> {
+    let wide_16 = capabilities().format == ExecutableFormat::Pe;
+    let mut out = String::new();
+    // up to where `text` is copied to `out`
+    let mut copied = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((at, c)) = chars.next() {
+        let rest = &text[at..];
+        if rest.starts_with("//") || rest.starts_with("/*") {
+            let end = match rest.starts_with("//") {
+                true => rest.find('\n').unwrap_or(rest.len()),
+                false => rest[2..].find("*/").map_or(rest.len(), |end| end + 4),
+            };
+            while chars.next_if(|&(next, _)| next < at + end).is_some() {}
+            continue;
+        }
+        if c == '_' || c.is_alphanumeric() {
+            let end = rest
+                .find(|c: char| !(c == '_' || c.is_alphanumeric()))
+                .unwrap_or(rest.len());
+            while chars.next_if(|&(next, _)| next < at + end).is_some() {}
+            let prefix = &rest[..end];
+            let wide = matches!(prefix, "L" | "u" | "U");
+            match chars.peek() {
+                Some(&(_, quote @ ('"' | '\''))) if wide || prefix == "u8" => {
+                    chars.next();
+                    let utf16 = prefix == "u" || (prefix == "L" && wide_16);
+                    let escape = wide.then_some((prefix, utf16));
+                    literal(text, &mut chars, quote, escape, &mut out, &mut copied)?;
+                }
+                _ => {}
+            }
+            continue;
+        }
+        if c == '"' || c == '\'' {
+            literal(text, &mut chars, c, None, &mut out, &mut copied)?;
+        }
+    }
+    if copied == 0 {
+        return Ok(None);
+    }
+    out.push_str(&text[copied..]);
+    Ok(Some(out))
+}
+
+/// Skip the rest of a literal opened with `quote`, escaping non-ASCII
+/// characters as code units of the `(prefix, utf16)` of wide literals.
+fn literal(
+    text: &str,
+    chars: &mut core::iter::Peekable<core::str::CharIndices>,
+    quote: char,
+    escape: Option<(&str, bool)>,
+    out: &mut String,
+    copied: &mut usize,
+) -> Result<(), usize> {
+    while let Some((at, c)) = chars.next() {
+        match c {
+            // an unterminated literal ends at the line end
+            '\n' => return Ok(()),
+            '\\' => {
+                chars.next();
+                continue;
+            }
+            _ if c == quote => return Ok(()),
+            _ => {}
+        }
+        let Some((prefix, utf16)) = escape.filter(|_| !c.is_ascii()) else {
+            continue;
+        };
+        let mut units = [0u16; 2];
+        let units: &[u32] = &match utf16 {
+            true => {
+                c.encode_utf16(&mut units)
+                    .iter()
+                    .map(|&unit| unit.into())
+                    .collect()
+            }
+            false => alloc::vec![c.into()],
+        };
+        let next = chars.peek().map(|&(_, next)| next);
+        if quote == '\'' && (units.len() > 1 || next != Some('\'')) {
+            return Err(at);
+        }
+        out.push_str(&text[*copied..at]);
+        for unit in units {
+            let _ = write!(out, "\\x{unit:x}");
+        }
+        // a hex digit would continue the escape, so the literal is split
+        if next.is_some_and(|next| next.is_ascii_hexdigit()) {
+            let _ = write!(out, "\" {prefix}\"");
+        }
+        *copied = at + c.len_utf8();
+    }
+    Ok(())
+}
+
+fn utf8(bytes: &[u8], skipped: usize) -> Result<String, EncodingError> {
+    match core::str::from_utf8(bytes) {
+        Ok(text) => Ok(String::from(text)),
+        Err(err) => {
+            Err(EncodingError::InvalidUtf8 {
+                offset: skipped + err.valid_up_to(),
+            })
+        }
+    }
+}
+
+fn utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> Result<String, EncodingError> {
+    let chunks = bytes.chunks_exact(2);
+    if !chunks.remainder().is_empty() {
+        return Err(EncodingError::InvalidUtf16 {
+            offset: bytes.len() + 1,
+        });
+    }
+    let units: Vec<u16> = chunks.map(|c| unit([c[0], c[1]])).collect();
+    let mut out = String::with_capacity(units.len());
+    let mut offset = 2;
+    for c in char::decode_utf16(units) {
+        let c = c.map_err(|_| EncodingError::InvalidUtf16 { offset })?;
+        offset += 2 * c.len_utf16();
+        out.push(c);
+    }
+    Ok(out)
+}
+
+pub(crate) type SourceFilter<'err> = Box<dyn FnMut(&str) -> String + 'err>;
+
+impl<'err> Context<'err> {
+    /// Rewrite every source passed to [`Context::compile_str`],
+    /// [`Context::compile_bytes`] or
+    /// [`Context::add_source_bytes`] before compiling it.
+    ///
+    /// The filter sees normalized text and may expand templates, inject
+    /// instrumentation, or return something that fails to compile to reject a
+    /// construct. Files added by path and [`Context::compile_string`] are
+    /// passed through untouched.
+    pub fn set_source_filter<F>(&mut self, filter: F) -> &mut Self
+    where
+        F: FnMut(&str) -> String + 'err,
+    {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Normalize `source` and run it through the source filter.
+    pub(crate) fn filter_source(&mut self, source: &[u8]) -> Result<String, Error> {
+        let source = normalize(source).map_err(Error::Encoding)?;
+        Ok(match &mut self.filter {
+            Some(filter) => filter(&source),
+            None => source,
+        })
+    }
+
+    /// Compile C source text, see [`normalize`] for the accepted input.
+    pub fn compile_str(&mut self, source: &str) -> Result<(), Error> {
+        self.compile_bytes(source.as_bytes())
+    }
+
+    /// Compile C source bytes in UTF-8 or UTF-16 with any line endings.
+    ///
+    /// Input that can not be brought into UTF-8 fails with
+    /// [`Error::Encoding`] before tcc sees it.
+    pub fn compile_bytes(&mut self, source: &[u8]) -> Result<(), Error> {
+        let source = self.filter_source(source)?;
+        self.check_source_limits(&source)?;
+        self.check_dialect("<string>", &source)?;
+        let source = CString::new(source).map_err(|err| {
+            Error::Encoding(EncodingError::Nul {
+                offset: err.nul_position(),
+            })
+        })?;
+        self.compile_string(&source)
+    }
+}
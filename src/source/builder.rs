@@ -0,0 +1,164 @@
+//! Programmatic assembly of C source.
+
+use alloc::string::{String, ToString};
+use core::fmt::Write;
+
+/// Incrementally assembled C translation unit.
+///
+/// Every piece is emitted on lines of its own, and [`Builder::raw_at`] keeps
+/// `#line` directives consistent so diagnostics point at the code that
+/// produced a block rather than at the assembled string.
+///
+/// ```
+/// use tcc::source::Builder;
+///
+/// let source = Builder::new()
+///     .include_system("stdio.h")
+///     .define("SCALE", Some("2"))
+///     .define_str("GREETING", "say \"hi\"\n")
+///     .function("int scale(int x)", "return x * SCALE;")
+///     .finish();
+/// assert!(source.contains(r#"#define GREETING "say \"hi\"\n""#));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Builder {
+    out:  String,
+    /// number of the line the next piece starts on
+    line: u32,
+    name: String,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Builder {
+    /// Builder for source compiled with [`Context::compile_str`], which tcc
+    /// calls `<string>`.
+    ///
+    /// [`Context::compile_str`]: crate::Context::compile_str
+    pub fn new() -> Self {
+        Self::named("<string>")
+    }
+
+    /// Builder for source that tcc will see under `name`, such as the name
+    /// given to [`Context::add_source_bytes`](crate::Context::add_source_bytes).
+    pub fn named(name: &str) -> Self {
+        Builder {
+            out:  String::new(),
+            line: 1,
+            name: name.to_string(),
+        }
+    }
+
+    /// `#include <header>`
+    pub fn include_system(&mut self, header: &str) -> &mut Self {
+        assert!(
+            !header.contains(['>', '\n']),
+            "invalid system header name {header:?}"
+        );
+        self.line(format_args!("#include <{header}>"))
+    }
+
+    /// `#include "header"`
+    pub fn include(&mut self, header: &str) -> &mut Self {
+        let header = string_literal(header);
+        self.line(format_args!("#include {header}"))
+    }
+
+    /// `#define name value`, or just `#define name` for `None`.
+    ///
+    /// # Panics
+    /// If `name` is not a C identifier.
+    pub fn define(&mut self, name: &str, value: Option<&str>) -> &mut Self {
+        assert!(is_identifier(name), "invalid macro name {name:?}");
+        match value {
+            // keep multi-line values inside the directive
+            Some(value) => {
+                let value = value.replace('\n', "\\\n");
+                let lines = value.matches('\n').count() as u32;
+                self.line(format_args!("#define {name} {value}"));
+                self.line += lines;
+                self
+            }
+            None => self.line(format_args!("#define {name}")),
+        }
+    }
+
+    /// `#define name "value"` with `value` escaped as a string literal.
+    pub fn define_str(&mut self, name: &str, value: &str) -> &mut Self {
+        self.define(name, Some(&string_literal(value)))
+    }
+
+    /// A function definition from its signature and body.
+    pub fn function(&mut self, signature: &str, body: &str) -> &mut Self {
+        self.line(format_args!("{signature}"));
+        self.line(format_args!("{{"));
+        self.raw(body);
+        self.line(format_args!("}}"))
+    }
+
+    /// Code copied verbatim.
+    pub fn raw(&mut self, code: &str) -> &mut Self {
+        let code = code.strip_suffix('\n').unwrap_or(code);
+        let lines = code.matches('\n').count() as u32;
+        self.line(format_args!("{code}"));
+        self.line += lines;
+        self
+    }
+
+    /// Code copied verbatim, with diagnostics attributed to `line` of
+    /// `file`, such as `file!()` and `line!()` of the Rust code embedding it.
+    pub fn raw_at(&mut self, code: &str, file: &str, line: u32) -> &mut Self {
+        let file = string_literal(file);
+        self.line(format_args!("#line {line} {file}"));
+        self.raw(code);
+        let (next, name) = (self.line + 1, string_literal(&self.name));
+        self.line(format_args!("#line {next} {name}"))
+    }
+
+    /// The assembled source.
+    pub fn finish(&self) -> String {
+        self.out.clone()
+    }
+
+    fn line(&mut self, line: core::fmt::Arguments) -> &mut Self {
+        let _ = writeln!(self.out, "{line}");
+        self.line += 1;
+        self
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
+
+/// Quote `s` as a C string literal.
+///
+/// Control characters are written as three digit octal escapes, which unlike
+/// hex escapes can not swallow a following character.
+pub fn string_literal(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 || c == '\x7f' => {
+                let _ = write!(out, "\\{:03o}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
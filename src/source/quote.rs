@@ -0,0 +1,206 @@
+//! Interpolation of Rust values into C source, see
+//! [`c_quote!`](crate::c_quote).
+
+use alloc::{
+    borrow::Cow,
+    format,
+    string::{String, ToString},
+};
+
+use super::string_literal;
+
+/// Rust values that have a C literal spelling.
+///
+/// Implemented for the primitive numbers, `bool`, `char` and strings; using
+/// any other type in [`c_quote!`](crate::c_quote) is a compile error.
+pub trait ToCLiteral {
+    fn to_c_literal(&self) -> String;
+}
+
+macro_rules! int_literal {
+    ($($ty:ty => $suffix:literal),* $(,)?) => {
+        $(
+            impl ToCLiteral for $ty {
+                fn to_c_literal(&self) -> String {
+                    #[allow(unused_comparisons)]
+                    match *self < 0 {
+                        // the most negative value has no positive literal
+                        true if *self == <$ty>::MIN => format!("({}{} - 1)", *self + 1, $suffix),
+                        true => format!("({}{})", self, $suffix),
+                        false => format!("{}{}", self, $suffix),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+int_literal! {
+    i8 => "", i16 => "", i32 => "", i64 => "LL", isize => "LL",
+    u8 => "", u16 => "", u32 => "u", u64 => "ULL", usize => "ULL",
+}
+
+macro_rules! float_literal {
+    ($($ty:ty => $suffix:literal),* $(,)?) => {
+        $(
+            impl ToCLiteral for $ty {
+                fn to_c_literal(&self) -> String {
+                    // `Debug` always keeps a decimal point or an exponent
+                    match self {
+                        x if x.is_nan() => format!("(0.0{0} / 0.0{0})", $suffix),
+                        x if x.is_infinite() && *x > 0.0 => format!("(1.0{0} / 0.0{0})", $suffix),
+                        x if x.is_infinite() => format!("(-1.0{0} / 0.0{0})", $suffix),
+                        x if x.is_sign_negative() => format!("({:?}{})", x, $suffix),
+                        x => format!("{:?}{}", x, $suffix),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+float_literal! { f32 => "f", f64 => "" }
+
+impl ToCLiteral for bool {
+    fn to_c_literal(&self) -> String {
+        String::from(if *self { "1" } else { "0" })
+    }
+}
+
+impl ToCLiteral for char {
+    /// A character constant for printable ASCII, the code point otherwise.
+    fn to_c_literal(&self) -> String {
+        match self {
+            '\'' => String::from(r"'\''"),
+            '\\' => String::from(r"'\\'"),
+            ' '..='~' => format!("'{self}'"),
+            c => (*c as u32).to_string(),
+        }
+    }
+}
+
+impl ToCLiteral for str {
+    fn to_c_literal(&self) -> String {
+        string_literal(self)
+    }
+}
+
+impl ToCLiteral for String {
+    fn to_c_literal(&self) -> String {
+        string_literal(self)
+    }
+}
+
+impl ToCLiteral for Cow<'_, str> {
+    fn to_c_literal(&self) -> String {
+        string_literal(self)
+    }
+}
+
+impl<T: ToCLiteral + ?Sized> ToCLiteral for &T {
+    fn to_c_literal(&self) -> String {
+        (**self).to_c_literal()
+    }
+}
+
+#[doc(hidden)]
+/// Output of [`c_quote!`](crate::c_quote) under construction.
+#[derive(Default)]
+pub struct Quote(String);
+
+impl Quote {
+    pub fn tokens(&mut self, tokens: &str) {
+        if tokens.is_empty() {
+            return;
+        }
+        // pieces are joined with spaces, except where C style has none
+        let last = self.0.chars().next_back();
+        let glued = match tokens.chars().next() {
+            Some(')' | ']' | ';' | ',') => true,
+            Some('(' | '[') => last.is_some_and(|c| c == '_' || c.is_alphanumeric()),
+            _ => matches!(last, None | Some('(' | '[')),
+        };
+        if !glued {
+            self.0.push(' ');
+        }
+        self.0.push_str(tokens);
+    }
+
+    pub fn value<T: ToCLiteral + ?Sized>(&mut self, value: &T) {
+        self.tokens(&value.to_c_literal());
+    }
+
+    pub fn finish(self) -> String {
+        self.0
+    }
+}
+
+/// Build C source from Rust tokens, interpolating `#name` with the C literal
+/// of the Rust variable `name`.
+///
+/// Values are converted through [`ToCLiteral`], so numbers keep their type
+/// (`u32` gets a `u` suffix, negative numbers are parenthesized) and strings
+/// are escaped; other types are rejected at compile time. As `#` introduces an
+/// interpolation, preprocessor directives have to go through
+/// [`Builder`](crate::source::Builder) instead. Long snippets may need a higher
+/// `#![recursion_limit]`.
+///
+/// ```
+/// use tcc::c_quote;
+///
+/// let scale = 0.5;
+/// let label = "half \"size\"";
+/// let source = c_quote! {
+///     const char *label = #label;
+///     double apply(double x) { return x * #scale; }
+/// };
+/// assert_eq!(
+///     source,
+///     r#"const char *label = "half \"size\""; double apply(double x) { return x * 0.5; }"#
+/// );
+/// ```
+#[macro_export]
+macro_rules! c_quote {
+    ($($tokens:tt)*) => {{
+        let mut quote = $crate::source::Quote::default();
+        $crate::__c_quote!(quote; []; $($tokens)*);
+        quote.finish()
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __c_quote {
+    ($quote:ident; [$($pending:tt)*]; # $var:ident $($rest:tt)*) => {
+        $quote.tokens(stringify!($($pending)*));
+        $quote.value(&$var);
+        $crate::__c_quote!($quote; []; $($rest)*);
+    };
+    ($quote:ident; [$($pending:tt)*]; { $($inner:tt)* } $($rest:tt)*) => {
+        $quote.tokens(stringify!($($pending)*));
+        $quote.tokens("{");
+        $crate::__c_quote!($quote; []; $($inner)*);
+        $quote.tokens("}");
+        $crate::__c_quote!($quote; []; $($rest)*);
+    };
+    ($quote:ident; [$($pending:tt)*]; ( $($inner:tt)* ) $($rest:tt)*) => {
+        $quote.tokens(stringify!($($pending)*));
+        $quote.tokens("(");
+        $crate::__c_quote!($quote; []; $($inner)*);
+        $quote.tokens(")");
+        $crate::__c_quote!($quote; []; $($rest)*);
+    };
+    ($quote:ident; [$($pending:tt)*]; [ $($inner:tt)* ] $($rest:tt)*) => {
+        $quote.tokens(stringify!($($pending)*));
+        $quote.tokens("[");
+        $crate::__c_quote!($quote; []; $($inner)*);
+        $quote.tokens("]");
+        $crate::__c_quote!($quote; []; $($rest)*);
+    };
+    ($quote:ident; [$($pending:tt)*]; $token:tt $($rest:tt)*) => {
+        $crate::__c_quote!($quote; [$($pending)* $token]; $($rest)*);
+    };
+    ($quote:ident; [$($pending:tt)*];) => {
+        $quote.tokens(stringify!($($pending)*));
+    };
+}
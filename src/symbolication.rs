@@ -0,0 +1,155 @@
+//! What crash reporters need to symbolicate frames in relocated code.
+
+use alloc::{borrow::ToOwned, string::String, vec::Vec};
+use core::{
+    ffi::{c_char, c_void, CStr},
+    fmt::Write,
+};
+
+use tcc_sys::{
+    tcc_list_symbols, tcc_rs_list_debug_sections, tcc_rs_list_symbol_sizes, SECTION_SIZES,
+};
+
+use crate::{json::write_str, RelocatedCtx};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Symbols and debug information of relocated code, see
+/// [`RelocatedCtx::export_symbolication`].
+///
+/// Crash reports only hold addresses; attaching a bundle per relocated
+/// module lets a pipeline like breakpad's or Sentry's map addresses between
+/// `base` and `base + size` back to functions.
+pub struct SymbolicationBundle {
+    /// Address the code was relocated to.
+    pub base:           usize,
+    /// Length of the relocated image.
+    pub size:           usize,
+    /// Symbols defined inside the image, by address.
+    pub symbols:        Vec<SymbolInfo>,
+    /// `.debug_*` sections, empty unless compiled with `-g` and `tcc-sys`
+    /// builds the vendored libtcc.
+    pub debug_sections: Vec<DebugSection>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A symbol of a [`SymbolicationBundle`].
+pub struct SymbolInfo {
+    pub name:    String,
+    /// Absolute address.
+    pub address: usize,
+    /// Size in bytes, 0 where unknown: sizes need the vendored libtcc, see
+    /// `SECTION_SIZES` in `tcc-sys`.
+    pub size:    usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Contents of a debug section, like `.debug_info`.
+pub struct DebugSection {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+impl SymbolicationBundle {
+    /// Whether `address` is inside the relocated image.
+    pub fn contains(&self, address: usize) -> bool {
+        address.wrapping_sub(self.base) < self.size
+    }
+
+    /// The symbol `address` belongs to and the offset into it.
+    ///
+    /// Without sizes an address belongs to the closest symbol below it.
+    pub fn lookup(&self, address: usize) -> Option<(&SymbolInfo, usize)> {
+        if !self.contains(address) {
+            return None;
+        }
+        let index = self.symbols.partition_point(|sym| sym.address <= address);
+        let sym = self.symbols[..index]
+            .iter()
+            .rev()
+            .find(|sym| sym.size == 0 || address - sym.address < sym.size)?;
+        Some((sym, address - sym.address))
+    }
+
+    /// The bundle as JSON, without the debug sections:
+    /// `{"base":…,"size":…,"symbols":[{"name":…,"address":…,"size":…}]}`.
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        let _ = write!(
+            out,
+            "{{\"base\":{},\"size\":{},\"symbols\":[",
+            self.base, self.size
+        );
+        for (i, sym) in self.symbols.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"name\":");
+            write_str(&mut out, &sym.name);
+            let _ = write!(out, ",\"address\":{},\"size\":{}}}", sym.address, sym.size);
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+impl<'a, 'err> RelocatedCtx<'a, 'err> {
+    /// Collect the base address, symbols and debug sections of the
+    /// relocated code for crash reporting, see [`SymbolicationBundle`].
+    pub fn export_symbolication(&self) -> SymbolicationBundle {
+        let (base, _) = self.bin.span();
+        let size = self.bin.len();
+        let state = self.inner.inner;
+
+        extern "C" fn sized(ctx: *mut c_void, name: *const c_char, addr: usize, size: usize) {
+            let symbols = unsafe { &mut *(ctx as *mut Vec<SymbolInfo>) };
+            let name = unsafe { CStr::from_ptr(name) };
+            symbols.push(SymbolInfo {
+                name: name.to_string_lossy().into_owned(),
+                address: addr,
+                size,
+            });
+        }
+        extern "C" fn without_size(ctx: *mut c_void, name: *const c_char, val: *const c_void) {
+            sized(ctx, name, val as usize, 0);
+        }
+        let mut symbols: Vec<SymbolInfo> = Vec::new();
+        let ctx = &mut symbols as *mut Vec<SymbolInfo> as *mut c_void;
+        match SECTION_SIZES {
+            true => unsafe { tcc_rs_list_symbol_sizes(state, ctx, sized) },
+            false => unsafe { tcc_list_symbols(state, ctx, Some(without_size)) },
+        }
+        // host functions the code was linked against are someone else's
+        symbols.retain(|sym| sym.address.wrapping_sub(base) < size);
+        symbols.sort_by(|a, b| (a.address, &a.name).cmp(&(b.address, &b.name)));
+
+        extern "C" fn debug(
+            ctx: *mut c_void,
+            name: *const c_char,
+            data: *const c_void,
+            len: usize,
+        ) {
+            let sections = unsafe { &mut *(ctx as *mut Vec<DebugSection>) };
+            let name = unsafe { CStr::from_ptr(name) };
+            let data = unsafe { core::slice::from_raw_parts(data as *const u8, len) };
+            sections.push(DebugSection {
+                name: name.to_string_lossy().into_owned(),
+                data: data.to_owned(),
+            });
+        }
+        let mut debug_sections: Vec<DebugSection> = Vec::new();
+        unsafe {
+            tcc_rs_list_debug_sections(
+                state,
+                &mut debug_sections as *mut Vec<DebugSection> as *mut c_void,
+                debug,
+            )
+        };
+
+        SymbolicationBundle {
+            base,
+            size,
+            symbols,
+            debug_sections,
+        }
+    }
+}
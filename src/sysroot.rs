@@ -0,0 +1,120 @@
+//! Falling back to the embedded musl headers when the host has no C
+//! library headers installed, see
+//! [`Context::fallback_to_embedded_sysroot`].
+
+use alloc::{ffi::CString, format};
+use core::ffi::CStr;
+use std::path::Path;
+
+use tcc_sys::PREPROCESS_OUTPUT;
+
+use crate::{capabilities, profile::EMBEDDED_MUSL, Context, Error, SourceLimits};
+
+/// Headers of the C standard and POSIX the fallback is tried for.
+const STANDARD_HEADERS: &[&str] = &[
+    "assert.h",
+    "complex.h",
+    "ctype.h",
+    "dirent.h",
+    "errno.h",
+    "fcntl.h",
+    "fenv.h",
+    "inttypes.h",
+    "limits.h",
+    "locale.h",
+    "math.h",
+    "pthread.h",
+    "setjmp.h",
+    "signal.h",
+    "stdio.h",
+    "stdlib.h",
+    "string.h",
+    "strings.h",
+    "threads.h",
+    "time.h",
+    "uchar.h",
+    "unistd.h",
+    "wchar.h",
+    "wctype.h",
+];
+
+/// Whether `header` belongs to the C library, so a sysroot provides it.
+fn is_standard(header: &str) -> bool {
+    STANDARD_HEADERS.contains(&header) || header.starts_with("sys/")
+}
+
+/// A source about to be compiled, see [`Context::probe_sysroot`].
+pub(crate) enum Probe<'a> {
+    String(&'a CStr),
+    File(&'a Path),
+}
+
+impl<'err> Context<'err> {
+    /// Retry compilations failing on a missing C library header, like
+    /// `<stdio.h>` on a machine without development headers, with the
+    /// embedded musl headers added to the system include paths.
+    ///
+    /// Until the switch, C sources are first preprocessed in a
+    /// [fork](Context::fork) of this context to find missing headers, so
+    /// this context only ever compiles them once. The switch is kept for the
+    /// rest of the context's life, reported as a warning diagnostic and by
+    /// [`Context::embedded_sysroot_used`]. Needs the `musl` feature, else
+    /// this fails with [`Error::UnsupportedOption`].
+    pub fn fallback_to_embedded_sysroot(&mut self, enable: bool) -> Result<&mut Self, Error> {
+        let caps = capabilities();
+        if enable && !(caps.vfs && caps.embedded_musl) {
+            return Err(Error::UnsupportedOption {
+                option: "embedded sysroot",
+                reason: "musl is not embedded, see the `musl` feature",
+            });
+        }
+        self.sysroot_fallback = enable;
+        Ok(self)
+    }
+
+    /// The embedded sysroot compilations switched to, see
+    /// [`Context::fallback_to_embedded_sysroot`], or `None` while the
+    /// host's headers are used.
+    pub fn embedded_sysroot_used(&self) -> Option<&'static str> {
+        self.sysroot
+    }
+
+    /// Switch to the embedded sysroot before `probe` is compiled if a fork
+    /// of this context fails on a missing C library header in it.
+    pub(crate) fn probe_sysroot(&mut self, probe: Probe) -> Result<(), Error> {
+        if !self.sysroot_fallback || self.sysroot.is_some() {
+            return Ok(());
+        }
+        if let Probe::File(path) = probe {
+            // objects, archives and linker scripts include nothing
+            if !path.extension().is_some_and(|ext| ext == "c" || ext == "S") {
+                return Ok(());
+            }
+        }
+        let mut scratch = self.fork()?;
+        scratch.sysroot_fallback = false;
+        scratch.banned.clear();
+        scratch.inline_asm = true;
+        scratch.source_limits = SourceLimits::default();
+        scratch.sink.user = None;
+        scratch.sink.collector.enabled = false;
+        let ret = match (probe, PREPROCESS_OUTPUT) {
+            (Probe::String(source), true) => {
+                scratch.preprocess_str(&source.to_string_lossy()).map(drop)
+            }
+            (Probe::String(source), false) => scratch.compile_unscreened(source),
+            (Probe::File(path), true) => scratch.preprocess_file(path).map(drop),
+            (Probe::File(path), false) => scratch.add_file_as_is(path),
+        };
+        let header = match ret {
+            Err(Error::IncludeNotFound { header, .. }) if is_standard(&header) => header,
+            _ => return Ok(()),
+        };
+        let message =
+            format!("warning: <{header}> not found on the host, using the embedded musl headers");
+        self.sink.dispatch(&CString::new(message).unwrap());
+        self.sysroot = Some("musl");
+        self.add_sys_include_path(format!("{EMBEDDED_MUSL}/include"));
+        Ok(())
+    }
+}
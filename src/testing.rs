@@ -0,0 +1,147 @@
+//! Helpers for tests that depend on how the vendored tcc behaves.
+
+use alloc::{format, string::String, vec, vec::Vec};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{capabilities, lock, profile::EMBEDDED_HEADERS, Context, Error};
+
+/// Lines of unchanged output shown around a difference.
+const CONTEXT: usize = 2;
+
+/// Preprocess every `.c` file under `dir` and compare the output with the
+/// `.i` file next to it, panicking with a diff of every mismatch.
+///
+/// Files are preprocessed with `-P`, so the expectations hold no line
+/// markers and thus no paths, with their own directory as include path and
+/// tcc's embedded headers, when available, as system headers. With
+/// `TCC_RS_BLESS` set in the environment the expectations are written
+/// instead of compared.
+///
+/// ```no_run
+/// #[test]
+/// fn macros() {
+///     tcc::testing::golden_preprocess("tests/preprocess");
+/// }
+/// ```
+pub fn golden_preprocess(dir: impl AsRef<Path>) {
+    golden_preprocess_with(dir, |_| {});
+}
+
+/// [`golden_preprocess`] with `configure` applied to the context of every
+/// file, e.g. to define macros or add include paths.
+pub fn golden_preprocess_with(dir: impl AsRef<Path>, mut configure: impl FnMut(&mut Context)) {
+    let bless = env::var_os("TCC_RS_BLESS").is_some();
+    let mut sources = Vec::new();
+    if let Err(err) = collect_sources(dir.as_ref(), &mut sources) {
+        panic!("failed to list {}: {err}", dir.as_ref().display());
+    }
+    sources.sort();
+
+    let mut failures = String::new();
+    for source in &sources {
+        let expected_path = source.with_extension("i");
+        let actual = match preprocess(source, &mut configure) {
+            Ok(actual) => actual,
+            Err(err) => {
+                failures += &format!("{}: preprocessing failed: {err}\n", source.display());
+                continue;
+            }
+        };
+        if bless {
+            if let Err(err) = fs::write(&expected_path, &actual) {
+                panic!("failed to write {}: {err}", expected_path.display());
+            }
+            continue;
+        }
+        match fs::read_to_string(&expected_path) {
+            Ok(expected) if expected.replace("\r\n", "\n") == actual => {}
+            Ok(expected) => {
+                failures += &format!("{}:\n", source.display());
+                failures += &diff(&expected.replace("\r\n", "\n"), &actual);
+            }
+            Err(err) => {
+                failures += &format!("{}: {err}\n", expected_path.display());
+            }
+        }
+    }
+    if !failures.is_empty() {
+        panic!("preprocessor output differs, set TCC_RS_BLESS to accept it\n{failures}");
+    }
+}
+
+fn collect_sources(dir: &Path, sources: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_sources(&path, sources)?;
+        } else if path.extension().is_some_and(|extension| extension == "c") {
+            sources.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn preprocess(source: &Path, configure: &mut impl FnMut(&mut Context)) -> Result<String, Error> {
+    let _lock = lock();
+    let mut ctx = Context::new()?;
+    let caps = capabilities();
+    if caps.vfs && caps.embedded_headers {
+        ctx.add_sys_include_path(EMBEDDED_HEADERS);
+    }
+    if let Some(dir) = source.parent() {
+        ctx.add_include_path(dir);
+    }
+    ctx.set_options(c"-P");
+    configure(&mut ctx);
+    ctx.preprocess_file(source)
+}
+
+/// `expected` against `actual` line by line, `-` marking expected lines
+/// that are missing and `+` lines that are new.
+fn diff(expected: &str, actual: &str) -> String {
+    let (old, new): (Vec<&str>, Vec<&str>) = (expected.lines().collect(), actual.lines().collect());
+    // longest common subsequence of the suffixes
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = match old[i] == new[j] {
+                true => common[i + 1][j + 1] + 1,
+                false => common[i + 1][j].max(common[i][j + 1]),
+            };
+        }
+    }
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', old[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(('-', old[i]));
+            i += 1;
+        } else {
+            lines.push(('+', new[j]));
+            j += 1;
+        }
+    }
+
+    let changed = |at: usize| {
+        let range = at.saturating_sub(CONTEXT)..(at + CONTEXT + 1).min(lines.len());
+        lines[range].iter().any(|(mark, _)| *mark != ' ')
+    };
+    let mut out = String::new();
+    let mut skipped = false;
+    for (at, (mark, line)) in lines.iter().enumerate() {
+        if changed(at) {
+            out += &format!("{mark} {line}\n");
+            skipped = false;
+        } else if !skipped {
+            out += "  ...\n";
+            skipped = true;
+        }
+    }
+    out
+}
@@ -1,11 +1,11 @@
 use alloc::{ffi::CString, rc::Rc};
-use core::{cell::Cell, ffi::c_int, intrinsics::transmute};
+use core::{cell::Cell, ffi::c_int, mem::transmute};
 use std::{
     env::temp_dir,
     fs::{remove_file, write},
 };
 
-use crate::{scoped, OutputType};
+use crate::{scoped, Diagnostic, Format, OutputType, Severity};
 
 #[test]
 fn set_call_back() {
@@ -13,7 +13,7 @@ fn set_call_back() {
     scoped(|scope| {
         let ctx = scope.spawn().unwrap();
         let call_back_ret = Rc::new(Cell::new(None));
-        ctx.set_output_type(OutputType::Memory);
+        ctx.set_output_type(OutputType::Memory).unwrap();
         ctx.set_call_back({
             let call_back_ret = call_back_ret.clone();
             move |_| call_back_ret.set(Some("called"))
@@ -33,7 +33,7 @@ fn add_sys_include_path() {
 
     scoped(|scope| {
         let ctx = scope.spawn().unwrap();
-        ctx.set_output_type(OutputType::Memory);
+        ctx.set_output_type(OutputType::Memory).unwrap();
         assert!(ctx.add_sys_include_path(&dir).compile_string(&p).is_ok());
         remove_file(dir.join("libtcc_test_0_9_27.h")).unwrap();
     })
@@ -49,7 +49,7 @@ fn add_include_path() {
 
     scoped(|scope| {
         let ctx = scope.spawn().unwrap();
-        ctx.set_output_type(OutputType::Memory);
+        ctx.set_output_type(OutputType::Memory).unwrap();
         assert!(ctx.add_include_path(&dir).compile_string(&p).is_ok());
         remove_file(dir.join("libtcc_test_0_9_27.h")).unwrap();
     })
@@ -71,8 +71,8 @@ fn symbol_define() {
 
     scoped(|scope| {
         let ctx = scope.spawn().unwrap();
-        ctx.set_output_type(OutputType::Memory);
-        ctx.define_symbol(&sym, &val);
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.define(&sym, Some(&val));
         assert!(ctx.compile_string(&p).is_err());
         ctx.undefine_symbol(&sym);
         assert!(ctx.compile_string(&p).is_ok());
@@ -80,6 +80,363 @@ fn symbol_define() {
     .unwrap();
 }
 
+#[test]
+fn define_many() {
+    let p = c"#if !defined(BARE) || BARE != 1 || VALUE != 2\n#error defines\n#endif\n";
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.define_many(&[(c"BARE", None), (c"VALUE", Some(c"2"))]);
+        ctx.compile_string(p).unwrap();
+
+        let log = ctx.record();
+        assert!(log.to_string().contains("define BARE\n"));
+        assert_eq!(log.to_string().parse::<crate::ConfigLog>().unwrap(), log);
+    })
+    .unwrap();
+}
+
+#[test]
+fn bulk_configuration() {
+    let p = c"#if !defined(BARE) || MAX(1, VALUE) != 2\n#error defines\n#endif\n";
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+
+        let bad = ctx.defines(&[("OK", None), ("2BAD", None)]);
+        assert!(matches!(
+            bad,
+            Err(crate::Error::InvalidEntry { index: 1, .. })
+        ));
+        let injected = ctx.defines(&[("VALUE", Some("2\n#define EVIL"))]);
+        assert!(matches!(
+            injected,
+            Err(crate::Error::InvalidEntry { index: 0, .. })
+        ));
+        assert!(!ctx.record().to_string().contains("define OK"));
+
+        let paths = ctx.add_include_paths(&["/usr/include", "bad\0path"]);
+        assert!(matches!(
+            paths,
+            Err(crate::Error::InvalidEntry { index: 1, .. })
+        ));
+        let libraries = ctx.add_libraries(&["", "m"]);
+        assert!(matches!(
+            libraries,
+            Err(crate::Error::InvalidEntry { index: 0, .. })
+        ));
+        let missing = ctx.add_libraries(&["tcc_rs_no_such_library"]);
+        assert!(matches!(
+            missing,
+            Err(crate::Error::InvalidEntry {
+                reason: "library not found",
+                ..
+            })
+        ));
+
+        ctx.defines(&[
+            ("BARE", None),
+            ("VALUE", Some("2")),
+            ("MAX(a, b)", Some("((a) > (b) ? (a) : (b))")),
+        ])
+        .unwrap();
+        ctx.compile_string(p).unwrap();
+    })
+    .unwrap();
+}
+
+#[test]
+fn library_not_found() {
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.add_library_path("/tcc_rs/no/such/dir");
+        let err = ctx.add_library(c"tcc_rs_missing");
+        let Err(crate::Error::LibraryNotFound {
+            name,
+            search_paths,
+            candidates,
+        }) = err
+        else {
+            panic!("expected LibraryNotFound, got {err:?}");
+        };
+        assert_eq!(name, "tcc_rs_missing");
+        assert!(search_paths
+            .iter()
+            .any(|path| path == "/tcc_rs/no/such/dir"));
+        assert!(candidates.iter().any(|file| file == "libtcc_rs_missing.a"));
+    })
+    .unwrap();
+}
+
+#[cfg(feature = "vfs")]
+#[test]
+fn linker_script() {
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        if crate::capabilities().format == crate::ExecutableFormat::Pe {
+            assert!(ctx.add_linker_script_str("INPUT(-lm)").is_err());
+            return;
+        }
+
+        let sections = ctx.add_linker_script_str("/* custom */\nSECTIONS { .text : { *(.text) } }");
+        assert!(matches!(
+            sections,
+            Err(crate::Error::LinkerScript { line: 2, .. })
+        ));
+        let commas = ctx.add_linker_script_str("GROUP(a.o, b.o)");
+        assert!(matches!(
+            commas,
+            Err(crate::Error::LinkerScript { line: 1, .. })
+        ));
+        let open = ctx.add_linker_script_str("INPUT(-lm");
+        assert!(matches!(open, Err(crate::Error::LinkerScript { .. })));
+
+        let script =
+            "/* GNU ld script */\nOUTPUT_FORMAT(elf64-x86-64)\nGROUP ( AS_NEEDED ( -lm ) )\n";
+        ctx.add_linker_script_str(script).unwrap();
+        ctx.compile_string(c"double cos(double); double f(double x) { return cos(x); }")
+            .unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        assert!(unsafe { relocated.get_symbol(c"f") }.is_some());
+    })
+    .unwrap();
+}
+
+#[test]
+fn archive_create() {
+    let dir = temp_dir();
+    let (one, two) = (dir.join("tcc_rs_ar_one.o"), dir.join("tcc_rs_ar_two.o"));
+    let lib = dir.join("libtcc_rs_ar.a");
+    scoped(|scope| {
+        for (object, source) in [
+            (&one, c"int one(void) { return 1; }"),
+            (&two, c"int two(void) { return 2; }"),
+        ] {
+            let ctx = scope.spawn().unwrap();
+            ctx.set_output_type(OutputType::Obj).unwrap();
+            ctx.compile_string(source).unwrap();
+            ctx.output_file(object).unwrap();
+        }
+    })
+    .unwrap();
+    crate::ar::create(&lib, &[&one, &two]).unwrap();
+    let archive = std::fs::read(&lib).unwrap();
+    assert!(archive.starts_with(b"!<arch>\n/ "));
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.compile_string(
+            c"int one(void); int two(void); int both(void) { return one() + two(); }",
+        )
+        .unwrap();
+        ctx.add_file(&lib).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let both: fn() -> c_int = unsafe { transmute(relocated.get_symbol(c"both").unwrap()) };
+        assert_eq!(both(), 3);
+    })
+    .unwrap();
+    for file in [one, two, lib] {
+        remove_file(file).unwrap();
+    }
+
+    let bytes = crate::ar::create_bytes(&[("notes.txt", b"not an object")]);
+    assert!(bytes.starts_with(b"!<arch>\n/ "));
+    assert!(bytes.ends_with(b"not an object\n"));
+}
+
+#[cfg(feature = "vfs")]
+#[test]
+fn link_combine() {
+    let objects = scoped(|scope| {
+        let mut objects = Vec::new();
+        for source in [
+            c"int one(void) { return 1; }",
+            c"int one(void); int two(void) { return one() + 1; }",
+        ] {
+            let ctx = scope.spawn().unwrap();
+            ctx.set_output_type(OutputType::Obj).unwrap();
+            ctx.compile_string(source).unwrap();
+            objects.push(ctx.output_bytes().unwrap());
+        }
+        objects
+    })
+    .unwrap()
+    .get()
+    .clone();
+
+    let combined = crate::link::combine(OutputType::Obj, &objects).unwrap();
+    assert!(!combined.is_empty());
+    let memory = crate::link::combine(OutputType::Memory, &objects);
+    assert!(matches!(memory, Err(crate::Error::InvalidState { .. })));
+    let garbage = crate::link::combine(OutputType::Obj, &[b"not an object"]);
+    assert!(garbage.is_err());
+
+    let object = temp_dir().join("tcc_rs_combined.o");
+    write(&object, combined).unwrap();
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.add_file(&object).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let two: fn() -> c_int = unsafe { transmute(relocated.get_symbol(c"two").unwrap()) };
+        assert_eq!(two(), 2);
+    })
+    .unwrap();
+    remove_file(object).unwrap();
+}
+
+#[test]
+fn gc_sections() {
+    let p = c"int tcc_rs_unused_helper(int x) { return x * 3; }\nint main(void) { return 0; }\n";
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        assert!(ctx.gc_sections(true).is_err());
+        assert!(ctx.gc_sections(false).is_ok());
+
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Exe).unwrap();
+        if ctx.gc_sections(true).is_err() {
+            return;
+        }
+        assert!(ctx.gc_sections(false).is_err());
+        ctx.compile_string(p).unwrap();
+        let exe = ctx.output_bytes().unwrap();
+        let name = b"tcc_rs_unused_helper";
+        assert!(!exe.windows(name.len()).any(|window| window == name));
+    })
+    .unwrap();
+}
+
+#[test]
+fn map_file() {
+    let p = c"int tcc_rs_mapped = 42;\nint main(void) { return tcc_rs_mapped; }\n";
+    let dir = temp_dir();
+    let (exe, map) = (dir.join("tcc_rs_mapped"), dir.join("tcc_rs_mapped.map"));
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        assert!(ctx.write_map_file(&map).is_err());
+
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Exe).unwrap();
+        if crate::capabilities().format != crate::ExecutableFormat::Elf {
+            assert!(ctx.write_map_file(&map).is_err());
+            return;
+        }
+        ctx.write_map_file(&map).unwrap();
+        ctx.compile_string(p).unwrap();
+        ctx.output_file(&exe).unwrap();
+
+        let text = std::fs::read_to_string(&map).unwrap();
+        assert!(text.starts_with("Sections:\n"));
+        assert!(text.lines().any(|line| line.contains(".text")));
+        assert!(text
+            .lines()
+            .any(|line| line.contains(".data") && line.ends_with(" tcc_rs_mapped")));
+        assert!(text.lines().any(|line| line.ends_with(" main")));
+        remove_file(&exe).unwrap();
+        remove_file(&map).unwrap();
+    })
+    .unwrap();
+}
+
+#[test]
+fn include_not_found() {
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .add_include_path("/tcc_rs/no/such/include");
+        let err = ctx.compile_string(c"#include \"tcc_rs_missing.h\"\n");
+        let Err(crate::Error::IncludeNotFound {
+            header,
+            search_paths,
+            ..
+        }) = err
+        else {
+            panic!("expected IncludeNotFound, got {err:?}");
+        };
+        assert_eq!(header, "tcc_rs_missing.h");
+        assert_eq!(search_paths[0], "/tcc_rs/no/such/include");
+
+        // other failures stay compile errors
+        let err = ctx.compile_string(c"int broken(void) { return }");
+        assert!(matches!(err, Err(crate::Error::Compile { .. })));
+    })
+    .unwrap();
+}
+
+#[cfg(feature = "vfs")]
+#[test]
+fn embedded_sysroot_fallback() {
+    let caps = crate::capabilities();
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        if !caps.embedded_musl {
+            assert!(ctx.fallback_to_embedded_sysroot(true).is_err());
+            return;
+        }
+        // no host headers, as on a machine without a C library installed
+        ctx.set_option("-nostdinc").unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.fallback_to_embedded_sysroot(true).unwrap();
+        assert_eq!(ctx.embedded_sysroot_used(), None);
+        // compiled once, so the definition before the include is no duplicate
+        ctx.compile_string(c"int before(void) { return 1; }\n#include <string.h>\nsize_t len(const char *s) { return strlen(s); }")
+            .unwrap();
+        assert_eq!(ctx.embedded_sysroot_used(), Some("musl"));
+
+        // headers outside the C library are not retried
+        let err = ctx.compile_string(c"#include \"tcc_rs_missing.h\"\n");
+        assert!(matches!(err, Err(crate::Error::IncludeNotFound { .. })));
+    })
+    .unwrap();
+}
+
+#[test]
+fn default_defines() {
+    let caps = crate::capabilities();
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.collect_diagnostics(true);
+        let defines = ctx.default_defines();
+        let has = |name: &core::ffi::CStr| defines.iter().any(|&(define, _)| define == name);
+        assert_eq!(has(c"__x86_64__"), caps.arch == crate::Arch::X86_64);
+        assert_eq!(has(c"_WIN32"), caps.format == crate::ExecutableFormat::Pe);
+        assert!(!has(c"_GNU_SOURCE"));
+
+        ctx.set_gnu_source(true);
+        assert!(ctx.default_defines().contains(&(c"_GNU_SOURCE", None)));
+        ctx.set_gnu_source(false);
+        assert!(!ctx.default_defines().contains(&(c"_GNU_SOURCE", None)));
+
+        // the backend's macro is there, another backend's warns
+        let foreign = match caps.arch {
+            crate::Arch::X86_64 => c"__aarch64__",
+            _ => c"__x86_64__",
+        };
+        ctx.define(foreign, None);
+        assert!(ctx
+            .diagnostics()
+            .iter()
+            .any(|diag| diag.message.contains("still generates")));
+        ctx.undefine_symbol(foreign);
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        let arch = defines[0].0.to_str().unwrap();
+        let p = CString::new(format!("#ifndef {arch}\n#error missing\n#endif\n")).unwrap();
+        ctx.compile_string(&p).unwrap();
+    })
+    .unwrap();
+}
+
 #[test]
 fn output_exe_file() {
     let p = CString::new(
@@ -96,7 +453,7 @@ fn output_exe_file() {
 
     scoped(|scope| {
         let ctx = scope.spawn().unwrap();
-        ctx.set_output_type(OutputType::Exe);
+        ctx.set_output_type(OutputType::Exe).unwrap();
         assert!(ctx.compile_string(&p).is_ok());
         let dir = temp_dir();
         let exe = dir.join("a.out");
@@ -121,7 +478,7 @@ fn output_lib() {
 
     scoped(|scope| {
         let ctx = scope.spawn().unwrap();
-        ctx.set_output_type(OutputType::Dll);
+        ctx.set_output_type(OutputType::Dll).unwrap();
         assert!(ctx.compile_string(&p).is_ok());
         let dir = temp_dir();
         let lib = dir.join("lib");
@@ -132,6 +489,70 @@ fn output_lib() {
     .unwrap();
 }
 
+#[cfg(target_os = "macos")]
+#[test]
+fn output_mach_o() {
+    use std::process::Command;
+
+    let exe = CString::new("int main(void) { return 42; }").unwrap();
+    let lib = CString::new("int add(int a, int b) { return a + b; }").unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Exe).unwrap();
+        ctx.compile_string(&exe).unwrap();
+        let path = temp_dir().join("tcc_rs_mach_o");
+        ctx.output_file(&path).unwrap();
+        let status = Command::new(&path).status().unwrap();
+        assert_eq!(status.code(), Some(42));
+        remove_file(&path).unwrap();
+
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Dll).unwrap();
+        ctx.compile_string(&lib).unwrap();
+        let path = temp_dir().join("libtcc_rs_mach_o.dylib");
+        ctx.output_file(&path).unwrap();
+        let name = CString::new(path.to_str().unwrap()).unwrap();
+        unsafe {
+            let handle = libc::dlopen(name.as_ptr(), libc::RTLD_NOW);
+            assert!(!handle.is_null());
+            let add: extern "C" fn(c_int, c_int) -> c_int =
+                transmute(libc::dlsym(handle, c"add".as_ptr()));
+            assert_eq!(add(1, 2), 3);
+            libc::dlclose(handle);
+        }
+        remove_file(&path).unwrap();
+    })
+    .unwrap();
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+#[test]
+fn output_elf_exe() {
+    use std::{os::unix::fs::PermissionsExt, process::Command};
+
+    let p = CString::new("int main(void) { return 42; }").unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Exe).unwrap();
+        ctx.compile_string(&p).unwrap();
+        let path = temp_dir().join("tcc_rs_elf");
+        ctx.output_file(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_ne!(mode & 0o111, 0);
+        let status = Command::new(&path).status().unwrap();
+        assert_eq!(status.code(), Some(42));
+        remove_file(&path).unwrap();
+    })
+    .unwrap();
+}
+
 #[test]
 fn output_obj() {
     let p = CString::new(
@@ -146,7 +567,7 @@ fn output_obj() {
 
     scoped(|scope| {
         let ctx = scope.spawn().unwrap();
-        ctx.set_output_type(OutputType::Obj);
+        ctx.set_output_type(OutputType::Obj).unwrap();
         assert!(ctx.compile_string(&p).is_ok());
         let dir = temp_dir();
         let obj = dir.join("obj");
@@ -173,7 +594,7 @@ fn run_func() {
 
     scoped(|scope| {
         let ctx = scope.spawn().unwrap();
-        ctx.set_output_type(OutputType::Memory);
+        ctx.set_output_type(OutputType::Memory).unwrap();
         assert!(ctx.compile_string(&p).is_ok());
         let mut relocated = ctx.relocate().unwrap();
 
@@ -210,13 +631,13 @@ fn add_symbol() {
 
     scoped(|scope| {
         let ctx = scope.spawn().unwrap();
-        ctx.set_output_type(OutputType::Memory);
+        ctx.set_output_type(OutputType::Memory).unwrap();
         assert!(ctx.compile_string(&p).is_ok());
         let mut relocated = ctx.relocate().unwrap();
         let add = unsafe { relocated.get_symbol(&sym).unwrap() };
 
         let ctx = scope.spawn().unwrap();
-        ctx.set_output_type(OutputType::Memory);
+        ctx.set_output_type(OutputType::Memory).unwrap();
         assert!(ctx.compile_string(&p2).is_ok());
         unsafe {
             ctx.add_symbol(&sym, add);
@@ -247,7 +668,7 @@ fn link_lib() {
 
     scoped(|scope| {
         let ctx = scope.spawn().unwrap();
-        ctx.set_output_type(OutputType::Dll);
+        ctx.set_output_type(OutputType::Dll).unwrap();
         assert!(ctx.compile_string(&p).is_ok());
 
         ctx.output_file(&lib).unwrap();
@@ -268,6 +689,7 @@ fn link_lib() {
 
         let ctx2 = scope.spawn().unwrap();
         ctx2.set_output_type(OutputType::Memory)
+            .unwrap()
             .add_library_path(&dir)
             .add_library(&lib_name)
             .unwrap();
@@ -283,3 +705,2929 @@ fn link_lib() {
     })
     .unwrap();
 }
+
+#[test]
+fn callback_macro() {
+    let p = CString::new(
+        r#"
+        int apply(int x);
+        int run(int a){
+            return apply(a) + 1;
+        }
+        "#
+        .as_bytes(),
+    )
+    .unwrap();
+    let apply = CString::new("apply".as_bytes()).unwrap();
+    let run = CString::new("run".as_bytes()).unwrap();
+    let offset = 10;
+    let cb = crate::tcc_callback!(|x: c_int| -> c_int { x * 2 + offset });
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        assert!(ctx.compile_string(&p).is_ok());
+        unsafe {
+            ctx.add_symbol(&apply, cb.as_ptr());
+        }
+        let mut relocated = ctx.relocate().unwrap();
+        let run: extern "C" fn(c_int) -> c_int =
+            unsafe { transmute(relocated.get_symbol(&run).unwrap()) };
+        assert_eq!(run(3), 17);
+    })
+    .unwrap();
+}
+
+#[test]
+fn panic_policy() {
+    use core::panic::AssertUnwindSafe;
+    use std::panic::catch_unwind;
+
+    use crate::{set_panic_policy, take_panic, PanicPolicy};
+
+    let cb = crate::tcc_callback!(|x: c_int| -> c_int {
+        assert!(x >= 0, "negative");
+        x * 2
+    });
+    let message = |payload: Box<dyn core::any::Any + Send>| {
+        payload.downcast::<&str>().map(|message| *message).unwrap()
+    };
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.compile_str("int twice(int x);\nint run(int a) { return twice(a) + 1; }")
+            .unwrap();
+        unsafe { ctx.add_symbol(c"twice", cb.as_ptr()) };
+        let mut relocated = ctx.relocate().unwrap();
+        let run: extern "C" fn(c_int) -> c_int =
+            unsafe { transmute(relocated.get_symbol(c"run").unwrap()) };
+
+        set_panic_policy(PanicPolicy::CaptureAndContinue);
+        assert_eq!(run(2), 5);
+        assert_eq!(run(-1), 1);
+        assert_eq!(message(take_panic().unwrap()), "negative");
+        assert!(take_panic().is_none());
+
+        set_panic_policy(PanicPolicy::Propagate);
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .set_call_back(|_| panic!("diagnostic"));
+        let compiled = catch_unwind(AssertUnwindSafe(|| ctx.compile_str("#error stop\n")));
+        set_panic_policy(PanicPolicy::Abort);
+        assert_eq!(message(compiled.unwrap_err()), "diagnostic");
+    })
+    .unwrap();
+}
+
+#[test]
+fn dedup_diagnostics() {
+    let p = CString::new(
+        r#"
+        void f(void){
+            int *p;
+            p = 1;
+            p = 2;
+            p = 3;
+        }
+        "#
+        .as_bytes(),
+    )
+    .unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .collect_diagnostics(true)
+            .dedup_diagnostics(true);
+        assert!(ctx.compile_string(&p).is_ok());
+        assert_eq!(ctx.diagnostics().len(), 1);
+        assert_eq!(ctx.diagnostics()[0].severity, Severity::Warning);
+        assert_eq!(ctx.diagnostics()[0].location.as_ref().unwrap().line, 4);
+        assert_eq!(ctx.suppressed_diagnostics(), 2);
+    })
+    .unwrap();
+}
+
+#[test]
+fn parse_diagnostic() {
+    let diag = Diagnostic::parse("In file included from a.c:1:\nb.h:2: warning: oops: again");
+    assert_eq!(diag.severity, Severity::Warning);
+    assert_eq!(diag.included_from[0].file, "a.c");
+    assert_eq!(diag.location.as_ref().unwrap().line, 2);
+    assert_eq!(diag.message, "oops: again");
+    assert_eq!(
+        Diagnostic::parse("tcc: error: undefined symbol 'x'").location,
+        None
+    );
+}
+
+#[test]
+fn sarif_export() {
+    let diag = Diagnostic::parse("<string>:2: warning: \"quoted\"");
+    assert_eq!(
+        crate::diag::to_sarif(&[diag]),
+        concat!(
+            r#"{"$schema":"https://json.schemastore.org/sarif-2.1.0.json","version":"2.1.0","#,
+            r#""runs":[{"tool":{"driver":{"name":"tcc","informationUri":"https://repo.or.cz/w/tinycc.git"}},"#,
+            r#""results":[{"level":"warning","message":{"text":"\"quoted\""},"#,
+            r#""locations":[{"physicalLocation":{"artifactLocation":{"uri":"<string>"},"region":{"startLine":2}}}]}]}]}"#
+        )
+    );
+}
+
+#[cfg(feature = "pretty-errors")]
+#[test]
+fn pretty_errors() {
+    let source = "int f(void) {\n    return x;\n}\n";
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        let (ret, diagnostics) = ctx.compile_with_diagnostics(&CString::new(source).unwrap());
+        assert!(ret.is_err());
+        let rendered = crate::diag::render_pretty(&diagnostics, source, false);
+        assert!(rendered.starts_with("error: 'x' undeclared\n"));
+        assert!(rendered.contains(" --> <string>:2\n"));
+        assert!(rendered.contains("2 |     return x;\n  |     ^^^^^^^^^\n"));
+        let colored = crate::diag::render_pretty(&diagnostics, source, true);
+        assert!(colored.contains("\x1b[1;31m^^^^^^^^^\x1b[0m"));
+    })
+    .unwrap();
+}
+
+#[cfg(feature = "miette")]
+#[test]
+fn miette_labels() {
+    use miette::Diagnostic as _;
+
+    let source = "int f(void) {\n    return x;\n}\n";
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .collect_diagnostics(true);
+        let err = ctx
+            .compile_string(&CString::new(source).unwrap())
+            .unwrap_err();
+        assert!(matches!(err, crate::Error::Compile { .. }));
+        assert_eq!(err.code().unwrap().to_string(), "tcc::compile");
+        assert!(err.source_code().is_some());
+        let labels = err.labels().unwrap().collect::<alloc::vec::Vec<_>>();
+        assert_eq!(labels.len(), 1);
+        assert_eq!(labels[0].label(), Some("'x' undeclared"));
+        assert_eq!(labels[0].offset(), "int f(void) {\n".len());
+        assert_eq!(labels[0].len(), "    return x;".len());
+        assert_eq!(err.related().unwrap().count(), 1);
+    })
+    .unwrap();
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn metrics() {
+    use alloc::sync::Arc;
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Mutex;
+
+    use metrics::{
+        Counter, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder, SharedString,
+        Unit,
+    };
+
+    use crate::metrics::{COMPILES, COMPILE_DURATION};
+
+    #[derive(Default)]
+    struct Durations(Mutex<alloc::vec::Vec<f64>>);
+
+    impl HistogramFn for Durations {
+        fn record(&self, value: f64) {
+            self.0.lock().unwrap().push(value);
+        }
+    }
+
+    /// Keeps the compile count and durations of string sources.
+    #[derive(Default)]
+    struct Compiles {
+        count:     Arc<AtomicU64>,
+        durations: Arc<Durations>,
+    }
+
+    impl Compiles {
+        fn of_strings(key: &Key, name: &str) -> bool {
+            key.name() == name
+                && key
+                    .labels()
+                    .any(|label| label.key() == "source" && label.value() == "string")
+        }
+    }
+
+    impl Recorder for Compiles {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            match Self::of_strings(key, COMPILES) {
+                true => Counter::from_arc(self.count.clone()),
+                false => Counter::noop(),
+            }
+        }
+
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+
+        fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+            match Self::of_strings(key, COMPILE_DURATION) {
+                true => Histogram::from_arc(self.durations.clone()),
+                false => Histogram::noop(),
+            }
+        }
+    }
+
+    let recorder = Compiles::default();
+    metrics::with_local_recorder(&recorder, || {
+        scoped(|scope| {
+            let ctx = scope.spawn().unwrap();
+            ctx.set_output_type(OutputType::Memory).unwrap();
+            ctx.compile_string(c"int add(int a, int b) { return a + b; }")
+                .unwrap();
+        })
+        .unwrap();
+    });
+    assert_eq!(recorder.count.load(Ordering::Relaxed), 1);
+    let durations = recorder.durations.0.lock().unwrap();
+    assert_eq!(durations.len(), 1);
+    assert!(durations[0] >= 0.0);
+}
+
+#[test]
+fn json_diagnostics() {
+    let p = CString::new("int f(void){ return x; }".as_bytes()).unwrap();
+    let messages = Rc::new(core::cell::RefCell::new(alloc::vec::Vec::new()));
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .diagnostics_format(Format::Json)
+            .set_call_back({
+                let messages = messages.clone();
+                move |msg| {
+                    messages
+                        .borrow_mut()
+                        .push(msg.to_str().unwrap().to_string())
+                }
+            });
+        assert!(ctx.compile_string(&p).is_err());
+    })
+    .unwrap();
+
+    let messages = messages.borrow();
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].starts_with(r#"{"severity":"error","file":"<string>","line":1,"#));
+}
+
+#[test]
+fn set_shared_call_back() {
+    let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink: crate::SharedCallBack = {
+        let seen = seen.clone();
+        std::sync::Arc::new(move |diag: &Diagnostic| seen.lock().unwrap().push(diag.clone()))
+    };
+    let workers: Vec<_> = (0..2)
+        .map(|_| {
+            let sink = sink.clone();
+            std::thread::spawn(move || {
+                scoped(|scope| {
+                    let ctx = scope.spawn().unwrap();
+                    ctx.set_output_type(OutputType::Memory)
+                        .unwrap()
+                        .set_shared_call_back(sink);
+                    assert!(ctx.compile_str("int main(void) { return x; }").is_err());
+                })
+                .unwrap();
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 2);
+    assert!(seen.iter().all(|diag| diag.severity == Severity::Error));
+}
+
+#[test]
+fn set_diagnostic_filter() {
+    let p = CString::new("void f(void){ int *p; p = 1; }\nint g(void){ return x; }").unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        let texts = Rc::new(core::cell::RefCell::new(Vec::new()));
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .collect_diagnostics(true)
+            .set_diagnostic_filter(|mut diag| {
+                if diag.severity == Severity::Warning {
+                    return None;
+                }
+                diag.message.push_str(" (did you declare it?)");
+                Some(diag)
+            })
+            .set_call_back({
+                let texts = texts.clone();
+                move |msg| texts.borrow_mut().push(msg.to_string_lossy().into_owned())
+            });
+        assert!(ctx.compile_string(&p).is_err());
+        assert_eq!(ctx.diagnostics().len(), 1);
+        assert_eq!(ctx.diagnostics()[0].severity, Severity::Error);
+        assert!(ctx.diagnostics()[0]
+            .message
+            .ends_with("(did you declare it?)"));
+        assert_eq!(ctx.suppressed_diagnostics(), 0);
+        assert_eq!(texts.borrow().len(), 1);
+        assert!(texts.borrow()[0].ends_with("(did you declare it?)"));
+    })
+    .unwrap();
+}
+
+#[test]
+fn diagnostics_receiver() {
+    let p = CString::new("void f(void){ int *p; p = 1; }".as_bytes()).unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        let rx = ctx.diagnostics_receiver();
+        assert!(ctx.compile_string(&p).is_ok());
+        let diag = rx.try_recv().unwrap();
+        assert_eq!(diag.severity, Severity::Warning);
+        assert!(rx.try_recv().is_err());
+    })
+    .unwrap();
+}
+
+#[test]
+fn compile_with_diagnostics() {
+    let p = CString::new("int f(void){ return x; }".as_bytes()).unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        let (ret, diagnostics) = ctx.compile_with_diagnostics(&p);
+        assert!(matches!(ret, Err(crate::Error::Compile { .. })));
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.contains("'x' undeclared"));
+        assert!(ctx.diagnostics().is_empty());
+    })
+    .unwrap();
+}
+
+#[test]
+fn check() {
+    let ok = CString::new("#ifdef TEST\nint f(void){ return 0; }\n#endif".as_bytes()).unwrap();
+    let bad = CString::new("#ifdef TEST\nint f(void){ return x; }\n#endif".as_bytes()).unwrap();
+    let sym = CString::new("TEST".as_bytes()).unwrap();
+    let val = CString::new("1".as_bytes()).unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.define(&sym, Some(&val));
+        assert!(ctx.check(&ok).is_ok());
+        let diagnostics = ctx.check(&bad).unwrap_err();
+        assert_eq!(diagnostics[0].location.as_ref().unwrap().line, 2);
+        // the context itself is untouched
+        assert!(ctx.compile_string(&ok).is_ok());
+        assert!(ctx.relocate().is_ok());
+    })
+    .unwrap();
+}
+
+#[test]
+fn add_source_bytes() {
+    let sym = CString::new("add".as_bytes()).unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .collect_diagnostics(true);
+        let err = ctx
+            .add_source_reader("broken.c", "int f(void){ return x; }".as_bytes())
+            .unwrap_err();
+        let crate::Error::Compile { diagnostics, .. } = err else {
+            panic!("unexpected error {err:?}");
+        };
+        assert_eq!(diagnostics[0].location.as_ref().unwrap().file, "broken.c");
+
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.add_source_bytes("add.c", b"int add(int a, int b){ return a + b; }")
+            .unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let add: extern "C" fn(c_int, c_int) -> c_int =
+            unsafe { transmute(relocated.get_symbol(&sym).unwrap()) };
+        assert_eq!(add(1, 2), 3);
+
+        // staged privately, yet next to `name` for quoted includes
+        let dir = temp_dir();
+        let header = dir.join("tcc_rs_staged.h");
+        write(&header, "#define STAGED 7\n").unwrap();
+        let name = dir.join("tcc_rs_staged.c");
+        let name = name.to_str().unwrap();
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        let source =
+            b"#include \"tcc_rs_staged.h\"\nint staged(void) { return STAGED; }\nconst char \
+                       *file(void) { return __FILE__; }";
+        let ret = ctx.add_source_bytes(name, source);
+        remove_file(&header).unwrap();
+        ret.unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let file: extern "C" fn() -> *const core::ffi::c_char =
+            unsafe { transmute(relocated.get_symbol(c"file").unwrap()) };
+        let file = unsafe { core::ffi::CStr::from_ptr(file()) };
+        assert_eq!(file.to_str().unwrap(), name);
+    })
+    .unwrap();
+}
+
+#[test]
+fn remap_path_prefix() {
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .collect_diagnostics(true)
+            .remap_path_prefix("/build/machine/", "");
+        let source = b"#include \"/build/machine/missing.h\"\n";
+        let err = ctx
+            .add_source_bytes("/build/machine/src/broken.c", source)
+            .unwrap_err();
+        let crate::Error::IncludeNotFound { diagnostics, .. } = err else {
+            panic!("unexpected error {err:?}");
+        };
+        assert_eq!(
+            diagnostics[0].location.as_ref().unwrap().file,
+            "src/broken.c"
+        );
+        assert!(!diagnostics[0].message.contains("/build/machine"));
+
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .remap_path_prefix("/build", "/elsewhere")
+            .remap_path_prefix("/build/machine", ".");
+        let source = b"const char *file(void) { return __FILE__; }";
+        ctx.add_source_bytes("/build/machine/file.c", source)
+            .unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let file: extern "C" fn() -> *const core::ffi::c_char =
+            unsafe { transmute(relocated.get_symbol(c"file").unwrap()) };
+        let file = unsafe { core::ffi::CStr::from_ptr(file()) };
+        assert_eq!(file.to_str().unwrap(), "./file.c");
+    })
+    .unwrap();
+}
+
+#[test]
+fn set_max_include_depth() {
+    let dir = temp_dir();
+    let headers = [
+        ("tcc_rs_depth_a.h", "#include \"tcc_rs_depth_b.h\"\n"),
+        ("tcc_rs_depth_b.h", "#include \"tcc_rs_depth_c.h\"\n"),
+        ("tcc_rs_depth_c.h", "int c;\n"),
+        ("tcc_rs_cycle.h", "#include \"tcc_rs_cycle.h\"\n"),
+    ];
+    for (name, content) in headers {
+        write(dir.join(name), content).unwrap();
+    }
+
+    scoped(|scope| {
+        let message = |depth, source: &core::ffi::CStr| {
+            let ctx = scope.spawn().unwrap();
+            ctx.set_output_type(OutputType::Memory)
+                .unwrap()
+                .add_include_path(&dir)
+                .collect_diagnostics(true)
+                .set_max_include_depth(Some(depth));
+            match ctx.compile_string(source) {
+                Ok(()) => None,
+                Err(crate::Error::Compile { diagnostics, .. }) => {
+                    Some(diagnostics[0].message.clone())
+                }
+                Err(err) => panic!("unexpected error {err:?}"),
+            }
+        };
+
+        assert_eq!(message(3, c"#include \"tcc_rs_depth_a.h\""), None);
+        let error = message(2, c"#include \"tcc_rs_depth_a.h\"").unwrap();
+        assert!(error.contains("include depth limit exceeded"));
+        assert!(error.contains("tcc_rs_depth_a.h -> "));
+        assert!(error.ends_with("tcc_rs_depth_c.h"));
+        let error = message(4, c"#include \"tcc_rs_cycle.h\"").unwrap();
+        assert!(error.contains("include cycle"));
+    })
+    .unwrap();
+
+    for (name, _) in headers {
+        remove_file(dir.join(name)).unwrap();
+    }
+}
+
+#[test]
+fn use_header_cache() {
+    let header = temp_dir().join("tcc_rs_cached.h");
+    write(&header, "#define CACHED 1\n").unwrap();
+    let cache = crate::HeaderCache::new();
+    let compile = || {
+        scoped(|scope| {
+            let ctx = scope.spawn().unwrap();
+            ctx.set_output_type(OutputType::Memory)
+                .unwrap()
+                .add_include_path(temp_dir())
+                .use_header_cache(&cache);
+            ctx.compile_string(c"#include \"tcc_rs_cached.h\"\nint x = CACHED;")
+                .is_ok()
+        })
+        .map(|ok| *ok.get())
+        .unwrap()
+    };
+
+    assert!(compile());
+    assert!(!cache.is_empty());
+    // served from the cache, the disk is not looked at again
+    write(&header, "#error stale\n").unwrap();
+    assert!(compile());
+    cache.clear();
+    assert!(!compile());
+    remove_file(header).unwrap();
+}
+
+#[test]
+fn msvc_compat() {
+    let p = CString::new(concat!(
+        "__declspec(dllexport) unsigned __int64 __stdcall\n",
+        "sum(_In_reads_(n) const int *xs, _In_ int n, _Out_opt_ int *__ptr64 count) {\n",
+        "    __pragma(warning(disable: 4100))\n",
+        "    unsigned __int64 total = 0;\n",
+        "    for (int i = 0; i < n; i++) total += xs[i];\n",
+        "    return total;\n",
+        "}\n",
+    ))
+    .unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .msvc_compat(true);
+        assert!(ctx.compile_string(&p).is_ok());
+
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .set_call_back(|_| {})
+            .msvc_compat(true)
+            .msvc_compat(false);
+        assert!(ctx.compile_string(&p).is_err());
+    })
+    .unwrap();
+}
+
+#[cfg(target_os = "windows")]
+#[test]
+fn windows_h() {
+    let p = CString::new(concat!(
+        "#include <windows.h>\n",
+        "DWORD ticks(void) { MessageBeep(MB_OK); return GetTickCount(); }\n",
+    ))
+    .unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.compile_string(&p).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        assert!(unsafe { relocated.get_symbol(c"ticks") }.is_some());
+    })
+    .unwrap();
+}
+
+#[test]
+fn memory_usage() {
+    let p = CString::new(
+        "static char zeros[65536];\nint table[1024] = {1};\nint touch(int i) { return zeros[i] + \
+         table[i]; }",
+    )
+    .unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.compile_string(&p).unwrap();
+        let relocated = ctx.relocate().unwrap();
+        let usage = relocated.memory_usage();
+        assert!(usage.total_mapped > 0);
+        if tcc_sys::SECTION_SIZES {
+            assert!(usage.code_bytes > 0);
+            assert!(usage.data_bytes >= 4096);
+            assert!(usage.bss_bytes >= 65536);
+            assert!(usage.code_bytes + usage.data_bytes <= usage.total_mapped);
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn output_bytes() {
+    let p = CString::new("int add(int a, int b){ return a + b; }".as_bytes()).unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Obj).unwrap();
+        assert!(ctx.compile_string(&p).is_ok());
+        let bytes = ctx.output_bytes().unwrap();
+        assert!(!bytes.is_empty());
+        if cfg!(target_os = "linux") {
+            assert_eq!(&bytes[..4], b"\x7fELF");
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn output_to_seekable() {
+    use std::io::{Cursor, Write};
+
+    let p = CString::new("int add(int a, int b){ return a + b; }".as_bytes()).unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Obj).unwrap();
+        ctx.compile_string(&p).unwrap();
+        let mut spooled = Vec::new();
+        ctx.output_to(&mut spooled).unwrap();
+
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Obj).unwrap();
+        ctx.compile_string(&p).unwrap();
+        let mut cursor = Cursor::new(Vec::new());
+        cursor.write_all(b"head").unwrap();
+        let written = ctx.output_to_seekable(&mut cursor).unwrap();
+        assert_eq!(written, spooled.len() as u64);
+        assert_eq!(&cursor.get_ref()[..4], b"head");
+        assert_eq!(&cursor.get_ref()[4..], &spooled[..]);
+    })
+    .unwrap();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let out = crate::artifacts::OutDir::temp().unwrap();
+        let mode = std::fs::metadata(out.dir()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+}
+
+#[test]
+fn output_transform() {
+    let p = CString::new("int add(int a, int b){ return a + b; }".as_bytes()).unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Obj).unwrap();
+        assert!(ctx.compile_string(&p).is_ok());
+        let plain = ctx.output_bytes().unwrap();
+        ctx.set_output_transform(|mut bytes| {
+            bytes.extend_from_slice(b"signature");
+            bytes
+        });
+        let signed = ctx.output_bytes().unwrap();
+        assert_eq!(signed.len(), plain.len() + 9);
+        assert!(signed.ends_with(b"signature"));
+    })
+    .unwrap();
+}
+
+#[test]
+fn sha1() {
+    use crate::build_id::sha1;
+
+    let hex = |digest: [u8; 20]| {
+        digest
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>()
+    };
+    assert_eq!(
+        hex(sha1(b"abc")),
+        "a9993e364706816aba3e25717850c26c9cd0d89d"
+    );
+    assert_eq!(
+        hex(sha1(
+            b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+        )),
+        "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+    );
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn build_id() {
+    use crate::{build_id::sha1, BuildId};
+
+    let p = CString::new("int add(int a, int b){ return a + b; }".as_bytes()).unwrap();
+    let build = |id: BuildId| {
+        scoped(|scope| {
+            let ctx = scope.spawn().unwrap();
+            ctx.set_output_type(OutputType::Dll).unwrap();
+            ctx.set_build_id(id.clone()).unwrap();
+            assert!(ctx.set_build_id(id).is_err());
+            ctx.compile_string(&p).unwrap();
+            ctx.output_bytes().unwrap()
+        })
+        .unwrap()
+        .get()
+        .clone()
+    };
+    let note = |elf: &[u8], len: u8| {
+        let header = [&[4, 0, 0, 0, len, 0, 0, 0, 3, 0, 0, 0][..], b"GNU\0"].concat();
+        let at = elf.windows(16).position(|w| w == header).unwrap() + 16;
+        at..at + len as usize
+    };
+
+    let elf = build(BuildId::Bytes(vec![0xde, 0xad, 0xbe, 0xef]));
+    assert_eq!(elf[note(&elf, 4)], [0xde, 0xad, 0xbe, 0xef]);
+
+    let elf = build(BuildId::Sha1OfContents);
+    let desc = note(&elf, 20);
+    let mut zeroed = elf.clone();
+    zeroed[desc.clone()].fill(0);
+    assert_eq!(elf[desc], sha1(&zeroed));
+
+    let elf = build(BuildId::Uuid);
+    assert_ne!(elf[note(&elf, 16)], [0; 16]);
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Obj).unwrap();
+        assert!(ctx.set_build_id(BuildId::Uuid).is_err());
+    })
+    .unwrap();
+}
+
+#[test]
+fn max_output_size() {
+    use crate::Error;
+
+    let p = CString::new("char table[65536] = {1}; int first(void) { return table[0]; }").unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.compile_string(&p).unwrap();
+        ctx.set_max_output_size(Some(4096));
+        assert!(matches!(
+            ctx.relocate(),
+            Err(Error::OutputTooLarge { limit: 4096, .. })
+        ));
+
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Obj).unwrap();
+        ctx.compile_string(&p).unwrap();
+        ctx.set_max_output_size(Some(4096));
+        assert!(matches!(
+            ctx.output_bytes(),
+            Err(Error::OutputTooLarge { size, .. }) if size > 65536
+        ));
+
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Obj).unwrap();
+        ctx.compile_string(&p).unwrap();
+        ctx.set_max_output_size(Some(4096))
+            .set_max_output_size(None);
+        assert!(ctx.output_bytes().unwrap().len() > 65536);
+    })
+    .unwrap();
+}
+
+#[test]
+fn source_limits() {
+    use crate::{Error, SourceLimit, SourceLimits};
+
+    let bomb = concat!(
+        "#define x x\n",
+        "#define A0 x\n",
+        "#define A1 (A0 + A0)\n",
+        "#define A2(p) (A1 + p + A1)\n",
+        "#define A3 \\\n A2(A2(1))\n",
+        "int f(int x) { return A3; }\n",
+    );
+    let limited = |limits: SourceLimits| {
+        scoped(move |scope| {
+            let ctx = scope.spawn().unwrap();
+            ctx.set_output_type(OutputType::Obj).unwrap();
+            ctx.set_source_limits(limits)?;
+            ctx.compile_str(bomb)
+        })
+        .unwrap()
+        .get()
+        .clone()
+    };
+
+    assert_eq!(
+        limited(SourceLimits {
+            max_macro_depth: Some(3),
+            ..SourceLimits::default()
+        }),
+        Err(Error::SourceLimitExceeded {
+            limit: SourceLimit::MacroDepth,
+            size:  5,
+            max:   3,
+        })
+    );
+    assert!(matches!(
+        limited(SourceLimits {
+            max_source_bytes: Some(16),
+            ..SourceLimits::default()
+        }),
+        Err(Error::SourceLimitExceeded {
+            limit: SourceLimit::SourceBytes,
+            ..
+        })
+    ));
+    let tokens = SourceLimits {
+        max_tokens: Some(20),
+        ..SourceLimits::default()
+    };
+    match tcc_sys::PREPROCESS_OUTPUT {
+        true => {
+            assert!(matches!(
+                limited(tokens),
+                Err(Error::SourceLimitExceeded {
+                    limit: SourceLimit::Tokens,
+                    ..
+                })
+            ))
+        }
+        false => {
+            assert!(matches!(
+                limited(tokens),
+                Err(Error::UnsupportedOption { .. })
+            ))
+        }
+    }
+    assert!(limited(SourceLimits {
+        max_source_bytes: Some(4096),
+        max_macro_depth: Some(5),
+        ..SourceLimits::default()
+    })
+    .is_ok());
+}
+
+#[test]
+fn config_log_round_trip() {
+    let sym = CString::new("NAME".as_bytes()).unwrap();
+    let val = CString::new("\"tab\tbed\\\"".as_bytes()).unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .add_include_path("/tmp/include dir")
+            .define(&sym, Some(&val))
+            .define(c"MAX(a, b)", Some(c"((a) > (b) ? (a) : (b))"));
+        let log = ctx.record();
+        let text = log.to_string();
+        assert_eq!(text.lines().count(), 4);
+        assert!(text.contains("define MAX(a,\\x20b) ((a) > (b) ? (a) : (b))\n"));
+        assert_eq!(text.parse::<crate::ConfigLog>().unwrap(), log);
+
+        let fresh = scope.spawn().unwrap();
+        log.replay(fresh).unwrap();
+        assert_eq!(fresh.record(), log);
+
+        // replaying goes through the same checks as the calls themselves
+        fresh.compile_string(c"int f(void) { return 0; }").unwrap();
+        drop(fresh.relocate().unwrap());
+        assert!(matches!(
+            log.replay(fresh),
+            Err(crate::Error::InvalidState { .. })
+        ));
+    })
+    .unwrap();
+}
+
+#[test]
+fn apply_env() {
+    use std::{env::join_paths, ffi::OsString};
+
+    let var = |name: &str| -> Option<OsString> {
+        match name {
+            "CPATH" => Some(join_paths(["/env/include/a", "", "/env/include/b"]).unwrap()),
+            "C_INCLUDE_PATH" => Some("/env/sysinclude".into()),
+            "LIBRARY_PATH" => Some("/env/lib".into()),
+            "TCC_RS_FLAGS" => Some("-Wall".into()),
+            _ => None,
+        }
+    };
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        let before = ctx.describe();
+        ctx.apply_vars(var);
+        let after = ctx.describe();
+        assert_eq!(
+            after.include_paths[before.include_paths.len()..],
+            [c"/env/include/a".to_owned(), c"/env/include/b".to_owned()]
+        );
+        assert_eq!(
+            after.sys_include_paths.last().unwrap().as_c_str(),
+            c"/env/sysinclude"
+        );
+        assert_eq!(after.library_paths.last().unwrap().as_c_str(), c"/env/lib");
+        assert_eq!(after.options.last().unwrap().as_c_str(), c"-Wall");
+
+        let log = ctx.record().to_string();
+        assert!(log.ends_with(concat!(
+            "include /env/include/a\n",
+            "include /env/include/b\n",
+            "sysinclude /env/sysinclude\n",
+            "library_path /env/lib\n",
+            "options -Wall\n",
+        )));
+    })
+    .unwrap();
+}
+
+#[test]
+fn fork() {
+    let p = CString::new("int answer(void) { return ANSWER; }").unwrap();
+
+    scoped(|scope| {
+        let template = scope.spawn().unwrap();
+        template
+            .set_output_type(OutputType::Memory)
+            .unwrap()
+            .add_include_path("/tmp/template")
+            .define(c"ANSWER", Some(c"42"));
+        template.collect_diagnostics(true);
+
+        for _ in 0..2 {
+            let mut fork = template.fork().unwrap();
+            assert_eq!(fork.record(), template.record());
+            assert!(fork.sink.collector.enabled);
+            fork.compile_string(&p).unwrap();
+            let mut relocated = fork.relocate().unwrap();
+            let answer: extern "C" fn() -> c_int =
+                unsafe { transmute(relocated.get_symbol(c"answer").unwrap()) };
+            assert_eq!(answer(), 42);
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn describe_diff() {
+    let sym = CString::new("TEST".as_bytes()).unwrap();
+    let val = CString::new("1".as_bytes()).unwrap();
+
+    scoped(|scope| {
+        let a = scope.spawn().unwrap();
+        a.set_output_type(OutputType::Memory)
+            .unwrap()
+            .add_include_path("/a")
+            .add_include_path("/b");
+        let b = scope.spawn().unwrap();
+        b.set_output_type(OutputType::Memory)
+            .unwrap()
+            .add_include_path("/b")
+            .add_include_path("/a")
+            .define(&sym, Some(&val));
+
+        let diff = a.describe().diff(&b.describe());
+        let lines: alloc::vec::Vec<_> = diff.iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            lines,
+            [
+                "~ include_paths: same entries, different order",
+                "+ defines: TEST=1"
+            ]
+        );
+    })
+    .unwrap();
+}
+
+#[test]
+fn capabilities() {
+    let caps = crate::capabilities();
+    assert!(!caps.version.is_empty());
+    #[cfg(target_arch = "x86_64")]
+    assert_eq!(caps.arch, crate::Arch::X86_64);
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    ))]
+    assert_eq!(caps.format, crate::ExecutableFormat::Elf);
+}
+
+#[test]
+fn keep_frame_pointers() {
+    use crate::Arch;
+
+    let caps = crate::capabilities();
+    let p = CString::new(
+        r#"
+        void *up(void) { return *(void **)__builtin_frame_address(0); }
+        int walks(void) { return up() == __builtin_frame_address(0); }
+        "#,
+    )
+    .unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        assert!(ctx.keep_frame_pointers(false).is_ok());
+        if !caps.frame_pointers {
+            assert!(ctx.keep_frame_pointers(true).is_err());
+            return;
+        }
+        ctx.keep_frame_pointers(true).unwrap();
+        // the saved frame pointer is at the frame address on x86 only
+        if !matches!(caps.arch, Arch::I386 | Arch::X86_64) {
+            return;
+        }
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.compile_string(&p).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let walks: extern "C" fn() -> c_int =
+            unsafe { transmute(relocated.get_symbol(c"walks").unwrap()) };
+        assert_eq!(walks(), 1);
+    })
+    .unwrap();
+}
+
+#[test]
+fn long_double() {
+    use crate::LongDouble;
+
+    let caps = crate::capabilities();
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    assert_eq!(caps.long_double, LongDouble::X87Extended);
+    let p = CString::new(
+        "int digits(void) { return __LDBL_MANT_DIG__; }\nint size(void) { return sizeof(long \
+         double); }",
+    )
+    .unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.compile_string(&p).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let digits: extern "C" fn() -> c_int =
+            unsafe { transmute(relocated.get_symbol(c"digits").unwrap()) };
+        let size: extern "C" fn() -> c_int =
+            unsafe { transmute(relocated.get_symbol(c"size").unwrap()) };
+        assert_eq!(digits() as u32, caps.long_double.mantissa_digits());
+        let sizes: &[c_int] = match caps.long_double {
+            LongDouble::Double => &[8],
+            LongDouble::X87Extended => &[12, 16],
+            LongDouble::Quad => &[16],
+        };
+        assert!(sizes.contains(&size()));
+    })
+    .unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn i386_output() {
+    use std::{path::Path, process::Command};
+
+    if crate::capabilities().arch != crate::Arch::I386 {
+        return;
+    }
+    // ELFCLASS32 and EM_386
+    let check = |bytes: &[u8]| {
+        assert_eq!(&bytes[..4], b"\x7fELF");
+        assert_eq!(bytes[4], 1);
+        assert_eq!(u16::from_le_bytes([bytes[18], bytes[19]]), 3);
+    };
+    let p = CString::new(
+        "long long mul(long long a, long long b) { return a * b; }\nint main(void) { return \
+         (int)(mul(1LL << 33, 3) >> 32); }",
+    )
+    .unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Obj).unwrap();
+        ctx.compile_string(&p).unwrap();
+        check(&ctx.output_bytes().unwrap());
+
+        // linking needs the 32-bit C library, from gcc-multilib or libc6-dev-i386
+        if !["/usr/lib32/crt1.o", "/usr/lib/i386-linux-gnu/crt1.o"]
+            .iter()
+            .any(|crt| Path::new(crt).exists())
+        {
+            return;
+        }
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Exe).unwrap();
+        ctx.compile_string(&p).unwrap();
+        let path = temp_dir().join("tcc_rs_i386");
+        ctx.output_file(&path).unwrap();
+        check(&std::fs::read(&path).unwrap());
+        let status = Command::new(&path).status().unwrap();
+        assert_eq!(status.code(), Some(6));
+        remove_file(&path).unwrap();
+    })
+    .unwrap();
+}
+
+#[cfg(not(target_os = "windows"))]
+#[test]
+fn cross_windows() {
+    use std::process::Command;
+
+    use crate::{Arch, Error, ExecutableFormat};
+
+    let caps = crate::capabilities();
+    if caps.format != ExecutableFormat::Pe {
+        scoped(|scope| {
+            let ctx = scope.spawn().unwrap();
+            assert!(matches!(
+                ctx.cross_windows(OutputType::Exe),
+                Err(Error::UnsupportedOption { .. })
+            ));
+        })
+        .unwrap();
+        return;
+    }
+    let machine: u16 = match caps.arch {
+        Arch::X86_64 => 0x8664,
+        Arch::I386 => 0x14c,
+        _ => return,
+    };
+    // the COFF header after the PE signature, e_lfanew points to it
+    let coff = |bytes: &[u8]| {
+        assert_eq!(&bytes[..2], b"MZ");
+        let at = u32::from_le_bytes(bytes[0x3c..0x40].try_into().unwrap()) as usize;
+        assert_eq!(&bytes[at..at + 4], b"PE\0\0");
+        assert_eq!(u16::from_le_bytes([bytes[at + 4], bytes[at + 5]]), machine);
+        u16::from_le_bytes([bytes[at + 22], bytes[at + 23]])
+    };
+    let exe = CString::new(
+        "#include <stdio.h>\n#include <windows.h>\nint main(void) { printf(\"%d\\n\", \
+         GetCurrentProcessId() != 0); return 42; }",
+    )
+    .unwrap();
+    let dll = CString::new(
+        "#include <windows.h>\n__declspec(dllexport) int add(int a, int b) { return a + b; }",
+    )
+    .unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.cross_windows(OutputType::Exe).unwrap();
+        ctx.compile_string(&exe).unwrap();
+        let path = temp_dir().join("tcc_rs_cross.exe");
+        ctx.output_file(&path).unwrap();
+        let characteristics = coff(&std::fs::read(&path).unwrap());
+        assert_eq!(characteristics & 0x2000, 0);
+        // run it where wine is installed
+        if let Ok(output) = Command::new("wine").arg(&path).output() {
+            assert_eq!(output.status.code(), Some(42));
+            assert_eq!(
+                alloc::string::String::from_utf8_lossy(&output.stdout).trim(),
+                "1"
+            );
+        }
+        remove_file(&path).unwrap();
+
+        let ctx = scope.spawn().unwrap();
+        ctx.cross_windows(OutputType::Dll).unwrap();
+        ctx.compile_string(&dll).unwrap();
+        let path = temp_dir().join("tcc_rs_cross.dll");
+        ctx.output_file(&path).unwrap();
+        let characteristics = coff(&std::fs::read(&path).unwrap());
+        assert_ne!(characteristics & 0x2000, 0);
+        remove_file(&path).unwrap();
+        // the exports tcc lists next to every DLL
+        let _ = remove_file(path.with_extension("def"));
+    })
+    .unwrap();
+}
+
+#[test]
+fn cross_linux() {
+    use crate::{Error, ExecutableFormat};
+
+    let caps = crate::capabilities();
+    let p =
+        CString::new("#include <string.h>\nint length(const char *s) { return (int)strlen(s); }")
+            .unwrap();
+
+    scoped(|scope| {
+        if caps.format != ExecutableFormat::Elf || !caps.embedded_musl {
+            let ctx = scope.spawn().unwrap();
+            assert!(matches!(
+                ctx.cross_linux(OutputType::Obj),
+                Err(Error::UnsupportedOption { .. })
+            ));
+            return;
+        }
+        // e_type: ET_REL and ET_DYN
+        for (output, e_type) in [(OutputType::Obj, 1), (OutputType::Dll, 3)] {
+            let ctx = scope.spawn().unwrap();
+            ctx.cross_linux(output).unwrap();
+            ctx.compile_string(&p).unwrap();
+            let bytes = ctx.output_bytes().unwrap();
+            assert_eq!(&bytes[..4], b"\x7fELF");
+            assert_eq!(u16::from_le_bytes([bytes[16], bytes[17]]), e_type);
+        }
+        let ctx = scope.spawn().unwrap();
+        assert!(ctx.cross_linux(OutputType::Memory).is_err());
+    })
+    .unwrap();
+}
+
+#[test]
+fn version_string() {
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        let version = ctx.version_string();
+        assert!(version.starts_with(&alloc::format!("tcc {} (", crate::VERSION_TCC)));
+    })
+    .unwrap();
+}
+
+#[test]
+fn relocation_model() {
+    use crate::{Error, RelocationModel};
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        assert!(matches!(
+            ctx.set_relocation_model(RelocationModel::Pic),
+            Err(Error::UnsupportedOption { .. })
+        ));
+        ctx.set_output_type(OutputType::Dll).unwrap();
+        assert!(ctx.set_relocation_model(RelocationModel::Static).is_err());
+        assert!(matches!(
+            ctx.set_pie(true),
+            Err(Error::UnsupportedOption { option: "-pie", .. })
+        ));
+    })
+    .unwrap();
+}
+
+#[test]
+fn dialect_options() {
+    use crate::CharSignedness;
+
+    let p = CString::new(
+        r#"
+        int $dollar = 1;
+        int char_is_unsigned(void) { return (char)-1 > 0; }
+        "#
+        .as_bytes(),
+    )
+    .unwrap();
+    let name = CString::new("char_is_unsigned".as_bytes()).unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.set_char_signedness(CharSignedness::Unsigned)
+            .unwrap()
+            .dollars_in_identifiers(true)
+            .unwrap();
+        ctx.compile_string(&p).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let addr = unsafe { relocated.get_symbol(&name) }.unwrap();
+        let f: extern "C" fn() -> c_int = unsafe { transmute(addr) };
+        assert_eq!(f(), 1);
+    })
+    .unwrap();
+}
+
+#[test]
+fn default_pack() {
+    let p = CString::new(
+        r#"
+        struct wire { char tag; int value; };
+        int wire_size(void) { return sizeof(struct wire); }
+        "#
+        .as_bytes(),
+    )
+    .unwrap();
+    let name = CString::new("wire_size".as_bytes()).unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        assert!(ctx.set_default_pack(Some(3)).is_err());
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.set_default_pack(Some(1)).unwrap();
+        ctx.compile_string(&p).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let addr = unsafe { relocated.get_symbol(&name) }.unwrap();
+        let f: extern "C" fn() -> c_int = unsafe { transmute(addr) };
+        assert_eq!(f(), 5);
+    })
+    .unwrap();
+}
+
+#[test]
+fn normalize_source() {
+    use crate::source::{normalize, EncodingError};
+
+    assert_eq!(
+        normalize(b"\xEF\xBB\xBFint a;\r\nint b;\r").unwrap(),
+        "int a;\nint b;\n"
+    );
+    let utf16: alloc::vec::Vec<u8> = [0xFF, 0xFE]
+        .into_iter()
+        .chain("int \u{e9};".encode_utf16().flat_map(u16::to_le_bytes))
+        .collect();
+    assert_eq!(normalize(&utf16).unwrap(), "int \u{e9};");
+    assert_eq!(
+        normalize(b"int a;\xFF"),
+        Err(EncodingError::InvalidUtf8 { offset: 6 })
+    );
+    assert_eq!(normalize(b"int\0"), Err(EncodingError::Nul { offset: 3 }));
+    // offsets count input bytes, byte order marks included
+    assert_eq!(
+        normalize(b"\xEF\xBB\xBFint\0"),
+        Err(EncodingError::Nul { offset: 6 })
+    );
+    let utf16: alloc::vec::Vec<u8> = [0xFE, 0xFF]
+        .into_iter()
+        .chain("\u{e9}\0".encode_utf16().flat_map(u16::to_be_bytes))
+        .collect();
+    assert_eq!(normalize(&utf16), Err(EncodingError::Nul { offset: 4 }));
+    // wide literals are escaped, narrow ones and comments kept
+    assert_eq!(
+        normalize("wchar_t *s = L\"\u{e9}a\"; char *t = \"\u{e9}\"; // L\"\u{e9}\"".as_bytes())
+            .unwrap(),
+        "wchar_t *s = L\"\\xe9\" L\"a\"; char *t = \"\u{e9}\"; // L\"\u{e9}\""
+    );
+    assert_eq!(
+        normalize("char16_t *s = u\"\u{1f600}\";".as_bytes()).unwrap(),
+        "char16_t *s = u\"\\xd83d\\xde00\";"
+    );
+    assert_eq!(
+        normalize("int c = u'\u{1f600}';".as_bytes()),
+        Err(EncodingError::WideChar { offset: 10 })
+    );
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        assert!(ctx
+            .compile_bytes(b"int f(void)\r\n{ return 1; }\r\n")
+            .is_ok());
+        assert!(matches!(
+            ctx.compile_bytes(b"\xC0"),
+            Err(crate::Error::Encoding(_))
+        ));
+    })
+    .unwrap();
+}
+
+#[test]
+fn source_filter() {
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.set_source_filter(|source| source.replace("${TYPE}", "long"));
+        assert!(ctx
+            .compile_str("${TYPE} twice(${TYPE} x) { return 2 * x; }")
+            .is_ok());
+        let name = CString::new("twice".as_bytes()).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        assert!(unsafe { relocated.get_symbol(&name) }.is_some());
+    })
+    .unwrap();
+}
+
+#[test]
+fn source_builder() {
+    use crate::source::Builder;
+
+    let source = Builder::new()
+        .define("ANSWER", Some("42"))
+        .function("int answer(void)", "return ANSWER;")
+        .raw_at("int broken(void) { return missing; }", "gen.rs", 10)
+        .raw("int after;")
+        .finish();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.collect_diagnostics(true);
+        assert!(ctx.compile_str(&source).is_err());
+        let location = ctx.diagnostics()[0].location.clone().unwrap();
+        assert_eq!((location.file.as_str(), location.line), ("gen.rs", 10));
+    })
+    .unwrap();
+}
+
+#[test]
+fn c_quote() {
+    let offset = -3;
+    let mask = 0xffu32;
+    let min = i64::MIN;
+    let source = crate::c_quote! {
+        long long min_value(void) { return #min; }
+        int apply(int x) { return (x & #mask) - #offset; }
+    };
+    assert!(source.contains("(-9223372036854775807LL - 1)"));
+    assert!(source.contains("(x & 255u) - (-3)"));
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.compile_str(&source).unwrap();
+        let name = CString::new("apply".as_bytes()).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let addr = unsafe { relocated.get_symbol(&name) }.unwrap();
+        let f: extern "C" fn(c_int) -> c_int = unsafe { transmute(addr) };
+        assert_eq!(f(0x1ff), 0xff + 3);
+    })
+    .unwrap();
+}
+
+#[test]
+fn exprs() {
+    use crate::exprs::{compile_binary, compile_nary, compile_unary};
+
+    let square = compile_unary("x * x").unwrap();
+    assert_eq!(square(3.0), 9.0);
+    let hypot = compile_binary("sqrt(x * x + y * y)").unwrap();
+    assert_eq!(hypot(3.0, 4.0), 5.0);
+    let mix = compile_nary("a * t + b * (1 - t)", &["a", "b", "t"]).unwrap();
+    assert_eq!(mix(&[2.0, 4.0, 0.5]), 3.0);
+    assert!(matches!(
+        compile_unary("x +"),
+        Err(crate::Error::Compile { .. })
+    ));
+    assert_eq!(
+        compile_binary("x > 0 && y > 0 ? x : y").unwrap()(1.0, 2.0),
+        1.0
+    );
+    assert_eq!(
+        compile_unary("(int)x * 2 - fabsf(x) * pow(x, 2) + floor(M_PI)").unwrap()(1.5),
+        1.625
+    );
+    assert!(matches!(
+        compile_nary("*(__args + 100000)", &["a"]),
+        Err(crate::Error::Compile { .. })
+    ));
+    for injected in [
+        "x); } void f(void) { system(\"true\"); return (0",
+        "({ *(int*)0 = 0; x; })",
+        "*(double *)0",
+        "(&x)[1000]",
+        "x + &&&x",
+        "x\n#define y",
+        "x))",
+        "((x)",
+        "*(__func__ + 100000)",
+        "x * *(__FILE__ + 100000)",
+        "__FUNCTION__[0]",
+        "x + abort()",
+        "frexp(x, 1000)",
+    ] {
+        assert!(
+            matches!(compile_unary(injected), Err(crate::Error::Compile { .. })),
+            "{injected:?} was accepted"
+        );
+    }
+}
+
+#[test]
+fn numeric_kernel_profile() {
+    let p = CString::new(
+        r#"
+        #include <assert.h>
+        int optimized(void) { assert(0); return __OPTIMIZE__; }
+        "#
+        .as_bytes(),
+    )
+    .unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.numeric_kernel_profile().unwrap();
+        ctx.compile_string(&p).unwrap();
+        let name = CString::new("optimized".as_bytes()).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let addr = unsafe { relocated.get_symbol(&name) }.unwrap();
+        let f: extern "C" fn() -> c_int = unsafe { transmute(addr) };
+        assert_eq!(f(), 1);
+    })
+    .unwrap();
+}
+
+#[test]
+fn provide_libm() {
+    let p = CString::new(
+        r#"
+        double sin(double); double pow(double, double); double sqrt(double);
+        double floor(double); double fabs(double);
+        double math(double x) { return sin(0) + pow(x, 2) + sqrt(x) + floor(-x) + fabs(-x); }
+        "#
+        .as_bytes(),
+    )
+    .unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .provide_libm(true);
+        ctx.compile_string(&p).unwrap();
+        let name = CString::new("math".as_bytes()).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let addr = unsafe { relocated.get_symbol(&name) }.unwrap();
+        let f: extern "C" fn(f64) -> f64 = unsafe { transmute(addr) };
+        assert_eq!(f(4.0), 0.0 + 16.0 + 2.0 - 4.0 + 4.0);
+    })
+    .unwrap();
+}
+
+#[test]
+fn host_allocator() {
+    use crate::ArenaHandle;
+
+    let p = CString::new(
+        r#"
+        #include <stdlib.h>
+        void *leak(void) {
+            free(malloc(16));
+            char *p = calloc(4, 8);
+            return realloc(p, 100);
+        }
+        "#
+        .as_bytes(),
+    )
+    .unwrap();
+    let arena = ArenaHandle::new();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .use_host_allocator(arena.clone());
+        ctx.compile_string(&p).unwrap();
+        let name = CString::new("leak".as_bytes()).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let addr = unsafe { relocated.get_symbol(&name) }.unwrap();
+        let f: extern "C" fn() -> *mut core::ffi::c_void = unsafe { transmute(addr) };
+        assert!(!f().is_null());
+    })
+    .unwrap();
+
+    assert_eq!(arena.total_allocations(), 3);
+    assert_eq!(arena.live_allocations(), 1);
+    assert_eq!(arena.allocated_bytes(), 100);
+    unsafe { arena.free_all() };
+    assert_eq!(arena.allocated_bytes(), 0);
+}
+
+#[test]
+fn host_allocator_user_definitions() {
+    use crate::ArenaHandle;
+
+    // the user's malloc sits at offset 0 of .text, before the code using it
+    let p = CString::new(
+        r#"
+        typedef __SIZE_TYPE__ size_t;
+        static char pool[64];
+        void *malloc(size_t n) { return n <= sizeof pool ? pool : 0; }
+        void free(void *p) { (void)p; }
+        void *own(void) { void *p = malloc(16); free(p); return p; }
+        "#
+        .as_bytes(),
+    )
+    .unwrap();
+    let arena = ArenaHandle::new();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .use_host_allocator(arena.clone());
+        ctx.compile_string(&p).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let own = unsafe { relocated.get_symbol(c"own") }.unwrap();
+        let own: extern "C" fn() -> *mut core::ffi::c_void = unsafe { transmute(own) };
+        assert!(!own().is_null());
+    })
+    .unwrap();
+
+    assert_eq!(arena.total_allocations(), 0);
+}
+
+#[test]
+fn host_allocator_debug() {
+    use crate::{ArenaHandle, HeapError};
+
+    let p = CString::new(
+        r#"
+        #include <stdlib.h>
+        static int local;
+        void misuse(void) {
+            void *p = malloc(8);
+            free(p);
+            free(p);
+            free(&local);
+            malloc(24);
+        }
+        "#
+        .as_bytes(),
+    )
+    .unwrap();
+    let arena = ArenaHandle::with_debug();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .use_host_allocator(arena.clone());
+        ctx.compile_string(&p).unwrap();
+        let name = CString::new("misuse".as_bytes()).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let addr = unsafe { relocated.get_symbol(&name) }.unwrap();
+        let f: extern "C" fn() = unsafe { transmute(addr) };
+        f();
+    })
+    .unwrap();
+
+    let errors = arena.take_errors();
+    assert!(matches!(
+        errors.as_slice(),
+        [
+            HeapError::DoubleFree { .. },
+            HeapError::InvalidFree { .. },
+            HeapError::Leak { size: 24, .. }
+        ]
+    ));
+}
+
+#[test]
+fn capture_stdio() {
+    use crate::{StdStream, StdioSink};
+
+    let p = CString::new(
+        r#"
+        int printf(const char *, ...);
+        int fprintf(void *, const char *, ...);
+        int puts(const char *);
+        int putchar(int);
+        extern void *stderr __attribute__((weak));
+        int report(void) {
+            int n = printf("%d|%-4s|%05.1f|%x|%%|%.3e|%g|%c", -42, "ab", 3.14159, 255u, 12345.678, 0.0001, 'z');
+            putchar('\n');
+            puts("done");
+            if (&stderr)
+                fprintf(stderr, "%+i\n", 7);
+            return n;
+        }
+        "#
+        .as_bytes(),
+    )
+    .unwrap();
+    let sink = StdioSink::new();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .capture_stdio(sink.clone());
+        ctx.compile_string(&p).unwrap();
+        let name = CString::new("report".as_bytes()).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let addr = unsafe { relocated.get_symbol(&name) }.unwrap();
+        let f: extern "C" fn() -> c_int = unsafe { transmute(addr) };
+        let expected = "-42|ab  |003.1|ff|%|1.235e+04|0.0001|z";
+        assert_eq!(f() as usize, expected.len());
+        assert_eq!(
+            sink.take_stdout(),
+            format!("{expected}\ndone\n").into_bytes()
+        );
+    })
+    .unwrap();
+
+    let stderr = sink.take_stderr();
+    assert!(stderr.is_empty() || stderr == b"+7\n");
+
+    let lines = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let seen = lines.clone();
+    let sink = StdioSink::with_writer(move |stream, bytes| {
+        seen.lock().unwrap().push((stream, bytes.to_vec()));
+    });
+    let p = CString::new(b"int puts(const char *); void hi(void) { puts(\"hi\"); }".as_slice())
+        .unwrap();
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .capture_stdio(sink);
+        ctx.compile_string(&p).unwrap();
+        let name = CString::new("hi".as_bytes()).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let addr = unsafe { relocated.get_symbol(&name) }.unwrap();
+        let f: extern "C" fn() = unsafe { transmute(addr) };
+        f();
+    })
+    .unwrap();
+    assert_eq!(
+        lines.lock().unwrap()[0],
+        (StdStream::Stdout, b"hi".to_vec())
+    );
+}
+
+#[test]
+fn provide_pthreads() {
+    let p = CString::new(
+        r#"
+        int pthread_create(void *, const void *, void *(*)(void *), void *);
+        int pthread_join(__SIZE_TYPE__, void **);
+        int pthread_mutex_lock(void *);
+        int pthread_mutex_unlock(void *);
+        static char mutex[64];
+        static int counter;
+        static void *work(void *arg) {
+            for (int i = 0; i < 1000; i++) {
+                pthread_mutex_lock(mutex);
+                counter++;
+                pthread_mutex_unlock(mutex);
+            }
+            return arg;
+        }
+        int run(void) {
+            __SIZE_TYPE__ t[4];
+            void *ret;
+            int sum = 0;
+            for (int i = 0; i < 4; i++)
+                pthread_create(&t[i], 0, work, (void *)(__SIZE_TYPE__)i);
+            for (int i = 0; i < 4; i++) {
+                pthread_join(t[i], &ret);
+                sum += (int)(__SIZE_TYPE__)ret;
+            }
+            return counter + sum;
+        }
+        "#
+        .as_bytes(),
+    )
+    .unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .provide_pthreads(true);
+        ctx.compile_string(&p).unwrap();
+        let name = CString::new("run".as_bytes()).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let addr = unsafe { relocated.get_symbol(&name) }.unwrap();
+        let f: extern "C" fn() -> c_int = unsafe { transmute(addr) };
+        assert_eq!(f(), 4000 + 6);
+    })
+    .unwrap();
+}
+
+#[test]
+fn stdatomic() {
+    if crate::capabilities().atomics == crate::Atomics::Unknown {
+        return;
+    }
+    let p = CString::new(
+        r#"
+        #include <stdatomic.h>
+        static atomic_int counter = ATOMIC_VAR_INIT(40);
+        int bump(void) {
+            int expected = 41;
+            atomic_fetch_add(&counter, 1);
+            atomic_compare_exchange_strong(&counter, &expected, 42);
+            return atomic_load(&counter);
+        }
+        static double ratio;
+        double scale(void) {
+            double expected = 1.5;
+            atomic_store(&ratio, 1.5);
+            atomic_compare_exchange_strong(&ratio, &expected, 2.25);
+            return atomic_load(&ratio) + atomic_exchange(&ratio, 0.5);
+        }
+        "#
+        .as_bytes(),
+    )
+    .unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.compile_string(&p).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let addr = unsafe { relocated.get_symbol(c"bump") }.unwrap();
+        let f: extern "C" fn() -> c_int = unsafe { transmute(addr) };
+        assert_eq!(f(), 42);
+        let addr = unsafe { relocated.get_symbol(c"scale") }.unwrap();
+        let f: extern "C" fn() -> f64 = unsafe { transmute(addr) };
+        assert_eq!(f(), 4.5);
+    })
+    .unwrap();
+
+    if crate::capabilities().atomics != crate::Atomics::Fallback {
+        return;
+    }
+    let p = c"
+        #include <stdatomic.h>
+        struct wide { long long a, b; };
+        struct wide load(struct wide *w) { return atomic_load(w); }
+    ";
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        assert!(ctx.compile_string(p).is_err());
+    })
+    .unwrap();
+}
+
+#[test]
+fn code_pool() {
+    use crate::{exec::CodePool, Context, Error};
+
+    let p = CString::new("int answer(void) { return 42; }").unwrap();
+    let compiled = || {
+        let mut ctx = Context::new().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.compile_string(&p).unwrap();
+        ctx
+    };
+    let pool = CodePool::new(usize::MAX);
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.compile_string(&p).unwrap();
+        let mut relocated = ctx.relocate_into(&pool).unwrap();
+        let answer: extern "C" fn() -> c_int =
+            unsafe { transmute(relocated.get_symbol(c"answer").unwrap()) };
+        assert_eq!(answer(), 42);
+        let region = pool.mapped();
+        assert!(region > 0);
+        assert_eq!(pool.in_use(), region);
+
+        // no room for a second region while the first is in use
+        pool.set_limit(region);
+        assert!(matches!(
+            compiled().into_module_in(&pool),
+            Err(Error::PoolExhausted { .. })
+        ));
+        drop(relocated);
+        assert_eq!(pool.in_use(), 0);
+        assert_eq!(pool.mapped(), region);
+
+        let module = compiled().into_module_in(&pool).unwrap();
+        let answer: extern "C" fn() -> c_int =
+            unsafe { transmute(module.get_symbol("answer").unwrap()) };
+        assert_eq!(answer(), 42);
+        assert!(pool.mapped() <= region);
+        drop(module);
+        pool.trim();
+        assert_eq!(pool.mapped(), 0);
+    })
+    .unwrap();
+}
+
+#[test]
+fn protect() {
+    use crate::exec::CodePool;
+
+    let p = CString::new("int counter; int bump(void) { return ++counter; }").unwrap();
+    let pool = CodePool::new(usize::MAX);
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.compile_string(&p).unwrap();
+        let mut relocated = ctx.relocate_into(&pool).unwrap();
+        match tcc_sys::SECTION_SIZES {
+            true => relocated.protect().unwrap(),
+            false => assert!(relocated.protect().is_err()),
+        }
+        let bump: extern "C" fn() -> c_int =
+            unsafe { transmute(relocated.get_symbol(c"bump").unwrap()) };
+        // code still runs and data stays writable
+        assert_eq!(bump(), 1);
+        assert_eq!(bump(), 2);
+    })
+    .unwrap();
+    assert_eq!(pool.in_use(), 0);
+}
+
+#[test]
+fn freeze() {
+    let p = CString::new("int answer(void) { return 42; }").unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.compile_string(&p).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        assert!(!relocated.is_frozen());
+        if !tcc_sys::SECTION_SIZES {
+            assert!(relocated.freeze().is_err());
+            assert!(!relocated.is_frozen());
+            return;
+        }
+        let answer: extern "C" fn() -> c_int =
+            unsafe { transmute(relocated.get_symbol(c"answer").unwrap()) };
+        relocated.freeze().unwrap();
+        assert!(relocated.is_frozen());
+        assert_eq!(answer(), 42);
+        relocated.unfreeze().unwrap();
+        assert!(!relocated.is_frozen());
+        assert_eq!(answer(), 42);
+        relocated.freeze().unwrap();
+        assert_eq!(answer(), 42);
+    })
+    .unwrap();
+}
+
+#[test]
+fn export_symbolication() {
+    let p =
+        CString::new("int one(void) { return 1; } int two(void) { return one() + 1; }").unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.compile_string(&p).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let bundle = relocated.export_symbolication();
+        assert_eq!(bundle.size, relocated.memory_usage().total_mapped);
+
+        let two = unsafe { relocated.get_symbol(c"two") }.unwrap() as usize;
+        assert!(bundle.contains(two));
+        let (sym, offset) = bundle.lookup(two + 1).unwrap();
+        assert_eq!((sym.name.as_str(), offset), ("two", 1));
+        assert!(bundle.symbols.iter().any(|sym| sym.name == "one"));
+        assert!(bundle.lookup(bundle.base + bundle.size).is_none());
+        if tcc_sys::SECTION_SIZES {
+            assert!(sym.size > 1);
+        }
+        assert!(bundle.to_json().contains("{\"name\":\"two\",\"address\":"));
+    })
+    .unwrap();
+}
+
+#[test]
+fn exported_symbols_preview() {
+    use crate::{ExportedSymbol, SymbolKind};
+
+    let p = CString::new(
+        "int entry(void) { return 1; } static int hidden(void) { return 2; } int counter; extern \
+         int missing;",
+    )
+    .unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.compile_string(&p).unwrap();
+        if !tcc_sys::SECTION_SIZES {
+            assert!(ctx.exported_symbols_preview().is_err());
+            return;
+        }
+        let symbols = ctx.exported_symbols_preview().unwrap();
+        let kind = |name: &str| {
+            symbols
+                .iter()
+                .find(|sym| sym.name == name)
+                .map(|sym| sym.kind)
+        };
+        assert_eq!(kind("entry"), Some(SymbolKind::Function));
+        assert_eq!(kind("counter"), Some(SymbolKind::Data));
+        assert_eq!((kind("hidden"), kind("missing")), (None, None));
+        assert!(symbols.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(symbols.contains(&ExportedSymbol {
+            name: "entry".into(),
+            kind: SymbolKind::Function,
+        }));
+    })
+    .unwrap();
+}
+
+#[test]
+fn plugin_interface() {
+    use crate::{tcc_plugin_interface, Error, PluginInterface};
+
+    tcc_plugin_interface! {
+        trait Plugin {
+            fn init();
+            fn tick(dt: f32) -> i32;
+            fn name(buf: *mut u8, len: usize) -> *const u8;
+        }
+    }
+
+    let header = <dyn Plugin>::header();
+    assert!(header.contains("void init(void);\n"));
+    assert!(header.contains("int tick(float dt);\n"));
+    assert!(header.contains("char const * name(char * buf, __SIZE_TYPE__ len);\n"));
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.use_plugin_interface::<dyn Plugin>();
+        let source = r#"
+            #include "Plugin.h"
+            static int ticks;
+            void init(void) { ticks = 10; }
+            int tick(float dt) { return ticks += (int)(dt * 2); }
+            char const * name(char * buf, __SIZE_TYPE__ len) { return "plugin"; }
+        "#;
+        ctx.compile_string(&CString::new(source).unwrap()).unwrap();
+        let relocated = ctx.relocate().unwrap();
+        let plugin = <dyn Plugin>::bind(&relocated).unwrap();
+        plugin.init();
+        assert_eq!(plugin.tick(1.5), 13);
+        assert_eq!(plugin.tick(0.5), 14);
+        let mut buf = [0u8; 8];
+        let name = plugin.name(buf.as_mut_ptr(), buf.len());
+        assert_eq!(unsafe { core::ffi::CStr::from_ptr(name.cast()) }, c"plugin");
+    })
+    .unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.compile_string(c"void init(void) {}").unwrap();
+        let relocated = ctx.relocate().unwrap();
+        let err = <dyn Plugin>::bind(&relocated).err();
+        assert!(matches!(err, Some(Error::SymbolNotFound(name)) if name == "tick"));
+    })
+    .unwrap();
+}
+
+#[test]
+fn plugin_abi_version() {
+    use crate::{tcc_plugin_interface, Error, PluginInterface};
+
+    tcc_plugin_interface! {
+        trait Versioned {
+            const ABI_VERSION: u32 = 2;
+            fn step(n: i32) -> i32;
+        }
+    }
+    tcc_plugin_interface! {
+        trait Unversioned {
+            fn step(n: i32) -> i32;
+        }
+    }
+
+    assert_eq!(<dyn Versioned>::version(), 2);
+    assert_eq!(
+        <dyn Unversioned>::version(),
+        crate::plugin::version(&[], "int step(int n);\n")
+    );
+    assert!(<dyn Versioned>::header()
+        .contains("const unsigned int __tcc_rs_plugin_abi_Versioned = 2u;"));
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.use_plugin_interface::<dyn Unversioned>();
+        let source = c"#include \"Unversioned.h\"\nint step(int n) { return n * 2; }";
+        ctx.compile_string(source).unwrap();
+        let relocated = ctx.relocate().unwrap();
+        let plugin = <dyn Unversioned>::bind_checked(&relocated).unwrap();
+        assert_eq!(plugin.step(4), 8);
+    })
+    .unwrap();
+
+    let bind = |source: &'static str| {
+        scoped(move |scope| {
+            let ctx = scope.spawn().unwrap();
+            ctx.set_output_type(OutputType::Memory).unwrap();
+            ctx.use_plugin_interface::<dyn Versioned>();
+            ctx.compile_string(&CString::new(source).unwrap()).unwrap();
+            let relocated = ctx.relocate().unwrap();
+            let step = <dyn Versioned>::bind_checked(&relocated).map(|plugin| plugin.step(1));
+            step
+        })
+        .unwrap()
+        .get()
+        .clone()
+    };
+    assert_eq!(
+        bind("#include \"Versioned.h\"\nint step(int n) { return n + 1; }"),
+        Ok(2)
+    );
+    assert_eq!(
+        bind("int step(int n) { return n + 1; }"),
+        Err(Error::PluginAbiMismatch {
+            interface: "Versioned",
+            expected:  2,
+            found:     None,
+        })
+    );
+    assert_eq!(
+        bind("const unsigned int __tcc_rs_plugin_abi_Versioned = 1; int step(int n) { return n; }"),
+        Err(Error::PluginAbiMismatch {
+            interface: "Versioned",
+            expected:  2,
+            found:     Some(1),
+        })
+    );
+}
+
+#[test]
+fn symbol_prefix() {
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        assert!(ctx.set_symbol_prefix("3mod_").is_err());
+        if !tcc_sys::SECTION_SIZES {
+            assert!(ctx.set_symbol_prefix("mod3_").is_err());
+            return;
+        }
+        ctx.set_symbol_prefix("mod3_").unwrap();
+        ctx.compile_string(c"int init(void); int twice(void) { return init() * 2; }")
+            .unwrap();
+        ctx.compile_string(c"int init(void) { return 21; } int mod3_ready = 1;")
+            .unwrap();
+        let names: Vec<_> = ctx
+            .exported_symbols_preview()
+            .unwrap()
+            .into_iter()
+            .map(|sym| sym.name)
+            .collect();
+        assert_eq!(names, ["mod3_init", "mod3_ready", "mod3_twice"]);
+
+        let mut relocated = ctx.relocate().unwrap();
+        assert!(unsafe { relocated.get_symbol(c"init") }.is_none());
+        assert!(unsafe { relocated.get_symbol(c"mod3_ready") }.is_some());
+        let twice = unsafe { relocated.get_symbol(c"mod3_twice") }.unwrap();
+        let twice: extern "C" fn() -> c_int = unsafe { transmute(twice) };
+        assert_eq!(twice(), 42);
+    })
+    .unwrap();
+}
+
+#[test]
+fn invalid_state() {
+    use crate::Error;
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.compile_string(c"int one(void) { return 1; }").unwrap();
+        drop(ctx.relocate().unwrap());
+
+        let invalid = |result: Result<(), Error>, operation| {
+            assert!(matches!(
+                result,
+                Err(Error::InvalidState { operation: op, .. }) if op == operation
+            ));
+        };
+        invalid(
+            ctx.compile_string(c"int two(void) { return 2; }"),
+            "compile_string",
+        );
+        invalid(
+            ctx.compile_str("int two(void) { return 2; }"),
+            "compile_string",
+        );
+        invalid(ctx.add_file("two.c"), "add_file");
+        invalid(
+            ctx.set_output_type(OutputType::Memory).map(drop),
+            "set_output_type",
+        );
+        invalid(ctx.relocate().map(drop), "relocate");
+    })
+    .unwrap();
+}
+
+#[test]
+fn output_type_mismatch() {
+    use crate::Error;
+
+    let invalid = |result: Result<(), Error>, operation| {
+        assert!(matches!(
+            result,
+            Err(Error::InvalidState { operation: op, .. }) if op == operation
+        ));
+    };
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Obj).unwrap();
+        ctx.compile_string(c"int one(void) { return 1; }").unwrap();
+        invalid(ctx.relocate().map(drop), "relocate");
+        assert!(!ctx.output_bytes().unwrap().is_empty());
+
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.compile_string(c"int one(void) { return 1; }").unwrap();
+        invalid(ctx.output_bytes().map(drop), "output_file");
+        assert!(ctx.relocate().is_ok());
+
+        let ctx = scope.spawn().unwrap();
+        if ctx.preprocess_str("int x;").is_ok() {
+            assert!(ctx.preprocess_str("int y;").unwrap().contains("int y;"));
+            invalid(ctx.compile_string(c"int z;"), "compile_string");
+            invalid(
+                ctx.set_output_type(OutputType::Obj).map(drop),
+                "set_output_type",
+            );
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn call_thread() {
+    use crate::exec::CallThread;
+
+    let p = CString::new(
+        r#"
+        int depth(int n) {
+            char frame[4096];
+            frame[0] = (char)n;
+            return n ? depth(n - 1) + frame[0] - (char)n + 1 : 0;
+        }
+        "#
+        .as_bytes(),
+    )
+    .unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.compile_string(&p).unwrap();
+        let name = CString::new("depth".as_bytes()).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let addr = unsafe { relocated.get_symbol(&name) }.unwrap() as usize;
+        let depth: extern "C" fn(c_int) -> c_int = unsafe { transmute(addr) };
+
+        // about 40 MiB of stack
+        let runner = CallThread::builder()
+            .stack_size(64 << 20)
+            .guard_pages(4)
+            .name("jit")
+            .build();
+        assert_eq!(runner.run(|| depth(10_000)).unwrap(), 10_000);
+    })
+    .unwrap();
+
+    let caught = std::panic::catch_unwind(|| {
+        CallThread::builder()
+            .build()
+            .run(|| panic!("boom"))
+            .unwrap();
+    });
+    assert!(caught.is_err());
+}
+
+#[test]
+fn profiles() {
+    use crate::Profile;
+
+    let p = CString::new(b"int answer(void) { return 42; }".as_slice()).unwrap();
+    for profile in [
+        Profile::SandboxedScripting,
+        Profile::FastDebug,
+        Profile::ReleaseKernel,
+        Profile::Freestanding,
+        Profile::Android,
+    ] {
+        scoped(|scope| {
+            let ctx = scope.spawn().unwrap();
+            ctx.with_profile(profile).unwrap();
+            ctx.compile_string(&p).unwrap();
+        })
+        .unwrap();
+    }
+
+    // implicit declarations are rejected in the sandbox
+    let p = CString::new(b"int f(void) { return g(); }".as_slice()).unwrap();
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.with_profile(Profile::SandboxedScripting).unwrap();
+        assert!(ctx.compile_string(&p).is_err());
+    })
+    .unwrap();
+}
+
+#[test]
+fn static_musl_exe() {
+    let p = CString::new("int main(void) { return 42; }").unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        if !crate::capabilities().embedded_musl {
+            assert!(matches!(
+                ctx.static_musl_exe(),
+                Err(crate::Error::UnsupportedOption {
+                    option: "-static",
+                    ..
+                })
+            ));
+            return;
+        }
+        ctx.static_musl_exe().unwrap();
+        ctx.compile_string(&p).unwrap();
+        let path = temp_dir().join("tcc_rs_musl");
+        ctx.output_file(&path).unwrap();
+        let status = std::process::Command::new(&path).status().unwrap();
+        assert_eq!(status.code(), Some(42));
+        remove_file(&path).unwrap();
+    })
+    .unwrap();
+}
+
+#[test]
+fn use_embedded_sysroot() {
+    let caps = crate::capabilities();
+    let p = CString::new(
+        "#include <stddef.h>\n#include <stdarg.h>\nsize_t first(int n, ...) { va_list ap; \
+         va_start(ap, n); size_t x = va_arg(ap, size_t); va_end(ap); return x; }",
+    )
+    .unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        if !caps.vfs || !(caps.embedded_headers || caps.embedded_libraries) {
+            assert!(ctx.use_embedded_sysroot().is_err());
+            return;
+        }
+        ctx.set_option("-nostdinc").unwrap();
+        ctx.use_embedded_sysroot().unwrap();
+        if caps.embedded_libraries {
+            assert_eq!(ctx.describe().lib_path.as_deref(), Some(c"/vfs/libraries"));
+        }
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        if caps.embedded_headers {
+            ctx.compile_string(&p).unwrap();
+            let mut relocated = ctx.relocate().unwrap();
+            assert!(unsafe { relocated.get_symbol(c"first") }.is_some());
+        }
+    })
+    .unwrap();
+}
+
+#[test]
+fn use_simd_headers() {
+    let p = CString::new(concat!(
+        "#include <immintrin.h>\n",
+        "float dot(const float *a, const float *b) {\n",
+        "    float out[4];\n",
+        "    __m128 sum = _mm_mul_ps(_mm_loadu_ps(a), _mm_loadu_ps(b));\n",
+        "    _mm_storeu_ps(out, _mm_sqrt_ps(_mm_mul_ps(sum, sum)));\n",
+        "    return out[0] + out[1] + out[2] + out[3] + __TCC_RS_SIMD_SCALAR;\n",
+        "}\n",
+    ))
+    .unwrap();
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        if !crate::capabilities().embedded_simd_headers {
+            assert!(matches!(
+                ctx.use_simd_headers(),
+                Err(crate::Error::UnsupportedOption {
+                    option: "simd headers",
+                    ..
+                })
+            ));
+            return;
+        }
+        ctx.set_output_type(OutputType::Memory)
+            .unwrap()
+            .provide_libm(true)
+            .use_simd_headers()
+            .unwrap();
+        ctx.compile_string(&p).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let addr = unsafe { relocated.get_symbol(c"dot") }.unwrap();
+        let dot: extern "C" fn(*const f32, *const f32) -> f32 = unsafe { transmute(addr) };
+        let (a, b) = ([1.0f32, 2.0, 3.0, 4.0], [4.0f32, 3.0, 2.0, 1.0]);
+        assert_eq!(dot(a.as_ptr(), b.as_ptr()), 21.0);
+    })
+    .unwrap();
+}
+
+#[test]
+fn jit() {
+    let add = crate::jit::<extern "C" fn(c_int, c_int) -> c_int>(
+        "int add(int a, int b) { return a + b; }",
+        "add",
+    )
+    .unwrap();
+    assert_eq!(add.call(2, 3), 5);
+    let raw = unsafe { add.get() };
+    assert_eq!(raw(4, 5), 9);
+    assert!(add.module().get_symbol("add").is_some());
+
+    assert!(matches!(
+        crate::jit::<extern "C" fn()>("void f(void) {}", "g"),
+        Err(crate::Error::SymbolNotFound(name)) if name == "g"
+    ));
+}
+
+#[test]
+fn run_main() {
+    let source = r#"
+        int main(int argc, char **argv) {
+            return argc * 10 + argv[argc - 1][0] - '0';
+        }
+    "#;
+    assert_eq!(crate::run_main(source, &["1", "7"]).unwrap(), 37);
+    assert!(crate::run_main("int main(void) { return missing(); }", &[]).is_err());
+}
+
+#[test]
+fn relocated_run_main() {
+    use core::sync::atomic::{AtomicI32, Ordering};
+
+    static DONE: AtomicI32 = AtomicI32::new(0);
+    let source = concat!(
+        "extern int done;\n",
+        "static int ready;\n",
+        "__attribute__((constructor)) static void init(void) { ready = 1; }\n",
+        "__attribute__((destructor)) static void fini(void) { done = ready; }\n",
+        "int main(int argc, char **argv, char **envp) {\n",
+        "    if (!ready || envp[1] || envp[0][0] != 'K') return -1;\n",
+        "    return argc * 10 + argv[argc - 1][0] - '0';\n",
+        "}\n",
+    );
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.set_env(crate::Env::new().set("KEY", "value"));
+        unsafe { ctx.add_symbol(c"done", DONE.as_ptr() as *const core::ffi::c_void) };
+        ctx.compile_str(source).unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        let code = relocated.run_main(&["1", "7"]);
+        assert_eq!(code.unwrap(), 37);
+        assert_eq!(DONE.load(Ordering::SeqCst), 1);
+
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.compile_str("int helper(void) { return 0; }").unwrap();
+        let mut relocated = ctx.relocate().unwrap();
+        assert!(relocated.run_main(&[]).is_err());
+    })
+    .unwrap();
+}
+
+#[test]
+fn set_env() {
+    use crate::{run_main_with_env, Env};
+
+    std::env::set_var("TCC_RS_HOST_SECRET", "1");
+    let source = concat!(
+        "char *getenv(const char *);\n",
+        "extern char **environ;\n",
+        "int main(int argc, char **argv, char **envp) {\n",
+        "    int n = 0;\n",
+        "    while (environ[n]) n++;\n",
+        "    if (envp != environ) return -1;\n",
+        "    return n * 100 + !!getenv(\"TCC_RS_HOST_SECRET\") * 10 + !!getenv(\"A\");\n",
+        "}\n",
+    );
+    let env = Env::new().set("A", "1").set("B", "2");
+    assert_eq!(run_main_with_env(source, &[], &env).unwrap(), 201);
+    let env = env.clear().set("A", "1");
+    assert_eq!(run_main_with_env(source, &[], &env).unwrap(), 101);
+    let env = Env::new().inherit().unset("TCC_RS_HOST_SECRET");
+    assert_eq!(run_main_with_env(source, &[], &env).unwrap() % 100, 0);
+    let env = Env::new().inherit();
+    assert_eq!(run_main_with_env(source, &[], &env).unwrap() % 100, 10);
+}
+
+#[test]
+fn out_dir() {
+    use crate::artifacts::OutDir;
+
+    let out = OutDir::temp().unwrap();
+    let dir = out.dir().to_path_buf();
+    let (a, b) = (out.path("add.o"), out.path("add.o"));
+    assert_eq!(a, dir.join("add.o"));
+    assert_eq!(b, dir.join("add-1.o"));
+    write(dir.join("add-2.o"), "taken").unwrap();
+    assert_eq!(out.path("add.o"), dir.join("add-3.o"));
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Obj).unwrap();
+        ctx.compile_str("int add(int a, int b) { return a + b; }")
+            .unwrap();
+        ctx.output_file(&b).unwrap();
+    })
+    .unwrap();
+    assert_eq!(out.files(), core::slice::from_ref(&b));
+    drop(out);
+    assert!(!dir.exists());
+
+    let out = OutDir::at(&dir).unwrap();
+    let path = out.path("kept");
+    write(&path, "").unwrap();
+    write(dir.join("other"), "").unwrap();
+    drop(out);
+    assert!(!path.exists() && dir.join("other").exists());
+    std::fs::remove_dir_all(OutDir::at(&dir).unwrap().keep()).unwrap();
+}
+
+#[test]
+fn build_static_library() {
+    use crate::{
+        artifacts::OutDir,
+        build::{library_file_name, Build, LibraryKind},
+        capabilities, ExecutableFormat,
+    };
+
+    if capabilities().format != ExecutableFormat::Elf {
+        return;
+    }
+    let out = OutDir::temp().unwrap();
+    let (add, mul) = (out.path("add.c"), out.path("a_rather_long_name_for_mul.c"));
+    write(&add, "int add(int a, int b) { return a + b; }").unwrap();
+    write(
+        &mul,
+        "static int twice(int a) { return 2 * a; }\nint mul(int a) { return SCALE * twice(a); }",
+    )
+    .unwrap();
+    Build::new()
+        .files([&add, &mul])
+        .define("SCALE", "3")
+        .out_dir(out.dir())
+        .cargo_metadata(false)
+        .try_compile("arith")
+        .unwrap();
+
+    let lib = std::fs::read(
+        out.dir()
+            .join(library_file_name("arith", LibraryKind::Static)),
+    )
+    .unwrap();
+    assert!(lib.starts_with(b"!<arch>\n/               "));
+    let index = &lib[8 + 60..];
+    assert_eq!(&index[..4], 2u32.to_be_bytes());
+    assert_eq!(&index[12..20], b"add\0mul\0");
+    assert!(lib.windows(4).any(|w| w == b"//  "));
+    assert!(Build::new()
+        .file(out.path("missing.c"))
+        .out_dir(out.dir())
+        .try_compile("missing")
+        .is_err());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn link_library_lines() {
+    use std::path::Path;
+
+    use crate::build::{library_file_name, link_lines, LibraryKind};
+
+    assert_eq!(library_file_name("foo", LibraryKind::Static), "libfoo.a");
+    assert_eq!(library_file_name("foo", LibraryKind::Dynamic), "libfoo.so");
+    let lines = link_lines(Path::new("/out/libfoo.so"), LibraryKind::Dynamic).unwrap();
+    assert_eq!(
+        lines,
+        [
+            "cargo:rustc-link-search=native=/out",
+            "cargo:rustc-link-lib=dylib=foo"
+        ]
+    );
+    let lines = link_lines(Path::new("libbar.a"), LibraryKind::Static).unwrap();
+    assert_eq!(lines[0], "cargo:rustc-link-search=native=.");
+    assert_eq!(lines[1], "cargo:rustc-link-lib=static=bar");
+    assert!(link_lines(Path::new("/out/foo.so"), LibraryKind::Dynamic).is_err());
+    assert!(link_lines(Path::new("/out/libfoo.a"), LibraryKind::Dynamic).is_err());
+}
+
+#[test]
+fn preprocess() {
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_options(c"-P");
+        let output = ctx
+            .preprocess_str("#define TWICE(x) ((x) + (x))\nint y = TWICE(3);\n")
+            .unwrap();
+        assert_eq!(output.trim(), "int y = ((3) + (3));");
+        assert!(ctx.preprocess_str("#error stop\n").is_err());
+    })
+    .unwrap();
+}
+
+#[test]
+fn golden_preprocess() {
+    use core::panic::AssertUnwindSafe;
+    use std::panic::catch_unwind;
+
+    use crate::{artifacts::OutDir, testing::golden_preprocess_with};
+
+    let out = OutDir::temp().unwrap();
+    let dir = out.dir().join("nested");
+    std::fs::create_dir(&dir).unwrap();
+    write(
+        dir.join("max.h"),
+        "#define MAX(a, b) ((a) > (b) ? (a) : (b))\n",
+    )
+    .unwrap();
+    write(
+        dir.join("max.c"),
+        "#include \"max.h\"\nint m = MAX(1, LIMIT);\n",
+    )
+    .unwrap();
+    let define = |ctx: &mut crate::Context| {
+        ctx.define(c"LIMIT", Some(c"2"));
+    };
+    write(dir.join("max.i"), "int m = ((1) > (2) ? (1) : (2));\n").unwrap();
+    golden_preprocess_with(out.dir(), define);
+
+    write(dir.join("max.i"), "int m = ((1) > (3) ? (1) : (3));\n").unwrap();
+    let err = catch_unwind(AssertUnwindSafe(|| {
+        golden_preprocess_with(out.dir(), define)
+    }));
+    let err = err.unwrap_err();
+    let message = err.downcast_ref::<String>().unwrap();
+    assert!(message.contains("- int m = ((1) > (3) ? (1) : (3));"));
+    assert!(message.contains("+ int m = ((1) > (2) ? (1) : (2));"));
+}
+
+#[cfg(feature = "debug")]
+#[test]
+fn live_states() {
+    use crate::debug::{live_states, StateKind};
+
+    // other tests create states concurrently, only look at new ones
+    let ids = |kind| {
+        live_states()
+            .into_iter()
+            .filter(|state| state.kind == kind)
+            .map(|state| state.id)
+            .collect::<Vec<_>>()
+    };
+    let new = |before: &[u64], now: Vec<u64>| {
+        now.into_iter()
+            .filter(|id| !before.contains(id))
+            .collect::<Vec<_>>()
+    };
+
+    let before = ids(StateKind::Context);
+    let guard = scoped(|scope| {
+        scope.spawn().unwrap();
+        ids(StateKind::Context)
+    })
+    .unwrap();
+    let contexts = new(&before, guard.get().clone());
+    assert!(!contexts.is_empty());
+    drop(guard);
+    let now = ids(StateKind::Context);
+    assert!(contexts.iter().any(|id| !now.contains(id)));
+
+    let before = ids(StateKind::Module);
+    let f = crate::jit::<extern "C" fn() -> c_int>("int f(void) { return 1; }", "f").unwrap();
+    let modules = new(&before, ids(StateKind::Module));
+    assert!(!modules.is_empty());
+    let report = live_states()
+        .into_iter()
+        .find(|state| state.id == modules[0])
+        .unwrap()
+        .to_string();
+    assert!(report.starts_with(&format!("Module #{} alive for", modules[0])));
+    drop(f);
+    let now = ids(StateKind::Module);
+    assert!(modules.iter().any(|id| !now.contains(id)));
+}
+
+#[test]
+fn set_std() {
+    use crate::{CStd, Error};
+
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Memory).unwrap();
+        ctx.set_std(CStd::C99).unwrap();
+        for std in [CStd::C89, CStd::C11, CStd::C2x] {
+            match ctx.set_std(std) {
+                Ok(_) | Err(Error::UnsupportedOption { .. }) => {}
+                Err(err) => panic!("unexpected error {err}"),
+            }
+        }
+        // tcc always defines __STDC_VERSION__
+        assert!(ctx.set_std(CStd::C89).is_err());
+    })
+    .unwrap();
+}
+
+#[test]
+fn warning() {
+    use crate::{Level, Warning};
+
+    assert!(Warning::supported().contains(&Warning::ImplicitFunctionDeclaration));
+    let source = "int main(void) { return f(); }";
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Obj)
+            .unwrap()
+            .collect_diagnostics(true)
+            .warning(Warning::ImplicitFunctionDeclaration, Level::Error)
+            .unwrap();
+        assert!(ctx.compile_str(source).is_err());
+
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Obj)
+            .unwrap()
+            .collect_diagnostics(true)
+            .warning(Warning::ImplicitFunctionDeclaration, Level::Allow)
+            .unwrap();
+        ctx.compile_str(source).unwrap();
+        assert!(ctx.diagnostics().is_empty());
+    })
+    .unwrap();
+}
+
+#[test]
+fn banned_functions() {
+    use crate::{Error, DEFAULT_BANNED_FUNCTIONS};
+
+    let source = concat!(
+        "#define RUN(cmd) system(cmd)\n",
+        "int system(const char *);\n",
+        "int execv(const char *, char *const[]);\n",
+        "struct s { int fork; };\n",
+        "int f(struct s *p) {\n",
+        "    RUN(\"ls\");\n",
+        "    return p->fork;\n",
+        "}\n",
+        "int (*g)(const char *, char *const[]) = execv;\n",
+    );
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Obj)
+            .unwrap()
+            .collect_diagnostics(true);
+        if !tcc_sys::PREPROCESS_OUTPUT {
+            assert!(ctx
+                .set_banned_functions(DEFAULT_BANNED_FUNCTIONS.iter().copied())
+                .is_err());
+            return;
+        }
+        ctx.set_banned_functions(DEFAULT_BANNED_FUNCTIONS.iter().copied())
+            .unwrap();
+        let Err(Error::BannedFunction {
+            function,
+            diagnostics,
+        }) = ctx.compile_str(source)
+        else {
+            panic!("banned functions were accepted");
+        };
+        assert_eq!(function, "system");
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|diag| (diag.location.as_ref().unwrap().line, diag.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (6, "call to banned function 'system'"),
+                (9, "call to banned function 'execv'"),
+            ]
+        );
+
+        // the struct member and declarations alone are fine
+        ctx.set_banned_functions(["system"]).unwrap();
+        ctx.compile_str("int system(const char *); struct s { int system; };")
+            .unwrap();
+        // calling through a dereference is still a use
+        let err =
+            ctx.compile_str("int system(const char *); int f(void) { return (*system)(\"sh\"); }");
+        assert!(matches!(err, Err(Error::BannedFunction { .. })));
+        // casts take the address too, function pointer members are fine
+        for cast in [
+            "int system(const char *); void *p = (void *)system;",
+            "int system(const char *); void *f(void) { return (void *)system; }",
+        ] {
+            assert!(matches!(
+                ctx.compile_str(cast),
+                Err(Error::BannedFunction { .. })
+            ));
+        }
+        ctx.compile_str("struct ops { int (*exec)(void); int (*system)(const char *); };")
+            .unwrap();
+        // a source the check can not preprocess is rejected
+        assert!(ctx
+            .compile_str("#include \"tcc_rs_missing.h\"\nint f(void) { return 0; }")
+            .is_err());
+        // the C string API is screened too
+        let err =
+            ctx.compile_string(c"int system(const char *); int f(void) { return system(\"sh\"); }");
+        assert!(matches!(err, Err(Error::BannedFunction { .. })));
+        ctx.set_banned_functions(Vec::<String>::new()).unwrap();
+        ctx.compile_str(source).unwrap();
+    })
+    .unwrap();
+}
+
+#[test]
+fn inline_asm() {
+    use crate::Error;
+
+    let source = concat!(
+        "#define BARRIER() __asm__ volatile(\"\" ::: \"memory\")\n",
+        "__asm__(\".globl spliced\");\n",
+        "int renamed(void) __asm__(\"other\");\n",
+        "void f(void) {\n",
+        "    BARRIER();\n",
+        "}\n",
+    );
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        assert!(crate::inline_asm_default());
+        ctx.set_output_type(OutputType::Obj)
+            .unwrap()
+            .collect_diagnostics(true);
+        if !tcc_sys::PREPROCESS_OUTPUT {
+            assert!(ctx.allow_inline_asm(false).is_err());
+            return;
+        }
+        ctx.allow_inline_asm(false).unwrap();
+        let Err(Error::Compile { diagnostics, .. }) = ctx.compile_str(source) else {
+            panic!("inline assembly was accepted");
+        };
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|diag| (diag.location.as_ref().unwrap().line, diag.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (2, "inline assembly not allowed"),
+                (5, "inline assembly not allowed"),
+            ]
+        );
+        // labels naming a declaration's symbol are fine
+        ctx.compile_str("int renamed(void) __asm__(\"other\");")
+            .unwrap();
+    })
+    .unwrap();
+}
+
+#[test]
+fn gnu_extensions() {
+    use crate::{Error, GnuExtensions};
+
+    let source = concat!(
+        "int f(int c) {\n",
+        "    /* ({ typeof in comments is fine */\n",
+        "    const char *s = \"case 1 ... 2: ({\";\n",
+        "    switch (c) { case 'a' ... 'z': return ({ 1; }); }\n",
+        "    return (typeof(c))0;\n",
+        "}\n",
+    );
+    scoped(|scope| {
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Obj).unwrap();
+        ctx.compile_str(source).unwrap();
+
+        let ctx = scope.spawn().unwrap();
+        ctx.set_output_type(OutputType::Obj)
+            .unwrap()
+            .collect_diagnostics(true)
+            .set_gnu_extensions(GnuExtensions::BANNED);
+        let Err(Error::Compile { diagnostics, .. }) = ctx.compile_str(source) else {
+            panic!("GNU extensions were accepted");
+        };
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|diag| (diag.location.as_ref().unwrap().line, diag.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (4, "GNU extension not allowed: case ranges"),
+                (4, "GNU extension not allowed: statement expressions"),
+                (5, "GNU extension not allowed: typeof"),
+            ]
+        );
+    })
+    .unwrap();
+}
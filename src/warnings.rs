@@ -0,0 +1,113 @@
+//! Typed `-W` switches, checked against the warnings the linked tcc knows.
+//!
+//! tcc ignores `-W` names it does not know, so a policy spelled as raw
+//! options silently stops working when tinycc renames a warning. Each
+//! [`Warning`] lists every spelling it went by and uses the one the linked
+//! tcc understands, see `TCC_WARNINGS` in `tcc-sys`.
+
+use alloc::{ffi::CString, format, vec::Vec};
+
+use tcc_sys::TCC_WARNINGS;
+
+use crate::{config::Setting, Context, Error};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+/// A warning tcc can emit, see [`Context::warning`].
+pub enum Warning {
+    /// every warning enabled by `-Wall`
+    All,
+
+    /// string literals are `const char[]`, `-Wwrite-strings`
+    WriteStrings,
+
+    /// options and attributes tcc does not implement, `-Wunsupported`
+    Unsupported,
+
+    /// calls to undeclared functions, `-Wimplicit-function-declaration`
+    ImplicitFunctionDeclaration,
+
+    /// `const` or `volatile` dropped by a conversion,
+    /// `-Wdiscarded-qualifiers`
+    DiscardedQualifiers,
+}
+
+impl Warning {
+    pub const VARIANTS: [Warning; 5] = [
+        Warning::All,
+        Warning::WriteStrings,
+        Warning::Unsupported,
+        Warning::ImplicitFunctionDeclaration,
+        Warning::DiscardedQualifiers,
+    ];
+
+    /// `-W` names of the warning, current first.
+    pub fn names(self) -> &'static [&'static str] {
+        match self {
+            Warning::All => &["all"],
+            Warning::WriteStrings => &["write-strings"],
+            Warning::Unsupported => &["unsupported"],
+            Warning::ImplicitFunctionDeclaration => &["implicit-function-declaration"],
+            Warning::DiscardedQualifiers => &["discarded-qualifiers"],
+        }
+    }
+
+    /// The name the linked tcc understands, `None` if it does not have the
+    /// warning.
+    pub fn name(self) -> Option<&'static str> {
+        self.names()
+            .iter()
+            .copied()
+            .find(|name| TCC_WARNINGS.contains(name))
+    }
+
+    /// The warnings the linked tcc has.
+    pub fn supported() -> Vec<Warning> {
+        Self::VARIANTS
+            .into_iter()
+            .filter(|warning| warning.name().is_some())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// What happens when a [`Warning`] triggers.
+pub enum Level {
+    /// nothing, `-Wno-<name>`
+    Allow,
+
+    /// a warning, `-W<name> -Wno-error=<name>`
+    Warn,
+
+    /// an error failing the compilation, `-Werror=<name>`
+    Error,
+}
+
+impl<'err> Context<'err> {
+    /// Set how `warning` is reported.
+    ///
+    /// Fails with [`Error::UnsupportedOption`] if the linked tcc does not
+    /// have the warning, rather than ignoring the policy like tcc would.
+    pub fn warning(&mut self, warning: Warning, level: Level) -> Result<&mut Self, Error> {
+        let primary = warning.names()[0];
+        let name = warning.name().ok_or(Error::UnsupportedOption {
+            option: primary,
+            reason: "the warning is unknown to the linked tcc",
+        })?;
+        let options = match level {
+            Level::Allow => [format!("-Wno-{name}"), format!("-Wno-error={name}")],
+            Level::Warn => [format!("-W{name}"), format!("-Wno-error={name}")],
+            Level::Error => [format!("-W{name}"), format!("-Werror={name}")],
+        };
+        for option in options {
+            let option = CString::new(option).expect("warning names have no NUL");
+            if self.apply(Setting::Options(option)) < 0 {
+                return Err(Error::UnsupportedOption {
+                    option: primary,
+                    reason: "rejected by tcc",
+                });
+            }
+        }
+        Ok(self)
+    }
+}
@@ -0,0 +1,248 @@
+//! Support for compiling against Windows headers.
+
+use alloc::ffi::CString;
+use core::ffi::CStr;
+
+use crate::{
+    capabilities, config::Setting, profile::EMBEDDED_HEADERS, Context, Error, ExecutableFormat,
+    OutputType,
+};
+
+/// Where the VFS serves the embedded Windows headers from, `winapi` holding
+/// `windows.h` and friends.
+const EMBEDDED_WIN32_HEADERS: [&str; 2] = ["/vfs/headers/win32", "/vfs/headers/win32/winapi"];
+
+/// Where the VFS serves tcc's embedded libraries and import definitions.
+pub(crate) const EMBEDDED_LIBRARIES: &str = "/vfs/libraries";
+
+/// Import libraries linked into programs by default, in addition to the C
+/// runtime: enough for `windows.h` snippets calling the common APIs.
+const DEFAULT_LIBRARIES: [&CStr; 2] = [c"kernel32", c"user32"];
+
+/// Import libraries [`Context::cross_windows`] links on top of
+/// [`DEFAULT_LIBRARIES`].
+const CROSS_LIBRARIES: [&CStr; 1] = [c"gdi32"];
+
+/// Windows version the headers declare APIs for under
+/// [`Context::cross_windows`]: Windows 7.
+const CROSS_WINNT: &CStr = c"0x0601";
+
+/// Definitions making Microsoft's dialect parse with tcc: keywords become
+/// the GNU attributes tcc knows or vanish, SAL annotations vanish.
+const MSVC_COMPAT: &[(&CStr, &CStr)] = &[
+    (c"__declspec(x)", c"__attribute__((x))"),
+    (c"__cdecl", c"__attribute__((__cdecl__))"),
+    (c"_cdecl", c"__attribute__((__cdecl__))"),
+    (c"__stdcall", c"__attribute__((__stdcall__))"),
+    (c"_stdcall", c"__attribute__((__stdcall__))"),
+    (c"__fastcall", c"__attribute__((__fastcall__))"),
+    (c"__forceinline", c"__inline__"),
+    (c"__inline", c"__inline__"),
+    (c"__int8", c"char"),
+    (c"__int16", c"short"),
+    (c"__int32", c"int"),
+    (c"__int64", c"long long"),
+    (c"__ptr32", c""),
+    (c"__ptr64", c""),
+    (c"__sptr", c""),
+    (c"__uptr", c""),
+    (c"__unaligned", c""),
+    (c"__w64", c""),
+    (c"__pragma(x)", c""),
+    // SAL
+    (c"_In_", c""),
+    (c"_In_opt_", c""),
+    (c"_In_z_", c""),
+    (c"_In_opt_z_", c""),
+    (c"_Out_", c""),
+    (c"_Out_opt_", c""),
+    (c"_Inout_", c""),
+    (c"_Inout_opt_", c""),
+    (c"_Inout_z_", c""),
+    (c"_Outptr_", c""),
+    (c"_Outptr_opt_", c""),
+    (c"_Outptr_result_maybenull_", c""),
+    (c"_Deref_out_", c""),
+    (c"_Ret_maybenull_", c""),
+    (c"_Ret_notnull_", c""),
+    (c"_Ret_z_", c""),
+    (c"_Check_return_", c""),
+    (c"_Must_inspect_result_", c""),
+    (c"_Use_decl_annotations_", c""),
+    (c"_Null_terminated_", c""),
+    (c"_NullNull_terminated_", c""),
+    (c"_Frees_ptr_", c""),
+    (c"_Frees_ptr_opt_", c""),
+    (c"_Reserved_", c""),
+    (c"_Pre_notnull_", c""),
+    (c"_Post_invalid_", c""),
+    (c"_Printf_format_string_", c""),
+    (c"_Analysis_noreturn_", c""),
+    (c"_Maybenull_", c""),
+    (c"_Notnull_", c""),
+    (c"_Interlocked_operand_", c""),
+    (c"_In_reads_(n)", c""),
+    (c"_In_reads_opt_(n)", c""),
+    (c"_In_reads_bytes_(n)", c""),
+    (c"_In_reads_bytes_opt_(n)", c""),
+    (c"_In_reads_z_(n)", c""),
+    (c"_Out_writes_(n)", c""),
+    (c"_Out_writes_opt_(n)", c""),
+    (c"_Out_writes_z_(n)", c""),
+    (c"_Out_writes_bytes_(n)", c""),
+    (c"_Out_writes_bytes_opt_(n)", c""),
+    (c"_Out_writes_to_(n, c)", c""),
+    (c"_Out_writes_to_opt_(n, c)", c""),
+    (c"_Out_writes_bytes_to_(n, c)", c""),
+    (c"_Out_writes_bytes_to_opt_(n, c)", c""),
+    (c"_Inout_updates_(n)", c""),
+    (c"_Inout_updates_opt_(n)", c""),
+    (c"_Inout_updates_bytes_(n)", c""),
+    (c"_Outptr_result_buffer_(n)", c""),
+    (c"_Outptr_result_bytebuffer_(n)", c""),
+    (c"_Ret_writes_(n)", c""),
+    (c"_Ret_writes_bytes_(n)", c""),
+    (c"_Field_size_(n)", c""),
+    (c"_Field_size_opt_(n)", c""),
+    (c"_Field_size_bytes_(n)", c""),
+    (c"_Field_z_", c""),
+    (c"_Success_(e)", c""),
+    (c"_Return_type_success_(e)", c""),
+    (c"_When_(c, a)", c""),
+    (c"_Pre_satisfies_(e)", c""),
+    (c"_Post_satisfies_(e)", c""),
+    (c"_Acquires_lock_(l)", c""),
+    (c"_Releases_lock_(l)", c""),
+    (c"_Requires_lock_held_(l)", c""),
+    (c"__drv_aliasesMem", c""),
+    (c"__drv_allocatesMem(k)", c""),
+    (c"__drv_freesMem(k)", c""),
+    // pre-Vista SAL
+    (c"__in", c""),
+    (c"__in_opt", c""),
+    (c"__out", c""),
+    (c"__out_opt", c""),
+    (c"__inout", c""),
+    (c"__inout_opt", c""),
+    (c"__in_ecount(n)", c""),
+    (c"__in_bcount(n)", c""),
+    (c"__out_ecount(n)", c""),
+    (c"__out_bcount(n)", c""),
+    (c"__deref_out", c""),
+    (c"__reserved", c""),
+    (c"__checkReturn", c""),
+    (c"__callback", c""),
+    (c"__nullterminated", c""),
+];
+
+impl<'err> Context<'err> {
+    /// Search paths every context targeting PE starts with, so
+    /// `#include <windows.h>` works without a Windows SDK: the embedded
+    /// win32 headers and, for linking, the embedded import definitions.
+    pub(crate) fn windows_defaults(&mut self) {
+        let caps = capabilities();
+        if caps.format != ExecutableFormat::Pe || !caps.vfs {
+            return;
+        }
+        if caps.embedded_win32_headers {
+            for path in EMBEDDED_WIN32_HEADERS {
+                self.add_sys_include_path(path);
+            }
+        }
+        if caps.embedded_libraries {
+            self.add_library_path(EMBEDDED_LIBRARIES);
+        }
+    }
+
+    /// Link [`DEFAULT_LIBRARIES`] into PE programs, called once `output` is
+    /// known since tcc only takes libraries after that.
+    pub(crate) fn windows_libraries(&mut self, output: OutputType) {
+        let links = matches!(
+            output,
+            OutputType::Memory | OutputType::Exe | OutputType::Dll
+        );
+        if !links || capabilities().format != ExecutableFormat::Pe {
+            return;
+        }
+        for library in DEFAULT_LIBRARIES {
+            // a missing definition file only matters once a symbol is used,
+            // which reports the symbol instead
+            let _ = self.add_library(library);
+        }
+    }
+
+    /// Define the `__declspec`, calling convention, integer type and SAL
+    /// annotation shims real Windows SDK headers expect from MSVC, or
+    /// remove them again.
+    ///
+    /// `__declspec(x)` and the calling conventions turn into the matching
+    /// GNU attributes, so `dllimport` and `__stdcall` keep working;
+    /// annotations tcc has no use for, like `_In_reads_(n)` or `__ptr64`,
+    /// expand to nothing. `_MSC_VER` is deliberately left undefined, headers
+    /// would take it as permission to use MSVC intrinsics tcc does not have.
+    pub fn msvc_compat(&mut self, enable: bool) -> &mut Self {
+        for (definition, value) in MSVC_COMPAT {
+            let setting = match enable {
+                true => Setting::Define((*definition).into(), Some((*value).into())),
+                false => {
+                    let definition = definition.to_bytes();
+                    let name = definition.split(|&b| b == b'(').next().unwrap();
+                    Setting::Undefine(CString::new(name).unwrap())
+                }
+            };
+            self.apply(setting);
+        }
+        self
+    }
+}
+
+impl<'err> Context<'err> {
+    /// Set up building a Windows program for the running tcc's PE target
+    /// from any host, with nothing from a Windows SDK or MinGW installed.
+    ///
+    /// Sets `output`, which must be [`OutputType::Exe`],
+    /// [`OutputType::Dll`] or [`OutputType::Obj`], and compiles with
+    /// `-nostdinc`: headers come from the embedded tcc and MinGW headers
+    /// only, so the host's are never picked up. `_WIN32_WINNT` and `WINVER`
+    /// select the Windows 7 API, define them again to target another
+    /// version. Programs link `msvcrt`, `kernel32`, `user32` and `gdi32`
+    /// through the embedded import definitions and tcc's runtime from the
+    /// embedded `libtcc1.a`; write them with [`Context::output_file`].
+    ///
+    /// Fails with [`Error::UnsupportedOption`] unless `tcc-sys` is built
+    /// for PE, with `link-pe` or for a Windows target, and embeds the
+    /// headers and libraries (`embed-headers-win32`, `embed-libraries`).
+    pub fn cross_windows(&mut self, output: OutputType) -> Result<&mut Self, Error> {
+        let caps = capabilities();
+        let unsupported = |reason| {
+            Err(Error::UnsupportedOption {
+                option: "cross_windows",
+                reason,
+            })
+        };
+        if caps.format != ExecutableFormat::Pe {
+            return unsupported("tcc does not target PE, see the `link-pe` feature of tcc-sys");
+        }
+        if !caps.vfs || !caps.embedded_win32_headers || !caps.embedded_libraries {
+            return unsupported("the MinGW headers and import definitions are not embedded");
+        }
+        if !matches!(output, OutputType::Exe | OutputType::Dll | OutputType::Obj) {
+            return unsupported("only files can be written for Windows");
+        }
+
+        self.set_output_type(output)?;
+        self.set_option("-nostdinc")?;
+        if caps.embedded_headers {
+            self.add_sys_include_path(EMBEDDED_HEADERS);
+        }
+        for name in [c"_WIN32_WINNT", c"WINVER"] {
+            self.apply(Setting::Define(name.into(), Some(CROSS_WINNT.into())));
+        }
+        if output != OutputType::Obj {
+            for library in CROSS_LIBRARIES {
+                self.add_library(library)?;
+            }
+        }
+        Ok(self)
+    }
+}
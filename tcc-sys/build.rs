@@ -1,6 +1,6 @@
-use std::{env, fs, path::PathBuf};
+use std::{env, fs, path::PathBuf, process::Command};
 
-use cargo_emit::rerun_if_changed;
+use cargo_emit::{rerun_if_changed, rerun_if_env_changed, warning};
 use cfg_if::cfg_if;
 use eyre::Result;
 use static_assertions::const_assert;
@@ -51,58 +51,54 @@ const ARCH: &[SupportedArchitecture] = &[
 // Make sure that either 0 or 1 arch is selected
 const_assert!(ARCH.len() <= 1);
 
-const LINK: &[()] = &[
+const LINK: &[ExecutableLinkage] = &[
     #[cfg(feature = "link-pe")]
     ExecutableLinkage::PortableExecutable,
     #[cfg(feature = "link-mach-o")]
     ExecutableLinkage::MachO,
+    #[cfg(feature = "link-elf")]
+    ExecutableLinkage::ELF,
 ];
 
 // Make sure that either 0 or 1 link is selected
 const_assert!(LINK.len() <= 1);
 
 fn generate_bindings() -> Result<()> {
-    let bindings = bindgen::Builder::default()
+    let mut builder = bindgen::Builder::default()
         .header("tinycc/libtcc.h")
         .parse_callbacks(Box::new(bindgen::CargoCallbacks))
-        .use_core()
-        .generate()?;
+        .use_core();
+    if cfg!(feature = "runtime-loading") {
+        builder = builder
+            .dynamic_library_name("LibTcc")
+            .dynamic_link_require_all(true);
+    }
+    let bindings = builder.generate()?;
     let out_path = PathBuf::from(env::var("OUT_DIR")?);
     bindings.write_to_file(out_path.join("bindings.rs"))?;
-    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR")?);
-    bindings.write_to_file(manifest_dir.join("bindings.rs"))?;
+    // the checked in copy always shows the linked flavour
+    if !cfg!(feature = "runtime-loading") {
+        let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR")?);
+        bindings.write_to_file(manifest_dir.join("bindings.rs"))?;
+    }
     Ok(())
 }
 
-fn build_static_library() -> Result<()> {
-    let mut cc = cc::Build::new();
-    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR")?);
-    let version = format!(
-        r#""{}""#,
-        fs::read_to_string(manifest_dir.join("tinycc").join("VERSION"))?.trim_end()
-    );
-
-    let cc = cc
-        .file("tinycc/libtcc.c")
-        .include(&manifest_dir)
-        .define("TCC_VERSION", version.as_str());
-
-    let mut defines: Vec<&'static str> = vec![];
-
-    let target: Option<SupportedArchitecture> = if ARCH.len() == 1 {
+fn target_architecture() -> SupportedArchitecture {
+    if ARCH.len() == 1 {
         cfg_if! {
             if #[cfg(feature = "arch-i386")] {
-                Some(SupportedArchitecture::I386)
+                SupportedArchitecture::I386
             } else if #[cfg(feature = "arch-arm32")] {
-                Some(SupportedArchitecture::ARM32)
+                SupportedArchitecture::ARM32
             } else if #[cfg(feature = "arch-arm64")] {
-                Some(SupportedArchitecture::ARM64)
+                SupportedArchitecture::ARM64
             } else if #[cfg(feature = "arch-c67")] {
-                Some(SupportedArchitecture::C67)
+                SupportedArchitecture::C67
             }else if #[cfg(feature = "arch-x86_64")] {
-                Some(SupportedArchitecture::X86_64)
+                SupportedArchitecture::X86_64
             } else if #[cfg(feature = "arch-rv64")] {
-                Some(SupportedArchitecture::RV64)
+                SupportedArchitecture::RV64
             } else {
                 panic!("must select a valid target")
             }
@@ -110,32 +106,28 @@ fn build_static_library() -> Result<()> {
     } else {
         cfg_if! {
             if #[cfg(target_arch = "x86")] {
-                Some(SupportedArchitecture::I386)
+                SupportedArchitecture::I386
             } else if #[cfg(target_arch = "arm")] {
-                Some(SupportedArchitecture::ARM32)
+                SupportedArchitecture::ARM32
             } else if #[cfg(target_arch = "aarch64")] {
-                Some(SupportedArchitecture::ARM64)
+                SupportedArchitecture::ARM64
             } else if #[cfg(target_arch = "x86_64")] {
-                Some(SupportedArchitecture::X86_64)
+                SupportedArchitecture::X86_64
             } else if #[cfg(target_arch = "riscv64")] {
-                Some(SupportedArchitecture::RV64)
+                SupportedArchitecture::RV64
             } else {
                 panic!("this target is not natively supported")
             }
         }
-    };
-
-    defines.push(target.unwrap().into());
+    }
+}
 
-    let linkage = if LINK.len() == 0 {
-        cfg_if! {
-            if #[cfg(target_os = "windows")] {
-                Some(ExecutableLinkage::PortableExecutable)
-            } else if #[cfg(target_os = "macos")] {
-                Some(ExecutableLinkage::MachO)
-            } else {
-                None
-            }
+fn executable_linkage() -> Option<ExecutableLinkage> {
+    if LINK.len() == 0 {
+        match target_os().as_str() {
+            "windows" => Some(ExecutableLinkage::PortableExecutable),
+            "macos" => Some(ExecutableLinkage::MachO),
+            _ => None,
         }
     } else {
         if cfg!(feature = "link-pe") {
@@ -143,18 +135,239 @@ fn build_static_library() -> Result<()> {
         } else if cfg!(feature = "link-mach-o") {
             Some(ExecutableLinkage::MachO)
         } else {
+            // `link-elf`, which tcc selects by leaving the others undefined
             None
         }
-    };
+    }
+}
+
+/// `target_os` of the crate being built, `cfg!` in a build script describes
+/// the host instead.
+fn target_os() -> String {
+    env::var("CARGO_CFG_TARGET_OS").unwrap_or_default()
+}
+
+/// `TARGETOS_*` define selecting tcc's defaults for ELF systems other than
+/// Linux: dynamic loader, ABI note and library layout.
+fn target_os_define(os: &str) -> Option<&'static str> {
+    match os {
+        "android" => Some("TARGETOS_ANDROID"),
+        "freebsd" => Some("TARGETOS_FreeBSD"),
+        "netbsd" => Some("TARGETOS_NetBSD"),
+        "openbsd" => Some("TARGETOS_OpenBSD"),
+        _ => None,
+    }
+}
+
+fn tcc_version() -> Result<String> {
+    if !cfg!(feature = "vendored") || cfg!(feature = "runtime-loading") {
+        // the system library is not ours to inspect, trust the user
+        rerun_if_env_changed!("TCC_VERSION");
+        return Ok(env::var("TCC_VERSION").unwrap_or_else(|_| "unknown".to_string()));
+    }
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR")?);
+    let version = fs::read_to_string(manifest_dir.join("tinycc").join("VERSION"))?;
+    Ok(version.trim_end().to_string())
+}
+
+/// Commit of the vendored tinycc checkout, `unknown` outside of a git work
+/// tree.
+fn tcc_commit() -> String {
+    Command::new("git")
+        .args(["-C", "tinycc", "rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|commit| commit.trim_end().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Whether ARM code passes floats in VFP registers, the `hard` ABI of
+/// `gnueabihf` targets, or in core registers, `softfp`. tcc always uses VFP
+/// instructions, fully soft floating point is not supported.
+fn arm_float_abi() -> &'static str {
+    if cfg!(feature = "arm-softfp") {
+        return "softfp";
+    }
+    // an ARM target names its ABI, other hosts get the common gnueabihf one
+    let arm = env::var("CARGO_CFG_TARGET_ARCH").is_ok_and(|arch| arch == "arm");
+    let hf = env::var("TARGET").is_ok_and(|target| target.ends_with("hf"));
+    match arm && !hf {
+        true => "softfp",
+        false => "hard",
+    }
+}
+
+/// Float ABI of the backend: `hard` everywhere but on ARM, see
+/// [`arm_float_abi`].
+fn float_abi(target: SupportedArchitecture) -> &'static str {
+    match target {
+        SupportedArchitecture::ARM32 => arm_float_abi(),
+        _ => "hard",
+    }
+}
+
+/// Bound checking and backtraces are supported by every backend but C67.
+fn has_runtime_checks(target: SupportedArchitecture) -> bool {
+    !matches!(target, SupportedArchitecture::C67)
+}
+
+/// How generated code gets C11 atomics: `native` if tcc implements them,
+/// `fallback` for the host-backed `fallback/stdatomic.h`, `unknown` for a
+/// libtcc that is not built here.
+fn atomics(target: SupportedArchitecture) -> Result<&'static str> {
+    if !cfg!(feature = "vendored") || cfg!(feature = "runtime-loading") {
+        return Ok("unknown");
+    }
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR")?);
+    let header = manifest_dir
+        .join("tinycc")
+        .join("include")
+        .join("stdatomic.h");
+    // C67 has no atomic builtins, older tinycc checkouts have no header
+    Ok(match target {
+        SupportedArchitecture::C67 => "fallback",
+        _ if !header.exists() => "fallback",
+        _ => "native",
+    })
+}
+
+/// `-W` names of a tinycc whose source is not at hand, as of the mob branch.
+const KNOWN_WARNINGS: &[&str] = &[
+    "all",
+    "error",
+    "write-strings",
+    "unsupported",
+    "implicit-function-declaration",
+    "discarded-qualifiers",
+];
 
-    if let Some(target) = target {
-        cc.define(target.into(), None);
+/// The `-W` names tcc understands, read from the `options_W` table of the
+/// vendored `libtcc.c`; tcc ignores the others with at most a warning.
+fn warnings() -> Result<Vec<String>> {
+    if !cfg!(feature = "vendored") || cfg!(feature = "runtime-loading") {
+        return Ok(KNOWN_WARNINGS.iter().map(|name| name.to_string()).collect());
     }
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR")?);
+    let source = fs::read_to_string(manifest_dir.join("tinycc").join("libtcc.c"))?;
+    let table = source
+        .split_once("options_W[]")
+        .and_then(|(_, rest)| rest.split_once("};"))
+        .map(|(table, _)| table)
+        .ok_or_else(|| eyre::eyre!("no options_W table in tinycc/libtcc.c"))?;
+    // entries look like `{ offsetof(TCCState, warn_all), WD_ALL, "all" },`
+    Ok(table
+        .lines()
+        .filter_map(|line| line.split('"').nth(1))
+        .map(|name| name.to_string())
+        .collect())
+}
+
+/// Write `TCC_WARNINGS` for `src/lib.rs` to include.
+fn generate_warnings() -> Result<()> {
+    let mut out = String::from(
+        "/// `-W` names the library understands, e.g. `implicit-function-declaration`.\n",
+    );
+    out.push_str("pub const TCC_WARNINGS: &[&str] = &[\n");
+    for name in warnings()? {
+        out.push_str(&format!("    {name:?},\n"));
+    }
+    out.push_str("];\n");
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+    fs::write(out_dir.join("warnings.rs"), out)?;
+    Ok(())
+}
+
+/// Tell the crate what it is being built with, see `src/lib.rs`.
+fn emit_build_info(
+    target: SupportedArchitecture,
+    linkage: Option<ExecutableLinkage>,
+    version: &str,
+    atomics: &str,
+) {
+    let float_abi = float_abi(target);
+    let define: &'static str = target.into();
+    let linkage: &'static str = linkage.unwrap_or(ExecutableLinkage::ELF).into();
+    println!("cargo:rustc-env=TCC_SYS_VERSION={version}");
+    println!("cargo:rustc-env=TCC_SYS_COMMIT={}", tcc_commit());
+    println!("cargo:rustc-env=TCC_SYS_TARGET={define}");
+    println!("cargo:rustc-env=TCC_SYS_LINKAGE={linkage}");
+    println!("cargo:rustc-env=TCC_SYS_ATOMICS={atomics}");
+    println!("cargo:rustc-env=TCC_SYS_FLOAT_ABI={float_abi}");
+    if has_runtime_checks(target) {
+        println!("cargo:rustc-env=TCC_SYS_RUNTIME_CHECKS=1");
+    }
+}
+
+fn build_static_library(
+    target: SupportedArchitecture,
+    linkage: Option<ExecutableLinkage>,
+    version: &str,
+) -> Result<()> {
+    let mut cc = cc::Build::new();
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR")?);
+    let version = format!(r#""{version}""#);
+
+    let cc = cc
+        .file("tinycc/libtcc.c")
+        .file("preprocess.c")
+        .file("sections.c")
+        .file("messages.c")
+        .include(&manifest_dir)
+        .define("TCC_VERSION", version.as_str());
+
+    cc.define(target.into(), None);
 
     if let Some(linkage) = linkage {
         cc.define(linkage.into(), None);
     }
 
+    let runtime_checks = if has_runtime_checks(target) { "1" } else { "0" };
+    cc.define("CONFIG_TCC_BCHECK", runtime_checks);
+    cc.define("CONFIG_TCC_BACKTRACE", runtime_checks);
+
+    if let Some(ExecutableLinkage::MachO) = linkage {
+        macho_defaults(cc, target);
+    }
+
+    if i386_on_x86_64_linux(target, linkage) {
+        multilib_defaults(cc);
+    }
+
+    if let SupportedArchitecture::ARM32 = target {
+        // what tinycc's configure sets up for ARM Linux
+        cc.define("TCC_ARM_EABI", "1");
+        cc.define("TCC_ARM_VFP", "1");
+        cc.define("TCC_ARM_VERSION", "7");
+        if float_abi(target) == "hard" {
+            cc.define("TCC_ARM_HARDFLOAT", "1");
+        }
+    }
+
+    let os = target_os();
+    if let Some(define) = target_os_define(&os) {
+        cc.define(define, None);
+    }
+    // bionic's loader refuses anything else since Android 5
+    if os == "android" {
+        cc.define("CONFIG_TCC_PIE", "1");
+    }
+
+    // headers, libraries and the dynamic loader are looked up under the
+    // sysroot, e.g. a BSD base system unpacked on a Linux build machine
+    rerun_if_env_changed!("TCC_SYSROOT");
+    if let Ok(sysroot) = env::var("TCC_SYSROOT") {
+        cc.define("CONFIG_SYSROOT", format!(r#""{sysroot}""#).as_str());
+    }
+
+    // what libtcc prints to stderr goes to the error callback, see
+    // messages.c
+    cc.define("fprintf", "tcc_rs_fprintf");
+    cc.define("vfprintf", "tcc_rs_vfprintf");
+    cc.define("fputs", "tcc_rs_fputs");
+    cc.define("fputc", "tcc_rs_fputc");
+
     if cfg!(feature = "vfs") {
         cc.define("CONFIG_VFS", None);
         cc.define("open", "vfs_open");
@@ -163,16 +376,129 @@ fn build_static_library() -> Result<()> {
         cc.define("close", "vfs_close");
     }
 
+    if cfg!(feature = "sanitize") {
+        sanitize(cc);
+    }
+
     cc.try_compile("libtcc")?;
     Ok(())
 }
 
+/// Instrument libtcc with AddressSanitizer and UndefinedBehaviorSanitizer.
+///
+/// Undefined behaviour traps, so no UBSan runtime is needed. The ASan
+/// runtime has to be the one of the Rust side, built with
+/// `RUSTFLAGS=-Zsanitizer=address`, or else the C toolchain's is linked.
+fn sanitize(cc: &mut cc::Build) {
+    cc.flag("-fsanitize=address,undefined")
+        .flag("-fno-omit-frame-pointer")
+        .flag_if_supported("-fsanitize-trap=undefined")
+        .flag_if_supported("-fsanitize-undefined-trap-on-error")
+        .debug(true);
+
+    let rust_asan = env::var("CARGO_CFG_SANITIZE").is_ok_and(|sanitizers| {
+        sanitizers
+            .split(',')
+            .any(|sanitizer| sanitizer == "address")
+    });
+    if !rust_asan {
+        warning!(
+            "the sanitize feature works best with RUSTFLAGS=-Zsanitizer=address, linking the C \
+             toolchain's libasan instead"
+        );
+        println!("cargo:rustc-link-lib=dylib=asan");
+    }
+}
+
+/// Whether the i386 backend is built for an x86_64 Linux, which keeps its
+/// 32-bit C library apart from the native one.
+fn i386_on_x86_64_linux(target: SupportedArchitecture, linkage: Option<ExecutableLinkage>) -> bool {
+    matches!(target, SupportedArchitecture::I386)
+        && linkage.is_none()
+        && target_os() == "linux"
+        && env::var("CARGO_CFG_TARGET_ARCH").is_ok_and(|arch| arch == "x86_64")
+}
+
+/// Search the 32-bit C library where multilib systems install it, like
+/// `gcc -m32` does: `/usr/lib32` on Debian and Ubuntu, the `i386-linux-gnu`
+/// multiarch directories otherwise. The headers are shared with the 64-bit
+/// library and pick the word size themselves.
+fn multilib_defaults(cc: &mut cc::Build) {
+    let sysroot = env::var("TCC_SYSROOT").unwrap_or_default();
+    let paths = |dirs: &[&str]| {
+        dirs.iter()
+            .map(|dir| format!("{sysroot}{dir}"))
+            .collect::<Vec<_>>()
+            .join(":")
+    };
+    let crt = paths(&["/usr/lib32", "/usr/lib/i386-linux-gnu"]);
+    let libs = paths(&[
+        "/usr/lib32",
+        "/lib32",
+        "/usr/lib/i386-linux-gnu",
+        "/lib/i386-linux-gnu",
+    ]);
+    let includes = paths(&[
+        "/usr/local/include",
+        "/usr/include/x86_64-linux-gnu",
+        "/usr/include/i386-linux-gnu",
+        "/usr/include",
+    ]);
+    cc.define("CONFIG_TCC_CRTPREFIX", format!(r#""{crt}""#).as_str());
+    cc.define(
+        "CONFIG_TCC_SYSINCLUDEPATHS",
+        format!(r#""{{B}}/include:{includes}""#).as_str(),
+    );
+    cc.define("CONFIG_TCC_LIBPATHS", format!(r#""{{B}}:{libs}""#).as_str());
+}
+
+/// Root of the macOS SDK holding the system headers and `libSystem.tbd`:
+/// `SDKROOT` if set, like for Apple's tools, else what `xcrun` reports.
+fn macos_sdk() -> Option<String> {
+    rerun_if_env_changed!("SDKROOT");
+    if let Ok(sdk) = env::var("SDKROOT") {
+        return Some(sdk);
+    }
+    Command::new("xcrun")
+        .args(["--sdk", "macosx", "--show-sdk-path"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|sdk| sdk.trim_end().to_string())
+}
+
+/// Make Mach-O output work like with ld64: headers and libraries come from
+/// the SDK, as recent macOS has neither in `/usr`, and arm64 code is
+/// ad-hoc signed since the kernel refuses to run it unsigned.
+fn macho_defaults(cc: &mut cc::Build, target: SupportedArchitecture) {
+    match macos_sdk() {
+        Some(sdk) => {
+            let includes = format!(r#""{{B}}/include:{sdk}/usr/include""#);
+            let libraries = format!(r#""{{B}}/lib:{sdk}/usr/lib""#);
+            cc.define("CONFIG_TCC_SYSINCLUDEPATHS", includes.as_str());
+            cc.define("CONFIG_TCC_LIBPATHS", libraries.as_str());
+        }
+        None => warning!("no macOS SDK found, set SDKROOT to produce Mach-O files"),
+    }
+    if let SupportedArchitecture::ARM64 = target {
+        cc.define("CONFIG_CODESIGN", "1");
+    }
+}
+
+/// Link against a libtcc installed on the system, found in `TCC_LIB_DIR` if
+/// set and on the default linker search path otherwise.
 fn link_dynamic_library() -> Result<()> {
-    todo!()
+    rerun_if_env_changed!("TCC_LIB_DIR");
+    if let Ok(dir) = env::var("TCC_LIB_DIR") {
+        println!("cargo:rustc-link-search=native={dir}");
+    }
+    println!("cargo:rustc-link-lib=dylib=tcc");
+    Ok(())
 }
 
 #[cfg(feature = "embed-headers")]
-fn generate_include_dir() -> Result<()> {
+fn generate_include_dir(atomics: &str) -> Result<()> {
     use eyre::eyre;
     use fs_extra::dir::CopyOptions;
 
@@ -195,6 +521,13 @@ fn generate_include_dir() -> Result<()> {
     )
     .map_err(|e| eyre!(e))?;
 
+    if atomics == "fallback" {
+        fs::copy(
+            manifest_dir.join("fallback").join("stdatomic.h"),
+            include_dir.join("base").join("stdatomic.h"),
+        )?;
+    }
+
     if cfg!(feature = "embed-headers-win32") || cfg!(target_os = "windows") {
         fs_extra::dir::copy(
             &manifest_dir.join("tinycc").join("win32").join("include"),
@@ -204,24 +537,131 @@ fn generate_include_dir() -> Result<()> {
         .map_err(|e| eyre!(e))?;
     }
 
+    if cfg!(feature = "embed-simd") {
+        fs_extra::dir::copy(
+            manifest_dir.join("simd"),
+            include_dir.join("simd"),
+            &dir_copy_opt,
+        )
+        .map_err(|e| eyre!(e))?;
+    }
+
+    if cfg!(feature = "embed-musl") {
+        rerun_if_env_changed!("MUSL_SYSROOT");
+        let sysroot = PathBuf::from(env::var("MUSL_SYSROOT").map_err(|_| {
+            eyre!("embed-musl needs MUSL_SYSROOT, a musl install with include/ and lib/libc.a")
+        })?);
+        let musl = include_dir.join("musl");
+        fs_extra::dir::copy(sysroot.join("include"), musl.join("include"), &dir_copy_opt)
+            .map_err(|e| eyre!(e))?;
+        fs::create_dir_all(musl.join("lib"))?;
+        for file in ["crt1.o", "crti.o", "crtn.o", "libc.a"] {
+            fs::copy(sysroot.join("lib").join(file), musl.join("lib").join(file))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Collect the embedded libraries in `OUT_DIR/lib`: the prebuilt ones, whose
+/// `libtcc1.a` is the x86_64 Windows runtime, with `libtcc1.a` replaced for
+/// i386: by the prebuilt 32-bit one for Windows, by one built for the
+/// i386 backend on x86_64 Linux.
+#[cfg(feature = "embed-libraries")]
+fn generate_library_dir(
+    target: SupportedArchitecture,
+    linkage: Option<ExecutableLinkage>,
+) -> Result<()> {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR")?);
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+    let lib_dir = out_dir.join("lib");
+    let _ = fs::remove_dir_all(&lib_dir);
+    fs::create_dir_all(&lib_dir)?;
+    for entry in fs::read_dir(manifest_dir.join("lib"))? {
+        let entry = entry?;
+        fs::copy(entry.path(), lib_dir.join(entry.file_name()))?;
+    }
+
+    if matches!(
+        (target, linkage),
+        (
+            SupportedArchitecture::I386,
+            Some(ExecutableLinkage::PortableExecutable)
+        )
+    ) {
+        fs::copy(lib_dir.join("libtcc1-32.a"), lib_dir.join("libtcc1.a"))?;
+    }
+    if cfg!(feature = "vendored") && i386_on_x86_64_linux(target, linkage) {
+        if let Err(err) = build_libtcc1_i386(&lib_dir) {
+            warning!(
+                "building the 32-bit libtcc1 failed ({}), is gcc-multilib installed?",
+                err
+            );
+            fs::copy(lib_dir.join("libtcc1-32.a"), lib_dir.join("libtcc1.a"))?;
+        }
+    }
+    Ok(())
+}
+
+/// Build tinycc's runtime library for i386 into `dir/libtcc1.a` with the C
+/// toolchain's `-m32`.
+#[cfg(feature = "embed-libraries")]
+fn build_libtcc1_i386(dir: &std::path::Path) -> Result<()> {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR")?);
+    let sources = manifest_dir.join("tinycc").join("lib");
+    let mut cc = cc::Build::new();
+    cc.cargo_metadata(false)
+        .out_dir(dir)
+        .pic(false)
+        .warnings(false)
+        .flag("-m32")
+        .flag_if_supported("-fno-stack-protector")
+        .define("TCC_TARGET_I386", None);
+    for file in ["libtcc1.c", "alloca.S", "alloca-bt.S", "builtin.c"] {
+        let path = sources.join(file);
+        if path.exists() {
+            cc.file(path);
+        }
+    }
+    cc.try_compile("tcc1")?;
     Ok(())
 }
 
 fn main() -> Result<()> {
     rerun_if_changed!("tinycc");
     rerun_if_changed!("config.h");
+    rerun_if_changed!("fallback");
+    rerun_if_changed!("simd");
+    rerun_if_changed!("lib");
+    rerun_if_changed!("preprocess.c");
+    rerun_if_changed!("sections.c");
+    rerun_if_changed!("messages.c");
     rerun_if_changed!("build.rs");
     generate_bindings()?;
 
-    if cfg!(feature = "vendored") {
-        build_static_library()?;
+    let target = target_architecture();
+    let linkage = executable_linkage();
+    let version = tcc_version()?;
+    let atomics = atomics(target)?;
+    emit_build_info(target, linkage, &version, atomics);
+    generate_warnings()?;
+
+    if cfg!(feature = "runtime-loading") {
+        // nothing to link, see `src/runtime.rs`
+    } else if cfg!(feature = "vendored") {
+        build_static_library(target, linkage, &version)?;
     } else {
         link_dynamic_library()?;
     }
 
     #[cfg(feature = "embed-headers")]
     {
-        generate_include_dir()?;
+        generate_include_dir(atomics)?;
+    }
+
+    #[cfg(feature = "embed-libraries")]
+    {
+        generate_library_dir(target, linkage)?;
     }
 
     Ok(())
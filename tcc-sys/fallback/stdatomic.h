@@ -0,0 +1,124 @@
+/* C11 <stdatomic.h> for tcc backends without atomic builtins.
+ *
+ * Every operation calls into the host through __tcc_rs_atomic_rmw and
+ * __tcc_rs_atomic_cas, which the tcc crate registers for in-memory code.
+ * All operations are sequentially consistent; the memory order arguments of
+ * the _explicit forms are evaluated and ignored. Objects of 1, 2, 4 and 8
+ * bytes are supported, values are passed to the host by their bit pattern so
+ * floating point types load, store and compare exchange unchanged; other
+ * sizes fail to compile. Arithmetic is only supported on integer types. */
+
+#ifndef _STDATOMIC_H
+#define _STDATOMIC_H
+
+#include <stddef.h>
+#include <stdint.h>
+#include <stdbool.h>
+
+typedef enum {
+    memory_order_relaxed,
+    memory_order_consume,
+    memory_order_acquire,
+    memory_order_release,
+    memory_order_acq_rel,
+    memory_order_seq_cst
+} memory_order;
+
+void __tcc_rs_atomic_rmw(volatile void *, size_t, int, const volatile void *, volatile void *);
+int __tcc_rs_atomic_cas(volatile void *, size_t, volatile void *, const volatile void *);
+
+#define __TCC_RS_LOAD 0
+#define __TCC_RS_SWAP 1
+#define __TCC_RS_ADD 2
+#define __TCC_RS_SUB 3
+#define __TCC_RS_AND 4
+#define __TCC_RS_OR 5
+#define __TCC_RS_XOR 6
+#define __TCC_RS_FENCE 7
+
+/* a negative array size for objects the host can not access atomically */
+#define __tcc_rs_sized(p) \
+    ((void)sizeof(char[sizeof(*(p)) == 1 || sizeof(*(p)) == 2 || sizeof(*(p)) == 4 \
+                       || sizeof(*(p)) == 8 ? 1 : -1]))
+
+#define __tcc_rs_rmw(p, op, v) ({ \
+    __tcc_rs_sized(p); \
+    __typeof__(*(p)) __tcc_rs_value = (v), __tcc_rs_old; \
+    __tcc_rs_atomic_rmw((p), sizeof(*(p)), (op), &__tcc_rs_value, &__tcc_rs_old); \
+    __tcc_rs_old; \
+})
+
+#define ATOMIC_VAR_INIT(value) (value)
+#define atomic_init(p, value) ((void)(*(p) = (value)))
+#define kill_dependency(y) (y)
+#define atomic_is_lock_free(p) \
+    (sizeof(*(p)) == 1 || sizeof(*(p)) == 2 || sizeof(*(p)) == 4 || sizeof(*(p)) == 8)
+
+#define atomic_thread_fence(order) \
+    ((void)(order), __tcc_rs_atomic_rmw(0, 0, __TCC_RS_FENCE, 0, 0))
+#define atomic_signal_fence(order) atomic_thread_fence(order)
+
+#define atomic_load(p) __tcc_rs_rmw(p, __TCC_RS_LOAD, 0)
+#define atomic_store(p, v) ((void)__tcc_rs_rmw(p, __TCC_RS_SWAP, v))
+#define atomic_exchange(p, v) __tcc_rs_rmw(p, __TCC_RS_SWAP, v)
+#define atomic_fetch_add(p, v) __tcc_rs_rmw(p, __TCC_RS_ADD, v)
+#define atomic_fetch_sub(p, v) __tcc_rs_rmw(p, __TCC_RS_SUB, v)
+#define atomic_fetch_and(p, v) __tcc_rs_rmw(p, __TCC_RS_AND, v)
+#define atomic_fetch_or(p, v) __tcc_rs_rmw(p, __TCC_RS_OR, v)
+#define atomic_fetch_xor(p, v) __tcc_rs_rmw(p, __TCC_RS_XOR, v)
+#define atomic_compare_exchange_strong(p, expected, desired) ({ \
+    __tcc_rs_sized(p); \
+    __typeof__(*(p)) __tcc_rs_desired = (desired); \
+    (bool)__tcc_rs_atomic_cas((p), sizeof(*(p)), (expected), &__tcc_rs_desired); \
+})
+#define atomic_compare_exchange_weak(p, expected, desired) \
+    atomic_compare_exchange_strong(p, expected, desired)
+
+#define atomic_load_explicit(p, order) ((void)(order), atomic_load(p))
+#define atomic_store_explicit(p, v, order) ((void)(order), atomic_store(p, v))
+#define atomic_exchange_explicit(p, v, order) ((void)(order), atomic_exchange(p, v))
+#define atomic_fetch_add_explicit(p, v, order) ((void)(order), atomic_fetch_add(p, v))
+#define atomic_fetch_sub_explicit(p, v, order) ((void)(order), atomic_fetch_sub(p, v))
+#define atomic_fetch_and_explicit(p, v, order) ((void)(order), atomic_fetch_and(p, v))
+#define atomic_fetch_or_explicit(p, v, order) ((void)(order), atomic_fetch_or(p, v))
+#define atomic_fetch_xor_explicit(p, v, order) ((void)(order), atomic_fetch_xor(p, v))
+#define atomic_compare_exchange_strong_explicit(p, expected, desired, success, failure) \
+    ((void)(success), (void)(failure), atomic_compare_exchange_strong(p, expected, desired))
+#define atomic_compare_exchange_weak_explicit(p, expected, desired, success, failure) \
+    ((void)(success), (void)(failure), atomic_compare_exchange_weak(p, expected, desired))
+
+typedef struct { volatile unsigned char __value; } atomic_flag;
+#define ATOMIC_FLAG_INIT { 0 }
+#define atomic_flag_test_and_set(f) ((bool)__tcc_rs_rmw(&(f)->__value, __TCC_RS_SWAP, 1))
+#define atomic_flag_clear(f) atomic_store(&(f)->__value, 0)
+#define atomic_flag_test_and_set_explicit(f, order) ((void)(order), atomic_flag_test_and_set(f))
+#define atomic_flag_clear_explicit(f, order) ((void)(order), atomic_flag_clear(f))
+
+#define ATOMIC_BOOL_LOCK_FREE 2
+#define ATOMIC_CHAR_LOCK_FREE 2
+#define ATOMIC_SHORT_LOCK_FREE 2
+#define ATOMIC_INT_LOCK_FREE 2
+#define ATOMIC_LONG_LOCK_FREE 2
+#define ATOMIC_LLONG_LOCK_FREE 2
+#define ATOMIC_POINTER_LOCK_FREE 2
+
+typedef volatile bool atomic_bool;
+typedef volatile char atomic_char;
+typedef volatile signed char atomic_schar;
+typedef volatile unsigned char atomic_uchar;
+typedef volatile short atomic_short;
+typedef volatile unsigned short atomic_ushort;
+typedef volatile int atomic_int;
+typedef volatile unsigned int atomic_uint;
+typedef volatile long atomic_long;
+typedef volatile unsigned long atomic_ulong;
+typedef volatile long long atomic_llong;
+typedef volatile unsigned long long atomic_ullong;
+typedef volatile intptr_t atomic_intptr_t;
+typedef volatile uintptr_t atomic_uintptr_t;
+typedef volatile size_t atomic_size_t;
+typedef volatile ptrdiff_t atomic_ptrdiff_t;
+typedef volatile intmax_t atomic_intmax_t;
+typedef volatile uintmax_t atomic_uintmax_t;
+
+#endif /* _STDATOMIC_H */
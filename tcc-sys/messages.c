@@ -0,0 +1,82 @@
+/* Messages libtcc prints to stderr itself instead of reporting them through
+ * the error callback. build.rs renames the stdio calls libtcc makes to the
+ * functions below, which pass what goes to stderr to the callback of the
+ * state being worked on; anything else, and stderr outside of libtcc calls,
+ * reaches the real stdio. */
+
+/* the real functions, before stdio.h declares them */
+#undef fprintf
+#undef vfprintf
+#undef fputs
+#undef fputc
+#include <stdio.h>
+
+#undef ONE_SOURCE
+#define ONE_SOURCE 0
+#include "tinycc/tcc.h"
+
+/* Report `text` as a warning unless it says what it is; trailing newlines
+ * go, the callback gets whole lines. */
+static void tcc_rs_report(TCCState *s, const char *text)
+{
+    char buf[2048];
+    size_t len;
+    if (strstr(text, "error: ") || strstr(text, "warning: "))
+        snprintf(buf, sizeof buf, "%s", text);
+    else
+        snprintf(buf, sizeof buf, "warning: %s", text);
+    len = strlen(buf);
+    while (len && buf[len - 1] == '\n')
+        buf[--len] = 0;
+    if (len)
+        s->error_func(s->error_opaque, buf);
+}
+
+/* The state whose callback gets stderr, if any. */
+static TCCState *tcc_rs_capturing(FILE *stream)
+{
+    TCCState *s = tcc_state;
+    return stream == stderr && s && s->error_func ? s : NULL;
+}
+
+int tcc_rs_vfprintf(FILE *stream, const char *format, va_list ap)
+{
+    char buf[2048];
+    TCCState *s = tcc_rs_capturing(stream);
+    int len;
+    if (!s)
+        return vfprintf(stream, format, ap);
+    len = vsnprintf(buf, sizeof buf, format, ap);
+    tcc_rs_report(s, buf);
+    return len;
+}
+
+int tcc_rs_fprintf(FILE *stream, const char *format, ...)
+{
+    va_list ap;
+    int len;
+    va_start(ap, format);
+    len = tcc_rs_vfprintf(stream, format, ap);
+    va_end(ap);
+    return len;
+}
+
+int tcc_rs_fputs(const char *text, FILE *stream)
+{
+    TCCState *s = tcc_rs_capturing(stream);
+    if (!s)
+        return fputs(text, stream);
+    tcc_rs_report(s, text);
+    return 0;
+}
+
+int tcc_rs_fputc(int c, FILE *stream)
+{
+    char text[2] = { (char)c, 0 };
+    if (!tcc_rs_capturing(stream))
+        return fputc(c, stream);
+    /* single characters only end lines tcc already printed */
+    if (c != '\n')
+        tcc_rs_report(tcc_rs_capturing(stream), text);
+    return c;
+}
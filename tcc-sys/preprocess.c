@@ -0,0 +1,22 @@
+/* Preprocessor output of libtcc users. The tcc driver opens `ppfp` from
+ * `-o` itself; the library never does and leaves it at stdout. */
+
+#undef ONE_SOURCE
+#define ONE_SOURCE 0
+#include "tinycc/tcc.h"
+
+int tcc_rs_preprocess_to(TCCState *s, const char *path)
+{
+    FILE *f = fopen(path, "wb");
+    if (!f)
+        return -1;
+    s->ppfp = f;
+    return 0;
+}
+
+void tcc_rs_preprocess_done(TCCState *s)
+{
+    if (s->ppfp && s->ppfp != stdout)
+        fclose(s->ppfp);
+    s->ppfp = stdout;
+}
@@ -0,0 +1,152 @@
+/* Sizes of what libtcc users relocate. The library only reports the size
+ * of the whole image, per-section numbers need the internal tables. */
+
+#undef ONE_SOURCE
+#define ONE_SOURCE 0
+#include "tinycc/tcc.h"
+
+void tcc_rs_section_sizes(TCCState *s, size_t *code, size_t *data, size_t *bss)
+{
+    int i;
+    *code = *data = *bss = 0;
+    for (i = 1; i < s->nb_sections; i++) {
+        Section *sec = s->sections[i];
+        if (!(sec->sh_flags & SHF_ALLOC))
+            continue;
+        if (sec->sh_type == SHT_NOBITS)
+            *bss += sec->data_offset;
+        else if (sec->sh_flags & SHF_EXECINSTR)
+            *code += sec->data_offset;
+        else
+            *data += sec->data_offset;
+    }
+}
+
+/* Address, size and ELF flags of every section of `s` loaded at run time,
+ * valid once relocated in memory. */
+void tcc_rs_list_sections(TCCState *s, void *ctx,
+                          void (*section)(void *ctx, size_t addr, size_t size, size_t flags))
+{
+    int i;
+    for (i = 1; i < s->nb_sections; i++) {
+        Section *sec = s->sections[i];
+        if ((sec->sh_flags & SHF_ALLOC) && sec->sh_addr && sec->data_offset)
+            section(ctx, (size_t)sec->sh_addr, sec->data_offset, (size_t)sec->sh_flags);
+    }
+}
+
+/* Name, address and size of every defined symbol of `s`, valid once
+ * relocated in memory; unlike tcc_list_symbols this knows sizes. */
+void tcc_rs_list_symbol_sizes(TCCState *s, void *ctx,
+                              void (*symbol)(void *ctx, const char *name, size_t addr, size_t size))
+{
+    int i;
+    for (i = 1; i < s->nb_sections; i++) {
+        Section *sec = s->sections[i];
+        ElfW(Sym) *sym, *end;
+        if (sec->sh_type != SHT_SYMTAB || strcmp(sec->name, ".symtab"))
+            continue;
+        end = (ElfW(Sym) *)(sec->data + sec->data_offset);
+        for (sym = (ElfW(Sym) *)sec->data + 1; sym < end; sym++) {
+            int type = ELFW(ST_TYPE)(sym->st_info);
+            if (sym->st_shndx == SHN_UNDEF || !sym->st_name
+                || type == STT_SECTION || type == STT_FILE)
+                continue;
+            symbol(ctx, (char *)sec->link->data + sym->st_name,
+                   (size_t)sym->st_value, (size_t)sym->st_size);
+        }
+    }
+}
+
+/* Contents of the debug sections of `s`, as left after relocating. */
+void tcc_rs_list_debug_sections(TCCState *s, void *ctx,
+                                void (*section)(void *ctx, const char *name,
+                                                const void *data, size_t len))
+{
+    int i;
+    for (i = 1; i < s->nb_sections; i++) {
+        Section *sec = s->sections[i];
+        if (!strncmp(sec->name, ".debug_", 7) && sec->data_offset)
+            section(ctx, sec->name, sec->data, sec->data_offset);
+    }
+}
+
+/* Name of every global symbol defined by the compiled code of `s`, and
+ * whether it is a function; unlike tcc_list_symbols usable before
+ * relocation, when symbols at offset 0 have a value of 0. */
+void tcc_rs_list_exports(TCCState *s, void *ctx,
+                         void (*symbol)(void *ctx, const char *name, int function))
+{
+    int i;
+    for (i = 1; i < s->nb_sections; i++) {
+        Section *sec = s->sections[i];
+        ElfW(Sym) *sym, *end;
+        if (sec->sh_type != SHT_SYMTAB || strcmp(sec->name, ".symtab"))
+            continue;
+        end = (ElfW(Sym) *)(sec->data + sec->data_offset);
+        for (sym = (ElfW(Sym) *)sec->data + 1; sym < end; sym++) {
+            int bind = ELFW(ST_BIND)(sym->st_info);
+            if (sym->st_shndx == SHN_UNDEF || sym->st_shndx == SHN_ABS || !sym->st_name
+                || (bind != STB_GLOBAL && bind != STB_WEAK)
+                || ELFW(ST_VISIBILITY)(sym->st_other) != STV_DEFAULT)
+                continue;
+            symbol(ctx, (char *)sec->link->data + sym->st_name,
+                   ELFW(ST_TYPE)(sym->st_info) == STT_FUNC);
+        }
+    }
+}
+
+/* Give every global symbol defined by the code compiled into `s` the name
+ * `prefix` followed by its own, except main and names already starting with
+ * `prefix`. The renamed copy is appended, while relocations keep the index
+ * of the original entry, which stays behind as a nameless local; they still
+ * resolve only because that entry keeps its value and section, so it must
+ * not be dropped or cleared. */
+void tcc_rs_prefix_symbols(TCCState *s, const char *prefix)
+{
+    Section *symtab = s->symtab;
+    size_t len = strlen(prefix);
+    int i, count = symtab->data_offset / sizeof(ElfW(Sym));
+    for (i = 1; i < count; i++) {
+        ElfW(Sym) *sym = (ElfW(Sym) *)symtab->data + i;
+        const char *name = (char *)symtab->link->data + sym->st_name;
+        int bind = ELFW(ST_BIND)(sym->st_info);
+        char *renamed;
+        if (sym->st_shndx == SHN_UNDEF || sym->st_shndx == SHN_ABS || !sym->st_name
+            || (bind != STB_GLOBAL && bind != STB_WEAK)
+            || ELFW(ST_VISIBILITY)(sym->st_other) != STV_DEFAULT
+            || !strcmp(name, "main") || !strncmp(name, prefix, len))
+            continue;
+        renamed = tcc_malloc(len + strlen(name) + 1);
+        strcpy(renamed, prefix);
+        strcat(renamed, name);
+        put_elf_sym(symtab, sym->st_value, sym->st_size, sym->st_info, sym->st_other,
+                    sym->st_shndx, renamed);
+        tcc_free(renamed);
+        /* adding the copy may have moved the table */
+        sym = (ElfW(Sym) *)symtab->data + i;
+        sym->st_name = 0;
+        sym->st_info = ELFW(ST_INFO)(STB_LOCAL, ELFW(ST_TYPE)(sym->st_info));
+    }
+}
+
+/* Every directory tcc_add_library searches, in order; returns whether it
+ * only looks for static archives. */
+int tcc_rs_library_search(TCCState *s, void *ctx, void (*path)(void *ctx, const char *dir))
+{
+    int i;
+    for (i = 0; i < s->nb_library_paths; i++)
+        path(ctx, s->library_paths[i]);
+    return s->static_link;
+}
+
+/* Every directory #include searches after the including file's own, in
+ * order: the -I paths, then the system ones. */
+void tcc_rs_include_search(TCCState *s, void *ctx, void (*path)(void *ctx, const char *dir))
+{
+    int i;
+    for (i = 0; i < s->nb_include_paths; i++)
+        path(ctx, s->include_paths[i]);
+    for (i = 0; i < s->nb_sysinclude_paths; i++)
+        path(ctx, s->sysinclude_paths[i]);
+}
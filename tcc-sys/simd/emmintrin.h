@@ -0,0 +1,344 @@
+/* SSE2 intrinsics for tcc, implemented lane by lane in plain C, see
+ * <xmmintrin.h>. Integer arithmetic wraps around like the instructions. */
+
+#ifndef __TCC_RS_EMMINTRIN_H
+#define __TCC_RS_EMMINTRIN_H
+
+#include <xmmintrin.h>
+
+typedef union __attribute__((aligned(16))) {
+    double __d[2];
+    unsigned long long __u[2];
+} __m128d;
+
+typedef union __attribute__((aligned(16))) {
+    signed char __i8[16];
+    short __i16[8];
+    int __i32[4];
+    long long __i64[2];
+    unsigned char __u8[16];
+    unsigned short __u16[8];
+    unsigned int __u32[4];
+    unsigned long long __u64[2];
+} __m128i;
+
+typedef __m128d __m128d_u;
+typedef __m128i __m128i_u;
+
+#define __TCC_RS_MASK64(cond) ((cond) ? 0xffffffffffffffffull : 0ull)
+
+/* double */
+
+__TCC_RS_INLINE __m128d _mm_setr_pd(double e0, double e1) {
+    __m128d r = {{e0, e1}};
+    return r;
+}
+
+__TCC_RS_INLINE __m128d _mm_set_pd(double e1, double e0) {
+    return _mm_setr_pd(e0, e1);
+}
+
+__TCC_RS_INLINE __m128d _mm_set1_pd(double a) {
+    return _mm_setr_pd(a, a);
+}
+
+__TCC_RS_INLINE __m128d _mm_setzero_pd(void) {
+    return _mm_set1_pd(0.0);
+}
+
+__TCC_RS_INLINE __m128d _mm_set_sd(double a) {
+    return _mm_setr_pd(a, 0.0);
+}
+
+__TCC_RS_INLINE __m128d _mm_loadu_pd(const double *p) {
+    return _mm_setr_pd(p[0], p[1]);
+}
+
+#define _mm_load_pd _mm_loadu_pd
+
+__TCC_RS_INLINE __m128d _mm_load1_pd(const double *p) {
+    return _mm_set1_pd(*p);
+}
+
+__TCC_RS_INLINE __m128d _mm_load_sd(const double *p) {
+    return _mm_set_sd(*p);
+}
+
+__TCC_RS_INLINE void _mm_storeu_pd(double *p, __m128d a) {
+    p[0] = a.__d[0];
+    p[1] = a.__d[1];
+}
+
+#define _mm_store_pd _mm_storeu_pd
+
+__TCC_RS_INLINE void _mm_store_sd(double *p, __m128d a) {
+    *p = a.__d[0];
+}
+
+__TCC_RS_INLINE double _mm_cvtsd_f64(__m128d a) {
+    return a.__d[0];
+}
+
+__TCC_RS_BINARY(__m128d, 2, _mm_add_pd, __d, a.__d[i] + b.__d[i])
+__TCC_RS_BINARY(__m128d, 2, _mm_sub_pd, __d, a.__d[i] - b.__d[i])
+__TCC_RS_BINARY(__m128d, 2, _mm_mul_pd, __d, a.__d[i] * b.__d[i])
+__TCC_RS_BINARY(__m128d, 2, _mm_div_pd, __d, a.__d[i] / b.__d[i])
+__TCC_RS_BINARY(__m128d, 2, _mm_min_pd, __d, a.__d[i] < b.__d[i] ? a.__d[i] : b.__d[i])
+__TCC_RS_BINARY(__m128d, 2, _mm_max_pd, __d, a.__d[i] > b.__d[i] ? a.__d[i] : b.__d[i])
+
+__TCC_RS_BINARY_LOW(__m128d, _mm_add_sd, __d, a.__d[i] + b.__d[i])
+__TCC_RS_BINARY_LOW(__m128d, _mm_sub_sd, __d, a.__d[i] - b.__d[i])
+__TCC_RS_BINARY_LOW(__m128d, _mm_mul_sd, __d, a.__d[i] * b.__d[i])
+__TCC_RS_BINARY_LOW(__m128d, _mm_div_sd, __d, a.__d[i] / b.__d[i])
+
+__TCC_RS_BINARY(__m128d, 2, _mm_and_pd, __u, a.__u[i] & b.__u[i])
+__TCC_RS_BINARY(__m128d, 2, _mm_andnot_pd, __u, ~a.__u[i] & b.__u[i])
+__TCC_RS_BINARY(__m128d, 2, _mm_or_pd, __u, a.__u[i] | b.__u[i])
+__TCC_RS_BINARY(__m128d, 2, _mm_xor_pd, __u, a.__u[i] ^ b.__u[i])
+
+__TCC_RS_BINARY(__m128d, 2, _mm_cmpeq_pd, __u, __TCC_RS_MASK64(a.__d[i] == b.__d[i]))
+__TCC_RS_BINARY(__m128d, 2, _mm_cmpneq_pd, __u, __TCC_RS_MASK64(a.__d[i] != b.__d[i]))
+__TCC_RS_BINARY(__m128d, 2, _mm_cmplt_pd, __u, __TCC_RS_MASK64(a.__d[i] < b.__d[i]))
+__TCC_RS_BINARY(__m128d, 2, _mm_cmple_pd, __u, __TCC_RS_MASK64(a.__d[i] <= b.__d[i]))
+__TCC_RS_BINARY(__m128d, 2, _mm_cmpgt_pd, __u, __TCC_RS_MASK64(a.__d[i] > b.__d[i]))
+__TCC_RS_BINARY(__m128d, 2, _mm_cmpge_pd, __u, __TCC_RS_MASK64(a.__d[i] >= b.__d[i]))
+
+__TCC_RS_BINARY(__m128d, 2, _mm_unpacklo_pd, __d, i ? b.__d[0] : a.__d[0])
+__TCC_RS_BINARY(__m128d, 2, _mm_unpackhi_pd, __d, i ? b.__d[1] : a.__d[1])
+
+__TCC_RS_INLINE __m128d _mm_sqrt_pd(__m128d a) {
+    a.__d[0] = sqrt(a.__d[0]);
+    a.__d[1] = sqrt(a.__d[1]);
+    return a;
+}
+
+__TCC_RS_INLINE __m128d _mm_shuffle_pd(__m128d a, __m128d b, int imm) {
+    return _mm_setr_pd(a.__d[imm & 1], b.__d[(imm >> 1) & 1]);
+}
+
+__TCC_RS_INLINE int _mm_movemask_pd(__m128d a) {
+    return (int)(a.__u[0] >> 63) | (int)(a.__u[1] >> 63) << 1;
+}
+
+/* integer */
+
+__TCC_RS_INLINE __m128i _mm_setr_epi32(int e0, int e1, int e2, int e3) {
+    __m128i r;
+    r.__i32[0] = e0;
+    r.__i32[1] = e1;
+    r.__i32[2] = e2;
+    r.__i32[3] = e3;
+    return r;
+}
+
+__TCC_RS_INLINE __m128i _mm_set_epi32(int e3, int e2, int e1, int e0) {
+    return _mm_setr_epi32(e0, e1, e2, e3);
+}
+
+__TCC_RS_INLINE __m128i _mm_set1_epi32(int a) {
+    return _mm_setr_epi32(a, a, a, a);
+}
+
+__TCC_RS_INLINE __m128i _mm_setzero_si128(void) {
+    return _mm_set1_epi32(0);
+}
+
+__TCC_RS_INLINE __m128i _mm_set1_epi8(char a) {
+    __m128i r;
+    int i;
+    for (i = 0; i < 16; i++)
+        r.__i8[i] = a;
+    return r;
+}
+
+__TCC_RS_INLINE __m128i _mm_set1_epi16(short a) {
+    __m128i r;
+    int i;
+    for (i = 0; i < 8; i++)
+        r.__i16[i] = a;
+    return r;
+}
+
+__TCC_RS_INLINE __m128i _mm_set_epi64x(long long e1, long long e0) {
+    __m128i r;
+    r.__i64[0] = e0;
+    r.__i64[1] = e1;
+    return r;
+}
+
+__TCC_RS_INLINE __m128i _mm_set1_epi64x(long long a) {
+    return _mm_set_epi64x(a, a);
+}
+
+__TCC_RS_INLINE __m128i _mm_loadu_si128(const __m128i_u *p) {
+    __m128i r;
+    const unsigned char *s = (const unsigned char *)p;
+    int i;
+    for (i = 0; i < 16; i++)
+        r.__u8[i] = s[i];
+    return r;
+}
+
+#define _mm_load_si128 _mm_loadu_si128
+
+__TCC_RS_INLINE void _mm_storeu_si128(__m128i_u *p, __m128i a) {
+    unsigned char *d = (unsigned char *)p;
+    int i;
+    for (i = 0; i < 16; i++)
+        d[i] = a.__u8[i];
+}
+
+#define _mm_store_si128 _mm_storeu_si128
+
+__TCC_RS_INLINE int _mm_cvtsi128_si32(__m128i a) {
+    return a.__i32[0];
+}
+
+__TCC_RS_INLINE long long _mm_cvtsi128_si64(__m128i a) {
+    return a.__i64[0];
+}
+
+__TCC_RS_INLINE __m128i _mm_cvtsi32_si128(int a) {
+    return _mm_setr_epi32(a, 0, 0, 0);
+}
+
+__TCC_RS_INLINE __m128i _mm_cvtsi64_si128(long long a) {
+    return _mm_set_epi64x(0, a);
+}
+
+__TCC_RS_BINARY(__m128i, 16, _mm_add_epi8, __u8, a.__u8[i] + b.__u8[i])
+__TCC_RS_BINARY(__m128i, 8, _mm_add_epi16, __u16, a.__u16[i] + b.__u16[i])
+__TCC_RS_BINARY(__m128i, 4, _mm_add_epi32, __u32, a.__u32[i] + b.__u32[i])
+__TCC_RS_BINARY(__m128i, 2, _mm_add_epi64, __u64, a.__u64[i] + b.__u64[i])
+__TCC_RS_BINARY(__m128i, 16, _mm_sub_epi8, __u8, a.__u8[i] - b.__u8[i])
+__TCC_RS_BINARY(__m128i, 8, _mm_sub_epi16, __u16, a.__u16[i] - b.__u16[i])
+__TCC_RS_BINARY(__m128i, 4, _mm_sub_epi32, __u32, a.__u32[i] - b.__u32[i])
+__TCC_RS_BINARY(__m128i, 2, _mm_sub_epi64, __u64, a.__u64[i] - b.__u64[i])
+__TCC_RS_BINARY(__m128i, 8, _mm_mullo_epi16, __u16, (unsigned)a.__u16[i] * b.__u16[i])
+
+__TCC_RS_BINARY(__m128i, 8, _mm_min_epi16, __i16, a.__i16[i] < b.__i16[i] ? a.__i16[i] : b.__i16[i])
+__TCC_RS_BINARY(__m128i, 8, _mm_max_epi16, __i16, a.__i16[i] > b.__i16[i] ? a.__i16[i] : b.__i16[i])
+__TCC_RS_BINARY(__m128i, 16, _mm_min_epu8, __u8, a.__u8[i] < b.__u8[i] ? a.__u8[i] : b.__u8[i])
+__TCC_RS_BINARY(__m128i, 16, _mm_max_epu8, __u8, a.__u8[i] > b.__u8[i] ? a.__u8[i] : b.__u8[i])
+
+__TCC_RS_BINARY(__m128i, 2, _mm_and_si128, __u64, a.__u64[i] & b.__u64[i])
+__TCC_RS_BINARY(__m128i, 2, _mm_andnot_si128, __u64, ~a.__u64[i] & b.__u64[i])
+__TCC_RS_BINARY(__m128i, 2, _mm_or_si128, __u64, a.__u64[i] | b.__u64[i])
+__TCC_RS_BINARY(__m128i, 2, _mm_xor_si128, __u64, a.__u64[i] ^ b.__u64[i])
+
+__TCC_RS_BINARY(__m128i, 16, _mm_cmpeq_epi8, __i8, -(a.__i8[i] == b.__i8[i]))
+__TCC_RS_BINARY(__m128i, 8, _mm_cmpeq_epi16, __i16, -(a.__i16[i] == b.__i16[i]))
+__TCC_RS_BINARY(__m128i, 4, _mm_cmpeq_epi32, __i32, -(a.__i32[i] == b.__i32[i]))
+__TCC_RS_BINARY(__m128i, 16, _mm_cmpgt_epi8, __i8, -(a.__i8[i] > b.__i8[i]))
+__TCC_RS_BINARY(__m128i, 8, _mm_cmpgt_epi16, __i16, -(a.__i16[i] > b.__i16[i]))
+__TCC_RS_BINARY(__m128i, 4, _mm_cmpgt_epi32, __i32, -(a.__i32[i] > b.__i32[i]))
+__TCC_RS_BINARY(__m128i, 16, _mm_cmplt_epi8, __i8, -(a.__i8[i] < b.__i8[i]))
+__TCC_RS_BINARY(__m128i, 8, _mm_cmplt_epi16, __i16, -(a.__i16[i] < b.__i16[i]))
+__TCC_RS_BINARY(__m128i, 4, _mm_cmplt_epi32, __i32, -(a.__i32[i] < b.__i32[i]))
+
+__TCC_RS_BINARY(__m128i, 16, _mm_unpacklo_epi8, __u8, i & 1 ? b.__u8[i >> 1] : a.__u8[i >> 1])
+__TCC_RS_BINARY(__m128i, 8, _mm_unpacklo_epi16, __u16, i & 1 ? b.__u16[i >> 1] : a.__u16[i >> 1])
+__TCC_RS_BINARY(__m128i, 4, _mm_unpacklo_epi32, __u32, i & 1 ? b.__u32[i >> 1] : a.__u32[i >> 1])
+__TCC_RS_BINARY(__m128i, 2, _mm_unpacklo_epi64, __u64, i ? b.__u64[0] : a.__u64[0])
+__TCC_RS_BINARY(__m128i, 16, _mm_unpackhi_epi8, __u8, i & 1 ? b.__u8[8 + (i >> 1)] : a.__u8[8 + (i >> 1)])
+__TCC_RS_BINARY(__m128i, 8, _mm_unpackhi_epi16, __u16, i & 1 ? b.__u16[4 + (i >> 1)] : a.__u16[4 + (i >> 1)])
+__TCC_RS_BINARY(__m128i, 4, _mm_unpackhi_epi32, __u32, i & 1 ? b.__u32[2 + (i >> 1)] : a.__u32[2 + (i >> 1)])
+__TCC_RS_BINARY(__m128i, 2, _mm_unpackhi_epi64, __u64, i ? b.__u64[1] : a.__u64[1])
+
+/* shifts by more than the lane width give 0, or the sign for srai */
+#define __TCC_RS_SHIFT(name, lanes, field, expr)         \
+    __TCC_RS_INLINE __m128i name(__m128i a, int n) {     \
+        int i;                                           \
+        for (i = 0; i < (lanes); i++)                    \
+            a.field[i] = (expr);                         \
+        return a;                                        \
+    }
+
+__TCC_RS_SHIFT(_mm_slli_epi16, 8, __u16, n > 15 ? 0 : (unsigned)a.__u16[i] << n)
+__TCC_RS_SHIFT(_mm_slli_epi32, 4, __u32, n > 31 ? 0 : a.__u32[i] << n)
+__TCC_RS_SHIFT(_mm_slli_epi64, 2, __u64, n > 63 ? 0 : a.__u64[i] << n)
+__TCC_RS_SHIFT(_mm_srli_epi16, 8, __u16, n > 15 ? 0 : a.__u16[i] >> n)
+__TCC_RS_SHIFT(_mm_srli_epi32, 4, __u32, n > 31 ? 0 : a.__u32[i] >> n)
+__TCC_RS_SHIFT(_mm_srli_epi64, 2, __u64, n > 63 ? 0 : a.__u64[i] >> n)
+__TCC_RS_SHIFT(_mm_srai_epi16, 8, __i16, a.__i16[i] >> (n > 15 ? 15 : n))
+__TCC_RS_SHIFT(_mm_srai_epi32, 4, __i32, a.__i32[i] >> (n > 31 ? 31 : n))
+
+__TCC_RS_INLINE __m128i _mm_shuffle_epi32(__m128i a, int imm) {
+    return _mm_setr_epi32(a.__i32[imm & 3], a.__i32[(imm >> 2) & 3], a.__i32[(imm >> 4) & 3],
+                          a.__i32[(imm >> 6) & 3]);
+}
+
+__TCC_RS_INLINE int _mm_movemask_epi8(__m128i a) {
+    int i, r = 0;
+    for (i = 0; i < 16; i++)
+        r |= (a.__u8[i] >> 7) << i;
+    return r;
+}
+
+/* conversions */
+
+__TCC_RS_INLINE __m128 _mm_cvtepi32_ps(__m128i a) {
+    return _mm_setr_ps((float)a.__i32[0], (float)a.__i32[1], (float)a.__i32[2],
+                       (float)a.__i32[3]);
+}
+
+__TCC_RS_INLINE __m128i _mm_cvtps_epi32(__m128 a) {
+    return _mm_setr_epi32(__tcc_rs_round_even(a.__f[0]), __tcc_rs_round_even(a.__f[1]),
+                          __tcc_rs_round_even(a.__f[2]), __tcc_rs_round_even(a.__f[3]));
+}
+
+__TCC_RS_INLINE __m128i _mm_cvttps_epi32(__m128 a) {
+    return _mm_setr_epi32(__tcc_rs_truncate(a.__f[0]), __tcc_rs_truncate(a.__f[1]),
+                          __tcc_rs_truncate(a.__f[2]), __tcc_rs_truncate(a.__f[3]));
+}
+
+__TCC_RS_INLINE __m128d _mm_cvtps_pd(__m128 a) {
+    return _mm_setr_pd(a.__f[0], a.__f[1]);
+}
+
+__TCC_RS_INLINE __m128 _mm_cvtpd_ps(__m128d a) {
+    return _mm_setr_ps((float)a.__d[0], (float)a.__d[1], 0.0f, 0.0f);
+}
+
+__TCC_RS_INLINE int _mm_cvtsd_si32(__m128d a) {
+    return __tcc_rs_round_even(a.__d[0]);
+}
+
+__TCC_RS_INLINE int _mm_cvttsd_si32(__m128d a) {
+    return __tcc_rs_truncate(a.__d[0]);
+}
+
+/* casts keep the bits */
+
+__TCC_RS_INLINE __m128i _mm_castps_si128(__m128 a) {
+    return _mm_setr_epi32(a.__u[0], a.__u[1], a.__u[2], a.__u[3]);
+}
+
+__TCC_RS_INLINE __m128 _mm_castsi128_ps(__m128i a) {
+    __m128 r;
+    int i;
+    for (i = 0; i < 4; i++)
+        r.__u[i] = a.__u32[i];
+    return r;
+}
+
+__TCC_RS_INLINE __m128i _mm_castpd_si128(__m128d a) {
+    return _mm_set_epi64x(a.__u[1], a.__u[0]);
+}
+
+__TCC_RS_INLINE __m128d _mm_castsi128_pd(__m128i a) {
+    __m128d r;
+    r.__u[0] = a.__u64[0];
+    r.__u[1] = a.__u64[1];
+    return r;
+}
+
+__TCC_RS_INLINE __m128 _mm_castpd_ps(__m128d a) {
+    return _mm_castsi128_ps(_mm_castpd_si128(a));
+}
+
+__TCC_RS_INLINE __m128d _mm_castps_pd(__m128 a) {
+    return _mm_castsi128_pd(_mm_castps_si128(a));
+}
+
+#endif
@@ -0,0 +1,263 @@
+/* SSE, SSE2, AVX and FMA intrinsics for tcc, implemented lane by lane in
+ * plain C, see <xmmintrin.h>. Fused multiply-add rounds twice for double;
+ * float lanes are computed in double, which is exact for the product. */
+
+#ifndef __TCC_RS_IMMINTRIN_H
+#define __TCC_RS_IMMINTRIN_H
+
+#include <emmintrin.h>
+
+typedef union __attribute__((aligned(32))) {
+    float __f[8];
+    unsigned int __u[8];
+} __m256;
+
+typedef union __attribute__((aligned(32))) {
+    double __d[4];
+    unsigned long long __u[4];
+} __m256d;
+
+typedef union __attribute__((aligned(32))) {
+    signed char __i8[32];
+    short __i16[16];
+    int __i32[8];
+    long long __i64[4];
+    unsigned char __u8[32];
+    unsigned short __u16[16];
+    unsigned int __u32[8];
+    unsigned long long __u64[4];
+} __m256i;
+
+typedef __m256 __m256_u;
+typedef __m256d __m256d_u;
+typedef __m256i __m256i_u;
+
+/* r.FIELD[i] = EXPR for every lane, with the operands named a, b and c */
+#define __TCC_RS_TERNARY(type, lanes, name, field, expr)      \
+    __TCC_RS_INLINE type name(type a, type b, type c) {       \
+        type r;                                               \
+        int i;                                                \
+        for (i = 0; i < (lanes); i++)                         \
+            r.field[i] = (expr);                              \
+        return r;                                             \
+    }
+
+/* FMA on 128 bits */
+
+__TCC_RS_TERNARY(__m128, 4, _mm_fmadd_ps, __f, (double)a.__f[i] * b.__f[i] + c.__f[i])
+__TCC_RS_TERNARY(__m128, 4, _mm_fmsub_ps, __f, (double)a.__f[i] * b.__f[i] - c.__f[i])
+__TCC_RS_TERNARY(__m128, 4, _mm_fnmadd_ps, __f, -((double)a.__f[i] * b.__f[i]) + c.__f[i])
+__TCC_RS_TERNARY(__m128d, 2, _mm_fmadd_pd, __d, a.__d[i] * b.__d[i] + c.__d[i])
+__TCC_RS_TERNARY(__m128d, 2, _mm_fmsub_pd, __d, a.__d[i] * b.__d[i] - c.__d[i])
+__TCC_RS_TERNARY(__m128d, 2, _mm_fnmadd_pd, __d, -(a.__d[i] * b.__d[i]) + c.__d[i])
+
+/* float */
+
+__TCC_RS_INLINE __m256 _mm256_set1_ps(float a) {
+    __m256 r;
+    int i;
+    for (i = 0; i < 8; i++)
+        r.__f[i] = a;
+    return r;
+}
+
+__TCC_RS_INLINE __m256 _mm256_setzero_ps(void) {
+    return _mm256_set1_ps(0.0f);
+}
+
+__TCC_RS_INLINE __m256 _mm256_setr_ps(float e0, float e1, float e2, float e3, float e4,
+                                      float e5, float e6, float e7) {
+    __m256 r = {{e0, e1, e2, e3, e4, e5, e6, e7}};
+    return r;
+}
+
+__TCC_RS_INLINE __m256 _mm256_set_ps(float e7, float e6, float e5, float e4, float e3,
+                                     float e2, float e1, float e0) {
+    return _mm256_setr_ps(e0, e1, e2, e3, e4, e5, e6, e7);
+}
+
+__TCC_RS_INLINE __m256 _mm256_loadu_ps(const float *p) {
+    __m256 r;
+    int i;
+    for (i = 0; i < 8; i++)
+        r.__f[i] = p[i];
+    return r;
+}
+
+#define _mm256_load_ps _mm256_loadu_ps
+
+__TCC_RS_INLINE void _mm256_storeu_ps(float *p, __m256 a) {
+    int i;
+    for (i = 0; i < 8; i++)
+        p[i] = a.__f[i];
+}
+
+#define _mm256_store_ps _mm256_storeu_ps
+
+__TCC_RS_INLINE float _mm256_cvtss_f32(__m256 a) {
+    return a.__f[0];
+}
+
+__TCC_RS_BINARY(__m256, 8, _mm256_add_ps, __f, a.__f[i] + b.__f[i])
+__TCC_RS_BINARY(__m256, 8, _mm256_sub_ps, __f, a.__f[i] - b.__f[i])
+__TCC_RS_BINARY(__m256, 8, _mm256_mul_ps, __f, a.__f[i] * b.__f[i])
+__TCC_RS_BINARY(__m256, 8, _mm256_div_ps, __f, a.__f[i] / b.__f[i])
+__TCC_RS_BINARY(__m256, 8, _mm256_min_ps, __f, a.__f[i] < b.__f[i] ? a.__f[i] : b.__f[i])
+__TCC_RS_BINARY(__m256, 8, _mm256_max_ps, __f, a.__f[i] > b.__f[i] ? a.__f[i] : b.__f[i])
+__TCC_RS_BINARY(__m256, 8, _mm256_and_ps, __u, a.__u[i] & b.__u[i])
+__TCC_RS_BINARY(__m256, 8, _mm256_andnot_ps, __u, ~a.__u[i] & b.__u[i])
+__TCC_RS_BINARY(__m256, 8, _mm256_or_ps, __u, a.__u[i] | b.__u[i])
+__TCC_RS_BINARY(__m256, 8, _mm256_xor_ps, __u, a.__u[i] ^ b.__u[i])
+
+__TCC_RS_TERNARY(__m256, 8, _mm256_fmadd_ps, __f, (double)a.__f[i] * b.__f[i] + c.__f[i])
+__TCC_RS_TERNARY(__m256, 8, _mm256_fmsub_ps, __f, (double)a.__f[i] * b.__f[i] - c.__f[i])
+__TCC_RS_TERNARY(__m256, 8, _mm256_fnmadd_ps, __f, -((double)a.__f[i] * b.__f[i]) + c.__f[i])
+
+__TCC_RS_INLINE __m256 _mm256_sqrt_ps(__m256 a) {
+    int i;
+    for (i = 0; i < 8; i++)
+        a.__f[i] = sqrtf(a.__f[i]);
+    return a;
+}
+
+__TCC_RS_INLINE __m128 _mm256_castps256_ps128(__m256 a) {
+    return _mm_loadu_ps(a.__f);
+}
+
+__TCC_RS_INLINE __m128 _mm256_extractf128_ps(__m256 a, int imm) {
+    return _mm_loadu_ps(a.__f + 4 * (imm & 1));
+}
+
+__TCC_RS_INLINE __m256 _mm256_set_m128(__m128 hi, __m128 lo) {
+    __m256 r;
+    _mm_storeu_ps(r.__f, lo);
+    _mm_storeu_ps(r.__f + 4, hi);
+    return r;
+}
+
+/* double */
+
+__TCC_RS_INLINE __m256d _mm256_set1_pd(double a) {
+    __m256d r = {{a, a, a, a}};
+    return r;
+}
+
+__TCC_RS_INLINE __m256d _mm256_setzero_pd(void) {
+    return _mm256_set1_pd(0.0);
+}
+
+__TCC_RS_INLINE __m256d _mm256_setr_pd(double e0, double e1, double e2, double e3) {
+    __m256d r = {{e0, e1, e2, e3}};
+    return r;
+}
+
+__TCC_RS_INLINE __m256d _mm256_set_pd(double e3, double e2, double e1, double e0) {
+    return _mm256_setr_pd(e0, e1, e2, e3);
+}
+
+__TCC_RS_INLINE __m256d _mm256_loadu_pd(const double *p) {
+    return _mm256_setr_pd(p[0], p[1], p[2], p[3]);
+}
+
+#define _mm256_load_pd _mm256_loadu_pd
+
+__TCC_RS_INLINE void _mm256_storeu_pd(double *p, __m256d a) {
+    int i;
+    for (i = 0; i < 4; i++)
+        p[i] = a.__d[i];
+}
+
+#define _mm256_store_pd _mm256_storeu_pd
+
+__TCC_RS_INLINE double _mm256_cvtsd_f64(__m256d a) {
+    return a.__d[0];
+}
+
+__TCC_RS_BINARY(__m256d, 4, _mm256_add_pd, __d, a.__d[i] + b.__d[i])
+__TCC_RS_BINARY(__m256d, 4, _mm256_sub_pd, __d, a.__d[i] - b.__d[i])
+__TCC_RS_BINARY(__m256d, 4, _mm256_mul_pd, __d, a.__d[i] * b.__d[i])
+__TCC_RS_BINARY(__m256d, 4, _mm256_div_pd, __d, a.__d[i] / b.__d[i])
+__TCC_RS_BINARY(__m256d, 4, _mm256_min_pd, __d, a.__d[i] < b.__d[i] ? a.__d[i] : b.__d[i])
+__TCC_RS_BINARY(__m256d, 4, _mm256_max_pd, __d, a.__d[i] > b.__d[i] ? a.__d[i] : b.__d[i])
+
+__TCC_RS_TERNARY(__m256d, 4, _mm256_fmadd_pd, __d, a.__d[i] * b.__d[i] + c.__d[i])
+__TCC_RS_TERNARY(__m256d, 4, _mm256_fmsub_pd, __d, a.__d[i] * b.__d[i] - c.__d[i])
+__TCC_RS_TERNARY(__m256d, 4, _mm256_fnmadd_pd, __d, -(a.__d[i] * b.__d[i]) + c.__d[i])
+
+__TCC_RS_INLINE __m256d _mm256_sqrt_pd(__m256d a) {
+    int i;
+    for (i = 0; i < 4; i++)
+        a.__d[i] = sqrt(a.__d[i]);
+    return a;
+}
+
+__TCC_RS_INLINE __m128d _mm256_castpd256_pd128(__m256d a) {
+    return _mm_loadu_pd(a.__d);
+}
+
+__TCC_RS_INLINE __m128d _mm256_extractf128_pd(__m256d a, int imm) {
+    return _mm_loadu_pd(a.__d + 2 * (imm & 1));
+}
+
+/* integer */
+
+__TCC_RS_INLINE __m256i _mm256_set1_epi32(int a) {
+    __m256i r;
+    int i;
+    for (i = 0; i < 8; i++)
+        r.__i32[i] = a;
+    return r;
+}
+
+__TCC_RS_INLINE __m256i _mm256_setzero_si256(void) {
+    return _mm256_set1_epi32(0);
+}
+
+__TCC_RS_INLINE __m256i _mm256_loadu_si256(const __m256i_u *p) {
+    __m256i r;
+    const unsigned char *s = (const unsigned char *)p;
+    int i;
+    for (i = 0; i < 32; i++)
+        r.__u8[i] = s[i];
+    return r;
+}
+
+#define _mm256_load_si256 _mm256_loadu_si256
+
+__TCC_RS_INLINE void _mm256_storeu_si256(__m256i_u *p, __m256i a) {
+    unsigned char *d = (unsigned char *)p;
+    int i;
+    for (i = 0; i < 32; i++)
+        d[i] = a.__u8[i];
+}
+
+#define _mm256_store_si256 _mm256_storeu_si256
+
+__TCC_RS_BINARY(__m256i, 8, _mm256_add_epi32, __u32, a.__u32[i] + b.__u32[i])
+__TCC_RS_BINARY(__m256i, 8, _mm256_sub_epi32, __u32, a.__u32[i] - b.__u32[i])
+__TCC_RS_BINARY(__m256i, 8, _mm256_mullo_epi32, __u32, a.__u32[i] * b.__u32[i])
+__TCC_RS_BINARY(__m256i, 4, _mm256_add_epi64, __u64, a.__u64[i] + b.__u64[i])
+__TCC_RS_BINARY(__m256i, 4, _mm256_and_si256, __u64, a.__u64[i] & b.__u64[i])
+__TCC_RS_BINARY(__m256i, 4, _mm256_or_si256, __u64, a.__u64[i] | b.__u64[i])
+__TCC_RS_BINARY(__m256i, 4, _mm256_xor_si256, __u64, a.__u64[i] ^ b.__u64[i])
+
+__TCC_RS_INLINE __m256 _mm256_cvtepi32_ps(__m256i a) {
+    __m256 r;
+    int i;
+    for (i = 0; i < 8; i++)
+        r.__f[i] = (float)a.__i32[i];
+    return r;
+}
+
+__TCC_RS_INLINE __m256i _mm256_cvttps_epi32(__m256 a) {
+    __m256i r;
+    int i;
+    for (i = 0; i < 8; i++)
+        r.__i32[i] = __tcc_rs_truncate(a.__f[i]);
+    return r;
+}
+
+#define _mm256_zeroupper() ((void)0)
+#define _mm256_zeroall() ((void)0)
+
+#endif
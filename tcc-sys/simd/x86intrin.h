@@ -0,0 +1,8 @@
+/* Everything in <immintrin.h>, the name gcc and clang also accept. */
+
+#ifndef __TCC_RS_X86INTRIN_H
+#define __TCC_RS_X86INTRIN_H
+
+#include <immintrin.h>
+
+#endif
@@ -0,0 +1,222 @@
+/* SSE intrinsics for tcc, implemented lane by lane in plain C.
+ *
+ * tcc emits no vector instructions, so every intrinsic is a small inline
+ * function over an array of lanes. Results match the instructions for
+ * ordinary inputs; approximations such as _mm_rcp_ps are exact instead.
+ * Code can test for __TCC_RS_SIMD_SCALAR to pick wider loops or a
+ * different algorithm when the intrinsics are not real. */
+
+#ifndef __TCC_RS_XMMINTRIN_H
+#define __TCC_RS_XMMINTRIN_H
+
+#define __TCC_RS_SIMD_SCALAR 1
+#define __TCC_RS_INLINE static inline
+
+float sqrtf(float);
+double sqrt(double);
+
+typedef union __attribute__((aligned(16))) {
+    float __f[4];
+    unsigned int __u[4];
+} __m128;
+
+typedef __m128 __m128_u;
+
+/* r.FIELD[i] = EXPR for every lane, with the operands named a and b */
+#define __TCC_RS_BINARY(type, lanes, name, field, expr)  \
+    __TCC_RS_INLINE type name(type a, type b) {          \
+        type r;                                          \
+        int i;                                           \
+        for (i = 0; i < (lanes); i++)                    \
+            r.field[i] = (expr);                         \
+        return r;                                        \
+    }
+
+/* the same for lane 0 only, the others are copied from a */
+#define __TCC_RS_BINARY_LOW(type, name, field, expr)     \
+    __TCC_RS_INLINE type name(type a, type b) {          \
+        type r = a;                                      \
+        int i = 0;                                       \
+        r.field[i] = (expr);                             \
+        return r;                                        \
+    }
+
+#define __TCC_RS_MASK32(cond) ((cond) ? 0xffffffffu : 0u)
+
+#define _MM_SHUFFLE(z, y, x, w) (((z) << 6) | ((y) << 4) | ((x) << 2) | (w))
+
+#define _MM_HINT_T0 3
+#define _MM_HINT_T1 2
+#define _MM_HINT_T2 1
+#define _MM_HINT_NTA 0
+#define _mm_prefetch(p, hint) ((void)(p), (void)(hint))
+
+__TCC_RS_INLINE __m128 _mm_setzero_ps(void) {
+    __m128 r = {{0.0f, 0.0f, 0.0f, 0.0f}};
+    return r;
+}
+
+__TCC_RS_INLINE __m128 _mm_setr_ps(float e0, float e1, float e2, float e3) {
+    __m128 r = {{e0, e1, e2, e3}};
+    return r;
+}
+
+__TCC_RS_INLINE __m128 _mm_set_ps(float e3, float e2, float e1, float e0) {
+    return _mm_setr_ps(e0, e1, e2, e3);
+}
+
+__TCC_RS_INLINE __m128 _mm_set1_ps(float a) {
+    return _mm_setr_ps(a, a, a, a);
+}
+
+#define _mm_set_ps1 _mm_set1_ps
+
+__TCC_RS_INLINE __m128 _mm_set_ss(float a) {
+    return _mm_setr_ps(a, 0.0f, 0.0f, 0.0f);
+}
+
+__TCC_RS_INLINE __m128 _mm_loadu_ps(const float *p) {
+    return _mm_setr_ps(p[0], p[1], p[2], p[3]);
+}
+
+#define _mm_load_ps _mm_loadu_ps
+
+__TCC_RS_INLINE __m128 _mm_load1_ps(const float *p) {
+    return _mm_set1_ps(*p);
+}
+
+#define _mm_load_ps1 _mm_load1_ps
+
+__TCC_RS_INLINE __m128 _mm_load_ss(const float *p) {
+    return _mm_set_ss(*p);
+}
+
+__TCC_RS_INLINE void _mm_storeu_ps(float *p, __m128 a) {
+    int i;
+    for (i = 0; i < 4; i++)
+        p[i] = a.__f[i];
+}
+
+#define _mm_store_ps _mm_storeu_ps
+
+__TCC_RS_INLINE void _mm_store_ss(float *p, __m128 a) {
+    *p = a.__f[0];
+}
+
+__TCC_RS_INLINE float _mm_cvtss_f32(__m128 a) {
+    return a.__f[0];
+}
+
+__TCC_RS_BINARY(__m128, 4, _mm_add_ps, __f, a.__f[i] + b.__f[i])
+__TCC_RS_BINARY(__m128, 4, _mm_sub_ps, __f, a.__f[i] - b.__f[i])
+__TCC_RS_BINARY(__m128, 4, _mm_mul_ps, __f, a.__f[i] * b.__f[i])
+__TCC_RS_BINARY(__m128, 4, _mm_div_ps, __f, a.__f[i] / b.__f[i])
+/* like the instructions, the second operand wins for NaN and equal zeros */
+__TCC_RS_BINARY(__m128, 4, _mm_min_ps, __f, a.__f[i] < b.__f[i] ? a.__f[i] : b.__f[i])
+__TCC_RS_BINARY(__m128, 4, _mm_max_ps, __f, a.__f[i] > b.__f[i] ? a.__f[i] : b.__f[i])
+
+__TCC_RS_BINARY_LOW(__m128, _mm_add_ss, __f, a.__f[i] + b.__f[i])
+__TCC_RS_BINARY_LOW(__m128, _mm_sub_ss, __f, a.__f[i] - b.__f[i])
+__TCC_RS_BINARY_LOW(__m128, _mm_mul_ss, __f, a.__f[i] * b.__f[i])
+__TCC_RS_BINARY_LOW(__m128, _mm_div_ss, __f, a.__f[i] / b.__f[i])
+__TCC_RS_BINARY_LOW(__m128, _mm_min_ss, __f, a.__f[i] < b.__f[i] ? a.__f[i] : b.__f[i])
+__TCC_RS_BINARY_LOW(__m128, _mm_max_ss, __f, a.__f[i] > b.__f[i] ? a.__f[i] : b.__f[i])
+
+__TCC_RS_BINARY(__m128, 4, _mm_and_ps, __u, a.__u[i] & b.__u[i])
+__TCC_RS_BINARY(__m128, 4, _mm_andnot_ps, __u, ~a.__u[i] & b.__u[i])
+__TCC_RS_BINARY(__m128, 4, _mm_or_ps, __u, a.__u[i] | b.__u[i])
+__TCC_RS_BINARY(__m128, 4, _mm_xor_ps, __u, a.__u[i] ^ b.__u[i])
+
+__TCC_RS_BINARY(__m128, 4, _mm_cmpeq_ps, __u, __TCC_RS_MASK32(a.__f[i] == b.__f[i]))
+__TCC_RS_BINARY(__m128, 4, _mm_cmpneq_ps, __u, __TCC_RS_MASK32(a.__f[i] != b.__f[i]))
+__TCC_RS_BINARY(__m128, 4, _mm_cmplt_ps, __u, __TCC_RS_MASK32(a.__f[i] < b.__f[i]))
+__TCC_RS_BINARY(__m128, 4, _mm_cmple_ps, __u, __TCC_RS_MASK32(a.__f[i] <= b.__f[i]))
+__TCC_RS_BINARY(__m128, 4, _mm_cmpgt_ps, __u, __TCC_RS_MASK32(a.__f[i] > b.__f[i]))
+__TCC_RS_BINARY(__m128, 4, _mm_cmpge_ps, __u, __TCC_RS_MASK32(a.__f[i] >= b.__f[i]))
+
+__TCC_RS_BINARY(__m128, 4, _mm_unpacklo_ps, __f, i & 1 ? b.__f[i >> 1] : a.__f[i >> 1])
+__TCC_RS_BINARY(__m128, 4, _mm_unpackhi_ps, __f, i & 1 ? b.__f[2 + (i >> 1)] : a.__f[2 + (i >> 1)])
+
+__TCC_RS_INLINE __m128 _mm_sqrt_ps(__m128 a) {
+    int i;
+    for (i = 0; i < 4; i++)
+        a.__f[i] = sqrtf(a.__f[i]);
+    return a;
+}
+
+__TCC_RS_INLINE __m128 _mm_sqrt_ss(__m128 a) {
+    a.__f[0] = sqrtf(a.__f[0]);
+    return a;
+}
+
+__TCC_RS_INLINE __m128 _mm_rcp_ps(__m128 a) {
+    int i;
+    for (i = 0; i < 4; i++)
+        a.__f[i] = 1.0f / a.__f[i];
+    return a;
+}
+
+__TCC_RS_INLINE __m128 _mm_rsqrt_ps(__m128 a) {
+    int i;
+    for (i = 0; i < 4; i++)
+        a.__f[i] = 1.0f / sqrtf(a.__f[i]);
+    return a;
+}
+
+__TCC_RS_INLINE __m128 _mm_shuffle_ps(__m128 a, __m128 b, int imm) {
+    return _mm_setr_ps(a.__f[imm & 3], a.__f[(imm >> 2) & 3], b.__f[(imm >> 4) & 3],
+                       b.__f[(imm >> 6) & 3]);
+}
+
+__TCC_RS_INLINE __m128 _mm_movehl_ps(__m128 a, __m128 b) {
+    return _mm_setr_ps(b.__f[2], b.__f[3], a.__f[2], a.__f[3]);
+}
+
+__TCC_RS_INLINE __m128 _mm_movelh_ps(__m128 a, __m128 b) {
+    return _mm_setr_ps(a.__f[0], a.__f[1], b.__f[0], b.__f[1]);
+}
+
+__TCC_RS_INLINE int _mm_movemask_ps(__m128 a) {
+    int i, r = 0;
+    for (i = 0; i < 4; i++)
+        r |= (int)(a.__u[i] >> 31) << i;
+    return r;
+}
+
+/* round half to even like the default MXCSR mode, out of range is INT_MIN */
+__TCC_RS_INLINE int __tcc_rs_round_even(double x) {
+    double r;
+    int i;
+    if (!(x > -2147483648.5 && x < 2147483647.5))
+        return (int)0x80000000u;
+    r = x >= 0 ? x + 0.5 : x - 0.5;
+    i = (int)r;
+    if ((double)i == r && (i & 1))
+        i += x >= 0 ? -1 : 1;
+    return i;
+}
+
+__TCC_RS_INLINE int __tcc_rs_truncate(double x) {
+    if (!(x > -2147483649.0 && x < 2147483648.0))
+        return (int)0x80000000u;
+    return (int)x;
+}
+
+__TCC_RS_INLINE int _mm_cvtss_si32(__m128 a) {
+    return __tcc_rs_round_even(a.__f[0]);
+}
+
+__TCC_RS_INLINE int _mm_cvttss_si32(__m128 a) {
+    return __tcc_rs_truncate(a.__f[0]);
+}
+
+__TCC_RS_INLINE __m128 _mm_cvtsi32_ss(__m128 a, int b) {
+    a.__f[0] = (float)b;
+    return a;
+}
+
+/* stores are never non-temporal here, so there is nothing to order */
+#define _mm_sfence() ((void)0)
+#define _mm_pause() ((void)0)
+
+#endif
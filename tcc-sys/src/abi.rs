@@ -0,0 +1,91 @@
+//! Sanity check of a libtcc that was not built alongside these bindings.
+
+/// Every function declared by the bundled `libtcc.h`.
+#[cfg_attr(feature = "runtime-loading", allow(dead_code))]
+const EXPECTED: &[&core::ffi::CStr] = &[
+    c"tcc_new",
+    c"tcc_delete",
+    c"tcc_set_lib_path",
+    c"tcc_set_error_func",
+    c"tcc_get_error_func",
+    c"tcc_get_error_opaque",
+    c"tcc_set_options",
+    c"tcc_add_include_path",
+    c"tcc_add_sysinclude_path",
+    c"tcc_define_symbol",
+    c"tcc_undefine_symbol",
+    c"tcc_add_file",
+    c"tcc_compile_string",
+    c"tcc_set_output_type",
+    c"tcc_add_library_path",
+    c"tcc_add_library",
+    c"tcc_add_symbol",
+    c"tcc_output_file",
+    c"tcc_run",
+    c"tcc_relocate",
+    c"tcc_get_symbol",
+    c"tcc_list_symbols",
+];
+
+/// Only exported by libtcc versions where `tcc_relocate` lost its memory
+/// argument, calling those through our two argument binding corrupts memory.
+pub(crate) const NEWER_API: &core::ffi::CStr = c"tcc_set_realloc";
+pub(crate) const NEWER_API_REASON: &str =
+    "libtcc exports tcc_set_realloc, tcc_relocate takes no memory argument";
+
+/// Check that the libtcc the process was linked against exposes the API of the
+/// bundled header.
+///
+/// Vendored builds are checked at compile time and always pass. With
+/// `runtime-loading` the check happens in
+/// [`load_library`](crate::runtime::load_library), so this only verifies that
+/// a library was loaded. On platforms without `dlsym` the check is skipped.
+pub fn check_abi() -> Result<(), &'static str> {
+    #[cfg(feature = "runtime-loading")]
+    return match crate::runtime::is_loaded() {
+        true => Ok(()),
+        false => Err("libtcc was not loaded, see tcc_sys::runtime::load_library"),
+    };
+    #[cfg(not(feature = "runtime-loading"))]
+    {
+        if cfg!(feature = "vendored") {
+            return Ok(());
+        }
+        imp::check()
+    }
+}
+
+#[cfg(all(unix, not(feature = "runtime-loading")))]
+mod imp {
+    use core::ffi::CStr;
+
+    use once_cell::sync::OnceCell;
+
+    use super::{EXPECTED, NEWER_API, NEWER_API_REASON};
+
+    static RESULT: OnceCell<Result<(), &'static str>> = OnceCell::new();
+
+    fn exported(name: &CStr) -> bool {
+        !unsafe { libc::dlsym(libc::RTLD_DEFAULT, name.as_ptr()) }.is_null()
+    }
+
+    pub(super) fn check() -> Result<(), &'static str> {
+        *RESULT.get_or_init(|| {
+            if EXPECTED.iter().any(|name| !exported(name)) {
+                Err("libtcc is missing functions declared by libtcc.h")
+            } else if exported(NEWER_API) {
+                Err(NEWER_API_REASON)
+            } else {
+                Ok(())
+            }
+        })
+    }
+}
+
+#[cfg(all(not(unix), not(feature = "runtime-loading")))]
+mod imp {
+    pub(super) fn check() -> Result<(), &'static str> {
+        let _ = (super::EXPECTED, super::NEWER_API_REASON);
+        Ok(())
+    }
+}
@@ -48,7 +48,7 @@ pub mod headers {
 pub mod libraries {
     #[iftree::include_file_tree(
         "
-    root_folder_variable = 'CARGO_MANIFEST_DIR'
+    root_folder_variable = 'OUT_DIR'
     base_folder = 'lib/'
     paths = '/**'
     
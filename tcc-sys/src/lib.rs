@@ -5,7 +5,59 @@
 #![allow(non_snake_case)]
 
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+include!(concat!(env!("OUT_DIR"), "/warnings.rs"));
 
+mod abi;
 pub mod assets;
+mod preprocess;
+mod sections;
 
-#[cfg(feature = "vfs")] mod vfs;
+pub use abi::check_abi;
+pub use preprocess::{tcc_rs_preprocess_done, tcc_rs_preprocess_to, PREPROCESS_OUTPUT};
+pub use sections::{
+    tcc_rs_include_search, tcc_rs_library_search, tcc_rs_list_debug_sections, tcc_rs_list_exports,
+    tcc_rs_list_sections, tcc_rs_list_symbol_sizes, tcc_rs_prefix_symbols, tcc_rs_section_sizes,
+    SECTION_SIZES,
+};
+#[cfg(feature = "runtime-loading")]
+pub mod runtime;
+#[cfg(feature = "runtime-loading")]
+pub use runtime::{
+    tcc_add_file, tcc_add_include_path, tcc_add_library, tcc_add_library_path, tcc_add_symbol,
+    tcc_add_sysinclude_path, tcc_compile_string, tcc_define_symbol, tcc_delete, tcc_get_error_func,
+    tcc_get_error_opaque, tcc_get_symbol, tcc_list_symbols, tcc_new, tcc_output_file, tcc_relocate,
+    tcc_run, tcc_set_error_func, tcc_set_lib_path, tcc_set_options, tcc_set_output_type,
+    tcc_undefine_symbol,
+};
+
+/// tinycc version the library was built from.
+pub const TCC_VERSION: &str = env!("TCC_SYS_VERSION");
+
+/// Commit of the vendored tinycc checkout, or `unknown`.
+pub const TCC_COMMIT: &str = env!("TCC_SYS_COMMIT");
+
+/// `TCC_TARGET_*` backend the library was built for.
+pub const TCC_TARGET: &str = env!("TCC_SYS_TARGET");
+
+/// `TCC_TARGET_PE`, `TCC_TARGET_MACHO` or `ELF`.
+pub const TCC_LINKAGE: &str = env!("TCC_SYS_LINKAGE");
+
+/// `native`, `fallback` or `unknown`: whether tcc implements C11 atomics
+/// itself or the embedded `stdatomic.h` calls back into the host.
+pub const TCC_ATOMICS: &str = env!("TCC_SYS_ATOMICS");
+
+/// `hard` or `softfp`: how floating point arguments are passed, only ARM
+/// code can use `softfp`.
+pub const TCC_FLOAT_ABI: &str = env!("TCC_SYS_FLOAT_ABI");
+
+/// Whether bound checking (`-b`) and backtraces (`-bt`) are compiled in.
+pub const TCC_RUNTIME_CHECKS: bool = option_env!("TCC_SYS_RUNTIME_CHECKS").is_some();
+
+pub const VFS: bool = cfg!(feature = "vfs");
+pub const EMBED_HEADERS: bool = cfg!(feature = "embed-headers");
+pub const EMBED_HEADERS_WIN32: bool = cfg!(feature = "embed-headers-win32");
+pub const EMBED_LIBRARIES: bool = cfg!(feature = "embed-libraries");
+pub const EMBED_MUSL: bool = cfg!(feature = "embed-musl");
+pub const EMBED_SIMD: bool = cfg!(feature = "embed-simd");
+
+#[cfg(feature = "vfs")] pub mod vfs;
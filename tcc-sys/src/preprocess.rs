@@ -0,0 +1,39 @@
+//! Where `-E` output goes, see `preprocess.c`.
+//!
+//! Only the vendored libtcc is built with the helpers; with any other the
+//! functions below fail, and [`PREPROCESS_OUTPUT`] tells in advance.
+
+use core::ffi::{c_char, c_int};
+
+use crate::TCCState;
+
+/// Whether preprocessor output can be redirected.
+pub const PREPROCESS_OUTPUT: bool =
+    cfg!(all(feature = "vendored", not(feature = "runtime-loading")));
+
+#[cfg(all(feature = "vendored", not(feature = "runtime-loading")))]
+extern "C" {
+    /// Write preprocessor output of `s` to the file at `path`, created or
+    /// truncated; returns -1 if it can not be opened.
+    pub fn tcc_rs_preprocess_to(s: *mut TCCState, path: *const c_char) -> c_int;
+
+    /// Close the file from [`tcc_rs_preprocess_to`], output goes to stdout
+    /// again.
+    pub fn tcc_rs_preprocess_done(s: *mut TCCState);
+}
+
+/// Unavailable, always -1.
+///
+/// # Safety
+/// Trivially safe, `unsafe` to match the vendored function.
+#[cfg(not(all(feature = "vendored", not(feature = "runtime-loading"))))]
+pub unsafe fn tcc_rs_preprocess_to(_: *mut TCCState, _: *const c_char) -> c_int {
+    -1
+}
+
+/// Unavailable, does nothing.
+///
+/// # Safety
+/// Trivially safe, `unsafe` to match the vendored function.
+#[cfg(not(all(feature = "vendored", not(feature = "runtime-loading"))))]
+pub unsafe fn tcc_rs_preprocess_done(_: *mut TCCState) {}
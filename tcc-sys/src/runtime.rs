@@ -0,0 +1,114 @@
+//! Resolve libtcc at runtime instead of linking it.
+//!
+//! With the `runtime-loading` feature the bindings are generated as a
+//! [`LibTcc`] function table. [`load_library`] opens a libtcc once per
+//! process and the free `tcc_*` functions below forward to it, so code written
+//! against the linked bindings keeps working unchanged.
+
+use core::{
+    ffi::{c_char, c_int, c_void},
+    fmt,
+};
+use std::ffi::OsStr;
+
+use once_cell::sync::OnceCell;
+
+use crate::{LibTcc, TCCErrorFunc, TCCState};
+
+static LIBRARY: OnceCell<LibTcc> = OnceCell::new();
+
+#[derive(Debug)]
+/// Reasons [`load_library`] can fail.
+pub enum LoadError {
+    /// The library could not be opened or lacks a function of `libtcc.h`.
+    Library(libloading::Error),
+    /// The library has an API that does not match `libtcc.h`.
+    Incompatible(&'static str),
+    /// Another libtcc was loaded before.
+    AlreadyLoaded,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Library(err) => write!(f, "failed to load libtcc: {err}"),
+            LoadError::Incompatible(reason) => write!(f, "incompatible libtcc: {reason}"),
+            LoadError::AlreadyLoaded => f.write_str("libtcc was already loaded"),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Load the libtcc at `path` for the rest of the process lifetime.
+///
+/// # Safety
+/// Loading a library runs its initializers, `path` must point to a libtcc
+/// that is safe to load.
+pub unsafe fn load_library(path: impl AsRef<OsStr>) -> Result<(), LoadError> {
+    if LIBRARY.get().is_some() {
+        return Err(LoadError::AlreadyLoaded);
+    }
+    let library = libloading::Library::new(path).map_err(LoadError::Library)?;
+    if library
+        .get::<*const c_void>(crate::abi::NEWER_API.to_bytes_with_nul())
+        .is_ok()
+    {
+        return Err(LoadError::Incompatible(crate::abi::NEWER_API_REASON));
+    }
+    let library = LibTcc::from_library(library).map_err(LoadError::Library)?;
+    LIBRARY.set(library).map_err(|_| LoadError::AlreadyLoaded)
+}
+
+pub(crate) fn is_loaded() -> bool {
+    LIBRARY.get().is_some()
+}
+
+fn library() -> &'static LibTcc {
+    LIBRARY
+        .get()
+        .expect("libtcc is not loaded, call tcc_sys::runtime::load_library first")
+}
+
+macro_rules! forward {
+    ($(fn $name:ident($($arg:ident: $ty:ty),* $(,)?) $(-> $ret:ty)?;)*) => {
+        $(
+            /// Forwards to the loaded libtcc.
+            ///
+            /// # Safety
+            /// Same as the C function; panics if no libtcc was loaded.
+            pub unsafe fn $name($($arg: $ty),*) $(-> $ret)? {
+                library().$name($($arg),*)
+            }
+        )*
+    };
+}
+
+forward! {
+    fn tcc_new() -> *mut TCCState;
+    fn tcc_delete(s: *mut TCCState);
+    fn tcc_set_lib_path(s: *mut TCCState, path: *const c_char);
+    fn tcc_set_error_func(s: *mut TCCState, error_opaque: *mut c_void, error_func: TCCErrorFunc);
+    fn tcc_get_error_func(s: *mut TCCState) -> TCCErrorFunc;
+    fn tcc_get_error_opaque(s: *mut TCCState) -> *mut c_void;
+    fn tcc_set_options(s: *mut TCCState, str_: *const c_char) -> c_int;
+    fn tcc_add_include_path(s: *mut TCCState, pathname: *const c_char) -> c_int;
+    fn tcc_add_sysinclude_path(s: *mut TCCState, pathname: *const c_char) -> c_int;
+    fn tcc_define_symbol(s: *mut TCCState, sym: *const c_char, value: *const c_char);
+    fn tcc_undefine_symbol(s: *mut TCCState, sym: *const c_char);
+    fn tcc_add_file(s: *mut TCCState, filename: *const c_char) -> c_int;
+    fn tcc_compile_string(s: *mut TCCState, buf: *const c_char) -> c_int;
+    fn tcc_set_output_type(s: *mut TCCState, output_type: c_int) -> c_int;
+    fn tcc_add_library_path(s: *mut TCCState, pathname: *const c_char) -> c_int;
+    fn tcc_add_library(s: *mut TCCState, libraryname: *const c_char) -> c_int;
+    fn tcc_add_symbol(s: *mut TCCState, name: *const c_char, val: *const c_void) -> c_int;
+    fn tcc_output_file(s: *mut TCCState, filename: *const c_char) -> c_int;
+    fn tcc_run(s: *mut TCCState, argc: c_int, argv: *mut *mut c_char) -> c_int;
+    fn tcc_relocate(s1: *mut TCCState, ptr: *mut c_void) -> c_int;
+    fn tcc_get_symbol(s: *mut TCCState, name: *const c_char) -> *mut c_void;
+    fn tcc_list_symbols(
+        s: *mut TCCState,
+        ctx: *mut c_void,
+        symbol_cb: Option<unsafe extern "C" fn(ctx: *mut c_void, name: *const c_char, val: *const c_void)>,
+    );
+}
@@ -0,0 +1,179 @@
+//! Sections and symbols of a tcc state, see `sections.c`.
+//!
+//! Only the vendored libtcc is built with the helpers; with any other the
+//! functions below report nothing, and [`SECTION_SIZES`] tells in advance.
+
+use core::ffi::{c_char, c_int, c_void};
+
+use crate::TCCState;
+
+/// Whether sections can be queried.
+pub const SECTION_SIZES: bool = cfg!(all(feature = "vendored", not(feature = "runtime-loading")));
+
+#[cfg(all(feature = "vendored", not(feature = "runtime-loading")))]
+extern "C" {
+    /// Sum the sizes of the sections of `s` loaded at run time into
+    /// executable code, initialized data including read-only data, and
+    /// zero-initialized data.
+    pub fn tcc_rs_section_sizes(
+        s: *mut TCCState,
+        code: *mut usize,
+        data: *mut usize,
+        bss: *mut usize,
+    );
+
+    /// Call `section` with the address, size and ELF `sh_flags` of every
+    /// section of `s` loaded at run time, once relocated in memory.
+    pub fn tcc_rs_list_sections(
+        s: *mut TCCState,
+        ctx: *mut c_void,
+        section: extern "C" fn(ctx: *mut c_void, addr: usize, size: usize, flags: usize),
+    );
+
+    /// Call `symbol` with the name, address and size of every symbol
+    /// defined by `s`, once relocated in memory.
+    pub fn tcc_rs_list_symbol_sizes(
+        s: *mut TCCState,
+        ctx: *mut c_void,
+        symbol: extern "C" fn(ctx: *mut c_void, name: *const c_char, addr: usize, size: usize),
+    );
+
+    /// Call `section` with the name and contents of every `.debug_*`
+    /// section of `s`.
+    pub fn tcc_rs_list_debug_sections(
+        s: *mut TCCState,
+        ctx: *mut c_void,
+        section: extern "C" fn(
+            ctx: *mut c_void,
+            name: *const c_char,
+            data: *const c_void,
+            len: usize,
+        ),
+    );
+
+    /// Call `symbol` with the name of every global symbol defined by the
+    /// code compiled into `s` and whether it is a function, before or
+    /// after relocation.
+    pub fn tcc_rs_list_exports(
+        s: *mut TCCState,
+        ctx: *mut c_void,
+        symbol: extern "C" fn(ctx: *mut c_void, name: *const c_char, function: c_int),
+    );
+
+    /// Rename every global symbol defined by the code compiled into `s` to
+    /// `prefix` followed by its name, except `main` and names already
+    /// starting with `prefix`.
+    pub fn tcc_rs_prefix_symbols(s: *mut TCCState, prefix: *const c_char);
+
+    /// Call `path` with every directory `tcc_add_library` searches, in
+    /// order, and return non-zero if it only looks for static archives.
+    pub fn tcc_rs_library_search(
+        s: *mut TCCState,
+        ctx: *mut c_void,
+        path: extern "C" fn(ctx: *mut c_void, dir: *const c_char),
+    ) -> c_int;
+
+    /// Call `path` with every directory `#include` searches after the
+    /// directory of the including file, in order.
+    pub fn tcc_rs_include_search(
+        s: *mut TCCState,
+        ctx: *mut c_void,
+        path: extern "C" fn(ctx: *mut c_void, dir: *const c_char),
+    );
+}
+
+/// Unavailable, sets all sizes to 0.
+///
+/// # Safety
+/// The pointers must be valid for writes, as for the vendored function.
+#[cfg(not(all(feature = "vendored", not(feature = "runtime-loading"))))]
+pub unsafe fn tcc_rs_section_sizes(
+    _: *mut TCCState,
+    code: *mut usize,
+    data: *mut usize,
+    bss: *mut usize,
+) {
+    *code = 0;
+    *data = 0;
+    *bss = 0;
+}
+
+/// Unavailable, lists nothing.
+///
+/// # Safety
+/// Trivially safe, `unsafe` to match the vendored function.
+#[cfg(not(all(feature = "vendored", not(feature = "runtime-loading"))))]
+pub unsafe fn tcc_rs_list_sections(
+    _: *mut TCCState,
+    _: *mut c_void,
+    _: extern "C" fn(ctx: *mut c_void, addr: usize, size: usize, flags: usize),
+) {
+}
+
+/// Unavailable, lists nothing.
+///
+/// # Safety
+/// Trivially safe, `unsafe` to match the vendored function.
+#[cfg(not(all(feature = "vendored", not(feature = "runtime-loading"))))]
+pub unsafe fn tcc_rs_list_symbol_sizes(
+    _: *mut TCCState,
+    _: *mut c_void,
+    _: extern "C" fn(ctx: *mut c_void, name: *const c_char, addr: usize, size: usize),
+) {
+}
+
+/// Unavailable, lists nothing.
+///
+/// # Safety
+/// Trivially safe, `unsafe` to match the vendored function.
+#[cfg(not(all(feature = "vendored", not(feature = "runtime-loading"))))]
+pub unsafe fn tcc_rs_list_debug_sections(
+    _: *mut TCCState,
+    _: *mut c_void,
+    _: extern "C" fn(ctx: *mut c_void, name: *const c_char, data: *const c_void, len: usize),
+) {
+}
+
+/// Unavailable, lists nothing.
+///
+/// # Safety
+/// Trivially safe, `unsafe` to match the vendored function.
+#[cfg(not(all(feature = "vendored", not(feature = "runtime-loading"))))]
+pub unsafe fn tcc_rs_list_exports(
+    _: *mut TCCState,
+    _: *mut c_void,
+    _: extern "C" fn(ctx: *mut c_void, name: *const c_char, function: c_int),
+) {
+}
+
+/// Unavailable, renames nothing.
+///
+/// # Safety
+/// Trivially safe, `unsafe` to match the vendored function.
+#[cfg(not(all(feature = "vendored", not(feature = "runtime-loading"))))]
+pub unsafe fn tcc_rs_prefix_symbols(_: *mut TCCState, _: *const c_char) {}
+
+/// Unavailable, lists nothing and reports shared libraries searched too.
+///
+/// # Safety
+/// Trivially safe, `unsafe` to match the vendored function.
+#[cfg(not(all(feature = "vendored", not(feature = "runtime-loading"))))]
+pub unsafe fn tcc_rs_library_search(
+    _: *mut TCCState,
+    _: *mut c_void,
+    _: extern "C" fn(ctx: *mut c_void, dir: *const c_char),
+) -> c_int {
+    0
+}
+
+/// Unavailable, lists nothing.
+///
+/// # Safety
+/// Trivially safe, `unsafe` to match the vendored function.
+#[cfg(not(all(feature = "vendored", not(feature = "runtime-loading"))))]
+pub unsafe fn tcc_rs_include_search(
+    _: *mut TCCState,
+    _: *mut c_void,
+    _: extern "C" fn(ctx: *mut c_void, dir: *const c_char),
+) {
+}
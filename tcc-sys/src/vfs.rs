@@ -1,11 +1,20 @@
 #![deny(clippy::alloc_instead_of_core)]
 #![deny(clippy::std_instead_of_core)]
 
-use core::{ffi::CStr, ptr::null_mut, slice};
-use std::io::{Cursor, Read, Seek, SeekFrom};
+use core::{cell::RefCell, ffi::CStr, ptr::null_mut, slice};
+use std::{
+    collections::BTreeMap,
+    io::{self, Cursor, ErrorKind, Read, Seek, SeekFrom, Write},
+    sync::{Arc, RwLock},
+    thread::LocalKey,
+};
 
-use libc::{c_char, c_int, c_void, off_t, size_t, ssize_t, SEEK_CUR, SEEK_END, SEEK_SET};
+use libc::{
+    c_char, c_int, c_uint, c_void, off_t, size_t, ssize_t, O_ACCMODE, O_CREAT, O_RDONLY, SEEK_CUR,
+    SEEK_END, SEEK_SET,
+};
 use once_cell::sync::Lazy;
+use qp_trie::{wrapper::BString, Trie};
 use stash::Stash;
 
 extern "C" {
@@ -65,6 +74,7 @@ impl VFS for PosixVFS {
 pub enum MemoryVFS {
     Static(Cursor<&'static [u8]>),
     Heap(Cursor<Vec<u8>>),
+    Shared(Cursor<Arc<[u8]>>),
 }
 
 #[allow(dead_code)]
@@ -76,6 +86,10 @@ impl MemoryVFS {
     pub fn new(data: &[u8]) -> Self {
         MemoryVFS::Heap(Cursor::new(data.to_vec()))
     }
+
+    pub fn shared(data: Arc<[u8]>) -> Self {
+        MemoryVFS::Shared(Cursor::new(data))
+    }
 }
 
 impl VFS for MemoryVFS {
@@ -83,6 +97,7 @@ impl VFS for MemoryVFS {
         if let Ok(n) = match self {
             MemoryVFS::Static(cursor) => cursor.read(buf),
             MemoryVFS::Heap(cursor) => cursor.read(buf),
+            MemoryVFS::Shared(cursor) => cursor.read(buf),
         } {
             Ok(n.try_into().map_err(|_| ())?)
         } else {
@@ -94,6 +109,7 @@ impl VFS for MemoryVFS {
         match self {
             MemoryVFS::Static(cursor) => cursor.seek(from),
             MemoryVFS::Heap(cursor) => cursor.seek(from),
+            MemoryVFS::Shared(cursor) => cursor.seek(from),
         }
         .map_err(|_| ())?
         .try_into()
@@ -106,6 +122,416 @@ impl VFS for MemoryVFS {
     }
 }
 
+static MOUNTS: Lazy<RwLock<Trie<BString, Arc<[u8]>>>> = Lazy::new(Default::default);
+
+/// Make `data` readable by tcc under exactly `path`, shadowing any file on
+/// disk until [`unmount`] is called.
+pub fn mount(path: &str, data: impl Into<Arc<[u8]>>) {
+    MOUNTS.write().unwrap().insert_str(path, data.into());
+}
+
+/// Remove a file added by [`mount`], returning whether it was mounted.
+pub fn unmount(path: &str) -> bool {
+    MOUNTS.write().unwrap().remove_str(path).is_some()
+}
+
+/// A value installed in a thread-local slot, the previous one is put back
+/// when this is dropped, also while unwinding out of the code the value was
+/// installed for.
+struct Installed<T: 'static> {
+    slot:     &'static LocalKey<RefCell<Option<T>>>,
+    /// `None` once restored
+    previous: Option<Option<T>>,
+}
+
+impl<T: 'static> Installed<T> {
+    fn new(slot: &'static LocalKey<RefCell<Option<T>>>, value: T) -> Self {
+        let previous = slot.with(|cell| cell.replace(Some(value)));
+        Self {
+            slot,
+            previous: Some(previous),
+        }
+    }
+
+    /// Put back the previous value, returning the installed one.
+    fn restore(mut self) -> Option<T> {
+        let previous = self.previous.take().unwrap();
+        self.slot.with(|cell| cell.replace(previous))
+    }
+}
+
+impl<T: 'static> Drop for Installed<T> {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            let _ = self.slot.try_with(|cell| *cell.borrow_mut() = previous);
+        }
+    }
+}
+
+/// Where tcc writes an output file instead of the disk, see
+/// [`capture_output`].
+pub trait OutputSink: Write + Seek {}
+
+impl<T: Write + Seek + ?Sized> OutputSink for T {}
+
+/// The output captured on this thread.
+struct Capture {
+    /// where tcc creates the file
+    path:   String,
+    /// valid while [`capture_output`] runs, which clears the slot before
+    /// returning or unwinding
+    writer: *mut (dyn OutputSink + 'static),
+    opened: bool,
+    /// the first error of `writer`, after which nothing is written
+    error:  Option<io::Error>,
+}
+
+thread_local! {
+    static CAPTURE: RefCell<Option<Capture>> = const { RefCell::new(None) };
+}
+
+/// Whether tcc's `fdopen` on a captured file can be served here.
+const CAPTURE_SUPPORTED: bool = cfg!(any(
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd"
+));
+
+/// Run `f` with the file tcc creates at `path` on this thread written into
+/// `writer` instead of the disk, returning its result and whether the file
+/// went to `writer`.
+///
+/// Only files tcc opens with `open` and `fdopen` can be captured, as ELF and
+/// Mach-O outputs are, and only where the C library can wrap a writer in a
+/// `FILE` (`fopencookie` or `funopen`). Anything else is still written to
+/// `path`, for the caller to read back. Fails with the first error of
+/// `writer`.
+pub fn capture_output<R>(
+    path: &str,
+    writer: &mut dyn OutputSink,
+    f: impl FnOnce() -> R,
+) -> (R, io::Result<bool>) {
+    let writer: *mut (dyn OutputSink + '_) = writer;
+    // only reachable while `f` runs: `installed` takes it out of `CAPTURE`
+    // again when `f` returns or unwinds
+    let writer: *mut (dyn OutputSink + 'static) = unsafe { core::mem::transmute(writer) };
+    let capture = Capture {
+        path: path.to_string(),
+        writer,
+        opened: false,
+        error: None,
+    };
+    let installed = Installed::new(&CAPTURE, capture);
+    let ret = f();
+    let capture = installed.restore().unwrap();
+    let captured = match capture.error {
+        Some(err) => Err(err),
+        None => Ok(capture.opened),
+    };
+    (ret, captured)
+}
+
+/// Whether creating `path` on this thread goes to a captured writer.
+fn capturing(path: &str) -> bool {
+    CAPTURE_SUPPORTED
+        && CAPTURE.with(|cell| {
+            let mut cell = cell.borrow_mut();
+            let Some(capture) = cell.as_mut().filter(|capture| capture.path == path) else {
+                return false;
+            };
+            capture.opened = true;
+            true
+        })
+}
+
+/// Apply `f` to the captured writer, `None` once it failed or outside of
+/// [`capture_output`].
+fn with_writer<T>(f: impl FnOnce(&mut dyn OutputSink) -> io::Result<T>) -> Option<T> {
+    CAPTURE.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        let capture = cell.as_mut().filter(|capture| capture.error.is_none())?;
+        match f(unsafe { &mut *capture.writer }) {
+            Ok(value) => Some(value),
+            Err(err) => {
+                capture.error = Some(err);
+                None
+            }
+        }
+    })
+}
+
+/// `lseek` style arguments as a [`SeekFrom`].
+fn seek_from(offset: i64, whence: c_int) -> Option<SeekFrom> {
+    Some(match whence {
+        // a negative position is EINVAL for lseek too
+        SEEK_SET => SeekFrom::Start(offset.try_into().ok()?),
+        SEEK_END => SeekFrom::End(offset),
+        SEEK_CUR => SeekFrom::Current(offset),
+        _ => return None,
+    })
+}
+
+/// A file created by tcc while it is captured, see [`capture_output`].
+struct CapturedFile;
+
+impl VFS for CapturedFile {
+    fn read(&mut self, _buf: &mut [u8]) -> Result<ssize_t, ()> {
+        Err(())
+    }
+
+    fn seek(&mut self, from: SeekFrom) -> Result<off_t, ()> {
+        let position = with_writer(|writer| writer.seek(from)).ok_or(())?;
+        position.try_into().map_err(|_| ())
+    }
+
+    fn close(&mut self) -> Result<c_int, ()> {
+        Ok(0)
+    }
+
+    fn fdopen(&mut self, mode: *const c_char) -> Result<*mut c_void, ()> {
+        let file = unsafe { cookie::open(mode) };
+        if file.is_null() {
+            Err(())
+        } else {
+            Ok(file)
+        }
+    }
+}
+
+/// `FILE` streams writing to the captured writer.
+#[cfg(target_os = "linux")]
+mod cookie {
+    use core::{ptr::null_mut, slice};
+
+    use libc::{c_char, c_int, c_void, size_t, ssize_t};
+
+    use super::{seek_from, with_writer};
+
+    #[repr(C)]
+    struct Functions {
+        read:  Option<unsafe extern "C" fn(*mut c_void, *mut c_char, size_t) -> ssize_t>,
+        write: Option<unsafe extern "C" fn(*mut c_void, *const c_char, size_t) -> ssize_t>,
+        seek:  Option<unsafe extern "C" fn(*mut c_void, *mut i64, c_int) -> c_int>,
+        close: Option<unsafe extern "C" fn(*mut c_void) -> c_int>,
+    }
+
+    extern "C" {
+        fn fopencookie(cookie: *mut c_void, mode: *const c_char, io: Functions) -> *mut c_void;
+    }
+
+    unsafe extern "C" fn write(_: *mut c_void, buf: *const c_char, len: size_t) -> ssize_t {
+        let buf = slice::from_raw_parts(buf.cast::<u8>(), len);
+        // 0 is the error return here
+        with_writer(|writer| writer.write_all(buf)).map_or(0, |()| len as ssize_t)
+    }
+
+    unsafe extern "C" fn seek(_: *mut c_void, offset: *mut i64, whence: c_int) -> c_int {
+        let Some(from) = seek_from(*offset, whence) else {
+            return -1;
+        };
+        match with_writer(|writer| writer.seek(from)).and_then(|at| at.try_into().ok()) {
+            Some(at) => {
+                *offset = at;
+                0
+            }
+            None => -1,
+        }
+    }
+
+    unsafe extern "C" fn close(_: *mut c_void) -> c_int {
+        0
+    }
+
+    pub(super) unsafe fn open(mode: *const c_char) -> *mut c_void {
+        let io = Functions {
+            read:  None,
+            write: Some(write),
+            seek:  Some(seek),
+            close: Some(close),
+        };
+        fopencookie(null_mut(), mode, io)
+    }
+}
+
+/// `FILE` streams writing to the captured writer.
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+mod cookie {
+    use core::{ptr::null, slice};
+
+    use libc::{c_char, c_int, c_void};
+
+    use super::{seek_from, with_writer};
+
+    extern "C" {
+        fn funopen(
+            cookie: *const c_void,
+            read: Option<unsafe extern "C" fn(*mut c_void, *mut c_char, c_int) -> c_int>,
+            write: Option<unsafe extern "C" fn(*mut c_void, *const c_char, c_int) -> c_int>,
+            seek: Option<unsafe extern "C" fn(*mut c_void, i64, c_int) -> i64>,
+            close: Option<unsafe extern "C" fn(*mut c_void) -> c_int>,
+        ) -> *mut c_void;
+    }
+
+    unsafe extern "C" fn write(_: *mut c_void, buf: *const c_char, len: c_int) -> c_int {
+        let buf = slice::from_raw_parts(buf.cast::<u8>(), len.try_into().unwrap_or(0));
+        with_writer(|writer| writer.write_all(buf)).map_or(-1, |()| len)
+    }
+
+    unsafe extern "C" fn seek(_: *mut c_void, offset: i64, whence: c_int) -> i64 {
+        seek_from(offset, whence)
+            .and_then(|from| with_writer(|writer| writer.seek(from)))
+            .and_then(|at| at.try_into().ok())
+            .unwrap_or(-1)
+    }
+
+    unsafe extern "C" fn close(_: *mut c_void) -> c_int {
+        0
+    }
+
+    pub(super) unsafe fn open(_mode: *const c_char) -> *mut c_void {
+        funopen(null(), None, Some(write), Some(seek), Some(close))
+    }
+}
+
+/// No way to wrap the writer in a `FILE`, files are not captured.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd"
+)))]
+mod cookie {
+    use core::ptr::null_mut;
+
+    use libc::{c_char, c_void};
+
+    pub(super) unsafe fn open(_mode: *const c_char) -> *mut c_void {
+        null_mut()
+    }
+}
+
+/// Files tcc has open on this thread, see [`limit_open_files`].
+struct Nesting {
+    limit: usize,
+    open:  Vec<(c_int, String)>,
+}
+
+thread_local! {
+    static NESTING: RefCell<Option<Nesting>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with at most `limit` files opened by tcc at once on this thread,
+/// which bounds the include depth of a compilation running in `f`.
+///
+/// Opening one more file yields a file holding only an `#error` that lists
+/// the open files, outermost first, so tcc stops with the whole chain in
+/// its message. The chain is called a cycle if it contains a file twice.
+pub fn limit_open_files<R>(limit: usize, f: impl FnOnce() -> R) -> R {
+    let nesting = Nesting {
+        limit,
+        open: Vec::new(),
+    };
+    let _installed = Installed::new(&NESTING, nesting);
+    f()
+}
+
+/// Record `fd` opened for `path`, or swap it for an `#error` file if that
+/// exceeds the limit.
+unsafe fn track(fd: c_int, path: &str) -> c_int {
+    NESTING.with(|nesting| {
+        let mut nesting = nesting.borrow_mut();
+        let Some(nesting) = nesting.as_mut().filter(|_| fd >= 0) else {
+            return fd;
+        };
+        if nesting.open.len() < nesting.limit {
+            nesting.open.push((fd, path.to_string()));
+            return fd;
+        }
+
+        if let Some(mut vfs) = FILES.take(SmallIndex(fd)) {
+            let _ = vfs.close();
+        }
+        let chain = nesting
+            .open
+            .iter()
+            .map(|(_, path)| path.as_str())
+            .chain([path])
+            .collect::<Vec<_>>();
+        let cycle = nesting.open.iter().any(|(_, open)| open == path);
+        let reason = match cycle {
+            true => "include cycle",
+            false => "include depth limit exceeded",
+        };
+        let error = format!("#error {reason}: {}\n", chain.join(" -> "));
+        FILES.put(Box::new(MemoryVFS::new(error.as_bytes()))).0
+    })
+}
+
+/// Content by path, `None` for files found missing.
+type Entries = BTreeMap<String, Option<Arc<[u8]>>>;
+
+/// Contents of headers read from disk, see [`with_cache`].
+#[derive(Clone, Default)]
+pub struct Cache(Arc<RwLock<Entries>>);
+
+impl Cache {
+    /// Number of paths cached, including ones found missing.
+    pub fn len(&self) -> usize {
+        self.0.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forget everything, e.g. after headers changed on disk.
+    pub fn clear(&self) {
+        self.0.write().unwrap().clear();
+    }
+}
+
+thread_local! {
+    static CACHE: RefCell<Option<(Cache, usize)>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with the headers tcc opens on this thread served from `cache`,
+/// returning its result and the number of opens the cache answered.
+///
+/// Paths are cached as given, missing ones included, and never checked
+/// against the disk again, so each header is read once for every
+/// compilation sharing the cache.
+pub fn with_cache<R>(cache: &Cache, f: impl FnOnce() -> R) -> (R, usize) {
+    let installed = Installed::new(&CACHE, (cache.clone(), 0));
+    let ret = f();
+    let (_, hits) = installed.restore().unwrap();
+    (ret, hits)
+}
+
+/// Content of `path` from the active cache, `Some(None)` if it is missing.
+fn cached(path: &str) -> Option<Option<Arc<[u8]>>> {
+    CACHE.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        let (cache, hits) = cell.as_mut()?;
+        if let Some(entry) = cache.0.read().unwrap().get(path) {
+            *hits += 1;
+            return Some(entry.clone());
+        }
+        let entry = match std::fs::read(path) {
+            Ok(data) => Some(Arc::from(data)),
+            Err(err) if err.kind() == ErrorKind::NotFound => None,
+            // left to `open` to report
+            Err(_) => return None,
+        };
+        cache
+            .0
+            .write()
+            .unwrap()
+            .insert(path.to_string(), entry.clone());
+        Some(entry)
+    })
+}
+
 static mut FILES: Lazy<Stash<Box<dyn VFS + 'static + Sync + Send>, SmallIndex>> =
     Lazy::new(Stash::default);
 
@@ -126,9 +552,16 @@ impl stash::Index for SmallIndex {
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn vfs_open(path: *const c_char, oflag: c_int, args: ...) -> c_int {
-    #[cfg(any(feature = "embed-headers", feature = "embed-libraries"))]
+pub unsafe extern "C" fn vfs_open(path: *const c_char, oflag: c_int, mut args: ...) -> c_int {
+    let name = CStr::from_ptr(path).to_string_lossy();
     if let Ok(path) = CStr::from_ptr(path).to_str() {
+        if oflag & O_CREAT != 0 && capturing(path) {
+            return FILES.put(Box::new(CapturedFile)).0;
+        }
+        if let Some(data) = MOUNTS.read().unwrap().get_str(path) {
+            return track(FILES.put(Box::new(MemoryVFS::shared(data.clone()))).0, path);
+        }
+
         #[cfg(feature = "embed-headers")]
         {
             let prefix = "/vfs/headers/";
@@ -137,7 +570,8 @@ pub unsafe extern "C" fn vfs_open(path: *const c_char, oflag: c_int, args: ...)
                 let path = path.strip_prefix(prefix).unwrap();
 
                 if let Some(file) = crate::assets::headers::ASSETS.get_str(path) {
-                    return FILES.put(Box::new(MemoryVFS::from_static(file))).0;
+                    let fd = FILES.put(Box::new(MemoryVFS::from_static(file))).0;
+                    return track(fd, &name);
                 }
             }
         }
@@ -149,15 +583,32 @@ pub unsafe extern "C" fn vfs_open(path: *const c_char, oflag: c_int, args: ...)
             if path.starts_with(prefix) {
                 let path = path.strip_prefix(prefix).unwrap();
                 if let Some(file) = crate::assets::libraries::ASSETS.get_str(path) {
-                    return FILES.put(Box::new(MemoryVFS::from_static(file))).0;
+                    let fd = FILES.put(Box::new(MemoryVFS::from_static(file))).0;
+                    return track(fd, &name);
+                }
+            }
+        }
+
+        if oflag & O_ACCMODE == O_RDONLY && path.ends_with(".h") {
+            match cached(path) {
+                Some(Some(data)) => {
+                    return track(FILES.put(Box::new(MemoryVFS::shared(data))).0, path);
                 }
+                Some(None) => return -1,
+                None => {}
             }
         }
     }
 
-    let fd = open(path, oflag, args);
+    // the mode is only passed when creating; forwarding `args` itself would
+    // hand `open` a pointer to the list instead
+    let mode = match oflag & O_CREAT {
+        0 => 0,
+        _ => args.next_arg::<c_uint>(),
+    };
+    let fd = open(path, oflag, mode);
     if fd >= 0 {
-        FILES.put(Box::new(PosixVFS::new(fd))).0
+        track(FILES.put(Box::new(PosixVFS::new(fd))).0, &name)
     } else {
         fd
     }
@@ -176,13 +627,10 @@ pub unsafe extern "C" fn vfs_read(fd: c_int, buf: *mut c_void, count: size_t) ->
 #[no_mangle]
 pub unsafe extern "C" fn vfs_lseek(fd: c_int, offset: off_t, whence: c_int) -> off_t {
     if let Some(vfs) = FILES.get_mut(SmallIndex(fd)) {
-        vfs.seek(match whence {
-            SEEK_SET => SeekFrom::Start(offset.try_into().unwrap()),
-            SEEK_END => SeekFrom::End(offset.into()),
-            SEEK_CUR => SeekFrom::Current(offset.into()),
-            _ => return -1,
-        })
-        .unwrap_or(-1)
+        match seek_from(offset.into(), whence) {
+            Some(from) => vfs.seek(from).unwrap_or(-1),
+            None => -1,
+        }
     } else {
         -1
     }
@@ -193,6 +641,11 @@ pub unsafe extern "C" fn vfs_close(fd: c_int) -> c_int {
     if let Some(vfs) = FILES.get_mut(SmallIndex(fd)) {
         let ret = vfs.close().unwrap_or(-1);
         FILES.take(SmallIndex(fd));
+        NESTING.with(|nesting| {
+            if let Some(nesting) = nesting.borrow_mut().as_mut() {
+                nesting.open.retain(|(open, _)| *open != fd);
+            }
+        });
         ret
     } else {
         -1